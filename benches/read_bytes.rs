@@ -0,0 +1,70 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+//! Compares reading a file with `S3File::read_bytes` against collecting it
+//! byte by byte with `Read::bytes`, as `read_bytes` used to.
+//!
+//! Run with `cargo bench --bench read_bytes`.
+
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Instant;
+use std::{env, fs, process};
+
+use futures::{Future, Stream};
+
+use pennsieve_rust::model::S3File;
+use pennsieve_rust::Error;
+
+const FILE_NAME: &str = "read_bytes.bin";
+const FILE_SIZE: usize = 100 * 1024 * 1024;
+
+fn time<F: FnOnce() -> usize>(name: &str, f: F) {
+    let start = Instant::now();
+    let bytes_read = f();
+    let elapsed = start.elapsed();
+    assert_eq!(
+        bytes_read, FILE_SIZE,
+        "{} read the wrong number of bytes",
+        name
+    );
+    println!("{:<24} {:>10.1} ms", name, elapsed.as_secs_f64() * 1000.0);
+}
+
+// How `read_bytes` used to read a file, before it read in blocks:
+#[allow(clippy::unbuffered_bytes)]
+fn read_each_byte(path: &Path) -> usize {
+    fs::File::open(path)
+        .unwrap()
+        .bytes()
+        .collect::<Result<Vec<u8>, _>>()
+        .unwrap()
+        .len()
+}
+
+fn main() {
+    let dir = env::temp_dir().join(format!("pennsieve-bench-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(FILE_NAME);
+
+    let block: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let mut file = fs::File::create(&path).unwrap();
+    for _ in 0..FILE_SIZE / block.len() {
+        file.write_all(&block).unwrap();
+    }
+    drop(file);
+
+    println!("reading a {} MiB file:", FILE_SIZE / (1024 * 1024));
+
+    time("Read::bytes", || read_each_byte(&path));
+
+    let s3_file = S3File::new(FILE_NAME.to_string(), FILE_SIZE as u64, None, None);
+    time("S3File::read_bytes", || {
+        s3_file
+            .read_bytes(&dir)
+            .fold(0, |total, block| Ok::<_, Error>(total + block.len()))
+            .wait()
+            .unwrap()
+    });
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -3,7 +3,11 @@
 mod ps;
 
 // Publicly re-export:
+#[cfg(feature = "blocking")]
+pub use crate::ps::api::BlockingPennsieve;
 pub use crate::ps::api::{PSChildren, PSId, PSName, Pennsieve};
-pub use crate::ps::config::{Config, Environment};
+pub use crate::ps::config::{
+    ApiCredentials, AuthMode, Config, Environment, ParallelismBounds, RetryPolicy,
+};
 pub use crate::ps::types::{Error, ErrorKind, Future, Result, Stream};
 pub use crate::ps::{api, error, model};
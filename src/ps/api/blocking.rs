@@ -0,0 +1,182 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+//! A synchronous wrapper around the Pennsieve API client, for scripts that
+//! don't want to manage a runtime of their own.
+
+use std::sync::Mutex;
+
+use futures;
+use tokio::runtime::Runtime;
+
+use super::{response, Pennsieve};
+use crate::ps::config::Config;
+use crate::ps::model::{self, DatasetNodeId, OrganizationId, PackageId};
+use crate::ps::{Error, Result};
+
+/// A Pennsieve API client whose methods block until the platform responds.
+///
+/// Each method runs the `Pennsieve` method of the same name on a runtime
+/// owned by the client, which is reused across calls. Methods without a
+/// blocking counterpart can be run with `block_on`.
+pub struct BlockingPennsieve {
+    client: Pennsieve,
+    runtime: Mutex<Runtime>,
+}
+
+impl BlockingPennsieve {
+    /// Create a new blocking Pennsieve API client.
+    pub fn new(config: Config) -> Result<Self> {
+        Self::from_client(Pennsieve::new(config))
+    }
+
+    /// Wrap an existing asynchronous client. The two share a session.
+    pub fn from_client(client: Pennsieve) -> Result<Self> {
+        Ok(Self {
+            client,
+            runtime: Mutex::new(Runtime::new()?),
+        })
+    }
+
+    /// Get the asynchronous client this client wraps.
+    pub fn client(&self) -> &Pennsieve {
+        &self.client
+    }
+
+    /// Run a future on this client's runtime, blocking until it completes.
+    ///
+    /// This must not be called from a task running on a tokio runtime.
+    pub fn block_on<F, T>(&self, future: F) -> Result<T>
+    where
+        F: futures::Future<Item = T, Error = Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.runtime.lock().unwrap().block_on(future)
+    }
+
+    /// Log in to the Pennsieve API.
+    pub fn login<S: Into<String>>(
+        &self,
+        api_key: S,
+        api_secret: S,
+    ) -> Result<response::ApiSession> {
+        self.block_on(self.client.login(api_key, api_secret))
+    }
+
+    /// Get the current user.
+    pub fn get_user(&self) -> Result<model::User> {
+        self.block_on(self.client.get_user())
+    }
+
+    /// List the organizations the user is a member of.
+    pub fn get_organizations(&self) -> Result<response::Organizations> {
+        self.block_on(self.client.get_organizations())
+    }
+
+    /// Get a specific organization.
+    pub fn get_organization_by_id(&self, id: OrganizationId) -> Result<response::Organization> {
+        self.block_on(self.client.get_organization_by_id(id))
+    }
+
+    /// Get a listing of the datasets the current user has access to.
+    pub fn get_datasets(&self) -> Result<Vec<response::Dataset>> {
+        self.block_on(self.client.get_datasets())
+    }
+
+    /// Create a new dataset with some request parameter defaults.
+    pub fn create_dataset<N: Into<String>, D: Into<String>>(
+        &self,
+        name: N,
+        description: Option<D>,
+    ) -> Result<response::Dataset> {
+        self.block_on(self.client.create_dataset(name, description))
+    }
+
+    /// Get a specific dataset by its ID.
+    pub fn get_dataset_by_id(&self, id: DatasetNodeId) -> Result<response::Dataset> {
+        self.block_on(self.client.get_dataset_by_id(id))
+    }
+
+    /// Get a specific dataset by its name.
+    pub fn get_dataset_by_name<N: Into<String>>(&self, name: N) -> Result<response::Dataset> {
+        self.block_on(self.client.get_dataset_by_name(name))
+    }
+
+    /// Get a dataset by ID or by name.
+    pub fn get_dataset<N: Into<String>>(&self, id_or_name: N) -> Result<response::Dataset> {
+        self.block_on(self.client.get_dataset(id_or_name))
+    }
+
+    /// Update an existing dataset.
+    pub fn update_dataset<N: Into<String>, D: Into<String>>(
+        &self,
+        id: DatasetNodeId,
+        name: N,
+        description: Option<D>,
+    ) -> Result<response::Dataset> {
+        self.block_on(self.client.update_dataset(id, name, description))
+    }
+
+    /// Delete an existing dataset.
+    pub fn delete_dataset(&self, id: DatasetNodeId) -> Result<()> {
+        self.block_on(self.client.delete_dataset(id))
+    }
+
+    /// Get a specific package.
+    pub fn get_package_by_id(&self, id: PackageId) -> Result<response::Package> {
+        self.block_on(self.client.get_package_by_id(id))
+    }
+
+    /// Get the members that belong to the current user's organization.
+    pub fn get_members(&self) -> Result<Vec<model::User>> {
+        self.block_on(self.client.get_members())
+    }
+
+    /// Get the teams that belong to the current user's organization.
+    pub fn get_teams(&self) -> Result<Vec<response::Team>> {
+        self.block_on(self.client.get_teams())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mockito::mock;
+
+    use crate::ps::config::Environment;
+
+    const FIXTURE_DATASET: &str = "N:dataset:e5902b32-7954-463b-bb4c-2c9cf5b3bcfb";
+
+    fn dataset_json(id: &str, name: &str) -> String {
+        format!(
+            r#"{{ "organization": "N:organization:1", "owner": "N:user:1",
+            "content": {{ "id": "{}", "name": "{}", "status": "NO_STATUS",
+            "automaticallyProcessPackages": false, "intId": 1,
+            "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+            id, name
+        )
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn blocking_client_reuses_its_runtime_across_calls() {
+        let _list = mock("GET", "/datasets/")
+            .with_status(200)
+            .with_body(format!("[{}]", dataset_json(FIXTURE_DATASET, "blocking")))
+            .create();
+        let _get = mock("GET", format!("/datasets/{}", FIXTURE_DATASET).as_str())
+            .with_status(200)
+            .with_body(dataset_json(FIXTURE_DATASET, "blocking"))
+            .create();
+
+        let ps = BlockingPennsieve::new(Config::new(Environment::NonProduction)).unwrap();
+
+        let datasets = ps.get_datasets().unwrap();
+        assert_eq!(datasets.len(), 1);
+
+        let dataset = ps
+            .get_dataset_by_id(DatasetNodeId::new(FIXTURE_DATASET))
+            .unwrap();
+        assert_eq!(dataset.id(), datasets[0].id());
+    }
+}
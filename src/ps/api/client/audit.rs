@@ -0,0 +1,151 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use log::error;
+use serde_derive::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::ps::model::ImportId;
+
+/// A trait defining a durable record of uploads. Unlike a `ProgressCallback`,
+/// which is told about every part sent, `on_file_uploaded` is called once for
+/// each file, after all of its parts have been sent.
+pub trait UploadAuditSink: Send + Sync {
+    /// Called when a file has been uploaded.
+    fn on_file_uploaded(&self, record: &UploadAuditRecord);
+}
+
+/// A record of a single uploaded file.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadAuditRecord {
+    timestamp: DateTime<Utc>,
+    file_path: PathBuf,
+    size: u64,
+    sha256: String,
+    import_id: ImportId,
+}
+
+impl UploadAuditRecord {
+    /// Creates a record for the file at `file_path`, timestamped now, by
+    /// reading the file to hash it.
+    pub fn for_file<P: AsRef<Path>>(file_path: P, import_id: ImportId) -> io::Result<Self> {
+        let mut file = File::open(file_path.as_ref())?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+        let mut size = 0;
+        loop {
+            let bytes_read = file.read(&mut buffer)?;
+            if bytes_read == 0 {
+                break;
+            }
+            hasher.input(&buffer[..bytes_read]);
+            size += bytes_read as u64;
+        }
+
+        Ok(Self {
+            timestamp: Utc::now(),
+            file_path: file_path.as_ref().to_path_buf(),
+            size,
+            sha256: format!("{:x}", hasher.result()),
+            import_id,
+        })
+    }
+
+    /// Returns when the file finished uploading.
+    pub fn timestamp(&self) -> &DateTime<Utc> {
+        &self.timestamp
+    }
+
+    /// Returns the path the file was uploaded from.
+    pub fn file_path(&self) -> &Path {
+        self.file_path.as_ref()
+    }
+
+    /// Returns the size of the file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the hex-encoded SHA-256 hash of the file's contents.
+    pub fn sha256(&self) -> &String {
+        &self.sha256
+    }
+
+    /// Returns the Pennsieve import ID the file was uploaded under.
+    pub fn import_id(&self) -> &ImportId {
+        &self.import_id
+    }
+}
+
+/// An implementation of `UploadAuditSink` that appends every record to a
+/// file as a line of JSON.
+#[derive(Debug)]
+pub struct FileAuditSink {
+    file: Mutex<File>,
+}
+
+impl FileAuditSink {
+    /// Opens `path` for appending, creating it if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl UploadAuditSink for FileAuditSink {
+    fn on_file_uploaded(&self, record: &UploadAuditRecord) {
+        let written = serde_json::to_string(record)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(self.file.lock().unwrap(), "{}", line));
+        if let Err(err) = written {
+            error!(
+                "couldn't record upload of {:?} in the audit log: {}",
+                record.file_path(),
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, fs};
+
+    use rand::Rng;
+
+    #[test]
+    fn file_audit_sink_writes_json_lines() {
+        let path = env::temp_dir().join(format!(
+            "pennsieve-audit-{}.jsonl",
+            rand::thread_rng().gen::<u32>()
+        ));
+        let source = concat!(env!("CARGO_MANIFEST_DIR"), "/test/data/small/example.csv");
+
+        let sink = FileAuditSink::open(&path).unwrap();
+        let record = UploadAuditRecord::for_file(source, ImportId::new("import id")).unwrap();
+        sink.on_file_uploaded(&record);
+        sink.on_file_uploaded(&record);
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["size"], 1117);
+        assert_eq!(lines[0]["importId"], "import id");
+        assert_eq!(lines[0]["sha256"], record.sha256().as_str());
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+//! A bound on the bytes of file chunks held in memory by uploads.
+
+use std::sync::{Arc, Mutex};
+
+use futures::task::{self, Task};
+use futures::{try_ready, Async, Future, Poll, Stream};
+
+use crate::ps::api::client::progress::ProgressUpdate;
+use crate::ps::api::request::chunked_http::{ChunkedFilePayload, FileChunk};
+use crate::ps::Error;
+
+#[derive(Debug)]
+struct State {
+    capacity: u64,
+    in_use: u64,
+    peak: u64,
+    waiting: Vec<Task>,
+}
+
+/// A budget of bytes shared by every chunk being uploaded. Clones share the
+/// same budget.
+#[derive(Clone, Debug)]
+pub struct ByteBudget {
+    state: Arc<Mutex<State>>,
+}
+
+impl ByteBudget {
+    /// A budget of `capacity` bytes.
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                capacity,
+                in_use: 0,
+                peak: 0,
+                waiting: vec![],
+            })),
+        }
+    }
+
+    /// A budget that never runs out, but still keeps count.
+    pub fn unbounded() -> Self {
+        Self::new(u64::MAX)
+    }
+
+    /// Returns the bytes currently held.
+    pub fn in_use(&self) -> u64 {
+        self.state.lock().unwrap().in_use
+    }
+
+    /// Returns the most bytes that have been held at once.
+    pub fn peak(&self) -> u64 {
+        self.state.lock().unwrap().peak
+    }
+
+    /// Waits until `bytes` can be held without exceeding the budget, then
+    /// holds them until the returned permit is dropped. A request for more
+    /// than the whole budget is granted once nothing else is held.
+    pub fn acquire(&self, bytes: u64) -> Acquire {
+        Acquire {
+            budget: self.clone(),
+            bytes,
+        }
+    }
+
+    fn release(&self, bytes: u64) {
+        let waiting = {
+            let mut state = self.state.lock().unwrap();
+            state.in_use -= bytes;
+            state.waiting.split_off(0)
+        };
+        for task in waiting {
+            task.notify();
+        }
+    }
+}
+
+/// A future resolving to a `BudgetPermit` once there's room in the budget.
+#[must_use = "futures do nothing unless polled"]
+pub struct Acquire {
+    budget: ByteBudget,
+    bytes: u64,
+}
+
+impl Future for Acquire {
+    type Item = BudgetPermit;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut state = self.budget.state.lock().unwrap();
+        if state.in_use == 0 || state.in_use.saturating_add(self.bytes) <= state.capacity {
+            state.in_use += self.bytes;
+            state.peak = state.peak.max(state.in_use);
+            Ok(Async::Ready(BudgetPermit {
+                budget: self.budget.clone(),
+                bytes: self.bytes,
+            }))
+        } else {
+            state.waiting.push(task::current());
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+/// Bytes held against a `ByteBudget`, released when the permit is dropped.
+#[derive(Debug)]
+pub struct BudgetPermit {
+    budget: ByteBudget,
+    bytes: u64,
+}
+
+impl Drop for BudgetPermit {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+/// The chunks of a file, each read only once the budget has room for it.
+/// Each chunk comes with the permit holding its bytes.
+#[must_use = "streams do nothing unless polled"]
+pub struct BudgetedChunks {
+    payload: ChunkedFilePayload,
+    budget: ByteBudget,
+    acquiring: Option<Acquire>,
+}
+
+impl BudgetedChunks {
+    pub fn new(payload: ChunkedFilePayload, budget: ByteBudget) -> Self {
+        Self {
+            payload,
+            budget,
+            acquiring: None,
+        }
+    }
+}
+
+impl Stream for BudgetedChunks {
+    type Item = (FileChunk, ProgressUpdate, BudgetPermit);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let next_chunk_size = self.payload.next_chunk_size();
+        let budget = &self.budget;
+        let permit = try_ready!(self
+            .acquiring
+            .get_or_insert_with(|| budget.acquire(next_chunk_size))
+            .poll());
+        self.acquiring = None;
+
+        match self.payload.poll()? {
+            Async::Ready(Some((chunk, update))) => Ok(Async::Ready(Some((chunk, update, permit)))),
+            Async::Ready(None) => Ok(Async::Ready(None)),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::future;
+
+    #[test]
+    fn permits_are_held_until_dropped() {
+        let budget = ByteBudget::new(10);
+        future::lazy(|| {
+            let first = budget.acquire(6).poll().unwrap();
+            let mut second = budget.acquire(6);
+            assert!(first.is_ready());
+            assert!(second.poll().unwrap().is_not_ready());
+
+            drop(first);
+            assert!(second.poll().unwrap().is_ready());
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+
+        assert_eq!(budget.in_use(), 0);
+        assert_eq!(budget.peak(), 6);
+    }
+
+    #[test]
+    fn oversized_requests_are_granted_alone() {
+        let budget = ByteBudget::new(10);
+        future::lazy(|| {
+            let big = budget.acquire(25).poll().unwrap();
+            assert!(big.is_ready());
+            assert!(budget.acquire(1).poll().unwrap().is_not_ready());
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+
+        assert_eq!(budget.in_use(), 0);
+    }
+}
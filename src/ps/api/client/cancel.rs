@@ -0,0 +1,26 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A handle for cancelling a running upload from another thread. Clones share
+/// the same flag, so cancelling any of them cancels the upload.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Asks the upload to stop. Chunks already in flight are allowed to
+    /// finish, but no more are sent.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Tests if `cancel` has been called on this token or any of its clones.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
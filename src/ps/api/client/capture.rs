@@ -0,0 +1,211 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use hyper::header::{HeaderMap, HeaderName, AUTHORIZATION};
+use log::error;
+
+// Headers holding credentials, whose values are never captured:
+const REDACTED_HEADERS: [&str; 3] = [super::X_SESSION_ID, super::X_API_KEY, "authorization"];
+
+const REDACTED: &str = "<redacted>";
+
+/// A trait defining a hook that is shown every request made to the platform
+/// and the response it got, byte for byte. Meant for debugging, so it's only
+/// called when enabled with `Config::with_debug_capture`.
+pub trait DebugCapture: Send + Sync {
+    /// Called once a response to `request` has been read in full.
+    fn on_exchange(&self, request: &CapturedRequest, response: &CapturedResponse);
+}
+
+/// A request as it was sent, with credentials redacted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl CapturedRequest {
+    pub(super) fn new(
+        method: &hyper::Method,
+        url: &url::Url,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Self {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if is_redacted(name) {
+                    REDACTED.to_string()
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).to_string()
+                };
+                (name.as_str().to_string(), value)
+            })
+            .collect();
+        Self {
+            method: method.to_string(),
+            url: url.to_string(),
+            headers,
+            body: body.to_vec(),
+        }
+    }
+
+    /// Returns the HTTP method.
+    pub fn method(&self) -> &String {
+        &self.method
+    }
+
+    /// Returns the URL requested, including any query parameters.
+    pub fn url(&self) -> &String {
+        &self.url
+    }
+
+    /// Returns the headers sent, with the values of credentials replaced.
+    pub fn headers(&self) -> &Vec<(String, String)> {
+        &self.headers
+    }
+
+    /// Returns the body sent.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+/// A response as it was received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedResponse {
+    status: u16,
+    body: Vec<u8>,
+}
+
+impl CapturedResponse {
+    pub(super) fn new(status: hyper::StatusCode, body: &[u8]) -> Self {
+        Self {
+            status: status.as_u16(),
+            body: body.to_vec(),
+        }
+    }
+
+    /// Returns the HTTP status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns the body received.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+fn is_redacted(name: &HeaderName) -> bool {
+    *name == AUTHORIZATION
+        || REDACTED_HEADERS
+            .iter()
+            .any(|redacted| name.as_str().eq_ignore_ascii_case(redacted))
+}
+
+/// An implementation of `DebugCapture` that writes each exchange to a pair
+/// of files in a directory, `<n>-request.http` and `<n>-response.http`,
+/// numbered in the order the responses arrived.
+#[derive(Debug)]
+pub struct FileDebugCapture {
+    dir: PathBuf,
+    exchanges: AtomicUsize,
+}
+
+impl FileDebugCapture {
+    /// Captures to `dir`, creating it if it doesn't exist.
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            exchanges: AtomicUsize::new(0),
+        })
+    }
+
+    fn write(
+        &self,
+        exchange: usize,
+        request: &CapturedRequest,
+        response: &CapturedResponse,
+    ) -> io::Result<()> {
+        let mut file = fs::File::create(self.dir.join(format!("{:04}-request.http", exchange)))?;
+        writeln!(file, "{} {}", request.method(), request.url())?;
+        for (name, value) in request.headers() {
+            writeln!(file, "{}: {}", name, value)?;
+        }
+        writeln!(file)?;
+        file.write_all(request.body())?;
+
+        let mut file = fs::File::create(self.dir.join(format!("{:04}-response.http", exchange)))?;
+        writeln!(file, "{}", response.status())?;
+        writeln!(file)?;
+        file.write_all(response.body())
+    }
+}
+
+impl DebugCapture for FileDebugCapture {
+    fn on_exchange(&self, request: &CapturedRequest, response: &CapturedResponse) {
+        let exchange = self.exchanges.fetch_add(1, Ordering::SeqCst);
+        if let Err(err) = self.write(exchange, request, response) {
+            error!(
+                "couldn't capture {} {} to {:?}: {}",
+                request.method(),
+                request.url(),
+                self.dir,
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    use hyper::header::HeaderValue;
+    use rand::Rng;
+
+    #[test]
+    fn file_debug_capture_redacts_credentials() {
+        let dir = env::temp_dir().join(format!(
+            "pennsieve-capture-{}",
+            rand::thread_rng().gen::<u32>()
+        ));
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        headers.insert(
+            super::super::X_SESSION_ID,
+            HeaderValue::from_static("secret"),
+        );
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        let request = CapturedRequest::new(
+            &hyper::Method::PUT,
+            &"https://api.pennsieve.net/user/".parse().unwrap(),
+            &headers,
+            br#"{"organization":null}"#,
+        );
+        let response = CapturedResponse::new(hyper::StatusCode::OK, b"{}");
+
+        let capture = FileDebugCapture::new(&dir).unwrap();
+        capture.on_exchange(&request, &response);
+
+        let request = fs::read_to_string(dir.join("0000-request.http")).unwrap();
+        let response = fs::read_to_string(dir.join("0000-response.http")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(request.starts_with("PUT https://api.pennsieve.net/user/\n"));
+        assert!(request.contains("content-type: application/json\n"));
+        assert!(!request.contains("secret"));
+        assert!(request.ends_with(r#"{"organization":null}"#));
+        assert_eq!(response, "200\n\n{}");
+    }
+}
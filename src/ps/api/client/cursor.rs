@@ -0,0 +1,82 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+//! Walking the datasets the current user has access to, a page at a time.
+
+use std::collections::VecDeque;
+
+use futures::{Async, Future as _Future, Poll, Stream};
+
+use super::Pennsieve;
+use crate::ps::api::response;
+use crate::ps::{Error, Future};
+
+/// A stream of every dataset the current user has access to. Datasets are
+/// requested from the platform a page at a time, and the next page is only
+/// requested once the datasets of the last one have all been taken.
+pub struct DatasetCursor {
+    ps: Pennsieve,
+    page_size: usize,
+    // The offset of the next page to request, or `None` once the last page
+    // has been requested:
+    offset: Option<usize>,
+    buffer: VecDeque<response::Dataset>,
+    in_flight: Option<Future<response::DatasetPage>>,
+}
+
+impl DatasetCursor {
+    /// Creates a cursor that requests `page_size` datasets at a time. At
+    /// least one dataset is always requested.
+    pub fn new(ps: Pennsieve, page_size: usize) -> Self {
+        Self {
+            ps,
+            page_size: page_size.max(1),
+            offset: Some(0),
+            buffer: VecDeque::new(),
+            in_flight: None,
+        }
+    }
+
+    /// Returns the number of datasets requested at a time.
+    #[allow(dead_code)]
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+}
+
+impl Stream for DatasetCursor {
+    type Item = response::Dataset;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<response::Dataset>, Error> {
+        loop {
+            if let Some(dataset) = self.buffer.pop_front() {
+                return Ok(Async::Ready(Some(dataset)));
+            }
+            if let Some(in_flight) = self.in_flight.as_mut() {
+                let page = match in_flight.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(page)) => page,
+                    // Nothing more is requested after a failed page:
+                    Err(err) => {
+                        self.in_flight = None;
+                        return Err(err);
+                    }
+                };
+                self.in_flight = None;
+                self.offset = if page.has_more() {
+                    Some(page.offset() + page.datasets().len())
+                } else {
+                    None
+                };
+                self.buffer.extend(page);
+                continue;
+            }
+            match self.offset.take() {
+                Some(offset) => {
+                    self.in_flight = Some(self.ps.get_datasets_paginated(self.page_size, offset))
+                }
+                None => return Ok(Async::Ready(None)),
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+//! Reading upload events from a server-sent event stream.
+
+use std::collections::VecDeque;
+
+use futures::{try_ready, Async, Poll, Stream};
+use log::warn;
+
+use crate::ps::model::UploadEvent;
+use crate::ps::Error;
+
+/// Splits a server-sent event stream into the data of its events. Event
+/// names, ids and comments are ignored; only `data` fields are kept.
+#[derive(Debug, Default)]
+struct EventStreamDecoder {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl EventStreamDecoder {
+    /// Adds `bytes` read from the stream, returning the data of every event
+    /// they completed.
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = vec![];
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches('\n').trim_end_matches('\r');
+
+            // A blank line dispatches the event:
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+                continue;
+            }
+            let (field, value) = match line.find(':') {
+                Some(0) => continue,
+                Some(colon) => (&line[..colon], &line[colon + 1..]),
+                None => (line, ""),
+            };
+            if field == "data" {
+                self.data
+                    .push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            }
+        }
+        events
+    }
+}
+
+/// The upload events sent over a server-sent event stream, ending after the
+/// first final event.
+pub struct UploadEvents<S> {
+    body: S,
+    decoder: EventStreamDecoder,
+    pending: VecDeque<UploadEvent>,
+    finished: bool,
+}
+
+impl<S> UploadEvents<S> {
+    pub fn new(body: S) -> Self {
+        Self {
+            body,
+            decoder: EventStreamDecoder::default(),
+            pending: VecDeque::new(),
+            finished: false,
+        }
+    }
+}
+
+impl<S> Stream for UploadEvents<S>
+where
+    S: Stream<Item = hyper::Chunk, Error = hyper::Error>,
+{
+    type Item = UploadEvent;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<UploadEvent>, Error> {
+        loop {
+            if self.finished {
+                return Ok(Async::Ready(None));
+            }
+            if let Some(event) = self.pending.pop_front() {
+                self.finished = event.is_final();
+                return Ok(Async::Ready(Some(event)));
+            }
+            match try_ready!(self.body.poll()) {
+                Some(chunk) => {
+                    for data in self.decoder.push(&chunk) {
+                        match serde_json::from_str(&data) {
+                            Ok(event) => self.pending.push_back(event),
+                            Err(err) => warn!("ignoring upload event {:?}: {}", data, err),
+                        }
+                    }
+                }
+                None => return Ok(Async::Ready(None)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{stream, Future};
+
+    #[test]
+    fn events_can_span_chunks_and_lines() {
+        let mut decoder = EventStreamDecoder::default();
+        assert!(decoder.push(b": keep-alive\n\nevent: upl").is_empty());
+        assert!(decoder
+            .push(b"oad\r\ndata: {\"a\":\r\ndata:1}\r\n")
+            .is_empty());
+        assert_eq!(decoder.push(b"\r\n"), vec!["{\"a\":\n1}".to_string()]);
+    }
+
+    #[test]
+    fn events_end_with_the_first_final_one() {
+        let body = stream::iter_ok::<_, hyper::Error>(vec![hyper::Chunk::from(
+            "data: {\"type\":\"progress\",\"partsUploaded\":1,\"partsTotal\":2}\n\n\
+             data: not json\n\n\
+             data: {\"type\":\"completed\"}\n\n\
+             data: {\"type\":\"failed\",\"message\":\"too late\"}\n\n",
+        )]);
+        let events = UploadEvents::new(body).collect().wait().unwrap();
+        assert_eq!(
+            events,
+            vec![
+                UploadEvent::Progress {
+                    parts_uploaded: 1,
+                    parts_total: 2
+                },
+                UploadEvent::Completed
+            ]
+        );
+    }
+}
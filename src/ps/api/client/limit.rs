@@ -0,0 +1,163 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+//! A bound on the number of requests the client has in flight at once.
+
+use std::sync::{Arc, Mutex};
+
+use futures::task::{self, Task};
+use futures::{Async, Future, Poll};
+
+use crate::ps::Error;
+
+#[derive(Debug)]
+struct State {
+    max_requests: Option<usize>,
+    in_flight: usize,
+    peak: usize,
+    waiting: Vec<Task>,
+}
+
+/// A limit on the requests in flight, shared by every request the client
+/// makes. Clones share the same count.
+#[derive(Clone, Debug)]
+pub struct RequestLimit {
+    state: Arc<Mutex<State>>,
+}
+
+impl RequestLimit {
+    /// A limit of `max_requests` requests in flight at once.
+    pub fn new(max_requests: usize) -> Self {
+        Self::with_max(Some(max_requests.max(1)))
+    }
+
+    /// A limit that's never reached, but still keeps count.
+    pub fn unlimited() -> Self {
+        Self::with_max(None)
+    }
+
+    fn with_max(max_requests: Option<usize>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                max_requests,
+                in_flight: 0,
+                peak: 0,
+                waiting: vec![],
+            })),
+        }
+    }
+
+    /// Returns the number of requests currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().unwrap().in_flight
+    }
+
+    /// Returns the most requests that have been in flight at once.
+    pub fn peak(&self) -> usize {
+        self.state.lock().unwrap().peak
+    }
+
+    /// Waits until another request can be made without exceeding the limit,
+    /// then counts it as in flight until the returned permit is dropped.
+    pub fn acquire(&self) -> AcquireRequest {
+        AcquireRequest {
+            limit: self.clone(),
+        }
+    }
+
+    fn release(&self) {
+        let waiting = {
+            let mut state = self.state.lock().unwrap();
+            state.in_flight -= 1;
+            state.waiting.split_off(0)
+        };
+        for task in waiting {
+            task.notify();
+        }
+    }
+}
+
+/// A future resolving to a `RequestPermit` once the limit allows another
+/// request.
+#[must_use = "futures do nothing unless polled"]
+pub struct AcquireRequest {
+    limit: RequestLimit,
+}
+
+impl Future for AcquireRequest {
+    type Item = RequestPermit;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut state = self.limit.state.lock().unwrap();
+        match state.max_requests {
+            Some(max_requests) if state.in_flight >= max_requests => {
+                state.waiting.push(task::current());
+                Ok(Async::NotReady)
+            }
+            _ => {
+                state.in_flight += 1;
+                state.peak = state.peak.max(state.in_flight);
+                Ok(Async::Ready(RequestPermit {
+                    limit: self.limit.clone(),
+                }))
+            }
+        }
+    }
+}
+
+/// A request counted against a `RequestLimit`, until the permit is dropped.
+#[derive(Debug)]
+pub struct RequestPermit {
+    limit: RequestLimit,
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        self.limit.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::future;
+
+    #[test]
+    fn requests_wait_for_a_permit_to_be_dropped() {
+        let limit = RequestLimit::new(2);
+        future::lazy(|| {
+            let first = limit.acquire().poll().unwrap();
+            let second = limit.acquire().poll().unwrap();
+            let mut third = limit.acquire();
+            assert!(first.is_ready() && second.is_ready());
+            assert!(third.poll().unwrap().is_not_ready());
+            assert_eq!(limit.in_flight(), 2);
+
+            drop(first);
+            assert!(third.poll().unwrap().is_ready());
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+
+        assert_eq!(limit.in_flight(), 0);
+        assert_eq!(limit.peak(), 2);
+    }
+
+    #[test]
+    fn unlimited_requests_are_still_counted() {
+        let limit = RequestLimit::unlimited();
+        future::lazy(|| {
+            let permits: Vec<_> = (0..100).map(|_| limit.acquire().poll().unwrap()).collect();
+            assert!(permits.iter().all(Async::is_ready));
+            assert_eq!(limit.in_flight(), 100);
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+
+        assert_eq!(limit.in_flight(), 0);
+        assert_eq!(limit.peak(), 100);
+    }
+}
@@ -1,23 +1,46 @@
 //! Functions to interact with the Pennsieve platform.
 
+pub mod audit;
+mod budget;
+mod cancel;
+pub mod capture;
+mod cursor;
+mod events;
+mod limit;
+mod parallelism;
 pub mod progress;
+mod proxy;
+mod retry;
 
+pub use self::cancel::CancellationToken;
+pub use self::cursor::DatasetCursor;
 pub use self::progress::{ProgressCallback, ProgressUpdate};
 
+use self::audit::{UploadAuditRecord, UploadAuditSink};
+use self::budget::{BudgetedChunks, ByteBudget};
+use self::capture::{CapturedRequest, CapturedResponse, DebugCapture};
+use self::limit::{RequestLimit, RequestPermit};
+use self::parallelism::{BufferAdaptive, FlattenUnordered, Parallelism};
+use self::progress::ProgressSender;
+use self::proxy::ProxyConnector;
+use self::retry::Retrier;
+
 use std::borrow::Borrow;
 use std::collections::HashMap;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::{iter, time};
+use std::{fs, iter, time};
 
 use futures::{Future as _Future, Stream as _Stream, *};
-use hyper::client::{Client, HttpConnector};
-use hyper::header::{HeaderName, HeaderValue};
+use hyper::client::Client;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
 use hyper::{self, Method, StatusCode};
 use hyper_tls::HttpsConnector;
-use lazy_static::lazy_static;
-use log::{debug, error};
-use rusoto_cognito_idp::{CognitoIdentityProvider, InitiateAuthRequest};
+use log::{debug, error, warn};
+use rusoto_cognito_idp::{
+    CognitoIdentityProvider, CognitoIdentityProviderClient, InitiateAuthRequest,
+};
 use rusoto_core::credential::{AwsCredentials, StaticProvider};
 use rusoto_core::request::HttpClient;
 use serde;
@@ -28,12 +51,12 @@ use tokio;
 use mockito;
 
 use super::request::chunked_http::ChunkedFilePayload;
-use super::{request, response};
-use crate::ps::config::{Config, Environment};
-use crate::ps::model::upload::MultipartUploadId;
+use super::{request, response, PSName};
+use crate::ps::config::{ApiCredentials, AuthMode, Config, Environment, RetryPolicy};
+use crate::ps::model::upload::{Checksum, MultipartUploadId};
 use crate::ps::model::{
-    self, DatasetId, DatasetNodeId, FileUpload, ImportId, OrganizationId, PackageId, SessionToken,
-    UploadId,
+    self, DatasetId, DatasetNodeId, DatasetRole, FileUpload, ImportId, OrganizationId, PackageId,
+    PipelineId, SessionToken, UploadId, UserId,
 };
 use crate::ps::util::futures::{into_future_trait, into_stream_trait};
 use crate::ps::{Error, ErrorKind, Future, Result, Stream};
@@ -44,62 +67,512 @@ use url::Url;
 // Pennsieve session authentication header:
 const X_SESSION_ID: &str = "X-SESSION-ID";
 
-const MAX_RETRIES: usize = 20;
-
-lazy_static! {
-    static ref ALL_METHODS: Vec<Method> = vec![
-        Method::GET,
-        Method::POST,
-        Method::PUT,
-        Method::DELETE,
-        Method::HEAD,
-        Method::OPTIONS,
-        Method::CONNECT,
-        Method::PATCH,
-        Method::TRACE,
-    ];
-    static ref NON_IDEMPOTENT_METHODS: Vec<Method> = vec![
-        Method::POST, Method::DELETE
-    ];
-    static ref IDEMPOTENT_METHODS: Vec<Method> = ALL_METHODS
-        .clone()
-        .into_iter()
-        .filter(|method| !NON_IDEMPOTENT_METHODS.contains(method))
-        .collect();
-
-    /// A map of retryable status codes to the list of methods that we
-    /// want to retry for those status codes.
-    static ref RETRYABLE_STATUS_CODES: HashMap<StatusCode, Vec<Method>> = vec![
-        // 4XX
-        (StatusCode::TOO_MANY_REQUESTS, ALL_METHODS.clone()),
-        // 5XX
-        (StatusCode::SERVICE_UNAVAILABLE, ALL_METHODS.clone()),
-        (StatusCode::BAD_GATEWAY, IDEMPOTENT_METHODS.clone()),
-        (StatusCode::GATEWAY_TIMEOUT, IDEMPOTENT_METHODS.clone()),
-    ].into_iter().collect();
-
-    /// A vec of status codes that cannot be resolved by retrying the
-    /// request and should be bubbled up directly to the caller
-    static ref NONRETRYABLE_STATUS_CODES: Vec<StatusCode> = vec![
-        StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN
-    ];
+// Pennsieve API key authentication header:
+const X_API_KEY: &str = "X-API-Key";
+
+// The header naming the organization a request is made in:
+const X_ORGANIZATION_ID: &str = "X-ORGANIZATION-ID";
+
+// The number of datasets requested per page when streaming all datasets:
+const DATASET_PAGE_SIZE: usize = 100;
+
+// The number of packages requested per page when streaming a dataset's packages:
+const PACKAGE_PAGE_SIZE: usize = 100;
+
+// The number of children requested per page when streaming a collection's children:
+const PACKAGE_CHILDREN_PAGE_SIZE: usize = 100;
+
+// The most collaborator counts requested at once when listing datasets with counts:
+const COLLABORATOR_COUNT_PARALLELISM: usize = 8;
+
+// The most datasets tagged at once when tagging them in bulk:
+const DATASET_TAG_PARALLELISM: usize = 8;
+
+// The most packages whose source files are requested at once when listing
+// every file in a dataset:
+const PACKAGE_SOURCES_PARALLELISM: usize = 8;
+
+// The most packages requested at once when fetching them in bulk:
+const PACKAGE_FETCH_PARALLELISM: usize = 8;
+
+// The most packages processed at once when processing a whole dataset:
+const PACKAGE_PROCESS_PARALLELISM: usize = 8;
+
+// The most datasets deleted at once when deleting them in bulk:
+const DATASET_DELETE_PARALLELISM: usize = 8;
+
+// The most samples requested in a single window of channel data:
+const CHANNEL_DATA_PAGE_SAMPLES: usize = 10_000;
+
+// The most upload statuses requested at once when fetching them in bulk:
+const UPLOAD_STATUS_PARALLELISM: usize = 8;
+
+// How often an upload's status is polled when its events can't be streamed:
+const UPLOAD_STATUS_POLL_INTERVAL: time::Duration = time::Duration::from_secs(2);
+
+// How soon before it expires a session is renewed, when auto refresh is enabled:
+const SESSION_REFRESH_MARGIN_SECS: i64 = 60;
+
+/// The length of time, in microseconds, covered by a window of
+/// `CHANNEL_DATA_PAGE_SAMPLES` samples from a channel sampled at `rate` Hz,
+/// or `None` if the rate is unusable.
+fn channel_data_window(rate: f64) -> Option<i64> {
+    if rate.is_finite() && rate > 0.0 {
+        Some(((CHANNEL_DATA_PAGE_SAMPLES as f64 * 1_000_000.0 / rate) as i64).max(1))
+    } else {
+        None
+    }
+}
+
+/// Tests if a failed login is worth retrying: the platform or Cognito
+/// couldn't be reached, timed out, or failed on their end.
+fn is_transient_login_error(err: &Error) -> bool {
+    match err.kind() {
+        ErrorKind::HyperError { .. }
+        | ErrorKind::IoError { .. }
+        | ErrorKind::TimeoutError { .. } => true,
+        ErrorKind::ApiError { status_code, .. } => {
+            status_code.is_server_error() || *status_code == StatusCode::TOO_MANY_REQUESTS
+        }
+        _ => false,
+    }
+}
+
+/// The upload event matching an upload status: every part still missing
+/// from an upload counts against its progress, and once none are, it's
+/// complete.
+fn upload_event_from_status(status: Option<response::FilesMissingParts>) -> model::UploadEvent {
+    match status {
+        Some(status) => {
+            let parts_total: usize = status
+                .files
+                .iter()
+                .map(|file| file.expected_total_parts)
+                .sum();
+            let parts_missing: usize = status
+                .files
+                .iter()
+                .map(|file| file.missing_parts.len())
+                .sum();
+            model::UploadEvent::Progress {
+                parts_uploaded: parts_total.saturating_sub(parts_missing),
+                parts_total,
+            }
+        }
+        None => model::UploadEvent::Completed,
+    }
+}
+
+/// Keeps the `ETag` the platform tagged a dataset response with, if any.
+fn dataset_with_etag(headers: &HeaderMap, dataset: response::Dataset) -> response::Dataset {
+    let etag = headers
+        .get(hyper::header::ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(String::from);
+    dataset.with_etag(etag)
+}
+
+/// The idempotency key an uploaded chunk is sent with, which is the same
+/// every time the same chunk is sent, and differs for any other chunk. If
+/// the key can't be sent in a header, there is none.
+fn chunk_idempotency_key(
+    import_id: &ImportId,
+    chunk_number: usize,
+    checksum: &Checksum,
+) -> Option<HeaderValue> {
+    HeaderValue::from_str(&format!("{}:{}:{}", import_id, chunk_number, checksum.0)).ok()
+}
+
+/// What every request the client makes is sent with: the user agent, the
+/// headers that authenticate a request to the platform and name the current
+/// organization, and the limit on requests in flight.
+#[derive(Clone)]
+struct RequestContext {
+    client: Client<HttpsConnector<ProxyConnector>>,
+    request_limit: RequestLimit,
+    user_agent: Option<HeaderValue>,
+    platform_headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl RequestContext {
+    /// Adds the headers every request is sent with to `req`. Only requests
+    /// `to_platform` are authenticated and name the current organization, so
+    /// neither is ever handed to anyone else, e.g. an origin the platform
+    /// redirected to, or a presigned storage URL.
+    fn prepare(&self, req: &mut hyper::Request<hyper::Body>, to_platform: bool) {
+        if to_platform {
+            for (header_name, header_value) in self.platform_headers.clone() {
+                req.headers_mut().insert(header_name, header_value);
+            }
+        }
+        if let Some(user_agent) = self.user_agent.clone() {
+            req.headers_mut()
+                .insert(hyper::header::USER_AGENT, user_agent);
+        }
+    }
+
+    /// Sends `req` once the limit on requests in flight allows. It counts
+    /// against the limit until the returned permit is dropped.
+    fn send(
+        &self,
+        req: hyper::Request<hyper::Body>,
+    ) -> Future<(hyper::Response<hyper::Body>, RequestPermit)> {
+        let client = self.client.clone();
+        let f = self.request_limit.acquire().and_then(move |permit| {
+            client
+                .request(req)
+                .map_err(Into::<Error>::into)
+                .map(|response| (response, permit))
+        });
+        into_future_trait(f)
+    }
+}
+
+/// Gives up on `f` with `ErrorKind::TimeoutError` once `timeout` elapses,
+/// if one is configured.
+fn with_timeout<T>(f: Future<T>, timeout: Option<time::Duration>) -> Future<T>
+where
+    T: 'static + Send,
+{
+    match timeout {
+        Some(timeout) => {
+            into_future_trait(tokio::timer::Timeout::new(f, timeout).map_err(move |err| {
+                if err.is_elapsed() {
+                    Error::timeout_error(timeout)
+                } else if err.is_timer() {
+                    err.into_timer().unwrap().into()
+                } else {
+                    err.into_inner().unwrap()
+                }
+            }))
+        }
+        None => f,
+    }
+}
+
+/// The offset of the first byte of a partial response, going by its
+/// `Content-Range` header, e.g. 100 for `bytes 100-999/1000`.
+fn content_range_start(headers: &HeaderMap) -> Option<u64> {
+    let range = headers.get(hyper::header::CONTENT_RANGE)?.to_str().ok()?;
+    let range = range.trim().strip_prefix("bytes ")?;
+    range.split('-').next()?.trim().parse().ok()
+}
+
+/// Request the file at `uri` from byte `offset` on, returning the response
+/// body along with the offset it starts at, and the permit counting the
+/// download against the limit on requests in flight. If the server answers
+/// with a range other than the one asked for, or can't satisfy it, the whole
+/// file is requested instead. The download URL is never sent the platform's
+/// auth headers, and the timeout, if any, covers waiting for the response
+/// rather than reading the whole file.
+fn request_download(
+    context: RequestContext,
+    timeout: Option<time::Duration>,
+    uri: hyper::Uri,
+    offset: u64,
+) -> Future<(hyper::Body, u64, RequestPermit)> {
+    let mut req = hyper::Request::get(uri.clone())
+        .body(hyper::Body::empty())
+        .unwrap();
+    context.prepare(&mut req, false);
+    if offset > 0 {
+        req.headers_mut().insert(
+            hyper::header::RANGE,
+            HeaderValue::from_str(&format!("bytes={}-", offset)).unwrap(),
+        );
+    }
+    debug!(
+        "ps:download_file<{uri}>:offset = {offset}",
+        uri = uri,
+        offset = offset
+    );
+
+    let f = with_timeout(context.send(req), timeout).and_then(move |(response, permit)| {
+        let status_code = response.status();
+        if offset > 0 {
+            let start = content_range_start(response.headers());
+            match status_code {
+                StatusCode::PARTIAL_CONTENT if start == Some(offset) => {
+                    return into_future_trait(future::ok((response.into_body(), offset, permit)));
+                }
+                StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE => {
+                    debug!(
+                        "ps:download_file<{uri}>:range = {start:?}, restarting",
+                        uri = uri,
+                        start = start
+                    );
+                    drop(permit);
+                    return request_download(context, timeout, uri, 0);
+                }
+                _ => (),
+            }
+        }
+        let body = response.into_body();
+        if status_code.is_success() {
+            into_future_trait(future::ok((body, 0, permit)))
+        } else {
+            into_future_trait(body.concat2().map_err(Into::into).and_then(move |body| {
+                Err(Error::api_error(
+                    status_code,
+                    String::from_utf8_lossy(&body),
+                ))
+            }))
+        }
+    });
+    into_future_trait(f)
+}
+
+/// A span around a single request, recording its method and route, and
+/// once it's done, its status and how long it took. Bodies are never
+/// recorded. Without the `tracing` feature, requests aren't traced.
+#[cfg(feature = "tracing")]
+struct RequestSpan {
+    span: tracing::Span,
+    started: time::Instant,
+}
+
+#[cfg(feature = "tracing")]
+impl RequestSpan {
+    fn new(method: &Method, route: &str) -> Self {
+        Self {
+            span: tracing::debug_span!(
+                "ps.request",
+                method = %method,
+                route = %route,
+                status = tracing::field::Empty,
+                elapsed_ms = tracing::field::Empty,
+            ),
+            started: time::Instant::now(),
+        }
+    }
+
+    fn trace(self, request: Future<RawResponse>) -> Future<RawResponse> {
+        into_future_trait(request.then(move |result| {
+            let span = self.span;
+            span.record("elapsed_ms", &(self.started.elapsed().as_millis() as u64));
+            match result {
+                Ok((status_code, _, _)) => {
+                    span.record("status", &status_code.as_u16());
+                    span.in_scope(|| tracing::debug!("request completed"));
+                }
+                Err(ref err) => span.in_scope(|| tracing::debug!(error = %err, "request failed")),
+            }
+            result
+        }))
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+struct RequestSpan;
+
+#[cfg(not(feature = "tracing"))]
+impl RequestSpan {
+    fn new(_method: &Method, _route: &str) -> Self {
+        RequestSpan
+    }
+
+    fn trace(self, request: Future<RawResponse>) -> Future<RawResponse> {
+        request
+    }
+}
+
+/// Read a whole response body into memory, failing with
+/// `ErrorKind::ResponseTooLarge` as soon as it grows past `max_bytes`, if
+/// given, rather than buffering it all first.
+fn read_body(body: hyper::Body, max_bytes: Option<usize>) -> Future<hyper::Chunk> {
+    match max_bytes {
+        None => into_future_trait(body.concat2().map_err(Into::into)),
+        Some(max_bytes) => into_future_trait(
+            body.map_err(Into::<Error>::into)
+                .fold(Vec::new(), move |mut bytes, chunk| {
+                    if bytes.len() + chunk.len() > max_bytes {
+                        return Err(Error::response_too_large(max_bytes));
+                    }
+                    bytes.extend_from_slice(&chunk);
+                    Ok(bytes)
+                })
+                .map(hyper::Chunk::from),
+        ),
+    }
+}
+
+/// Parse a JSON response body. `serde_json::from_slice` fails if the body is
+/// empty, so an empty body is parsed as `null`, which suits types with
+/// nothing to parse, such as `()`, `Option`s and `response::EmptyMap`. Any
+/// other type fails with `ErrorKind::EmptyResponse` rather than a confusing
+/// parse error.
+fn parse_json<Q: serde::de::DeserializeOwned>(chunk: hyper::Chunk) -> Result<Q> {
+    let bytes = chunk.into_bytes();
+    if bytes.is_empty() {
+        return serde_json::from_slice(b"null")
+            .map_err(|_| Error::empty_response(std::any::type_name::<Q>()));
+    }
+    serde_json::from_slice(&bytes).map_err(Into::into)
 }
 
-/// Given the number of the current attempt, calculate the delay (in
-/// milliseconds) for how long we should wait until the next retry
-///
-/// # Arguments
-///
-/// * `try_num` - The number of this attempt, indexed at 0
-fn retry_delay(try_num: usize) -> u64 {
-    500 * try_num as u64
+/// Parse a JSON response body holding an array of `what`s. If the array
+/// can't be parsed in one go, its elements are parsed one at a time to find
+/// the index of the first bad one, so the error can say which it was.
+fn parse_json_list<T: serde::de::DeserializeOwned>(
+    chunk: hyper::Chunk,
+    what: &str,
+) -> Result<Vec<T>> {
+    match serde_json::from_slice(&chunk) {
+        Ok(list) => Ok(list),
+        Err(err) => {
+            let elements: Vec<serde_json::Value> = match serde_json::from_slice(&chunk) {
+                Ok(elements) => elements,
+                Err(_) => return Err(err.into()),
+            };
+            let bad_element = elements
+                .into_iter()
+                .map(serde_json::from_value::<T>)
+                .enumerate()
+                .find_map(|(index, element)| element.err().map(|err| (index, err)));
+            Err(match bad_element {
+                Some((index, err)) => Error::serde_json_error(format!(
+                    "failed to parse {} at index {}: {}",
+                    what, index, err
+                )),
+                None => err.into(),
+            })
+        }
+    }
 }
 
 struct PennsieveImpl {
     config: Config,
-    http_client: Client<HttpsConnector<HttpConnector>>,
+    connector: HttpsConnector<ProxyConnector>,
+    http_client: Client<HttpsConnector<ProxyConnector>>,
     session_token: Option<SessionToken>,
     current_organization: Option<OrganizationId>,
+    shutdown: CancellationToken,
+    upload_audit_sink: Option<Arc<dyn UploadAuditSink>>,
+    debug_capture: Option<Arc<dyn DebugCapture>>,
+    // The bytes of file chunks held in memory by uploads:
+    chunk_budget: ByteBudget,
+    // The requests in flight:
+    request_limit: RequestLimit,
+    // When the session expires, in seconds since the epoch:
+    session_expiry: Option<i64>,
+    login_credentials: Option<LoginCredentials>,
+    // A renewal of the session that's underway, shared by every request
+    // waiting on it:
+    session_refresh: Option<future::Shared<Future<response::ApiSession>>>,
+    // Where logins are sent; only ever changed by tests:
+    cognito_region: rusoto_core::region::Region,
+}
+
+/// What's needed to log in again when the session is renewed.
+#[derive(Clone)]
+struct LoginCredentials {
+    api_key: String,
+    api_secret: String,
+    app_client_id: String,
+}
+
+/// Tests if a session expiring at `expiry` should be renewed at `now`, both
+/// in seconds since the epoch.
+fn session_is_expiring(expiry: i64, now: i64) -> bool {
+    expiry - now <= SESSION_REFRESH_MARGIN_SECS
+}
+
+/// A Cognito client for logging in, built from the client state so that
+/// it can be made while the client lock is already held.
+fn cognito_client(inner: &PennsieveImpl) -> CognitoIdentityProviderClient {
+    CognitoIdentityProviderClient::new_with(
+        HttpClient::from_connector(inner.connector.clone()),
+        StaticProvider::from(AwsCredentials::default()),
+        inner.cognito_region.clone(),
+    )
+}
+
+/// Run the Cognito auth flow with `credentials`, resolving to the new
+/// session and when it expires, in seconds since the epoch. Nothing is
+/// stored on the client; see `store_session`.
+fn initiate_session(
+    cognito: CognitoIdentityProviderClient,
+    credentials: &LoginCredentials,
+) -> Future<(response::ApiSession, i64)> {
+    let mut auth_parameters = HashMap::<String, String>::new();
+    auth_parameters.insert("USERNAME".to_string(), credentials.api_key.clone());
+    auth_parameters.insert("PASSWORD".to_string(), credentials.api_secret.clone());
+
+    let request = InitiateAuthRequest {
+        analytics_metadata: None,
+        auth_flow: "USER_PASSWORD_AUTH".to_string(),
+        auth_parameters: Some(auth_parameters),
+        client_id: credentials.app_client_id.clone(),
+        client_metadata: None,
+        user_context_data: None,
+    };
+
+    into_future_trait(cognito.initiate_auth(request).map_err(Into::into).and_then(
+        move |response| {
+            let authentication_result = response.clone().authentication_result.ok_or(
+                crate::ps::Error::initiate_auth_error(
+                    "No authentication result, does another challenge need to be passed?",
+                ),
+            )?;
+
+            let access_token =
+                authentication_result
+                    .access_token
+                    .ok_or(crate::ps::Error::initiate_auth_error(
+                        "No access token in the Cognito initiate auth response.",
+                    ))?;
+
+            let id_token =
+                authentication_result
+                    .id_token
+                    .ok_or(crate::ps::Error::initiate_auth_error(
+                        "No ID token in the Cognito initiate auth response.",
+                    ))?;
+
+            let payload_parts: Vec<&str> = id_token.split(".").collect();
+            let payload_b64 = base64_url::decode(payload_parts[1])?;
+            let payload_str = std::str::from_utf8(&payload_b64)
+                .map_err(|err| crate::ps::Error::initiate_auth_error(err.to_string()))?;
+            let payload: serde_json::Value = serde_json::from_str(payload_str)?;
+
+            let organization_node_id_value = payload
+                .get("custom:organization_node_id")
+                .ok_or(crate::ps::Error::initiate_auth_error(
+                "Cognito response payload does not have the `custom:organization_node_id` property",
+            ))?;
+
+            let organization_node_id = organization_node_id_value.as_str().ok_or(
+                crate::ps::Error::initiate_auth_error(
+                    "Cognito response payload `custom:organization_node_id` is not a string.",
+                ),
+            )?;
+            let exp = payload["exp"]
+                .as_i64()
+                .ok_or(crate::ps::Error::initiate_auth_error(
+                    "Cognito response payload does not have an expiration date `exp`.",
+                ))?;
+
+            let session = response::ApiSession::new(
+                SessionToken::new(access_token),
+                organization_node_id.to_string(),
+                exp as i32,
+            );
+            Ok((session, exp))
+        },
+    ))
+}
+
+/// Store a session started with `credentials`, keeping the credentials so
+/// the session can be renewed. The current organization is left as it is.
+fn store_session(
+    inner: &mut PennsieveImpl,
+    session: &response::ApiSession,
+    expiry: i64,
+    credentials: LoginCredentials,
+) {
+    inner.session_token = Some(session.session_token().clone());
+    inner.session_expiry = Some(expiry);
+    inner.login_credentials = Some(credentials);
 }
 
 /// The Pennsieve client.
@@ -126,6 +599,9 @@ type RequestParam = (String, String);
 // a POST/PUT body, but a type is still expected:
 type Nothing = serde_json::Value;
 
+// A response as read from the platform: its status, headers and body:
+type RawResponse = (StatusCode, HeaderMap, hyper::Chunk);
+
 // =============================================================================
 
 // Useful builder macros:
@@ -198,6 +674,15 @@ macro_rules! put {
     };
 }
 
+macro_rules! head {
+    ($target:expr, $route:expr) => {
+        $target.request($route, Method::HEAD, params!(), payload!())
+    };
+    ($target:expr, $route:expr, $params:expr) => {
+        $target.request($route, Method::HEAD, $params, payload!())
+    };
+}
+
 macro_rules! delete {
     ($target:expr, $route:expr) => {
         $target.request($route, Method::DELETE, params!(), payload!())
@@ -215,14 +700,33 @@ macro_rules! delete {
 impl Pennsieve {
     /// Create a new Pennsieve API client.
     pub fn new(config: Config) -> Self {
-        let connector = HttpsConnector::new(4).expect("ps:couldn't create https connector");
+        let connector =
+            proxy::https_connector(&config).expect("ps:couldn't create https connector");
         let http_client = Client::builder().build(connector.clone());
+        let chunk_budget = match config.max_in_flight_bytes() {
+            Some(max_in_flight_bytes) => ByteBudget::new(max_in_flight_bytes),
+            None => ByteBudget::unbounded(),
+        };
+        let request_limit = match config.concurrency_limit() {
+            Some(concurrency_limit) => RequestLimit::new(concurrency_limit),
+            None => RequestLimit::unlimited(),
+        };
         Self {
             inner: Arc::new(Mutex::new(PennsieveImpl {
                 config,
+                connector,
                 http_client,
                 session_token: None,
                 current_organization: None,
+                shutdown: CancellationToken::new(),
+                upload_audit_sink: None,
+                debug_capture: None,
+                chunk_budget,
+                request_limit,
+                session_expiry: None,
+                login_credentials: None,
+                session_refresh: None,
+                cognito_region: rusoto_core::region::Region::UsEast1,
             })),
         }
     }
@@ -231,11 +735,76 @@ impl Pennsieve {
         self.inner.lock().unwrap().session_token.clone()
     }
 
+    /// The headers that authenticate a request, going by the configured
+    /// auth mode: the API key, or the session token if there is one. Fails
+    /// with `ErrorKind::InvalidArguments` if the API key can't be sent in a
+    /// header, rather than sending the request unauthenticated.
+    fn auth_headers(&self) -> Result<Vec<(HeaderName, HeaderValue)>> {
+        let auth_mode = self.inner.lock().unwrap().config.auth_mode().clone();
+        match auth_mode {
+            AuthMode::ApiKey { key } => {
+                let key = HeaderValue::from_str(&key).map_err(|_| {
+                    Error::invalid_arguments("the API key isn't a valid header value")
+                })?;
+                Ok(vec![(
+                    HeaderName::from_bytes(X_API_KEY.as_bytes()).unwrap(),
+                    key,
+                )])
+            }
+            AuthMode::Session => Ok(match self.session_token() {
+                Some(session_token) => vec![
+                    (
+                        HeaderName::from_bytes(X_SESSION_ID.as_bytes()).unwrap(),
+                        HeaderValue::from_str(session_token.borrow()).unwrap(),
+                    ),
+                    (
+                        hyper::header::AUTHORIZATION,
+                        HeaderValue::from_str(&format!("Bearer {}", session_token.take())).unwrap(),
+                    ),
+                ],
+                None => vec![],
+            }),
+        }
+    }
+
+    /// What every request is sent with, as things stand: the user agent,
+    /// the auth headers and current organization, and the limit on requests
+    /// in flight.
+    fn request_context(&self) -> Result<RequestContext> {
+        let mut platform_headers = self.auth_headers()?;
+        if let Some(organization) = self
+            .current_organization()
+            .and_then(|org| HeaderValue::from_str(org.borrow()).ok())
+        {
+            platform_headers.push((
+                HeaderName::from_bytes(X_ORGANIZATION_ID.as_bytes()).unwrap(),
+                organization,
+            ));
+        }
+        let inner = self.inner.lock().unwrap();
+        Ok(RequestContext {
+            client: inner.http_client.clone(),
+            request_limit: inner.request_limit.clone(),
+            user_agent: inner.config.user_agent().cloned(),
+            platform_headers,
+        })
+    }
+
     fn chunk_to_string(body: &hyper::Chunk) -> String {
         let as_bytes: Vec<u8> = body.to_vec();
         String::from_utf8_lossy(&as_bytes).to_string()
     }
 
+    /// What is logged of a response body: the body itself only if the
+    /// config opts into logging bodies, as they can hold credentials.
+    fn loggable_body(body: &hyper::Chunk, log_bodies: bool) -> String {
+        if log_bodies {
+            Self::chunk_to_string(body)
+        } else {
+            format!("<{} bytes>", body.len())
+        }
+    }
+
     fn get_url(&self) -> url::Url {
         #[cfg(feature = "mocks")]
         let url = mockito::server_url().parse::<Url>().unwrap();
@@ -265,6 +834,27 @@ impl Pennsieve {
         params: I,
         payload: Option<&P>,
     ) -> Future<Q>
+    where
+        P: serde::Serialize,
+        I: IntoIterator<Item = RequestParam> + Send,
+        Q: 'static + Send + serde::de::DeserializeOwned,
+        S: Into<String> + Send,
+    {
+        let response = self.request_with_headers(route, method, params, payload, vec![]);
+        into_future_trait(response.map(|(_, parsed)| parsed))
+    }
+
+    /// Like `request`, but sends `additional_headers` along with the
+    /// request, and returns the response headers along with the parsed
+    /// body.
+    fn request_with_headers<I, P, Q, S>(
+        &self,
+        route: S,
+        method: Method,
+        params: I,
+        payload: Option<&P>,
+        mut additional_headers: Vec<(HeaderName, HeaderValue)>,
+    ) -> Future<(HeaderMap, Q)>
     where
         P: serde::Serialize,
         I: IntoIterator<Item = RequestParam> + Send,
@@ -280,18 +870,21 @@ impl Pennsieve {
             .unwrap_or_else(|| Ok(vec![]))
             .map_err(Into::into);
 
+        additional_headers.push((
+            hyper::header::CONTENT_TYPE,
+            hyper::header::HeaderValue::from_str("application/json").unwrap(),
+        ));
+
         match serialized_payload {
-            Ok(body) => self.request_with_body(
-                route,
-                method,
-                params,
-                body,
-                vec![(
-                    hyper::header::CONTENT_TYPE,
-                    hyper::header::HeaderValue::from_str("application/json").unwrap(),
-                )],
-                true,
-            ),
+            Ok(body) => {
+                let response =
+                    self.request_raw(route, method, params, body, additional_headers, true);
+                into_future_trait(
+                    response.and_then(|(headers, body)| {
+                        parse_json(body).map(|parsed| (headers, parsed))
+                    }),
+                )
+            }
             Err(err) => into_future_trait(futures::failed(err)),
         }
     }
@@ -308,6 +901,9 @@ impl Pennsieve {
     /// retry again. Therefore, we try not to use retry_on_failure for
     /// requests with large byte payloads (such as uploads).
     ///
+    /// A request sent with the idempotency key header named in the config
+    /// is retried as if its method were idempotent.
+    ///
     /// # Arguments
     ///
     /// * `route` - The target Pennsieve API route
@@ -329,11 +925,66 @@ impl Pennsieve {
         I: IntoIterator<Item = RequestParam>,
         Q: 'static + Send + serde::de::DeserializeOwned,
         S: Into<String>,
+    {
+        let response = self.request_bytes(
+            route,
+            method,
+            params,
+            body,
+            additional_headers,
+            retry_on_failure,
+        );
+
+        // Finally, attempt to parse the JSON response into a typeful
+        // representation.
+        into_future_trait(response.and_then(|chunk| parse_json(chunk)))
+    }
+
+    /// Like `request_with_body`, but returns the raw response body rather
+    /// than parsing it.
+    fn request_bytes<I, S>(
+        &self,
+        route: S,
+        method: Method,
+        params: I,
+        body: Vec<u8>,
+        additional_headers: Vec<(HeaderName, HeaderValue)>,
+        retry_on_failure: bool,
+    ) -> Future<hyper::Chunk>
+    where
+        I: IntoIterator<Item = RequestParam>,
+        S: Into<String>,
+    {
+        let response = self.request_raw(
+            route,
+            method,
+            params,
+            body,
+            additional_headers,
+            retry_on_failure,
+        );
+        into_future_trait(response.map(|(_, body)| body))
+    }
+
+    /// Like `request_bytes`, but returns the response headers along with
+    /// the body.
+    fn request_raw<I, S>(
+        &self,
+        route: S,
+        method: Method,
+        params: I,
+        body: Vec<u8>,
+        additional_headers: Vec<(HeaderName, HeaderValue)>,
+        retry_on_failure: bool,
+    ) -> Future<(HeaderMap, hyper::Chunk)>
+    where
+        I: IntoIterator<Item = RequestParam>,
+        S: Into<String>,
     {
         let route: String = route.into();
         let params: Vec<RequestParam> = params.into_iter().collect();
 
-        let response = if retry_on_failure {
+        if retry_on_failure {
             //  A retry state object that is threaded through the
             //  retry loop in order to track state
             struct RetryState {
@@ -343,9 +994,16 @@ impl Pennsieve {
                 method: Method,
                 body: Vec<u8>,
                 additional_headers: Vec<(HeaderName, HeaderValue)>,
+                retrier: Retrier,
                 try_num: usize,
+                idempotency_key: bool,
             }
 
+            let idempotency_key = match self.inner.lock().unwrap().config.idempotency_key_header() {
+                Some(header) => additional_headers.iter().any(|(name, _)| name == header),
+                None => false,
+            };
+
             let retry_state = RetryState {
                 ps: self.clone(),
                 route,
@@ -353,7 +1011,9 @@ impl Pennsieve {
                 method,
                 body,
                 additional_headers,
+                retrier: Retrier::new(self.inner.lock().unwrap().config.retry_policy().clone()),
                 try_num: 0,
+                idempotency_key,
             };
 
             let f = future::loop_fn(retry_state, move |mut retry_state| {
@@ -363,33 +1023,37 @@ impl Pennsieve {
                         retry_state.route.clone(),
                         retry_state.params.clone(),
                         retry_state.method.clone(),
-                        retry_state.body.clone().into(),
+                        retry_state.body.clone(),
                         retry_state.additional_headers.clone(),
                     )
-                    .and_then(|(status_code, body)| {
+                    .and_then(|(status_code, headers, body)| {
                         // if the status code is considered retryable, wait for a few seconds and
                         // restart the loop to retry again.
-                        match RETRYABLE_STATUS_CODES.get(&status_code) {
-                            Some(retryable_methods)
-                                if retryable_methods.contains(&retry_state.method) =>
-                            {
-                                retry_state.try_num += 1;
-
-                                if retry_state.try_num > MAX_RETRIES {
-                                    into_future_trait(future::err(Error::api_error(
+                        let retryable = retry::status_is_retryable(
+                            retry_state.retrier.policy(),
+                            status_code,
+                            &retry_state.method,
+                            retry_state.idempotency_key,
+                        );
+                        match status_code {
+                            _ if retryable => {
+                                match retry_state.retrier.backoff(retry_state.try_num, true) {
+                                    Some(wait) => {
+                                        debug!(
+                                            "ps:request<{method}:{route}>:status = {status}",
+                                            method = retry_state.method,
+                                            route = retry_state.route,
+                                            status = status_code
+                                        );
+                                        retry_state.try_num += 1;
+                                        into_future_trait(
+                                            wait.map(move |_| future::Loop::Continue(retry_state)),
+                                        )
+                                    }
+                                    None => into_future_trait(future::err(Error::api_error(
                                         status_code,
                                         String::from_utf8_lossy(&body),
-                                    )))
-                                } else {
-                                    let delay = retry_delay(retry_state.try_num);
-                                    debug!("Rate limit exceeded, retrying in {} ms...", delay);
-
-                                    let deadline =
-                                        time::Instant::now() + time::Duration::from_millis(delay);
-                                    let continue_loop = tokio::timer::Delay::new(deadline)
-                                        .map_err(Into::into)
-                                        .map(move |_| future::Loop::Continue(retry_state));
-                                    into_future_trait(continue_loop)
+                                    ))),
                                 }
                             }
                             _ if status_code.is_client_error() || status_code.is_server_error() => {
@@ -398,48 +1062,61 @@ impl Pennsieve {
                                     String::from_utf8_lossy(&body),
                                 )))
                             }
-                            _ => into_future_trait(future::ok(future::Loop::Break(body))),
+                            _ => {
+                                into_future_trait(future::ok(future::Loop::Break((headers, body))))
+                            }
                         }
                     })
             });
             into_future_trait(f)
         } else {
             let f = self
-                .single_request(
-                    route,
-                    params,
-                    method,
-                    body.into(),
-                    additional_headers.clone(),
-                )
-                .and_then(|(status_code, body)| {
+                .single_request(route, params, method, body, additional_headers.clone())
+                .and_then(|(status_code, headers, body)| {
                     if status_code.is_client_error() || status_code.is_server_error() {
                         future::err(Error::api_error(
                             status_code,
                             String::from_utf8_lossy(&body),
                         ))
                     } else {
-                        future::ok(body)
+                        future::ok((headers, body))
                     }
                 });
             into_future_trait(f)
-        };
+        }
+    }
 
-        // Finally, attempt to parse the JSON response into a typeful
-        // representation. serde_json::from_slice will fail if the
-        // response body is empty, so we need to convert the empty
-        // body into a valid "null" json string in that case.
-        let json = response.and_then(|chunk| {
-            let bytes = chunk.into_bytes();
-            let bytes = if bytes.is_empty() {
-                b"null"[..].into()
-            } else {
-                bytes
-            };
-            serde_json::from_slice(&bytes).map_err(Into::into)
-        });
+    /// Make a GET request for a JSON array of `what`s. If an element can't
+    /// be parsed, the error says which one.
+    fn get_list<I, T, S>(&self, route: S, params: I, what: &'static str) -> Future<Vec<T>>
+    where
+        I: IntoIterator<Item = RequestParam>,
+        T: 'static + Send + serde::de::DeserializeOwned,
+        S: Into<String>,
+    {
+        let response = self.request_bytes(route, Method::GET, params, vec![], vec![], true);
+        into_future_trait(response.and_then(move |chunk| parse_json_list(chunk, what)))
+    }
 
-        into_future_trait(json)
+    /// Make a HEAD request to test if the resource at the given route
+    /// exists, without downloading it. A 404 means it doesn't; any other
+    /// failure is an error.
+    fn resource_exists<S>(&self, route: S) -> Future<bool>
+    where
+        S: Into<String> + Send,
+    {
+        let exists = head!(self, route).then(|exists: Result<()>| match exists {
+            Ok(()) => Ok(true),
+            Err(err) => match err.kind() {
+                ErrorKind::ApiError { status_code, .. }
+                    if *status_code == StatusCode::NOT_FOUND =>
+                {
+                    Ok(false)
+                }
+                _ => Err(err),
+            },
+        });
+        into_future_trait(exists)
     }
 
     /// Make a single request to the platform. This function is used
@@ -459,11 +1136,102 @@ impl Pennsieve {
         route: String,
         params: Vec<RequestParam>,
         method: Method,
-        body: hyper::Body,
+        body: Vec<u8>,
+        additional_headers: Vec<(HeaderName, HeaderValue)>,
+    ) -> Future<RawResponse> {
+        let span = RequestSpan::new(&method, &route);
+        let this = self.clone();
+        span.trace(into_future_trait(self.ensure_fresh_session().and_then(
+            move |_| this.send_request(route, params, method, body, additional_headers),
+        )))
+    }
+
+    /// Renew the session if auto refresh is enabled and the session is
+    /// about to expire. Requests made while a renewal is underway wait for
+    /// that renewal rather than starting their own.
+    fn ensure_fresh_session(&self) -> Future<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.config.auto_refresh() {
+            return into_future_trait(future::ok(()));
+        }
+
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs() as i64)
+            .unwrap_or(0);
+        let refresh = match (inner.session_expiry, inner.login_credentials.clone()) {
+            (Some(expiry), Some(credentials)) if session_is_expiring(expiry, now) => {
+                match inner.session_refresh.clone() {
+                    Some(refresh) => refresh,
+                    None => {
+                        debug!("ps:session expires at {}, renewing it", expiry);
+                        // The renewal is kept on the client, so it only
+                        // holds a weak reference back to the client:
+                        let client = Arc::downgrade(&self.inner);
+                        let refresh = into_future_trait(
+                            initiate_session(cognito_client(&inner), &credentials).then(
+                                move |result| {
+                                    if let Some(client) = client.upgrade() {
+                                        let mut inner = client.lock().unwrap();
+                                        inner.session_refresh = None;
+                                        if let Ok((session, expiry)) = &result {
+                                            store_session(
+                                                &mut inner,
+                                                session,
+                                                *expiry,
+                                                credentials,
+                                            );
+                                        }
+                                    }
+                                    result.map(|(session, _)| session)
+                                },
+                            ),
+                        )
+                        .shared();
+                        inner.session_refresh = Some(refresh.clone());
+                        refresh
+                    }
+                }
+            }
+            _ => return into_future_trait(future::ok(())),
+        };
+
+        into_future_trait(refresh.map(|_| ()).map_err(|err| (*err).clone()))
+    }
+
+    /// Send a single request to the platform as-is, following redirects if
+    /// the config allows. Used by `single_request` once the session is
+    /// known to be fresh.
+    fn send_request(
+        &self,
+        route: String,
+        params: Vec<RequestParam>,
+        method: Method,
+        body: Vec<u8>,
         additional_headers: Vec<(HeaderName, HeaderValue)>,
-    ) -> Future<(StatusCode, hyper::Chunk)> {
-        let token = self.session_token().clone();
-        let client = self.inner.lock().unwrap().http_client.clone();
+    ) -> Future<RawResponse> {
+        let context = match self.request_context() {
+            Ok(context) => context,
+            Err(err) => return into_future_trait(future::err(err)),
+        };
+        let timeout = self.inner.lock().unwrap().config.request_timeout().cloned();
+        let log_bodies = self.inner.lock().unwrap().config.log_bodies();
+        let max_response_bytes = self.inner.lock().unwrap().config.max_response_bytes();
+        let debug_capture = {
+            let inner = self.inner.lock().unwrap();
+            inner
+                .debug_capture
+                .clone()
+                .filter(|_| inner.config.debug_capture())
+        };
+        let max_redirects = {
+            let config = &self.inner.lock().unwrap().config;
+            if config.follow_redirects() {
+                config.max_redirects()
+            } else {
+                0
+            }
+        };
 
         let mut url = self.get_url();
         url.set_path(&route);
@@ -473,58 +1241,136 @@ impl Pennsieve {
             url.query_pairs_mut().append_pair(k.as_str(), v.as_str());
         }
 
-        let f = url
-            .to_string()
-            .parse::<hyper::Uri>()
-            .map_err(Into::<Error>::into)
-            .into_future()
-            .and_then(move |uri| {
+        let origin = url.origin();
+
+        // Each iteration makes one request, looping again for as long as
+        // the platform redirects us elsewhere and we're allowed to follow:
+        let response = future::loop_fn(
+            (url, method, body, 0),
+            move |(url, method, body, redirects)| {
+                let uri = match url.as_str().parse::<hyper::Uri>() {
+                    Ok(uri) => uri,
+                    Err(err) => return into_future_trait(future::err(err.into())),
+                };
                 let mut req = hyper::Request::builder()
                     .method(method.clone())
                     .uri(uri)
-                    .body(body)
+                    .body(body.clone().into())
                     .unwrap();
 
-                // If a session token exists, use it to set the
-                // "X-SESSION-ID" header to make subsequent requests,
-                // and add it to the authorization header:
-                if let Some(session_token) = token {
-                    req.headers_mut().insert(
-                        X_SESSION_ID,
-                        HeaderValue::from_str(session_token.borrow()).unwrap(),
-                    );
-                    req.headers_mut().insert(
-                        hyper::header::AUTHORIZATION,
-                        HeaderValue::from_str(&format!("Bearer {}", session_token.take())).unwrap(),
-                    );
-                }
+                // Only the platform itself is sent the auth headers, not a
+                // different origin we were redirected to:
+                context.prepare(&mut req, url.origin() == origin);
 
-                for (header_name, header_value) in additional_headers {
+                for (header_name, header_value) in additional_headers.clone() {
                     req.headers_mut().insert(header_name, header_value);
                 }
 
-                // Make the actual request:
-                client
-                    .request(req)
-                    .map_err(Into::into)
-                    .and_then(|response| {
+                let captured_request = debug_capture
+                    .as_ref()
+                    .map(|_| CapturedRequest::new(&method, &url, req.headers(), &body));
+                let debug_capture = debug_capture.clone();
+
+                // Make the actual request, once the concurrency limit allows,
+                // counting against it until the response has been read:
+                let response = context
+                    .send(req)
+                    .and_then(move |(response, permit)| {
                         let status_code = response.status();
-                        response
-                            .into_body()
-                            .concat2()
-                            .map(move |body: hyper::Chunk| {
+                        let location = response
+                            .headers()
+                            .get(hyper::header::LOCATION)
+                            .and_then(|location| location.to_str().ok())
+                            .and_then(|location| url.join(location).ok());
+
+                        match location {
+                            Some(location) if status_code.is_redirection() => {
+                                if redirects >= max_redirects {
+                                    return into_future_trait(future::err(Error::api_error(
+                                        status_code,
+                                        format!(
+                                            "not following redirect to {} after {} redirect(s)",
+                                            location, redirects
+                                        ),
+                                    )));
+                                }
                                 debug!(
-                                    "ps:request<{method}:{url}>:serialize:payload = {payload}",
+                                    "ps:request<{method}:{url}>:redirect = {location}",
                                     method = method,
                                     url = url,
-                                    payload = Self::chunk_to_string(&body)
+                                    location = location
                                 );
-                                (status_code, body)
-                            })
-                            .map_err(Into::into)
-                    })
-            });
+                                // Only 307 and 308 require the original method and
+                                // body to be resent; anything else becomes a GET,
+                                // except a HEAD, which has no body to lose:
+                                let (method, body) = match status_code {
+                                    StatusCode::TEMPORARY_REDIRECT
+                                    | StatusCode::PERMANENT_REDIRECT => (method, body),
+                                    _ if method == Method::HEAD => (method, body),
+                                    _ => (Method::GET, vec![]),
+                                };
+                                into_future_trait(future::ok(future::Loop::Continue((
+                                    location,
+                                    method,
+                                    body,
+                                    redirects + 1,
+                                ))))
+                            }
+                            _ => {
+                                let headers = response.headers().clone();
+                                into_future_trait(
+                                read_body(response.into_body(), max_response_bytes)
+                                    .map(move |body: hyper::Chunk| {
+                                        drop(permit);
+                                        debug!(
+                                            "ps:request<{method}:{url}>:serialize:payload = {payload}",
+                                            method = method,
+                                            url = url,
+                                            payload = Self::loggable_body(&body, log_bodies)
+                                        );
+                                        if let (Some(capture), Some(request)) =
+                                            (debug_capture, captured_request)
+                                        {
+                                            capture.on_exchange(
+                                                &request,
+                                                &CapturedResponse::new(status_code, &body),
+                                            );
+                                        }
+                                        future::Loop::Break((status_code, headers, body))
+                                    }),
+                            )
+                            }
+                        }
+                    });
+                into_future_trait(response)
+            },
+        );
+
+        // If a timeout is configured, give up on the request (including
+        // following redirects and reading the response body) once it elapses:
+        with_timeout(into_future_trait(response), timeout)
+    }
 
+    /// Measure the round-trip latency to the platform by requesting its
+    /// health check, failing if the platform can't be reached or doesn't
+    /// answer it successfully. A redirect counts as an answer only when
+    /// redirects aren't followed. The request is made once, without
+    /// retries, so the latency isn't inflated by backoff.
+    pub fn ping(&self) -> Future<time::Duration> {
+        let follow_redirects = self.inner.lock().unwrap().config.follow_redirects();
+        let started = time::Instant::now();
+        let f = self
+            .send_request("/health".to_string(), vec![], Method::GET, vec![], vec![])
+            .and_then(move |(status_code, _, body)| {
+                if status_code.is_success() || (status_code.is_redirection() && !follow_redirects) {
+                    Ok(started.elapsed())
+                } else {
+                    Err(Error::api_error(
+                        status_code,
+                        String::from_utf8_lossy(&body),
+                    ))
+                }
+            });
         into_future_trait(f)
     }
 
@@ -543,106 +1389,271 @@ impl Pennsieve {
         self.inner.lock().unwrap().current_organization = id.cloned()
     }
 
+    /// Stop starting new file uploads, so the client can be shut down
+    /// cleanly. Files whose upload has already begun are uploaded to
+    /// completion; uploads then fail with `ErrorKind::ShuttingDown` rather
+    /// than moving on to their next file.
+    pub fn begin_shutdown(&self) {
+        self.inner.lock().unwrap().shutdown.cancel();
+    }
+
+    /// Tests if `begin_shutdown` has been called on this client or any of
+    /// its clones.
+    pub fn is_shutting_down(&self) -> bool {
+        self.inner.lock().unwrap().shutdown.is_cancelled()
+    }
+
+    /// Set the sink that a record of every uploaded file is written to, or
+    /// stop recording uploads with `None`.
+    pub fn set_upload_audit_sink(&self, sink: Option<Arc<dyn UploadAuditSink>>) {
+        self.inner.lock().unwrap().upload_audit_sink = sink;
+    }
+
+    /// Set the hook that's shown the bytes of every request and response,
+    /// or stop capturing with `None`. The hook is only called while
+    /// `Config::with_debug_capture` is enabled.
+    pub fn set_debug_capture(&self, capture: Option<Arc<dyn DebugCapture>>) {
+        self.inner.lock().unwrap().debug_capture = capture;
+    }
+
+    /// Returns the bytes of file chunks currently held in memory by uploads.
+    pub fn in_flight_bytes(&self) -> u64 {
+        self.inner.lock().unwrap().chunk_budget.in_use()
+    }
+
+    /// Returns the most bytes of file chunks that uploads have held in
+    /// memory at once.
+    pub fn peak_in_flight_bytes(&self) -> u64 {
+        self.inner.lock().unwrap().chunk_budget.peak()
+    }
+
+    /// Returns the number of requests currently in flight.
+    pub fn in_flight_requests(&self) -> usize {
+        self.inner.lock().unwrap().request_limit.in_flight()
+    }
+
+    /// Returns the most requests that have been in flight at once.
+    pub fn peak_in_flight_requests(&self) -> usize {
+        self.inner.lock().unwrap().request_limit.peak()
+    }
+
     /// Set the session token the user is associated with.
     pub fn set_session_token(&self, token: Option<SessionToken>) {
-        self.inner.lock().unwrap().session_token = token;
+        let mut inner = self.inner.lock().unwrap();
+        inner.session_token = token;
+        // Nothing is known about when a token set from outside expires:
+        inner.session_expiry = None;
     }
 
     /// Set the active environment
     pub fn set_environment(&self, env: Environment) {
-        self.inner.lock().unwrap().config = Config::new(env);
+        let mut inner = self.inner.lock().unwrap();
+        inner.config = inner.config.clone().with_env(env);
     }
 
     /// Log in to the Pennsieve API.
     ///
     /// If successful, the Pennsieve client will store the resulting session
     /// token for subsequent API calls.
+    ///
+    /// If the client is configured with `AuthMode::ApiKey`, requests are
+    /// authenticated with that key, so nothing is sent to the platform: the
+    /// session is just marked as present, with the key as its token.
     #[allow(dead_code)]
     pub fn login<S: Into<String>>(
         &self,
         api_key: S,
         api_secret: S,
     ) -> Future<response::ApiSession> {
-        let cognito = rusoto_cognito_idp::CognitoIdentityProviderClient::new_with(
-            HttpClient::new().expect("failed to create request dispatcher"),
-            StaticProvider::from(AwsCredentials::default()),
-            rusoto_core::region::Region::UsEast1,
-        );
-
-        let mut auth_parameters = HashMap::<String, String>::new();
-        auth_parameters.insert("USERNAME".to_string(), api_key.into());
-        auth_parameters.insert("PASSWORD".to_string(), api_secret.into());
+        let auth_mode = self.inner.lock().unwrap().config.auth_mode().clone();
+        if let AuthMode::ApiKey { key } = auth_mode {
+            let session_token = SessionToken::new(key);
+            self.set_session_token(Some(session_token.clone()));
+            let organization = self
+                .current_organization()
+                .map(OrganizationId::take)
+                .unwrap_or_default();
+            return into_future_trait(future::ok(response::ApiSession::new(
+                session_token,
+                organization,
+                0,
+            )));
+        }
 
+        let api_key = api_key.into();
+        let api_secret = api_secret.into();
         let this = self.clone();
 
-        into_future_trait(get!(self, "/authentication/cognito-config").and_then(
-            move |config_response: serde_json::Value| {
-                let app_client_id = config_response.get("tokenPool")
-                    .ok_or(crate::ps::Error::initiate_auth_error("Pennsieve server Cognito config missing token pool."))?
-                    .get("appClientId")
-                    .ok_or(crate::ps::Error::initiate_auth_error("Pennsieve server Cognito config missing token pool client id."))?
-                    .as_str()
-                    .ok_or(crate::ps::Error::initiate_auth_error("Cognito application client ID is not a string"))?
-                    .to_string();
-
-                let request = InitiateAuthRequest {
-                    analytics_metadata: None,
-                    auth_flow: "USER_PASSWORD_AUTH".to_string(),
-                    auth_parameters: Some(auth_parameters),
-                    client_id: app_client_id,
-                    client_metadata: None,
-                    user_context_data: None,
-                };
+        into_future_trait(
+            get!(self, "/authentication/cognito-config")
+                .and_then(move |config_response: serde_json::Value| {
+                    let app_client_id = config_response
+                        .get("tokenPool")
+                        .ok_or(crate::ps::Error::initiate_auth_error(
+                            "Pennsieve server Cognito config missing token pool.",
+                        ))?
+                        .get("appClientId")
+                        .ok_or(crate::ps::Error::initiate_auth_error(
+                            "Pennsieve server Cognito config missing token pool client id.",
+                        ))?
+                        .as_str()
+                        .ok_or(crate::ps::Error::initiate_auth_error(
+                            "Cognito application client ID is not a string",
+                        ))?
+                        .to_string();
 
-                Ok(request)
-            }
+                    Ok(app_client_id)
+                })
+                .and_then(move |app_client_id| {
+                    this.login_with_cognito_pool(api_key, api_secret, app_client_id)
+                }),
         )
-        .and_then(move |request| {
-            cognito
-                .initiate_auth(request)
-                .map_err(Into::into)
-                .and_then(move |response| {
-                    let authentication_result = response.clone().authentication_result
-                        .ok_or(crate::ps::Error::initiate_auth_error("No authentication result, does another challenge need to be passed?"))?;
+    }
 
-                    let access_token = authentication_result.access_token
-                        .ok_or(crate::ps::Error::initiate_auth_error("No access token in the Cognito initiate auth response."))?;
+    /// Log in to the Pennsieve API, then make the user's preferred
+    /// organization the current one, as most calls need one set. Returns the
+    /// user, and the organizations they're a member of.
+    pub fn initialize_session<S: Into<String>>(
+        &self,
+        api_key: S,
+        api_secret: S,
+    ) -> Future<(model::User, response::Organizations)> {
+        let ps = self.clone();
+        let f = self
+            .login(api_key, api_secret)
+            .and_then({
+                let ps = ps.clone();
+                move |_| ps.get_user()
+            })
+            .and_then({
+                let ps = ps.clone();
+                move |user| ps.set_preferred_organization(user.preferred_organization().cloned())
+            })
+            .and_then(move |user| {
+                ps.get_organizations()
+                    .map(|organizations| (user, organizations))
+            });
+        into_future_trait(f)
+    }
 
-                    let id_token = authentication_result.id_token
-                        .ok_or(crate::ps::Error::initiate_auth_error(
-                            "No ID token in the Cognito initiate auth response."
-                        ))?;
-
-                    let payload_parts: Vec<&str> = id_token.split(".").collect();
-                    let payload_b64 = base64_url::decode(payload_parts[1])?;
-                    let payload_str = std::str::from_utf8(&payload_b64).map_err(|err| {
-                        crate::ps::Error::initiate_auth_error(err.to_string())
-                    })?;
-                    let payload: serde_json::Value = serde_json::from_str(payload_str)?;
-
-                    let organization_node_id_value = payload.get("custom:organization_node_id")
-                        .ok_or(crate::ps::Error::initiate_auth_error("Cognito response payload does not have the `custom:organization_node_id` property"))?;
-
-                    let organization_node_id = organization_node_id_value.as_str()
-                        .ok_or(crate::ps::Error::initiate_auth_error("Cognito response payload `custom:organization_node_id` is not a string."))?;
-                    let exp = payload["exp"].as_i64()
-                        .ok_or(crate::ps::Error::initiate_auth_error("Cognito response payload does not have an expiration date `exp`."))?;
-
-                    this.set_current_organization(Some(&OrganizationId::new(
-                        organization_node_id,
-                    )));
+    /// Log in to the Pennsieve API with the credentials the client was
+    /// configured with, as by `Config::from_env`, or otherwise with those
+    /// in the `PENNSIEVE_API_KEY` and `PENNSIEVE_SECRET_KEY` environment
+    /// variables. Fails with `ErrorKind::InvalidArguments` naming the first
+    /// variable that isn't set.
+    pub fn login_from_env(&self) -> Future<response::ApiSession> {
+        let credentials = match self.inner.lock().unwrap().config.credentials().cloned() {
+            Some(credentials) => Ok(credentials),
+            None => ApiCredentials::from_env(),
+        };
+        match credentials {
+            Ok(credentials) => self.login(
+                credentials.api_key().to_string(),
+                credentials.api_secret().to_string(),
+            ),
+            Err(err) => into_future_trait(future::err(err)),
+        }
+    }
 
-                    let session_token = SessionToken::new(access_token);
-                    this.set_session_token(Some(session_token.clone()));
+    /// Log in to the Pennsieve API like `login`, retrying up to
+    /// `max_attempts` times in all when the platform or Cognito can't be
+    /// reached or fail on their end. Useful at startup, when the client
+    /// may be up before the services it authenticates against.
+    ///
+    /// Attempts back off exponentially from `backoff`, with jitter, and
+    /// never wait longer than the configured retry policy's maximum delay.
+    /// Bad credentials, or any other error retrying won't fix, fail
+    /// immediately.
+    pub fn login_with_retry<S: Into<String>>(
+        &self,
+        api_key: S,
+        api_secret: S,
+        max_attempts: usize,
+        backoff: time::Duration,
+    ) -> Future<response::ApiSession> {
+        let api_key = api_key.into();
+        let api_secret = api_secret.into();
+        let max_attempts = max_attempts.max(1);
+        let retrier = {
+            let configured = self.inner.lock().unwrap().config.retry_policy().clone();
+            let policy = RetryPolicy::new(max_attempts - 1, backoff.as_millis() as u64);
+            Retrier::new(match configured.max_delay_ms() {
+                Some(max_delay_ms) => policy.with_max_delay_ms(max_delay_ms),
+                None => policy,
+            })
+        };
+        let this = self.clone();
 
-                    Ok(response::ApiSession::new(
-                        session_token,
-                        organization_node_id.to_string(),
-                        exp as i32
-                    ))
+        let f = future::loop_fn(0, move |try_num| {
+            let retrier = retrier.clone();
+            this.login(api_key.clone(), api_secret.clone())
+                .map(future::Loop::Break)
+                .or_else(move |err| {
+                    match retrier.backoff(try_num, is_transient_login_error(&err)) {
+                        Some(wait) => {
+                            debug!("Login failed: {error}", error = err);
+                            into_future_trait(
+                                wait.map(move |_| future::Loop::Continue(try_num + 1)),
+                            )
+                        }
+                        None => into_future_trait(future::err(err)),
+                    }
                 })
+        });
+
+        into_future_trait(f)
+    }
+
+    /// Log in to the Pennsieve API through the Cognito app client with the
+    /// given ID, skipping the lookup of the platform's Cognito config that
+    /// `login` does. Useful when the app client ID is already known.
+    ///
+    /// If successful, the Pennsieve client will store the resulting session
+    /// token for subsequent API calls.
+    pub fn login_with_cognito_pool<S: Into<String>>(
+        &self,
+        api_key: S,
+        api_secret: S,
+        app_client_id: String,
+    ) -> Future<response::ApiSession> {
+        self.authenticate(
+            self.cognito_client(),
+            LoginCredentials {
+                api_key: api_key.into(),
+                api_secret: api_secret.into(),
+                app_client_id,
             },
-        ))
+        )
+    }
+
+    /// A Cognito client that connects the same way as the rest of the
+    /// client, through a proxy if one is configured.
+    fn cognito_client(&self) -> CognitoIdentityProviderClient {
+        let inner = self.inner.lock().unwrap();
+        cognito_client(&inner)
+    }
+
+    /// Log in with `credentials`, storing the resulting session (and the
+    /// credentials used, so the session can be renewed) on success, and
+    /// making the session's organization the current one.
+    ///
+    /// The Cognito client is taken as an argument so that callers already
+    /// holding the client lock can build it.
+    fn authenticate(
+        &self,
+        cognito: CognitoIdentityProviderClient,
+        credentials: LoginCredentials,
+    ) -> Future<response::ApiSession> {
+        let this = self.clone();
+        into_future_trait(
+            initiate_session(cognito, &credentials).map(move |(session, expiry)| {
+                let mut inner = this.inner.lock().unwrap();
+                inner.current_organization =
+                    Some(OrganizationId::new(session.organization().clone()));
+                store_session(&mut inner, &session, expiry, credentials);
+                session
+            }),
+        )
     }
 
     /// Get the current user.
@@ -670,14 +1681,94 @@ impl Pennsieve {
         get!(self, "/organizations/")
     }
 
+    /// Make the organization `id` the current one, once it's known that the
+    /// user is a member of it; otherwise, fail with
+    /// `ErrorKind::InvalidArguments` and leave the current organization as
+    /// it is. Unlike `set_current_organization`, this catches a mistyped or
+    /// inaccessible organization now rather than with a 403 later.
+    pub fn switch_organization(&self, id: OrganizationId) -> Future<response::Organization> {
+        let ps = self.clone();
+        let f = self.get_organizations().and_then(move |organizations| {
+            match organizations
+                .into_iter()
+                .find(|organization| organization.organization().id() == &id)
+            {
+                Some(organization) => {
+                    ps.set_current_organization(Some(&id));
+                    Ok(organization)
+                }
+                None => Err(Error::invalid_arguments(format!(
+                    "not a member of organization {}",
+                    id
+                ))),
+            }
+        });
+        into_future_trait(f)
+    }
+
     /// Get a specific organization.
     pub fn get_organization_by_id(&self, id: OrganizationId) -> Future<response::Organization> {
         get!(self, route!("/organizations/{id}", id))
     }
 
+    /// Get the feature flags enabled for an organization.
+    pub fn get_organization_features(&self, id: OrganizationId) -> Future<response::Features> {
+        get!(self, route!("/organizations/{id}/features", id))
+    }
+
     /// Get a listing of the datasets the current user has access to.
     pub fn get_datasets(&self) -> Future<Vec<response::Dataset>> {
-        get!(self, "/datasets/")
+        self.get_list("/datasets/", params!(), "dataset")
+    }
+
+    /// Get a single page of the datasets the current user has access to.
+    pub fn get_datasets_paginated(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Future<response::DatasetPage> {
+        get!(
+            self,
+            "/datasets/paginated",
+            params!("limit" => limit.to_string(), "offset" => offset.to_string())
+        )
+    }
+
+    /// Stream all of the datasets the current user has access to, requesting
+    /// them from the platform one page at a time.
+    pub fn get_all_datasets_stream(&self) -> Stream<response::Dataset> {
+        into_stream_trait(self.iter_datasets())
+    }
+
+    /// Walk the datasets the current user has access to with a cursor that
+    /// requests the next page only once the last has been used up.
+    pub fn iter_datasets(&self) -> DatasetCursor {
+        DatasetCursor::new(self.clone(), DATASET_PAGE_SIZE)
+    }
+
+    /// Get the datasets of an organization that the current user has access
+    /// to, each with its collaborator counts. The dataset's owner is
+    /// available from the dataset itself.
+    pub fn get_datasets_with_counts(
+        &self,
+        org: OrganizationId,
+    ) -> Future<Vec<(response::Dataset, response::CollaboratorCounts)>> {
+        let ps = self.clone();
+        let f = self.get_datasets().and_then(move |datasets| {
+            let with_counts = datasets
+                .into_iter()
+                .filter(move |dataset| *dataset.organization() == *org)
+                .map(move |dataset| {
+                    ps.get_dataset_collaborator_counts(dataset.id().clone())
+                        .map(|counts| (dataset, counts))
+                });
+
+            stream::iter_ok(with_counts)
+                .buffered(COLLABORATOR_COUNT_PARALLELISM)
+                .collect()
+        });
+
+        into_future_trait(f)
     }
 
     /// Create a new dataset using full request object.
@@ -697,9 +1788,110 @@ impl Pennsieve {
         self.create_dataset_with_request(request::dataset::Create::new(name, description))
     }
 
-    /// Get a specific dataset by its ID.
+    /// Get the template an organization applies to new datasets, if it has
+    /// one.
+    pub fn get_dataset_template(
+        &self,
+        org: OrganizationId,
+    ) -> Future<Option<model::DatasetTemplate>> {
+        let template = get!(self, route!("/organizations/{org}/dataset-template", org)).then(
+            |template: Result<model::DatasetTemplate>| match template {
+                Ok(template) => Ok(Some(template)),
+                Err(err) => match err.kind() {
+                    ErrorKind::ApiError { status_code, .. }
+                        if *status_code == StatusCode::NOT_FOUND =>
+                    {
+                        Ok(None)
+                    }
+                    _ => Err(err),
+                },
+            },
+        );
+        into_future_trait(template)
+    }
+
+    /// Create a new dataset with the description, license and tags of the
+    /// organization's dataset template, then add the template's
+    /// collaborators to it. If the organization has no template, this is
+    /// the same as `create_dataset` without a description.
+    pub fn create_dataset_from_template<N: Into<String>>(
+        &self,
+        org: OrganizationId,
+        name: N,
+    ) -> Future<response::Dataset> {
+        let name = name.into();
+        let ps = self.clone();
+        let f = self.get_dataset_template(org).and_then(move |template| {
+            let template = template.unwrap_or_default();
+            let request = request::dataset::Create::new(name, template.description().cloned())
+                .with_license(template.license().cloned())
+                .with_tags(template.tags().clone());
+
+            let inner = ps.clone();
+            ps.create_dataset_with_request(request)
+                .and_then(move |dataset| {
+                    let id = dataset.id().clone();
+                    let collaborators = template.collaborators().clone();
+                    stream::iter_ok(collaborators)
+                        .for_each(move |collaborator| {
+                            inner
+                                .add_dataset_collaborator(
+                                    id.clone(),
+                                    collaborator.id().clone(),
+                                    collaborator.role(),
+                                )
+                                .map(|_| ())
+                        })
+                        .map(|_| dataset)
+                })
+        });
+        into_future_trait(f)
+    }
+
+    /// Get a specific dataset by its ID. If the platform tags the dataset
+    /// with an `ETag`, it's kept on the response; see `update_dataset_if_match`.
     pub fn get_dataset_by_id(&self, id: DatasetNodeId) -> Future<response::Dataset> {
-        get!(self, route!("/datasets/{id}", id))
+        let f = self.request_with_headers(
+            route!("/datasets/{id}", id),
+            Method::GET,
+            params!(),
+            payload!(),
+            vec![],
+        );
+        into_future_trait(f.map(|(headers, dataset)| dataset_with_etag(&headers, dataset)))
+    }
+
+    /// Get a specific dataset by its integer ID, such as the one returned
+    /// by `preview_upload`. Like `get_dataset_by_id`, the dataset includes
+    /// its child packages and ETag.
+    pub fn get_dataset_by_int_id(&self, id: DatasetId) -> Future<response::Dataset> {
+        let f = self.request_with_headers(
+            route!("/datasets/int/{id}", id),
+            Method::GET,
+            params!(),
+            payload!(),
+            vec![],
+        );
+        into_future_trait(f.map(|(headers, dataset)| dataset_with_etag(&headers, dataset)))
+    }
+
+    /// Test if a dataset exists, without fetching it.
+    pub fn dataset_exists(&self, id: DatasetNodeId) -> Future<bool> {
+        self.resource_exists(route!("/datasets/{id}", id))
+    }
+
+    /// Get a specific dataset by its ID, with its child packages sorted into
+    /// the given order. `get_dataset_by_id` leaves them in whatever order the
+    /// platform returns, which can differ between calls.
+    pub fn get_dataset_by_id_sorted(
+        &self,
+        id: DatasetNodeId,
+        order: response::ChildOrder,
+    ) -> Future<response::Dataset> {
+        into_future_trait(self.get_dataset_by_id(id).map(move |mut dataset| {
+            dataset.sort_children(order);
+            dataset
+        }))
     }
 
     /// Get a specific dataset by its name.
@@ -744,14 +1936,152 @@ impl Pennsieve {
         }
     }
 
+    /// Get the DOI, publication status, and latest published version of the
+    /// data set. A data set without a DOI has a `doi()` of `None`.
+    pub fn get_dataset_publication(&self, id: DatasetNodeId) -> Future<response::PublicationInfo> {
+        let dataset_id = id.clone();
+        let doi = get!(self, route!("/datasets/{dataset_id}/doi", dataset_id)).then(
+            |doi: Result<response::Doi>| match doi {
+                Ok(doi) => Ok(Some(doi.take())),
+                Err(err) => match err.kind() {
+                    ErrorKind::ApiError { status_code, .. }
+                        if *status_code == StatusCode::NOT_FOUND =>
+                    {
+                        Ok(None)
+                    }
+                    _ => Err(err),
+                },
+            },
+        );
+        let publication = get!(self, route!("/datasets/{id}", id));
+
+        into_future_trait(
+            publication
+                .join(doi)
+                .map(|(info, doi): (response::PublicationInfo, Option<String>)| info.with_doi(doi)),
+        )
+    }
+
     /// Get the user collaborators of the data set.
     pub fn get_dataset_user_collaborators(&self, id: DatasetNodeId) -> Future<Vec<model::User>> {
-        get!(self, route!("/datasets/{id}/collaborators/users", id))
+        self.get_list(
+            route!("/datasets/{id}/collaborators/users", id),
+            params!(),
+            "collaborator",
+        )
+    }
+
+    /// Add a user collaborator to the data set with the given role. If the
+    /// user is already a collaborator, their role is changed instead.
+    pub fn add_dataset_collaborator(
+        &self,
+        id: DatasetNodeId,
+        user: UserId,
+        role: DatasetRole,
+    ) -> Future<response::ChangeResponse> {
+        put!(
+            self,
+            route!("/datasets/{id}/collaborators/users", id),
+            params!(),
+            payload!(request::dataset::AddCollaborator::new(user, role))
+        )
+    }
+
+    /// Change the role of an existing user collaborator on the data set,
+    /// returning the collaborator with their new role. The owner role can't
+    /// be granted this way, as a data set has exactly one owner.
+    pub fn update_dataset_collaborator_role(
+        &self,
+        dataset: DatasetNodeId,
+        user: UserId,
+        new_role: DatasetRole,
+    ) -> Future<model::User> {
+        if new_role == DatasetRole::Owner {
+            return into_future_trait(future::err(Error::invalid_arguments(
+                "the owner role can't be granted to a collaborator",
+            )));
+        }
+
+        let inner = self.clone();
+        let dataset_id = dataset.clone();
+        into_future_trait(
+            self.add_dataset_collaborator(dataset, user.clone(), new_role)
+                .and_then(move |_| inner.get_dataset_user_collaborators(dataset_id.clone()))
+                .and_then(move |collaborators| {
+                    collaborators
+                        .into_iter()
+                        .find(|collaborator| *collaborator.id() == user)
+                        .ok_or_else(|| {
+                            Error::not_found(format!(
+                                "{:?} is not a collaborator on the dataset",
+                                user
+                            ))
+                        })
+                }),
+        )
+    }
+
+    /// Remove a user collaborator from the data set.
+    pub fn remove_dataset_collaborator(
+        &self,
+        id: DatasetNodeId,
+        user: UserId,
+    ) -> Future<response::ChangeResponse> {
+        delete!(
+            self,
+            route!("/datasets/{id}/collaborators/users", id),
+            params!(),
+            payload!(request::dataset::RemoveCollaborator::new(user))
+        )
+    }
+
+    /// Remove a user collaborator from the data set, identifying the user
+    /// by their email address rather than their ID.
+    pub fn remove_dataset_collaborator_by_email<E: Into<String>>(
+        &self,
+        id: DatasetNodeId,
+        email: E,
+    ) -> Future<response::ChangeResponse> {
+        let email = email.into();
+        let inner = self.clone();
+        let dataset_id = id.clone();
+        into_future_trait(
+            self.get_dataset_user_collaborators(id)
+                .and_then(move |collaborators| {
+                    collaborators
+                        .into_iter()
+                        .find(|user| user.email().to_lowercase() == email.to_lowercase())
+                        .map(|user| user.id().clone())
+                        .ok_or_else(|| {
+                            Error::not_found(format!(
+                                "no collaborator with email \"{}\" on dataset {}",
+                                email, dataset_id
+                            ))
+                        })
+                        .map(|user_id| (dataset_id, user_id))
+                })
+                .and_then(move |(dataset_id, user_id)| {
+                    inner.remove_dataset_collaborator(dataset_id, user_id)
+                }),
+        )
+    }
+
+    /// Get the number of user, organization and team collaborators of the
+    /// data set.
+    pub fn get_dataset_collaborator_counts(
+        &self,
+        id: DatasetNodeId,
+    ) -> Future<response::CollaboratorCounts> {
+        get!(self, route!("/datasets/{id}/collaborators/counts", id))
     }
 
     /// Get the team collaborators of the data set.
     pub fn get_dataset_team_collaborators(&self, id: DatasetNodeId) -> Future<Vec<model::Team>> {
-        get!(self, route!("/datasets/{id}/collaborators/teams", id))
+        self.get_list(
+            route!("/datasets/{id}/collaborators/teams", id),
+            params!(),
+            "team",
+        )
     }
 
     /// Get the organization role on the data set.
@@ -765,1219 +2095,5878 @@ impl Pennsieve {
         )
     }
 
-    /// Update an existing dataset.
+    /// Update an existing dataset. A description of `None` leaves the
+    /// dataset's description as it is.
     pub fn update_dataset<N: Into<String>, D: Into<String>>(
         &self,
         id: DatasetNodeId,
         name: N,
         description: Option<D>,
+    ) -> Future<response::Dataset> {
+        self.update_dataset_if_match(id, name, description, None)
+    }
+
+    /// Update an existing dataset, but only if it hasn't changed since it
+    /// was fetched with the ETag `etag`, such as `response::Dataset::etag`.
+    /// If it has, this fails with `ErrorKind::Conflict` and nothing is
+    /// changed. An `etag` of `None` updates the dataset unconditionally, as
+    /// `update_dataset` does.
+    pub fn update_dataset_if_match<N: Into<String>, D: Into<String>>(
+        &self,
+        id: DatasetNodeId,
+        name: N,
+        description: Option<D>,
+        etag: Option<&str>,
+    ) -> Future<response::Dataset> {
+        let if_match = match etag.map(HeaderValue::from_str) {
+            Some(Ok(etag)) => vec![(hyper::header::IF_MATCH, etag)],
+            Some(Err(_)) => {
+                return into_future_trait(future::err(Error::invalid_arguments(format!(
+                    "invalid etag: {:?}",
+                    etag
+                ))))
+            }
+            None => vec![],
+        };
+        let f = self
+            .request_with_headers(
+                route!("/datasets/{id}", id),
+                Method::PUT,
+                params!(),
+                payload!(request::dataset::Update::new(name, description)),
+                if_match,
+            )
+            .map(|(headers, dataset)| dataset_with_etag(&headers, dataset))
+            .map_err(|err| match err.kind() {
+                ErrorKind::ApiError {
+                    status_code: StatusCode::PRECONDITION_FAILED,
+                    message,
+                    ..
+                } => Error::conflict(message.clone()),
+                _ => err,
+            });
+        into_future_trait(f)
+    }
+
+    /// Rename an existing dataset, keeping its description.
+    pub fn rename_dataset<N: Into<String>>(
+        &self,
+        id: DatasetNodeId,
+        name: N,
     ) -> Future<response::Dataset> {
         put!(
             self,
             route!("/datasets/{id}", id),
             params!(),
-            payload!(request::dataset::Update::new(name, description))
+            payload!(request::dataset::Update::rename(name))
         )
     }
 
-    /// Delete an existing dataset.
-    pub fn delete_dataset(&self, id: DatasetNodeId) -> Future<()> {
-        let f: Future<response::EmptyMap> = delete!(self, route!("/datasets/{id}", id));
-        into_future_trait(f.map(|_| ()))
+    /// Get the readme of a dataset: its long-form markdown, as opposed to
+    /// the short description.
+    pub fn get_dataset_readme(&self, id: DatasetNodeId) -> Future<String> {
+        let f: Future<response::DatasetReadme> = get!(self, route!("/datasets/{id}/readme", id));
+        into_future_trait(f.map(response::DatasetReadme::take))
     }
 
-    /// Create a new package.
-    /// TODO: see https://github.com/Pennsieve/pennsieve-rust/pull/45/files#r265581502
-    /// for a strategy for cleaning up API functions with many optional arguments.
-    pub fn create_package<N, D, P, F>(
-        &self,
-        name: N,
-        package_type: P,
-        dataset: D,
-        parent: Option<F>,
-    ) -> Future<response::Package>
-    where
-        D: Into<DatasetNodeId>,
-        N: Into<String>,
-        P: Into<String>,
-        F: Into<String>,
-    {
-        post!(
+    /// Replace the readme of a dataset with `markdown`. The short description
+    /// is left as it is; see `update_dataset` to change that.
+    pub fn update_dataset_readme(&self, id: DatasetNodeId, markdown: String) -> Future<()> {
+        let f = put!(
             self,
-            "/packages/",
+            route!("/datasets/{id}/readme", id),
             params!(),
-            payload!(request::package::Create::new(
-                name,
-                package_type,
-                dataset,
-                parent
-            ))
+            payload!(request::dataset::UpdateReadme::new(markdown))
         )
+        .map(|_: Nothing| ());
+        into_future_trait(f)
     }
 
-    /// Get a specific package.
-    pub fn get_package_by_id(&self, id: PackageId) -> Future<response::Package> {
-        get!(self, route!("/packages/{id}", id))
+    /// Add `tag` to a dataset, keeping its existing tags. A dataset that
+    /// already has the tag is left as it is.
+    pub fn add_tag_to_dataset(&self, id: DatasetNodeId, tag: &str) -> Future<response::Dataset> {
+        let ps = self.clone();
+        let tag = tag.to_string();
+
+        let f = self.get_dataset_by_id(id.clone()).and_then(move |dataset| {
+            if dataset.tags().contains(&tag) {
+                return into_future_trait(future::ok(dataset));
+            }
+            let mut tags = dataset.tags().clone();
+            tags.push(tag);
+            put!(
+                ps,
+                route!("/datasets/{id}", id),
+                params!(),
+                payload!(request::dataset::Update::rename(dataset.name().clone()).with_tags(tags))
+            )
+        });
+
+        into_future_trait(f)
     }
 
-    /// Get the source files that are part of a package.
-    pub fn get_package_sources(&self, id: PackageId) -> Future<response::Files> {
-        get!(self, route!("/packages/{id}/sources", id))
+    /// Add `tag` to each of `datasets`, a few at a time. A dataset that
+    /// can't be tagged doesn't stop the others from being tagged; instead,
+    /// each dataset is returned, in order, with whether it was tagged.
+    pub fn add_tag_to_datasets(
+        &self,
+        datasets: Vec<DatasetNodeId>,
+        tag: &str,
+    ) -> Future<Vec<(DatasetNodeId, bool)>> {
+        let ps = self.clone();
+        let tag = tag.to_string();
+
+        let f = stream::iter_ok(datasets)
+            .map(move |id| {
+                ps.add_tag_to_dataset(id.clone(), &tag).then(move |result| {
+                    if let Err(ref err) = result {
+                        warn!("couldn't tag dataset {}: {}", id, err);
+                    }
+                    Ok::<_, Error>((id, result.is_ok()))
+                })
+            })
+            .buffered(DATASET_TAG_PARALLELISM)
+            .collect();
+
+        into_future_trait(f)
     }
 
-    /// Update an existing package.
-    pub fn update_package<N: Into<String>>(
+    /// Get the number of packages of each type in a dataset.
+    pub fn get_dataset_package_type_counts(
         &self,
-        id: PackageId,
-        name: N,
-    ) -> Future<response::Package> {
-        put!(
-            self,
-            route!("/packages/{id}", id),
-            params!(),
-            payload!(request::package::Update::new(name))
+        id: DatasetNodeId,
+    ) -> Future<response::PackageTypeCounts> {
+        get!(self, route!("/datasets/{id}/packageTypeCounts", id))
+    }
+
+    /// Get the number of files in a dataset without walking its contents,
+    /// e.g. to size a progress bar before a download. Every package other
+    /// than a collection is counted as a single file.
+    pub fn get_dataset_file_count(&self, id: DatasetNodeId) -> Future<usize> {
+        into_future_trait(
+            self.get_dataset_package_type_counts(id)
+                .map(|counts| counts.total() - counts.get(model::PackageType::Collection.as_str())),
         )
     }
 
-    /// Process a package in the UPLOADED state.
-    pub fn process_package(&self, id: PackageId) -> Future<()> {
-        let f = put!(self, route!("/packages/{id}/process", id)).map(|_: Nothing| ());
-        into_future_trait(f)
+    /// Get a single page of the packages in a dataset, starting from the
+    /// cursor returned with the previous page.
+    pub fn get_dataset_packages_paginated(
+        &self,
+        id: DatasetNodeId,
+        page_size: usize,
+        cursor: Option<String>,
+    ) -> Future<response::PackagePage> {
+        let mut params = params!("pageSize" => page_size.to_string());
+        if let Some(cursor) = cursor {
+            params.push(param!("cursor", cursor));
+        }
+        get!(self, route!("/datasets/{id}/packages", id), params)
     }
 
-    /// Move several packages to a destination package.
-    /// If destination is None, the package is moved to the top level of the dataset.
-    pub fn mv<T: Into<PackageId>, D: Into<PackageId>>(
+    /// Stream all of the packages in a dataset, however deeply nested,
+    /// requesting them from the platform one page at a time.
+    pub fn get_dataset_packages_stream(&self, id: DatasetNodeId) -> Stream<response::Package> {
+        let ps = self.clone();
+        // `None` once the last page has been fetched, otherwise the cursor
+        // of the next page (which is `None` for the first page):
+        let pages = stream::unfold(Some(None), move |cursor: Option<Option<String>>| {
+            cursor.map(|cursor| {
+                ps.get_dataset_packages_paginated(id.clone(), PACKAGE_PAGE_SIZE, cursor)
+                    .map(|page| {
+                        let next_cursor = page.cursor().cloned().map(Some);
+                        (page, next_cursor)
+                    })
+            })
+        });
+
+        into_stream_trait(pages.map(|page| stream::iter_ok(page.take())).flatten())
+    }
+
+    /// Get the packages in a dataset that are still being worked on, i.e.
+    /// whose state isn't terminal.
+    pub fn get_processing_packages(&self, id: DatasetNodeId) -> Future<Vec<model::Package>> {
+        into_future_trait(
+            self.get_dataset_packages_stream(id)
+                .filter(|package| {
+                    package
+                        .state_enum()
+                        .map(|state| !state.is_terminal())
+                        .unwrap_or(false)
+                })
+                .map(response::Package::take)
+                .collect(),
+        )
+    }
+
+    /// Get the packages in a dataset of the given type. If `recursive`, the
+    /// search is deep, taking in packages nested in collections at any
+    /// depth; otherwise it's shallow, and only the packages at the top level
+    /// of the dataset are searched.
+    pub fn get_dataset_packages_by_type(
         &self,
-        things: Vec<T>,
-        destination: Option<D>,
-    ) -> Future<response::MoveResponse> {
-        post!(
+        id: DatasetNodeId,
+        package_type: model::PackageType,
+        recursive: bool,
+    ) -> Future<Vec<response::Package>> {
+        let is_of_type = move |package: &response::Package| {
+            package.package_type().map(String::as_str) == Some(package_type.as_str())
+        };
+        if recursive {
+            into_future_trait(
+                self.get_dataset_packages_stream(id)
+                    .filter(is_of_type)
+                    .collect(),
+            )
+        } else {
+            into_future_trait(self.get_dataset_by_id(id).map(move |dataset| {
+                dataset
+                    .children()
+                    .into_iter()
+                    .flatten()
+                    .filter(|package| is_of_type(package))
+                    .cloned()
+                    .collect()
+            }))
+        }
+    }
+
+    /// Stream every source file in a dataset, however deeply nested its
+    /// package, along with the package it belongs to: e.g. to back the
+    /// dataset up. Each file's S3 location is available from the file. The
+    /// sources of a few packages are requested at a time.
+    pub fn get_dataset_file_manifest(&self, id: DatasetNodeId) -> Stream<(PackageId, model::File)> {
+        let ps = self.clone();
+        let files = self
+            .get_dataset_packages_stream(id)
+            .filter(|package| {
+                package.package_type().map(String::as_str)
+                    != Some(model::PackageType::Collection.as_str())
+            })
+            .map(move |package| {
+                let package_id = package.id().clone();
+                ps.get_package_sources(package_id.clone())
+                    .map(move |sources| {
+                        let files = sources
+                            .take()
+                            .into_iter()
+                            .map(move |file| (package_id.clone(), file));
+                        stream::iter_ok(files)
+                    })
+            })
+            .buffered(PACKAGE_SOURCES_PARALLELISM)
+            .flatten();
+
+        into_stream_trait(files)
+    }
+
+    /// Get the default processing pipeline of a dataset.
+    pub fn get_dataset_pipeline(&self, id: DatasetNodeId) -> Future<response::DatasetPipeline> {
+        get!(self, route!("/datasets/{id}/pipeline", id))
+    }
+
+    /// Set the default processing pipeline used for packages uploaded to a dataset.
+    pub fn set_dataset_pipeline(
+        &self,
+        id: DatasetNodeId,
+        pipeline_id: PipelineId,
+    ) -> Future<response::DatasetPipeline> {
+        put!(
             self,
-            "/data/move",
+            route!("/datasets/{id}/pipeline", id),
             params!(),
-            payload!(request::mv::Move::new(things, destination))
+            payload!(request::dataset::SetPipeline::new(pipeline_id))
         )
     }
 
-    /// Get the members that belong to the current users organization.
-    pub fn get_members(&self) -> Future<Vec<model::User>> {
-        into_future_trait(match self.current_organization() {
-            Some(org) => self.get_members_by_organization(org),
-            None => into_future_trait(future::err::<_, Error>(ErrorKind::NoOrganizationSet.into())),
-        })
+    /// Delete an existing dataset.
+    pub fn delete_dataset(&self, id: DatasetNodeId) -> Future<()> {
+        let f: Future<response::EmptyMap> = delete!(self, route!("/datasets/{id}", id));
+        into_future_trait(f.map(|_| ()))
     }
 
-    /// Get the members that belong to the specified organization.
-    pub fn get_members_by_organization(&self, id: OrganizationId) -> Future<Vec<model::User>> {
-        get!(self, route!("/organizations/{id}/members", id))
-    }
+    /// Delete several datasets, a few at a time. Each dataset is returned
+    /// with the result of deleting it, in the order given, so one that
+    /// couldn't be deleted doesn't stop the rest.
+    pub fn delete_datasets(
+        &self,
+        ids: Vec<DatasetNodeId>,
+    ) -> Future<Vec<(DatasetNodeId, Result<()>)>> {
+        let ps = self.clone();
+        let f = stream::iter_ok(ids)
+            .map(move |id| {
+                ps.delete_dataset(id.clone())
+                    .then(move |result| Ok::<_, Error>((id, result)))
+            })
+            .buffered(DATASET_DELETE_PARALLELISM)
+            .collect();
 
-    /// Get the members that belong to the current users organization.
-    pub fn get_teams(&self) -> Future<Vec<response::Team>> {
-        into_future_trait(match self.current_organization() {
-            Some(org) => self.get_teams_by_organization(org),
-            None => into_future_trait(future::err::<_, Error>(ErrorKind::NoOrganizationSet.into())),
-        })
+        into_future_trait(f)
     }
 
-    /// Get the teams that belong to the specified organization.
-    pub fn get_teams_by_organization(&self, id: OrganizationId) -> Future<Vec<response::Team>> {
-        get!(self, route!("/organizations/{id}/teams", id))
+    /// Create a new package, optionally with metadata `properties`. The
+    /// `package_type` can be given as a `model::PackageType`, or as the
+    /// platform spells it.
+    /// TODO: see https://github.com/Pennsieve/pennsieve-rust/pull/45/files#r265581502
+    /// for a strategy for cleaning up API functions with many optional arguments.
+    pub fn create_package<N, D, P, F>(
+        &self,
+        name: N,
+        package_type: P,
+        dataset: D,
+        parent: Option<F>,
+        properties: Option<Vec<model::Property>>,
+    ) -> Future<response::Package>
+    where
+        D: Into<DatasetNodeId>,
+        N: Into<String>,
+        P: Into<String>,
+        F: Into<String>,
+    {
+        post!(
+            self,
+            "/packages/",
+            params!(),
+            payload!(
+                request::package::Create::new(name, package_type, dataset, parent)
+                    .with_properties(properties.unwrap_or_default())
+            )
+        )
     }
 
-    /// Generate a preview of the files to be uploaded.
-    pub fn preview_upload<P, Q>(
+    /// Like `create_package`, but tags the package with `import_id`, a
+    /// client-generated ID, so that the request can be retried after a
+    /// network error without creating a duplicate. If a package with
+    /// `import_id` already exists where it would have been created, that
+    /// package is returned instead.
+    pub fn create_package_with_id<N, D, P, F>(
         &self,
-        organization_id: &OrganizationId,
-        dataset_id: &DatasetId,
-        path: Option<P>,
-        files: &[(UploadId, Q)],
-        append: bool,
-        is_directory_upload: bool,
-    ) -> Future<response::UploadPreview>
+        name: N,
+        package_type: P,
+        dataset: D,
+        parent: Option<F>,
+        properties: Option<Vec<model::Property>>,
+        import_id: ImportId,
+    ) -> Future<response::Package>
     where
-        P: AsRef<Path>,
-        Q: AsRef<Path>,
+        D: Into<DatasetNodeId>,
+        N: Into<String>,
+        P: Into<String>,
+        F: Into<String>,
     {
-        let s3_files: Result<Vec<model::S3File>> = files
-            .iter()
-            .map(|(upload_id, file)| {
-                let path = path.as_ref();
-                if is_directory_upload {
-                    path.ok_or_else(|| {
-                        Error::invalid_arguments(
-                            "Path cannot be None when is_directory_upload is true",
-                        )
-                    })
-                    .and_then(|path| {
-                        FileUpload::new_recursive_upload(*upload_id, path, file.as_ref())
-                    })
-                } else if let Some(path) = path {
-                    FileUpload::new_non_recursive_upload(*upload_id, path.as_ref().join(file))
-                } else {
-                    FileUpload::new_non_recursive_upload(*upload_id, file)
-                }
-            })
-            .collect::<Result<Vec<_>>>()
-            .and_then(|file_uploads| {
-                file_uploads
-                    .iter()
-                    .map(|file_upload| file_upload.to_s3_file())
-                    .collect()
-            });
-
         let ps = self.clone();
-        let organization_id = organization_id.clone();
-        let dataset_id = dataset_id.clone();
+        let dataset = dataset.into();
+        let parent = parent.map(Into::into).map(PackageId::new);
+        let created: Future<response::Package> = post!(
+            self,
+            "/packages/",
+            params!(),
+            payload!(request::package::Create::new(
+                name,
+                package_type,
+                dataset.clone(),
+                parent.clone().map(String::from)
+            )
+            .with_properties(properties.unwrap_or_default())
+            .with_import_id(import_id.clone()))
+        );
 
-        let post = s3_files.into_future().and_then(move |s3_files| {
-            post!(
-                ps,
-                route!(
-                    "/upload/preview/organizations/{organization_id}",
-                    organization_id
-                ),
-                params!(
-                    "append" => if append { "true" } else { "false" },
-                    "dataset_id" => String::from(dataset_id)
+        let f = created.or_else(move |err| {
+            match err.kind() {
+                ErrorKind::ApiError {
+                    status_code: StatusCode::CONFLICT,
+                    ..
+                } => {}
+                _ => return into_future_trait(future::err(err)),
+            }
+            // The platform already has a package with the ID, most likely
+            // from an earlier attempt at this request:
+            let siblings = match parent {
+                Some(parent) => into_future_trait(ps.get_package_children_stream(parent).collect()),
+                None => into_future_trait(
+                    ps.get_dataset_by_id(dataset)
+                        .map(|dataset| dataset.children().cloned().unwrap_or_default()),
                 ),
-                &request::UploadPreview::new(&s3_files)
-            )
+            };
+            into_future_trait(siblings.and_then(move |siblings| {
+                siblings
+                    .into_iter()
+                    .find(|sibling| sibling.import_id() == Some(&import_id))
+                    .ok_or(err)
+            }))
         });
 
-        into_future_trait(post)
+        into_future_trait(f)
     }
 
-    #[allow(clippy::too_many_arguments)]
-    /// Upload a batch of files using the upload service.
-    pub fn upload_file_chunks<P, C>(
+    /// Create a new collection in a dataset, under the collection `parent`
+    /// if given, or at the top level of the dataset otherwise.
+    pub fn create_collection<N, D, F>(
         &self,
-        organization_id: &OrganizationId,
-        import_id: &ImportId,
-        path: P,
-        files: Vec<model::S3File>,
-        missing_parts: Option<response::FilesMissingParts>,
-        progress_callback: C,
-        parallelism: usize,
-    ) -> Stream<ImportId>
+        name: N,
+        dataset: D,
+        parent: Option<F>,
+    ) -> Future<response::Package>
     where
-        P: 'static + AsRef<Path>,
-        C: 'static + ProgressCallback + Clone,
+        D: Into<DatasetNodeId>,
+        N: Into<String>,
+        F: Into<String>,
     {
-        let ps = self.clone();
-        let organization_id = organization_id.clone();
-        let import_id = import_id.clone();
-        let progress_callback = progress_callback.clone();
+        self.create_package(name, model::PackageType::Collection, dataset, parent, None)
+    }
 
-        let missing_file_names: Option<Vec<String>> = missing_parts
-            .clone()
-            .map(|mp| mp.files.into_iter().map(|f| f.file_name).collect());
+    /// Get the metadata properties of a package.
+    pub fn get_package_properties(&self, id: PackageId) -> Future<Vec<model::Property>> {
+        get!(self, route!("/packages/{id}/properties", id))
+    }
 
-        let fs = stream::futures_unordered(
-            files
-                .into_iter()
-                .filter(|file| match &missing_file_names {
-                    None => true,
-                    Some(mp) => mp.contains(file.file_name()),
-                })
-                .zip(iter::repeat(path.as_ref().to_path_buf()))
-                .map(|file| future::ok::<(model::S3File, PathBuf), Error>(file.clone())),
+    /// Replace the metadata properties of a package.
+    pub fn update_package_properties(
+        &self,
+        id: PackageId,
+        properties: Vec<model::Property>,
+    ) -> Future<Vec<model::Property>> {
+        put!(
+            self,
+            route!("/packages/{id}/properties", id),
+            params!(),
+            payload!(request::package::UpdateProperties::new(properties))
         )
-        .map(move |(file, path): (model::S3File, PathBuf)| {
-            let mut file_path = path.clone();
-            let file = file.clone();
+    }
 
-            file_path.push(file.file_name());
+    /// Get a specific package.
+    pub fn get_package_by_id(&self, id: PackageId) -> Future<response::Package> {
+        get!(self, route!("/packages/{id}", id))
+    }
 
-            let file_missing_parts: Option<response::FileMissingParts> = match missing_parts {
-                Some(ref mp) => mp
-                    .files
-                    .iter()
-                    .find(|p| &p.file_name == file.file_name())
-                    .cloned(),
-                None => None,
-            };
+    /// Test if a package exists, without fetching it.
+    pub fn package_exists(&self, id: PackageId) -> Future<bool> {
+        self.resource_exists(route!("/packages/{id}", id))
+    }
 
-            let chunked_file_payload = if let Some(chunked_upload_properties) =
-                file.chunked_upload()
-            {
-                debug!(
-                    "ps:upload_file_chunks<file = {file_name}> :: \
-                     Chunk size received from the upload service: {chunk_size}.",
-                    file_name = file.file_name(),
-                    chunk_size = chunked_upload_properties.chunk_size
-                );
+    /// Get many packages by ID, a few at a time. The platform has no bulk
+    /// endpoint, so each package is requested on its own. The results are in
+    /// the order of `ids`, and a package that couldn't be fetched fails only
+    /// its own result rather than the whole batch.
+    pub fn get_packages(&self, ids: Vec<PackageId>) -> Future<Vec<Result<response::Package>>> {
+        let ps = self.clone();
+        let f = stream::iter_ok(ids)
+            .map(move |id| ps.get_package_by_id(id).then(Ok::<_, Error>))
+            .buffered(PACKAGE_FETCH_PARALLELISM)
+            .collect();
 
-                ChunkedFilePayload::new_with_chunk_size(
-                    import_id.clone(),
-                    file_path,
-                    chunked_upload_properties.chunk_size,
-                    file_missing_parts.as_ref(),
-                )
-            } else {
-                debug!(
-                    "ps:upload_file_chunks<file = {file_name}> :: \
-                     No chunk size received from the upload service. \
-                     Falling back to default.",
-                    file_name = file.file_name()
-                );
-                ChunkedFilePayload::new(import_id.clone(), file_path, file_missing_parts.as_ref())
-            };
+        into_future_trait(f)
+    }
 
-            let ps = ps.clone();
-            let organization_id = organization_id.clone();
-            let import_id = import_id.clone();
-            let progress_callback = progress_callback.clone();
+    /// Get a page of the children of a collection, starting at `offset`.
+    /// Unlike `get_package_by_id`, which returns every child at once, this
+    /// copes with collections holding many thousands of packages.
+    pub fn get_package_children(
+        &self,
+        id: PackageId,
+        limit: usize,
+        offset: usize,
+    ) -> Future<response::PackageChildrenPage> {
+        get!(
+            self,
+            route!("/packages/{id}/children", id),
+            params!("limit" => limit.to_string(), "offset" => offset.to_string())
+        )
+    }
 
-            chunked_file_payload
-                .map(move |(file_chunk, progress_update)| {
-                    if let Some(MultipartUploadId(multipart_upload_id)) = file.multipart_upload_id()
-                    {
-                        let import_id = import_id.clone();
-                        let import_id_clone = import_id.clone();
-                        let organization_id = organization_id.clone();
-                        let progress_callback = progress_callback.clone();
+    /// Stream all of the children of a collection, requesting them from the
+    /// platform one page at a time.
+    pub fn get_package_children_stream(&self, id: PackageId) -> Stream<response::Package> {
+        let ps = self.clone();
+        let pages = stream::unfold(Some(0), move |offset: Option<usize>| {
+            offset.map(|offset| {
+                ps.get_package_children(id.clone(), PACKAGE_CHILDREN_PAGE_SIZE, offset)
+                    .map(|page| {
+                        let next_offset = if page.has_more() {
+                            Some(page.offset() + page.children().len())
+                        } else {
+                            None
+                        };
+                        (page, next_offset)
+                    })
+            })
+        });
 
-                        into_future_trait(
-                            ps.request_with_body(
-                                route!(
-                                    "/upload/chunk/organizations/{organization_id}/id/{import_id}",
-                                    organization_id,
-                                    import_id
-                                ),
-                                Method::POST,
-                                params!(
-                                    "filename" => file.file_name().to_string(),
-                                    "multipartId" => multipart_upload_id.to_string(),
-                                    "chunkChecksum" => file_chunk.checksum.0,
-                                    "chunkNumber" => file_chunk.chunk_number.to_string()
-                                ),
-                                file_chunk.bytes,
-                                vec![],
-                                false,
-                            )
-                            .and_then(
-                                move |response: response::UploadResponse| {
-                                    if response.success {
-                                        progress_callback.on_update(&progress_update.clone());
-                                        future::ok(import_id_clone)
-                                    } else {
-                                        future::err(Error::upload_error(
-                                            response.error.unwrap_or_else(|| {
-                                                "no error message supplied".into()
-                                            }),
-                                        ))
-                                    }
-                                },
-                            ),
-                        )
-                    } else {
-                        into_future_trait(future::err(Error::upload_error(format!(
-                            "no multipartId was provided for file: {}",
-                            file.file_name()
-                        ))))
-                    }
-                })
-                .map_err(Into::into)
-                .buffer_unordered(parallelism)
-        })
-        .flatten();
+        into_stream_trait(pages.map(|page| stream::iter_ok(page.take())).flatten())
+    }
 
-        into_stream_trait(fs)
+    /// Get the collections containing a package, in order from the top
+    /// level of its dataset down to its parent, e.g. to render breadcrumbs.
+    /// A package at the top level of its dataset has none.
+    pub fn get_package_ancestors(&self, id: PackageId) -> Future<Vec<response::Package>> {
+        let f: Future<response::Package> = get!(
+            self,
+            route!("/packages/{id}", id),
+            params!("includeAncestors" => "true")
+        );
+        into_future_trait(f.map(|package| package.ancestors().cloned().unwrap_or_default()))
     }
 
-    /// Complete an upload to the upload service
-    pub fn complete_upload(
+    /// Stream the samples of a timeseries channel from `start` up to `end`
+    /// (in microseconds), as `(timestamp, value)` pairs. The range is
+    /// clamped to the channel's own bounds, and requested a window of a few
+    /// thousand samples at a time; an empty or inverted range yields nothing.
+    pub fn get_channel_data(
         &self,
-        organization_id: &OrganizationId,
-        import_id: &ImportId,
-        dataset_id: &DatasetNodeId,
-        destination_id: Option<&PackageId>,
-        append: bool,
-    ) -> Future<response::Manifests> {
-        let mut params = params!(
-            "datasetId" => dataset_id,
-            "append" => if append { "true" } else { "false" }
-        );
-        if let Some(dest_id) = destination_id {
-            params.push(param!("destinationId", dest_id.clone()));
+        package: PackageId,
+        channel: &model::Channel,
+        start: i64,
+        end: i64,
+    ) -> Stream<(i64, f64)> {
+        let start = start.max(channel.start());
+        let end = end.min(channel.end());
+        if start >= end {
+            return into_stream_trait(stream::empty());
         }
 
-        post!(
+        let window = channel_data_window(channel.rate()).unwrap_or(end - start);
+        let ps = self.clone();
+        let channel = channel.id().clone();
+        let windows = stream::unfold(start, move |window_start| {
+            if window_start >= end {
+                return None;
+            }
+            let window_end = window_start.saturating_add(window).min(end);
+            Some(
+                ps.get_channel_data_window(
+                    package.clone(),
+                    channel.clone(),
+                    window_start,
+                    window_end,
+                )
+                .map(move |data| (data, window_end)),
+            )
+        });
+
+        into_stream_trait(windows.map(|data| stream::iter_ok(data.take())).flatten())
+    }
+
+    /// Get a single window of samples from a timeseries channel.
+    fn get_channel_data_window(
+        &self,
+        package: PackageId,
+        channel: model::ChannelId,
+        start: i64,
+        end: i64,
+    ) -> Future<response::ChannelData> {
+        get!(
             self,
             route!(
-                "/upload/complete/organizations/{organization_id}/id/{import_id}",
-                organization_id,
-                import_id
+                "/timeseries/{package}/channels/{channel}/data",
+                package,
+                channel
             ),
-            params
+            params!("start" => start.to_string(), "end" => end.to_string())
         )
     }
 
-    /// Get the upload status using the upload service
-    pub fn get_upload_status(
+    /// Search for packages matching `query`, optionally only within the
+    /// given dataset. No matches is an empty `Vec`, not an error.
+    pub fn search_packages(
         &self,
-        organization_id: &OrganizationId,
-        import_id: &ImportId,
-    ) -> Future<Option<response::FilesMissingParts>> {
+        query: &str,
+        dataset: Option<DatasetNodeId>,
+    ) -> Future<Vec<response::Package>> {
+        let mut params = params!("query" => query);
+        if let Some(dataset) = dataset {
+            params.push(param!("datasetId", dataset));
+        }
+        // The platform may respond to a search without matches with no body:
+        let f: Future<Option<Vec<response::Package>>> = get!(self, "/search/packages", params);
+        into_future_trait(f.map(Option::unwrap_or_default))
+    }
+
+    /// Poll a package every `poll_interval` until it reaches the `target`
+    /// state, failing with `ErrorKind::PackageStateTimeout` if it hasn't
+    /// done so once `timeout` has elapsed. A request that times out fails
+    /// with `ErrorKind::TimeoutError` instead.
+    pub fn wait_for_package_state(
+        &self,
+        id: PackageId,
+        target: model::PackageState,
+        poll_interval: time::Duration,
+        timeout: time::Duration,
+    ) -> Future<model::Package> {
+        let ps = self.clone();
+        let deadline = time::Instant::now() + timeout;
+
+        let f = future::loop_fn((), move |_| {
+            let id = id.clone();
+            let target = target.clone();
+            ps.get_package_by_id(id.clone()).and_then(move |package| {
+                if package.state_enum().as_ref() == Some(&target) {
+                    return into_future_trait(future::ok(future::Loop::Break(package.take())));
+                }
+
+                let now = time::Instant::now();
+                if now >= deadline {
+                    return into_future_trait(future::err(Error::package_state_timeout(
+                        &id,
+                        target.as_str(),
+                        timeout,
+                    )));
+                }
+
+                // Never sleep past the deadline, so one last poll happens
+                // right before giving up:
+                let next_poll = (now + poll_interval).min(deadline);
+                into_future_trait(
+                    tokio::timer::Delay::new(next_poll)
+                        .map_err(Into::into)
+                        .map(|_| future::Loop::Continue(())),
+                )
+            })
+        });
+
+        into_future_trait(f)
+    }
+
+    /// Get the source files that are part of a package.
+    pub fn get_package_sources(&self, id: PackageId) -> Future<response::Files> {
+        get!(self, route!("/packages/{id}/sources", id))
+    }
+
+    /// Get the metadata of a single source file of a package, without
+    /// listing all of them as `get_package_sources` does. An unknown file
+    /// fails with a 404 `ErrorKind::ApiError`.
+    pub fn get_file(&self, package: PackageId, file_id: u64) -> Future<response::File> {
+        let file_id = file_id.to_string();
         get!(
             self,
-            route!(
-                "/upload/status/organizations/{organization_id}/id/{import_id}",
-                organization_id,
-                import_id
-            )
+            route!("/packages/{package}/sources/{file_id}", package, file_id)
         )
     }
 
-    /// Get the hash of an uploaded file from the upload service
-    pub fn get_upload_hash<S>(
-        &self,
-        import_id: &ImportId,
-        file_name: S,
-    ) -> Future<response::FileHash>
-    where
-        S: Into<String>,
-    {
+    /// Get a presigned URL from which a source file of a package can be
+    /// downloaded.
+    pub fn get_file_url(&self, package: PackageId, file_id: u64) -> Future<response::FileUrl> {
+        let file_id = file_id.to_string();
         get!(
             self,
-            route!("/upload/hash/id/{import_id}", import_id),
-            params!("fileName" => file_name)
+            route!("/packages/{package}/files/{file_id}", package, file_id)
         )
     }
 
-    pub fn upload_file_chunks_with_retries<P, C>(
+    /// Download a file to `dest`, reporting progress to `progress_callback`
+    /// as each chunk of the response body is written. If `dest` is a
+    /// directory, the file is written into it under its own name.
+    ///
+    /// The body is first written to a `.part` file next to the destination,
+    /// which is only renamed into place once the download completes, so an
+    /// interrupted download never leaves a truncated file behind. Downloads
+    /// are not associated with an import, so the progress updates carry an
+    /// empty import ID.
+    pub fn download_file<P, C>(
         &self,
-        organization_id: &OrganizationId,
-        import_id: &ImportId,
-        path: &P,
-        files: Vec<model::S3File>,
+        file: &model::File,
+        dest: P,
         progress_callback: C,
-        parallelism: usize,
-    ) -> Stream<ImportId>
+    ) -> Future<PathBuf>
     where
-        P: 'static + AsRef<Path> + Send,
+        P: AsRef<Path>,
         C: 'static + ProgressCallback + Clone,
     {
-        #[derive(Clone)]
-        struct LoopDependencies<C: ProgressCallback + Clone> {
-            organization_id: OrganizationId,
-            import_id: ImportId,
-            path: PathBuf,
-            files: Vec<model::S3File>,
-            missing_parts: Option<response::FilesMissingParts>,
-            result: Option<Vec<ImportId>>,
-            progress_callback: C,
-            try_num: usize,
-            ps: Pennsieve,
-            parallelism: usize,
-        }
+        self.download(file, dest.as_ref(), progress_callback, false)
+    }
 
-        impl<C: ProgressCallback + Clone> LoopDependencies<C> {
-            pub fn increment_attempt_count(self) -> Self {
-                Self {
-                    organization_id: self.organization_id,
-                    import_id: self.import_id,
-                    path: self.path,
-                    files: self.files,
-                    missing_parts: self.missing_parts,
-                    result: self.result,
-                    progress_callback: self.progress_callback,
-                    try_num: self.try_num + 1,
-                    ps: self.ps,
-                    parallelism: self.parallelism,
-                }
-            }
-        }
+    /// Like `download_file`, but an interrupted download is resumed rather
+    /// than started over. The `.part` file is kept if the download fails,
+    /// and the next attempt only requests the bytes it's missing, with a
+    /// `Range` header. If the server doesn't return exactly those bytes,
+    /// the whole file is downloaded again.
+    pub fn download_file_resumable<P, C>(
+        &self,
+        file: &model::File,
+        dest: P,
+        progress_callback: C,
+    ) -> Future<PathBuf>
+    where
+        P: AsRef<Path>,
+        C: 'static + ProgressCallback + Clone,
+    {
+        self.download(file, dest.as_ref(), progress_callback, true)
+    }
 
-        let ld = LoopDependencies {
-            organization_id: organization_id.clone(),
-            import_id: import_id.clone(),
-            path: path.as_ref().to_path_buf(),
-            files,
-            missing_parts: None,
-            result: None,
-            progress_callback,
-            try_num: 0,
-            ps: self.clone(),
-            parallelism,
+    fn download<C>(
+        &self,
+        file: &model::File,
+        dest: &Path,
+        progress_callback: C,
+        resume: bool,
+    ) -> Future<PathBuf>
+    where
+        C: 'static + ProgressCallback + Clone,
+    {
+        let dest = if dest.is_dir() {
+            dest.join(file.name())
+        } else {
+            dest.to_path_buf()
+        };
+        let mut part_path = dest.clone().into_os_string();
+        part_path.push(".part");
+        let part_path = PathBuf::from(part_path);
+        let part_path_err = part_path.clone();
+
+        // The bytes already downloaded by an earlier attempt, if resuming:
+        let offset = if resume {
+            fs::metadata(&part_path)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0)
+        } else {
+            0
         };
 
-        let retry_loop = future::loop_fn(ld, |mut ld| {
+        let size = file.size();
+        let context = match self.request_context() {
+            Ok(context) => context,
+            Err(err) => return into_future_trait(future::err(err)),
+        };
+        let timeout = self.inner.lock().unwrap().config.request_timeout().cloned();
 
-            let ld_err = ld.clone();
+        // Prefer a presigned URL when the platform told us which file this is:
+        let url = match (file.package_id(), file.id()) {
+            (Some(package_id), Some(file_id)) => into_future_trait(
+                self.get_file_url(package_id.clone(), file_id)
+                    .map(response::FileUrl::take),
+            ),
+            _ => into_future_trait(future::ok(file.s3_url())),
+        };
 
-            ld.ps
-                .get_upload_status(&ld.organization_id, &ld.import_id)
-                .map(|parts| {
-                    ld.missing_parts = parts;
-                    ld
-                })
-                .and_then(|ld| {
-                    ld.ps
-                        .upload_file_chunks(
-                            &ld.organization_id,
-                            &ld.import_id,
-                            ld.path.clone(),
-                            ld.files.clone(),
-                            ld.missing_parts.clone(),
-                            ld.progress_callback.clone(),
-                            ld.parallelism,
+        let f = url
+            .and_then(|url| url.parse::<hyper::Uri>().map_err(Into::into))
+            .and_then(move |uri| request_download(context, timeout, uri, offset))
+            .and_then(move |(body, offset, permit)| {
+                let chunk_callback = progress_callback.clone();
+                let chunk_dest = dest.clone();
+
+                let part_file = if offset > 0 {
+                    fs::OpenOptions::new().append(true).open(&part_path)
+                } else {
+                    fs::File::create(&part_path)
+                };
+                part_file
+                    .map_err(Into::<Error>::into)
+                    .into_future()
+                    .and_then(move |part_file| {
+                        body.map_err(Into::<Error>::into).fold(
+                            (part_file, 0, offset),
+                            move |(mut part_file, part_number, bytes_written), chunk| {
+                                part_file.write_all(&chunk)?;
+                                let part_number = part_number + 1;
+                                let bytes_written = bytes_written + chunk.len() as u64;
+                                chunk_callback.on_update(&ProgressUpdate::new(
+                                    part_number,
+                                    ImportId::new(""),
+                                    chunk_dest.clone(),
+                                    bytes_written,
+                                    size,
+                                    false,
+                                ));
+                                Ok::<_, Error>((part_file, part_number, bytes_written))
+                            },
                         )
-                        .collect()
-                        .map(future::Loop::Break)
-                })
-                .into_future()
-                .or_else(move |err| {
+                    })
+                    .and_then(move |(part_file, part_number, bytes_written)| {
+                        // The download is in flight until the file is written:
+                        drop(permit);
+                        part_file.sync_all()?;
+                        fs::rename(&part_path, &dest)?;
+                        progress_callback.on_update(&ProgressUpdate::new(
+                            part_number,
+                            ImportId::new(""),
+                            dest.clone(),
+                            bytes_written,
+                            size,
+                            true,
+                        ));
+                        Ok(dest)
+                    })
+            })
+            .or_else(move |err| {
+                // Don't leave a partially downloaded file lying around,
+                // unless the download is to be resumed from it:
+                if !resume {
+                    let _ = fs::remove_file(&part_path_err);
+                }
+                Err(err)
+            });
 
-                    debug!("Upload encountered an error: {error}", error = err);
-                    match err.kind() {
-                        // error cannot be retried, bubble up the error
-                        ErrorKind::ApiError{ status_code, .. } if NONRETRYABLE_STATUS_CODES.contains(status_code) => {
-                            debug!("Upload received status {status} from API which cannot be retried", status = status_code);
-                            into_future_trait(future::err(err))
-                        }
+        into_future_trait(f)
+    }
 
-                        // error that should be retried (if we are under MAX_RETRIES), retry the upload
-                        _ if MAX_RETRIES > ld_err.try_num => {
-                            let delay = retry_delay(ld_err.try_num);
-
-                            debug!("Waiting {millis} millis to retry...", millis = delay);
-
-                            // delay
-                            let deadline = time::Instant::now() + time::Duration::from_millis(delay);
-                            let continue_loop = tokio::timer::Delay::new(deadline)
-                                .map_err(Into::<Error>::into)
-                                .map(move |_| {
-                                    debug!(
-                                        "Attempting to resume missing parts. Attempt {try_num}/{retries})...",
-                                        try_num = ld_err.try_num, retries = MAX_RETRIES
-                                    );
-                                    future::Loop::Continue(ld_err.increment_attempt_count())
-                                });
-                            into_future_trait(continue_loop)
-                        }
+    /// Compare the source files of a package against local copies held in
+    /// `local_dir`, returning for each source file whether the SHA-256
+    /// checksum of the local copy matches the hash recorded by the upload
+    /// service. Source files with no local copy are reported as mismatches.
+    pub fn verify_package_against_local(
+        &self,
+        package: PackageId,
+        local_dir: &Path,
+    ) -> Future<Vec<(String, bool)>> {
+        let ps = self.clone();
+        let local_dir = local_dir.to_path_buf();
 
-                        // MAX_RETRIES exceeded, bubble up the error
-                        _ => {
-                            error!("Retries exceeded during upload. Bubbling up error {error}", error = err);
-                            into_future_trait(future::err(err))
-                        }
-                    }
+        let f = self
+            .get_package_by_id(package.clone())
+            .and_then(|pkg| {
+                pkg.import_id().cloned().ok_or_else(|| {
+                    Error::not_found(format!("no import id recorded for package {}", pkg.id()))
                 })
-        })
-        .map(|import_ids| {
-            future::ok::<Stream<ImportId>, Error>(
-                into_stream_trait(stream::futures_unordered(
-                    import_ids
-                        .iter()
-                        .map(|import_id| future::ok(import_id.clone())),
-                )),
-            )
-                .into_stream()
-            .flatten()
-        })
-        .into_stream()
-        .flatten();
+            })
+            .and_then(move |import_id| {
+                ps.get_package_sources(package)
+                    .map(|sources| (ps, import_id, sources))
+            })
+            .and_then(move |(ps, import_id, sources)| {
+                let checks = sources.take().into_iter().map(move |file| {
+                    let file_name = file.name().clone();
+                    let local_path = local_dir.join(&file_name);
+                    ps.get_upload_hash(&import_id, file_name.clone())
+                        .and_then(move |remote| {
+                            let matches = if local_path.is_file() {
+                                Checksum::of_file(&local_path)?.matches(&remote.hash)
+                            } else {
+                                false
+                            };
+                            Ok((file_name, matches))
+                        })
+                });
+                future::join_all(checks)
+            });
 
-        into_stream_trait(retry_loop)
+        into_future_trait(f)
     }
-}
-
-#[cfg(test)]
-pub mod tests {
-    use super::*;
-    use std::{fs, path, result, sync, thread};
-
-    use lazy_static::lazy_static;
-    use mockito::mock;
-
-    // use ps::api::{PSChildren, PSId, PSName};
-    use crate::ps::config::Environment;
-    use crate::ps::util::futures::into_future_trait;
-    use crate::ps::util::rand_suffix;
-
-    const TEST_ENVIRONMENT: Environment = Environment::NonProduction;
-    const TEST_API_KEY: &str = env!("PENNSIEVE_API_KEY");
-    const TEST_SECRET_KEY: &str = env!("PENNSIEVE_SECRET_KEY");
-
-    // "Agent Testing"
-    const FIXTURE_ORGANIZATION: &str = "N:organization:713eeb6e-c42c-445d-8a60-818c741ea87a";
 
-    // Dedicated agent email
-    #[allow(dead_code)]
-    const FIXTURE_EMAIL: &str = "agent-test@pennsieve.com";
+    /// Update an existing package.
+    pub fn update_package<N: Into<String>>(
+        &self,
+        id: PackageId,
+        name: N,
+    ) -> Future<response::Package> {
+        put!(
+            self,
+            route!("/packages/{id}", id),
+            params!(),
+            payload!(request::package::Update::new(name))
+        )
+    }
 
-    // // Dedicated agent user
-    // #[allow(dead_code)]
-    // const FIXTURE_USER: &str = "N:user:6caa1955-c39e-4198-83c6-aa8fe3afbe93";
+    /// Rename a package, like `update_package`, but first make sure no other
+    /// package in the same collection (or at the top level of the dataset)
+    /// already has the name, failing with `ErrorKind::InvalidArguments` if
+    /// one does. Siblings with the same name confuse `get_child_by_name`,
+    /// which only ever finds the first of them.
+    ///
+    /// The check is best-effort: it's made before the rename rather than by
+    /// the platform, so a sibling given the same name in the meantime isn't
+    /// caught.
+    pub fn rename_package_checked<N: Into<String>>(
+        &self,
+        id: PackageId,
+        name: N,
+    ) -> Future<response::Package> {
+        let name = name.into();
+        let ps = self.clone();
+        let package_id = id.clone();
+        let route = route!("/packages/{package_id}", package_id);
+        let f: Future<response::Package> = get!(self, route, params!("includeAncestors" => "true"));
+        let f = f
+            .and_then({
+                let ps = ps.clone();
+                move |package| -> Future<Vec<response::Package>> {
+                    let siblings = match package.ancestors().and_then(|ancestors| ancestors.last())
+                    {
+                        Some(parent) => into_future_trait(
+                            ps.get_package_by_id(parent.id().clone())
+                                .map(|parent| parent.children().cloned()),
+                        ),
+                        None => into_future_trait(
+                            ps.get_dataset_by_id(package.dataset_id().clone())
+                                .map(|dataset| dataset.children().cloned()),
+                        ),
+                    };
+                    into_future_trait(siblings.map(Option::unwrap_or_default))
+                }
+            })
+            .and_then(move |siblings| {
+                let taken = siblings
+                    .iter()
+                    .filter(|sibling| sibling.id() != &id)
+                    .any(|sibling| sibling.name() == &name);
+                if taken {
+                    into_future_trait(future::err(Error::invalid_arguments(format!(
+                        "another package is already named {:?}",
+                        name
+                    ))))
+                } else {
+                    ps.update_package(id, name)
+                }
+            });
+        into_future_trait(f)
+    }
 
-    const FIXTURE_DATASET: &str = "N:dataset:e5902b32-7954-463b-bb4c-2c9cf5b3bcfb";
-    const FIXTURE_DATASET_NAME: &str = "AGENT-FIXTURE";
+    /// Process a package in the UPLOADED state.
+    pub fn process_package(&self, id: PackageId) -> Future<()> {
+        let f = put!(self, route!("/packages/{id}/process", id)).map(|_: Nothing| ());
+        into_future_trait(f)
+    }
 
-    const FIXTURE_PACKAGE: &str = "N:collection:c602852e-3cc0-4b24-a68a-dd84045dfa47";
-    const FIXTURE_PACKAGE_NAME: &str = "AGENT-TEST-PACKAGE";
+    /// Process every package in a dataset that's in the UPLOADED state, as
+    /// needed once uploads are complete when the dataset doesn't process
+    /// packages automatically. Packages in collections are included. Each
+    /// package is returned with the result of processing it, so one that
+    /// couldn't be processed doesn't stop the rest.
+    pub fn process_dataset_packages(
+        &self,
+        dataset: DatasetNodeId,
+    ) -> Future<Vec<(PackageId, Result<()>)>> {
+        let ps = self.clone();
+        let f = self
+            .get_dataset_packages_stream(dataset)
+            .filter(|package| package.state_enum() == Some(model::PackageState::Uploaded))
+            .map(move |package| {
+                let id = package.id().clone();
+                ps.process_package(id.clone())
+                    .then(move |result| Ok::<_, Error>((id, result)))
+            })
+            .buffered(PACKAGE_PROCESS_PARALLELISM)
+            .collect();
 
-    lazy_static! {
-        static ref CONFIG: Config = Config::new(TEST_ENVIRONMENT);
-        static ref TEST_FILES: Vec<String> = test_data_files("/small");
-        static ref TEST_DATA_DIR: String = test_data_dir("/small");
-        pub static ref BIG_TEST_FILES: Vec<String> = test_data_files("/big");
-        pub static ref BIG_TEST_DATA_DIR: String = test_data_dir("/big");
-        pub static ref MEDIUM_TEST_FILES: Vec<String> = test_data_files("/medium");
-        pub static ref MEDIUM_TEST_DATA_DIR: String = test_data_dir("/medium");
+        into_future_trait(f)
     }
 
-    /// given a 'runner' function, run the given Pennsieve instance
-    /// through that function and block until completion
-    fn run<F, T>(ps: &Pennsieve, runner: F) -> Result<T>
-    where
-        F: Fn(Pennsieve) -> Future<T>,
-        T: 'static + Send,
-    {
-        let mut rt = tokio::runtime::Runtime::new()?;
-        let result = rt.block_on(runner(ps.clone()));
-        rt.shutdown_on_idle();
-        result
+    /// Delete an existing package.
+    ///
+    /// The platform reports deletions per package rather than with a
+    /// status code, so a package that could not be deleted is surfaced
+    /// as an `ApiError`.
+    pub fn delete_package(&self, id: PackageId) -> Future<()> {
+        let f = self
+            .delete_packages(vec![id])
+            .and_then(|mut results| results.pop().map(|(_, result)| result).unwrap_or(Ok(())));
+        into_future_trait(f)
     }
 
-    struct Inner(sync::Mutex<bool>);
-
-    impl Inner {
-        pub fn new() -> Self {
-            Inner(sync::Mutex::new(false))
+    /// Delete several packages. The platform deletes them all in a single
+    /// request, reporting which couldn't be deleted, so each package is
+    /// returned with the result of deleting it, in the order given. If the
+    /// request fails outright, every package is returned with that error.
+    pub fn delete_packages(&self, ids: Vec<PackageId>) -> Future<Vec<(PackageId, Result<()>)>> {
+        if ids.is_empty() {
+            return into_future_trait(future::ok(vec![]));
         }
+        let f = post!(
+            self,
+            "/data/delete",
+            params!(),
+            payload!(request::package::Delete::new(ids.clone()))
+        )
+        .then(move |response: Result<response::DeleteResponse>| {
+            let results = ids
+                .into_iter()
+                .map(|id| {
+                    let result = match response {
+                        Ok(ref response) => {
+                            let id: &str = id.borrow();
+                            match response
+                                .failures()
+                                .iter()
+                                .find(|failure| failure.id() == id)
+                            {
+                                Some(failure) => Err(Error::api_error(
+                                    StatusCode::BAD_REQUEST,
+                                    format!(
+                                        "failed to delete package {id}: {error}",
+                                        id = failure.id(),
+                                        error = failure.error()
+                                    ),
+                                )),
+                                None => Ok(()),
+                            }
+                        }
+                        Err(ref err) => Err(err.clone()),
+                    };
+                    (id, result)
+                })
+                .collect();
+            Ok(results)
+        });
+        into_future_trait(f)
     }
 
-    pub struct ProgressIndicator {
-        inner: sync::Arc<Inner>,
+    /// Move several packages to a destination package.
+    /// If destination is None, the package is moved to the top level of the dataset.
+    pub fn mv<T: Into<PackageId>, D: Into<PackageId>>(
+        &self,
+        things: Vec<T>,
+        destination: Option<D>,
+    ) -> Future<response::MoveResponse> {
+        post!(
+            self,
+            "/data/move",
+            params!(),
+            payload!(request::mv::Move::new(things, destination))
+        )
     }
 
-    impl Clone for ProgressIndicator {
-        fn clone(&self) -> Self {
-            Self {
-                inner: Arc::clone(&self.inner),
+    /// Like `mv`, but fails if any of the packages could not be moved. The
+    /// platform reports these per package rather than with a status code,
+    /// so they are surfaced as an `ApiError` listing each failed package and
+    /// why. Packages that were moved stay moved.
+    pub fn mv_strict<T: Into<PackageId>, D: Into<PackageId>>(
+        &self,
+        things: Vec<T>,
+        destination: Option<D>,
+    ) -> Future<response::MoveResponse> {
+        let f = self.mv(things, destination).and_then(|response| {
+            if response.failures().is_empty() {
+                return Ok(response);
             }
-        }
+            let failures: Vec<String> = response
+                .failures()
+                .iter()
+                .map(|failure| format!("{}: {}", failure.id(), failure.error()))
+                .collect();
+            Err(Error::api_error(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "failed to move {} package(s): {}",
+                    failures.len(),
+                    failures.join("; ")
+                ),
+            ))
+        });
+        into_future_trait(f)
     }
 
-    impl ProgressIndicator {
-        pub fn new() -> Self {
-            Self {
-                inner: sync::Arc::new(Inner::new()),
-            }
+    /// Move a collection, along with everything nested inside it, to the top
+    /// level of another dataset.
+    ///
+    /// The collection structure is recreated in the target dataset, reusing
+    /// any collection that already exists there under the same name and
+    /// parent, and the packages inside each collection are moved into its
+    /// counterpart. A source collection is deleted once everything inside it
+    /// has been moved. Packages that could not be moved are reported as
+    /// failures in the response rather than aborting the rest of the move.
+    pub fn move_collection_to_dataset(
+        &self,
+        collection: PackageId,
+        target_dataset: DatasetNodeId,
+    ) -> Future<response::MoveResponse> {
+        let ps = self.clone();
+        let f = self
+            .get_package_by_id(collection)
+            .join(self.get_dataset_by_id(target_dataset.clone()))
+            .and_then(move |(collection, dataset)| {
+                let siblings = dataset.children().cloned().unwrap_or_default();
+                ps.move_collection_into(collection, siblings, target_dataset, None)
+            });
+        into_future_trait(f)
+    }
+
+    // Moves the contents of `collection` into a collection of the same name
+    // among `siblings` in the target dataset, creating it under `parent` if
+    // it doesn't exist yet, then recurses into any nested collections.
+    fn move_collection_into(
+        &self,
+        collection: response::Package,
+        siblings: Vec<response::Package>,
+        target_dataset: DatasetNodeId,
+        parent: Option<PackageId>,
+    ) -> Future<response::MoveResponse> {
+        fn is_collection(package: &response::Package) -> bool {
+            package.package_type().map(String::as_str)
+                == Some(model::PackageType::Collection.as_str())
         }
+
+        let ps = self.clone();
+        let source_id = collection.id().clone();
+
+        let destination = match siblings
+            .iter()
+            .find(|sibling| is_collection(sibling) && sibling.name() == collection.name())
+        {
+            Some(existing) => self.get_package_by_id(existing.id().clone()),
+            None => self.create_collection(
+                collection.name().clone(),
+                target_dataset.clone(),
+                parent.map(String::from),
+            ),
+        };
+
+        let f = destination.then(move |destination| {
+            let destination = match destination {
+                Ok(destination) => destination,
+                Err(err) => {
+                    return into_future_trait(future::ok(response::MoveResponse::failed(
+                        source_id,
+                        err.to_string(),
+                    )));
+                }
+            };
+            let destination_id = destination.id().clone();
+            let existing = destination.children().cloned().unwrap_or_default();
+
+            let (collections, packages): (Vec<_>, Vec<_>) = collection
+                .children()
+                .cloned()
+                .unwrap_or_default()
+                .into_iter()
+                .partition(is_collection);
+
+            let moved_packages = if packages.is_empty() {
+                into_future_trait(future::ok(response::MoveResponse::empty(
+                    destination_id.clone(),
+                )))
+            } else {
+                let ids: Vec<PackageId> = packages.iter().map(|p| p.id().clone()).collect();
+                into_future_trait(ps.mv(ids.clone(), Some(destination_id.clone())).or_else(
+                    move |err| {
+                        Ok(ids.into_iter().fold(
+                            response::MoveResponse::empty(destination_id),
+                            |response, id| {
+                                response.merge(response::MoveResponse::failed(id, err.to_string()))
+                            },
+                        ))
+                    },
+                ))
+            };
+
+            let moved_collections: Vec<_> = collections
+                .into_iter()
+                .map(|nested| {
+                    let ps = ps.clone();
+                    let nested_id = nested.id().clone();
+                    let existing = existing.clone();
+                    let target_dataset = target_dataset.clone();
+                    let parent = destination.id().clone();
+
+                    // Only the immediate children of a package are included
+                    // in a listing, so fetch the nested collection's own:
+                    ps.get_package_by_id(nested_id.clone())
+                        .then(move |nested| match nested {
+                            Ok(nested) => ps.move_collection_into(
+                                nested,
+                                existing,
+                                target_dataset,
+                                Some(parent),
+                            ),
+                            Err(err) => into_future_trait(future::ok(
+                                response::MoveResponse::failed(nested_id, err.to_string()),
+                            )),
+                        })
+                })
+                .collect();
+
+            let f = moved_packages
+                .join(future::join_all(moved_collections))
+                .map(|(moved_packages, moved_collections)| {
+                    moved_collections
+                        .into_iter()
+                        .fold(moved_packages, response::MoveResponse::merge)
+                })
+                .and_then(move |response| {
+                    if !response.failures().is_empty() {
+                        return into_future_trait(future::ok(response));
+                    }
+                    // Everything was moved out, so the source collection
+                    // is now empty:
+                    into_future_trait(ps.delete_package(source_id.clone()).then(move |deleted| {
+                        match deleted {
+                            Ok(_) => Ok(response),
+                            Err(err) => Ok(response
+                                .merge(response::MoveResponse::failed(source_id, err.to_string()))),
+                        }
+                    }))
+                });
+            into_future_trait(f)
+        });
+
+        into_future_trait(f)
     }
 
-    impl ProgressCallback for ProgressIndicator {
-        fn on_update(&self, _update: &ProgressUpdate) {
-            *self.inner.0.lock().unwrap() = true;
+    /// Get the organization the user is associated with. If none has been
+    /// set, the user's preferred organization is looked up and becomes the
+    /// current organization. Fails with `NoOrganizationSet` only if the user
+    /// has no preferred organization either.
+    pub fn resolve_organization(&self) -> Future<OrganizationId> {
+        if let Some(org) = self.current_organization() {
+            return into_future_trait(future::ok(org));
         }
+        let this = self.clone();
+        let f = self.get_user().and_then(move |user| {
+            let org = user
+                .preferred_organization()
+                .cloned()
+                .ok_or_else(|| Error::from(ErrorKind::NoOrganizationSet))?;
+            this.set_current_organization(Some(&org));
+            Ok(org)
+        });
+        into_future_trait(f)
     }
 
-    fn ps() -> Pennsieve {
-        Pennsieve::new((*CONFIG).clone())
+    /// Like `get_members`, but falls back to the user's preferred
+    /// organization if no organization has been set.
+    pub fn get_members_resolved(&self) -> Future<Vec<model::User>> {
+        let this = self.clone();
+        into_future_trait(
+            self.resolve_organization()
+                .and_then(move |org| this.get_members_by_organization(org)),
+        )
     }
 
-    // Returns the test data directory `<project>/data/<data_dir>`:
-    fn test_data_dir(data_dir: &str) -> String {
-        concat!(env!("CARGO_MANIFEST_DIR"), "/test/data").to_string() + data_dir
+    /// Like `get_teams`, but falls back to the user's preferred
+    /// organization if no organization has been set.
+    pub fn get_teams_resolved(&self) -> Future<Vec<response::Team>> {
+        let this = self.clone();
+        into_future_trait(
+            self.resolve_organization()
+                .and_then(move |org| this.get_teams_by_organization(org)),
+        )
     }
 
-    // Returns a `Vec<String>` of test data filenames taken from the specified
-    // test data directory:
-    fn test_data_files(data_dir: &str) -> Vec<String> {
-        match fs::read_dir(test_data_dir(data_dir)) {
+    /// Get the members that belong to the current users organization.
+    pub fn get_members(&self) -> Future<Vec<model::User>> {
+        into_future_trait(match self.current_organization() {
+            Some(org) => self.get_members_by_organization(org),
+            None => into_future_trait(future::err::<_, Error>(ErrorKind::NoOrganizationSet.into())),
+        })
+    }
+
+    /// Get the members that belong to the specified organization.
+    pub fn get_members_by_organization(&self, id: OrganizationId) -> Future<Vec<model::User>> {
+        self.get_list(
+            route!("/organizations/{id}/members", id),
+            params!(),
+            "member",
+        )
+    }
+
+    /// Get the members of the specified organization that have `role` in it.
+    /// The endpoint can't filter by role, so every member is fetched and
+    /// filtered here; a role nobody has gives an empty list.
+    pub fn get_members_by_role(
+        &self,
+        id: OrganizationId,
+        role: model::Role,
+    ) -> Future<Vec<model::User>> {
+        into_future_trait(self.get_members_by_organization(id).map(move |members| {
+            members
+                .into_iter()
+                .filter(|member| member.role_enum().as_ref() == Some(&role))
+                .collect()
+        }))
+    }
+
+    /// Get the members that belong to the current users organization.
+    pub fn get_teams(&self) -> Future<Vec<response::Team>> {
+        into_future_trait(match self.current_organization() {
+            Some(org) => self.get_teams_by_organization(org),
+            None => into_future_trait(future::err::<_, Error>(ErrorKind::NoOrganizationSet.into())),
+        })
+    }
+
+    /// Get the teams that belong to the specified organization.
+    pub fn get_teams_by_organization(&self, id: OrganizationId) -> Future<Vec<response::Team>> {
+        self.get_list(route!("/organizations/{id}/teams", id), params!(), "team")
+    }
+
+    /// Get the members of a team in the specified organization.
+    pub fn get_team_members(
+        &self,
+        org: OrganizationId,
+        team: model::TeamId,
+    ) -> Future<Vec<model::User>> {
+        self.get_list(
+            route!("/organizations/{org}/teams/{team}/members", org, team),
+            params!(),
+            "member",
+        )
+    }
+
+    /// Get temporary AWS credentials for uploading files to a dataset
+    /// directly to S3, rather than sending their chunks through the upload
+    /// service.
+    ///
+    /// To upload a file directly, preview the upload to get an import ID,
+    /// then put the file in `s3_bucket()` under the key given by
+    /// `s3_key().as_upload_key(&import_id, file_name)`, signing requests
+    /// with `temp_credentials()` and encrypting with `encryption_key_id()`.
+    /// The credentials expire at `temp_credentials().expiration()`.
+    pub fn get_upload_credentials(
+        &self,
+        organization_id: &OrganizationId,
+        dataset_id: &DatasetNodeId,
+    ) -> Future<model::UploadCredential> {
+        let f: Future<response::UploadCredential> = get!(
+            self,
+            route!("/security/user/credentials/upload/{dataset_id}", dataset_id),
+            params!("organizationId" => organization_id)
+        );
+        into_future_trait(f.map(response::UploadCredential::take))
+    }
+
+    /// Like `get_upload_credentials`, but for the current organization,
+    /// falling back to the user's preferred organization if none has been
+    /// set.
+    pub fn get_upload_credentials_resolved(
+        &self,
+        dataset_id: DatasetNodeId,
+    ) -> Future<model::UploadCredential> {
+        let this = self.clone();
+        into_future_trait(
+            self.resolve_organization()
+                .and_then(move |org| this.get_upload_credentials(&org, &dataset_id)),
+        )
+    }
+
+    /// Generate a preview of the files to be uploaded.
+    pub fn preview_upload<P, Q>(
+        &self,
+        organization_id: &OrganizationId,
+        dataset_id: &DatasetId,
+        path: Option<P>,
+        files: &[(UploadId, Q)],
+        append: bool,
+        is_directory_upload: bool,
+    ) -> Future<response::UploadPreview>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let s3_files: Result<Vec<model::S3File>> = files
+            .iter()
+            .map(|(upload_id, file)| {
+                let path = path.as_ref();
+                if is_directory_upload {
+                    path.ok_or_else(|| {
+                        Error::invalid_arguments(
+                            "Path cannot be None when is_directory_upload is true",
+                        )
+                    })
+                    .and_then(|path| {
+                        FileUpload::new_recursive_upload(*upload_id, path, file.as_ref())
+                    })
+                } else if let Some(path) = path {
+                    FileUpload::new_non_recursive_upload(*upload_id, path.as_ref().join(file))
+                } else {
+                    FileUpload::new_non_recursive_upload(*upload_id, file)
+                }
+            })
+            .collect::<Result<Vec<_>>>()
+            .and_then(|file_uploads| {
+                file_uploads
+                    .iter()
+                    .map(|file_upload| file_upload.to_s3_file())
+                    .collect()
+            });
+
+        let ps = self.clone();
+        let organization_id = organization_id.clone();
+        let dataset_id = dataset_id.clone();
+
+        let post = s3_files.into_future().and_then(move |s3_files| {
+            post!(
+                ps,
+                route!(
+                    "/upload/preview/organizations/{organization_id}",
+                    organization_id
+                ),
+                params!(
+                    "append" => if append { "true" } else { "false" },
+                    "dataset_id" => String::from(dataset_id)
+                ),
+                &request::UploadPreview::new(&s3_files)
+            )
+        });
+
+        into_future_trait(post)
+    }
+
+    /// Preview an upload of `files`, read from the directory `path`, to a
+    /// dataset, returning a session for each package the preview groups
+    /// them into. Each session can be persisted, then uploaded (or resumed
+    /// after a crash) with `resume_upload`, and completed with
+    /// `complete_upload`.
+    pub fn begin_upload<P, Q>(
+        &self,
+        organization_id: &OrganizationId,
+        dataset_id: &DatasetNodeId,
+        path: P,
+        files: &[(UploadId, Q)],
+        append: bool,
+        is_directory_upload: bool,
+    ) -> Future<Vec<model::UploadSession>>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let ps = self.clone();
+        let organization_id = organization_id.clone();
+        let dataset_id = dataset_id.clone();
+        let path = path.as_ref().to_path_buf();
+        let files: Vec<(UploadId, PathBuf)> = files
+            .iter()
+            .map(|(upload_id, file)| (*upload_id, file.as_ref().to_path_buf()))
+            .collect();
+
+        let f = self
+            .get_dataset_by_id(dataset_id.clone())
+            .and_then(move |dataset| {
+                ps.preview_upload(
+                    &organization_id,
+                    dataset.int_id(),
+                    Some(&path),
+                    &files,
+                    append,
+                    is_directory_upload,
+                )
+                .map(move |preview| {
+                    preview
+                        .into_iter()
+                        .map(|package| {
+                            model::UploadSession::new(
+                                organization_id.clone(),
+                                package.import_id().clone(),
+                                dataset_id.clone(),
+                                package.files().clone(),
+                                path.clone(),
+                            )
+                        })
+                        .collect()
+                })
+            });
+
+        into_future_trait(f)
+    }
+
+    /// Work out what uploading `files`, read from the directory `path`, to a
+    /// dataset would do, without uploading anything: the packages the upload
+    /// service would group the files into, how many bytes would be sent, and
+    /// where in the dataset each file would go. As with `preview_upload`,
+    /// this fails with `ErrorKind::PathDoesNotExist` or
+    /// `ErrorKind::PathIsNotAFile` if any file can't be read.
+    pub fn plan_upload<P, Q>(
+        &self,
+        organization_id: &OrganizationId,
+        dataset_id: &DatasetNodeId,
+        path: P,
+        files: &[(UploadId, Q)],
+        append: bool,
+        is_directory_upload: bool,
+    ) -> Future<model::UploadPlan>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let ps = self.clone();
+        let organization_id = organization_id.clone();
+        let path = path.as_ref().to_path_buf();
+        let files: Vec<(UploadId, PathBuf)> = files
+            .iter()
+            .map(|(upload_id, file)| (*upload_id, file.as_ref().to_path_buf()))
+            .collect();
+
+        let f = self
+            .get_dataset_by_id(dataset_id.clone())
+            .and_then(move |dataset| {
+                ps.preview_upload(
+                    &organization_id,
+                    dataset.int_id(),
+                    Some(&path),
+                    &files,
+                    append,
+                    is_directory_upload,
+                )
+                .map(|preview| model::UploadPlan::new(preview.take()))
+            });
+
+        into_future_trait(f)
+    }
+
+    /// Upload the files of a session from `begin_upload`, retrying on
+    /// failure. Only the parts the upload service reports missing are sent,
+    /// so a session from a process that died partway through picks up where
+    /// it left off.
+    pub fn resume_upload<C>(
+        &self,
+        session: &model::UploadSession,
+        progress_callback: C,
+        parallelism: usize,
+    ) -> Stream<ImportId>
+    where
+        C: 'static + ProgressCallback + Clone,
+    {
+        self.upload_file_chunks_with_retries(
+            session.organization_id(),
+            session.import_id(),
+            &session.path().to_path_buf(),
+            session.files().clone(),
+            progress_callback,
+            parallelism,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    /// Upload a batch of files using the upload service.
+    ///
+    /// Each file is read from `path`, which must be the same directory the
+    /// files were previewed from; if any file can't be found there the stream
+    /// fails before anything is uploaded.
+    pub fn upload_file_chunks<P, C>(
+        &self,
+        organization_id: &OrganizationId,
+        import_id: &ImportId,
+        path: P,
+        files: Vec<model::S3File>,
+        missing_parts: Option<response::FilesMissingParts>,
+        progress_callback: C,
+        parallelism: usize,
+    ) -> Stream<ImportId>
+    where
+        P: 'static + AsRef<Path>,
+        C: 'static + ProgressCallback + Clone,
+    {
+        self.upload_file_chunks_controlled(
+            organization_id,
+            import_id,
+            path,
+            files,
+            missing_parts,
+            progress_callback,
+            Parallelism::fixed(parallelism),
+            CancellationToken::new(),
+        )
+    }
+
+    /// Like `upload_file_chunks`, but the number of chunks sent concurrently
+    /// follows `parallelism`, which is told the outcome of every chunk, and
+    /// the stream fails with `ErrorKind::Cancelled` instead of sending any
+    /// further chunks once `cancellation` is cancelled.
+    #[allow(clippy::too_many_arguments)]
+    fn upload_file_chunks_controlled<P, C>(
+        &self,
+        organization_id: &OrganizationId,
+        import_id: &ImportId,
+        path: P,
+        files: Vec<model::S3File>,
+        missing_parts: Option<response::FilesMissingParts>,
+        progress_callback: C,
+        parallelism: Parallelism,
+        cancellation: CancellationToken,
+    ) -> Stream<ImportId>
+    where
+        P: 'static + AsRef<Path>,
+        C: 'static + ProgressCallback + Clone,
+    {
+        let ps = self.clone();
+        let organization_id = organization_id.clone();
+        let import_id = import_id.clone();
+        let progress_callback = progress_callback.clone();
+
+        let missing_file_names: Option<Vec<String>> = missing_parts
+            .clone()
+            .map(|mp| mp.files.into_iter().map(|f| f.file_name).collect());
+
+        let files: Vec<model::S3File> = files
+            .into_iter()
+            .filter(|file| match &missing_file_names {
+                None => true,
+                Some(mp) => mp.contains(file.file_name()),
+            })
+            .collect();
+
+        // Every file must be found under `path`. If it isn't, the caller most
+        // likely passed a different path than the one used for the preview:
+        for file in &files {
+            let file_path = path.as_ref().join(file.file_name());
+            if !file_path.exists() {
+                return into_stream_trait(stream::once(Err(Error::path_does_not_exist(file_path))));
+            }
+            if !file_path.is_file() {
+                return into_stream_trait(stream::once(Err(Error::path_is_not_a_file(file_path))));
+            }
+        }
+
+        let shutdown = self.inner.lock().unwrap().shutdown.clone();
+        let audit_sink = self.inner.lock().unwrap().upload_audit_sink.clone();
+        let chunk_budget = self.inner.lock().unwrap().chunk_budget.clone();
+        let file_parallelism = self.inner.lock().unwrap().config.file_parallelism();
+        let chunk_size = self.inner.lock().unwrap().config.chunk_size();
+        let idempotency_key_header = self
+            .inner
+            .lock()
+            .unwrap()
+            .config
+            .idempotency_key_header()
+            .cloned();
+
+        let fs = stream::futures_unordered(
+            files
+                .into_iter()
+                .zip(iter::repeat(path.as_ref().to_path_buf()))
+                .map(|file| future::ok::<(model::S3File, PathBuf), Error>(file.clone())),
+        )
+        .map(move |(file, path): (model::S3File, PathBuf)| {
+            // This is only reached when there's room to start another file,
+            // so a shutdown lets the files already started finish:
+            if shutdown.is_cancelled() {
+                return into_stream_trait(stream::once(Err(Error::shutting_down())));
+            }
+
+            let mut file_path = path.clone();
+            let file = file.clone();
+
+            file_path.push(file.file_name());
+            let audited_file_path = file_path.clone();
+            let audited_import_id = import_id.clone();
+            let completed_file_path = file_path.clone();
+            let file_callback = progress_callback.clone();
+
+            progress_callback.on_file_start(&file_path, file.size());
+
+            let file_missing_parts: Option<response::FileMissingParts> = match missing_parts {
+                Some(ref mp) => mp
+                    .files
+                    .iter()
+                    .find(|p| &p.file_name == file.file_name())
+                    .cloned(),
+                None => None,
+            };
+
+            let chunked_file_payload = if let Some(chunked_upload_properties) =
+                file.chunked_upload()
+            {
+                debug!(
+                    "ps:upload_file_chunks<file = {file_name}> :: \
+                     Chunk size received from the upload service: {chunk_size}.",
+                    file_name = file.file_name(),
+                    chunk_size = chunked_upload_properties.chunk_size
+                );
+
+                ChunkedFilePayload::new_with_chunk_size(
+                    import_id.clone(),
+                    file_path,
+                    chunked_upload_properties.chunk_size,
+                    file_missing_parts.as_ref(),
+                )
+            } else if let Some(chunk_size) = chunk_size {
+                debug!(
+                    "ps:upload_file_chunks<file = {file_name}> :: \
+                     No chunk size received from the upload service. \
+                     Using the configured chunk size: {chunk_size}.",
+                    file_name = file.file_name(),
+                    chunk_size = chunk_size
+                );
+                ChunkedFilePayload::new_with_chunk_size(
+                    import_id.clone(),
+                    file_path,
+                    chunk_size,
+                    file_missing_parts.as_ref(),
+                )
+            } else {
+                debug!(
+                    "ps:upload_file_chunks<file = {file_name}> :: \
+                     No chunk size received from the upload service. \
+                     Falling back to default.",
+                    file_name = file.file_name()
+                );
+                ChunkedFilePayload::new(import_id.clone(), file_path, file_missing_parts.as_ref())
+            };
+
+            let ps = ps.clone();
+            let organization_id = organization_id.clone();
+            let import_id = import_id.clone();
+            let progress_callback = progress_callback.clone();
+            let parallelism = parallelism.clone();
+            let chunk_parallelism = parallelism.clone();
+            let cancellation = cancellation.clone();
+            let idempotency_key_header = idempotency_key_header.clone();
+
+            let chunk_uploads = BudgetedChunks::new(chunked_file_payload, chunk_budget.clone())
+                .map(move |(file_chunk, progress_update, permit)| {
+                    let upload = if cancellation.is_cancelled() {
+                        into_future_trait(future::err(Error::cancelled()))
+                    } else if let Some(MultipartUploadId(multipart_upload_id)) =
+                        file.multipart_upload_id()
+                    {
+                        let import_id = import_id.clone();
+                        let import_id_clone = import_id.clone();
+                        let organization_id = organization_id.clone();
+                        let progress_callback = progress_callback.clone();
+
+                        // With an idempotency key, the platform ignores a chunk
+                        // it already has, so the chunk can be retried by itself:
+                        let idempotency_key = idempotency_key_header.clone().and_then(|header| {
+                            chunk_idempotency_key(
+                                &import_id,
+                                file_chunk.chunk_number,
+                                &file_chunk.checksum,
+                            )
+                            .map(|key| (header, key))
+                        });
+                        let retry_on_failure = idempotency_key.is_some();
+
+                        into_future_trait(
+                            ps.request_with_body(
+                                route!(
+                                    "/upload/chunk/organizations/{organization_id}/id/{import_id}",
+                                    organization_id,
+                                    import_id
+                                ),
+                                Method::POST,
+                                params!(
+                                    "filename" => file.file_name().to_string(),
+                                    "multipartId" => multipart_upload_id.to_string(),
+                                    "chunkChecksum" => file_chunk.checksum.0,
+                                    "chunkNumber" => file_chunk.chunk_number.to_string()
+                                ),
+                                file_chunk.bytes,
+                                idempotency_key.into_iter().collect(),
+                                retry_on_failure,
+                            )
+                            .and_then(
+                                move |response: response::UploadResponse| {
+                                    if response.success {
+                                        // Stamped now, as the chunk was read
+                                        // well before it was sent:
+                                        progress_callback.on_update(
+                                            &progress_update
+                                                .clone()
+                                                .timestamped(time::Instant::now()),
+                                        );
+                                        future::ok(import_id_clone)
+                                    } else {
+                                        future::err(Error::upload_error(
+                                            response.error.unwrap_or_else(|| {
+                                                "no error message supplied".into()
+                                            }),
+                                        ))
+                                    }
+                                },
+                            ),
+                        )
+                    } else {
+                        into_future_trait(future::err(Error::upload_error(format!(
+                            "no multipartId was provided for file: {}",
+                            file.file_name()
+                        ))))
+                    };
+                    // The chunk's bytes count against the budget until it's sent:
+                    upload.then(move |result| {
+                        drop(permit);
+                        result
+                    })
+                })
+                .map(move |upload| {
+                    let parallelism = chunk_parallelism.clone();
+                    upload.then(move |result| {
+                        parallelism.on_result(&result);
+                        result
+                    })
+                });
+
+            // The file is complete once every one of its chunks is sent:
+            let completion = future::lazy(move || {
+                file_callback.on_file_complete(&completed_file_path);
+                Ok(None)
+            });
+            let file_uploads = BufferAdaptive::new(chunk_uploads, parallelism)
+                .chain(completion.into_stream().filter_map(|none| none));
+
+            match audit_sink.clone() {
+                None => into_stream_trait(file_uploads),
+                Some(sink) => {
+                    // Record the file once every one of its chunks is sent:
+                    let record = future::lazy(move || {
+                        UploadAuditRecord::for_file(audited_file_path, audited_import_id)
+                            .map(|record| sink.on_file_uploaded(&record))
+                            .map(|_| None)
+                            .map_err(Into::into)
+                    });
+                    into_stream_trait(
+                        file_uploads.chain(record.into_stream().filter_map(|none| none)),
+                    )
+                }
+            }
+        });
+
+        // Only `file_parallelism` files are open and uploading at once, and
+        // every chunk is passed on as soon as it's sent:
+        into_stream_trait(FlattenUnordered::new(fs, file_parallelism))
+    }
+
+    /// Complete an upload to the upload service
+    pub fn complete_upload(
+        &self,
+        organization_id: &OrganizationId,
+        import_id: &ImportId,
+        dataset_id: &DatasetNodeId,
+        destination_id: Option<&PackageId>,
+        append: bool,
+    ) -> Future<response::Manifests> {
+        self.complete_upload_with_options(
+            organization_id,
+            import_id,
+            dataset_id,
+            destination_id,
+            append,
+            &request::CompleteUpload::new(),
+        )
+    }
+
+    /// Complete an upload to the upload service, choosing how the uploaded
+    /// files are processed.
+    pub fn complete_upload_with_options(
+        &self,
+        organization_id: &OrganizationId,
+        import_id: &ImportId,
+        dataset_id: &DatasetNodeId,
+        destination_id: Option<&PackageId>,
+        append: bool,
+        options: &request::CompleteUpload,
+    ) -> Future<response::Manifests> {
+        let mut params = params!(
+            "datasetId" => dataset_id,
+            "append" => if append { "true" } else { "false" }
+        );
+        if let Some(dest_id) = destination_id {
+            params.push(param!("destinationId", dest_id.clone()));
+        }
+
+        // An upload completed the default way is sent without a body, as
+        // it always has been:
+        let payload = Some(options).filter(|options| !options.is_default());
+
+        self.request(
+            route!(
+                "/upload/complete/organizations/{organization_id}/id/{import_id}",
+                organization_id,
+                import_id
+            ),
+            Method::POST,
+            params,
+            payload,
+        )
+    }
+
+    /// Append files to an existing package: preview them as an append,
+    /// upload their chunks, and complete the upload into `package_id`.
+    ///
+    /// Each of `files` is a path to a file, read from wherever it is, so
+    /// the files needn't share a directory, but no two may share a name.
+    /// As with `upload_file_chunks`, the configured `file_parallelism`
+    /// bounds the files uploaded at once.
+    /// Resolves to the manifests of every import the files were split into.
+    pub fn append_to_package<P, C>(
+        &self,
+        organization_id: &OrganizationId,
+        dataset_id: &DatasetNodeId,
+        package_id: &PackageId,
+        files: Vec<P>,
+        progress_callback: C,
+        parallelism: usize,
+    ) -> Future<response::Manifests>
+    where
+        P: AsRef<Path>,
+        C: 'static + ProgressCallback + Clone,
+    {
+        // Uploaded files are identified by name, so each name has to lead
+        // back to one directory to read the file from:
+        let mut source_dirs: HashMap<String, PathBuf> = HashMap::new();
+        for file in &files {
+            let file = file.as_ref();
+            let name = match file.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_string(),
+                None => {
+                    return into_future_trait(future::err(Error::invalid_unicode_path(
+                        file.to_path_buf(),
+                    )));
+                }
+            };
+            let dir = file.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+            if source_dirs.insert(name.clone(), dir).is_some() {
+                return into_future_trait(future::err(Error::invalid_arguments(format!(
+                    "more than one file named {:?} can't be appended at once",
+                    name
+                ))));
+            }
+        }
+        let files: Vec<(UploadId, PathBuf)> = files
+            .iter()
+            .enumerate()
+            .map(|(id, file)| (UploadId::from(id as u64), file.as_ref().to_path_buf()))
+            .collect();
+
+        let ps = self.clone();
+        let organization_id = organization_id.clone();
+        let dataset_id = dataset_id.clone();
+        let package_id = package_id.clone();
+        let file_parallelism = self.inner.lock().unwrap().config.file_parallelism();
+
+        let f = self
+            .get_dataset_by_id(dataset_id.clone())
+            .and_then({
+                let ps = ps.clone();
+                let organization_id = organization_id.clone();
+                move |dataset| {
+                    ps.preview_upload(
+                        &organization_id,
+                        dataset.int_id(),
+                        None::<&Path>,
+                        &files,
+                        true,
+                        false,
+                    )
+                }
+            })
+            .and_then(move |preview| {
+                stream::iter_ok(preview)
+                    .and_then(move |package| {
+                        let import_id = package.import_id().clone();
+                        let uploads = package
+                            .files()
+                            .iter()
+                            .map(|file| match source_dirs.get(file.file_name()) {
+                                Some(dir) => ps.upload_file_chunks_with_retries(
+                                    &organization_id,
+                                    &import_id,
+                                    dir,
+                                    vec![file.clone()],
+                                    progress_callback.clone(),
+                                    parallelism,
+                                ),
+                                None => into_stream_trait(stream::once(Err(Error::upload_error(
+                                    format!(
+                                        "the preview named a file that wasn't appended: {}",
+                                        file.file_name()
+                                    ),
+                                )))),
+                            })
+                            .collect::<Vec<_>>();
+
+                        let ps = ps.clone();
+                        let organization_id = organization_id.clone();
+                        let dataset_id = dataset_id.clone();
+                        let package_id = package_id.clone();
+                        FlattenUnordered::new(
+                            stream::iter_ok::<_, Error>(uploads),
+                            file_parallelism,
+                        )
+                        .collect()
+                        .and_then(move |_| {
+                            ps.complete_upload(
+                                &organization_id,
+                                &import_id,
+                                &dataset_id,
+                                Some(&package_id),
+                                true,
+                            )
+                        })
+                    })
+                    .map(stream::iter_ok)
+                    .flatten()
+                    .collect()
+                    .map(|entries| entries.into_iter().collect())
+            });
+
+        into_future_trait(f)
+    }
+
+    /// Get the upload status using the upload service
+    pub fn get_upload_status(
+        &self,
+        organization_id: &OrganizationId,
+        import_id: &ImportId,
+    ) -> Future<Option<response::FilesMissingParts>> {
+        get!(
+            self,
+            route!(
+                "/upload/status/organizations/{organization_id}/id/{import_id}",
+                organization_id,
+                import_id
+            )
+        )
+    }
+
+    /// Get the upload statuses of many imports at once, keyed by import ID.
+    /// The statuses are requested concurrently, a few at a time.
+    pub fn get_upload_statuses(
+        &self,
+        organization_id: &OrganizationId,
+        import_ids: Vec<ImportId>,
+    ) -> Future<HashMap<ImportId, Option<response::FilesMissingParts>>> {
+        let ps = self.clone();
+        let organization_id = organization_id.clone();
+        let statuses = stream::iter_ok(import_ids)
+            .map(move |import_id| {
+                ps.get_upload_status(&organization_id, &import_id)
+                    .map(|status| (import_id, status))
+            })
+            .buffer_unordered(UPLOAD_STATUS_PARALLELISM)
+            .collect()
+            .map(|statuses| statuses.into_iter().collect());
+
+        into_future_trait(statuses)
+    }
+
+    /// Follow the progress of an upload as the upload service reports it,
+    /// until it completes or fails.
+    ///
+    /// Events are streamed from the platform where it offers them. Where
+    /// it doesn't, the upload's status is polled instead, and an event is
+    /// yielded whenever it changes.
+    pub fn watch_upload(
+        &self,
+        organization_id: &OrganizationId,
+        import_id: &ImportId,
+    ) -> Stream<model::UploadEvent> {
+        let mut url = self.get_url();
+        url.set_path(&route!(
+            "/upload/events/organizations/{organization_id}/id/{import_id}",
+            organization_id,
+            import_id
+        ));
+
+        let ps = self.clone();
+        let organization_id = organization_id.clone();
+        let import_id = import_id.clone();
+
+        let this = self.clone();
+        let f = self
+            .ensure_fresh_session()
+            .and_then(move |_| {
+                let uri = url.as_str().parse::<hyper::Uri>()?;
+                let mut req = hyper::Request::get(uri)
+                    .header(hyper::header::ACCEPT, "text/event-stream")
+                    .body(hyper::Body::empty())
+                    .unwrap();
+                let context = this.request_context()?;
+                context.prepare(&mut req, true);
+                let timeout = this.inner.lock().unwrap().config.request_timeout().cloned();
+                Ok(with_timeout(context.send(req), timeout))
+            })
+            .flatten()
+            // The stream of events is open for as long as the upload runs,
+            // so it stops counting against the limit on requests in flight
+            // once it's been opened:
+            .map(|(response, _permit)| response)
+            .and_then(move |response| {
+                let status_code = response.status();
+                let is_event_stream = response
+                    .headers()
+                    .get(hyper::header::CONTENT_TYPE)
+                    .and_then(|content_type| content_type.to_str().ok())
+                    .filter(|content_type| content_type.starts_with("text/event-stream"))
+                    .is_some();
+
+                if status_code.is_success() && is_event_stream {
+                    into_future_trait(future::ok(into_stream_trait(events::UploadEvents::new(
+                        response.into_body(),
+                    ))))
+                } else if status_code.is_success()
+                    || status_code == StatusCode::NOT_FOUND
+                    || status_code == StatusCode::METHOD_NOT_ALLOWED
+                    || status_code == StatusCode::NOT_IMPLEMENTED
+                {
+                    debug!(
+                        "ps:watch_upload<{import_id}>: no event stream ({status}), polling",
+                        import_id = import_id,
+                        status = status_code
+                    );
+                    into_future_trait(future::ok(
+                        ps.poll_upload_events(organization_id, import_id),
+                    ))
+                } else {
+                    into_future_trait(response.into_body().concat2().map_err(Into::into).and_then(
+                        move |body| {
+                            Err(Error::api_error(
+                                status_code,
+                                String::from_utf8_lossy(&body),
+                            ))
+                        },
+                    ))
+                }
+            });
+
+        into_stream_trait(f.flatten_stream())
+    }
+
+    /// The events of an upload, found by polling its status every
+    /// `UPLOAD_STATUS_POLL_INTERVAL`. Only changes are yielded.
+    fn poll_upload_events(
+        &self,
+        organization_id: OrganizationId,
+        import_id: ImportId,
+    ) -> Stream<model::UploadEvent> {
+        let ps = self.clone();
+        let polls = stream::unfold(
+            (None, true),
+            move |(last, first): (Option<model::UploadEvent>, bool)| {
+                if matches!(last, Some(ref event) if event.is_final()) {
+                    return None;
+                }
+                let wait = if first {
+                    into_future_trait(future::ok(()))
+                } else {
+                    let deadline = time::Instant::now() + UPLOAD_STATUS_POLL_INTERVAL;
+                    into_future_trait(tokio::timer::Delay::new(deadline).map_err(Into::into))
+                };
+                let ps = ps.clone();
+                let organization_id = organization_id.clone();
+                let import_id = import_id.clone();
+                Some(
+                    wait.and_then(move |_| ps.get_upload_status(&organization_id, &import_id))
+                        .map(move |status| {
+                            let event = upload_event_from_status(status);
+                            let changed = Some(&event) != last.as_ref();
+                            (
+                                Some(event.clone()).filter(|_| changed),
+                                (Some(event), false),
+                            )
+                        }),
+                )
+            },
+        )
+        .filter_map(|event| event);
+
+        into_stream_trait(polls)
+    }
+
+    /// Get the status of the ETL processing of an upload. Processing
+    /// starts once the upload is completed and carries on asynchronously,
+    /// so poll this until the status is terminal to wait for it.
+    pub fn get_manifest_status(&self, import_id: &ImportId) -> Future<response::ManifestStatus> {
+        get!(self, route!("/upload/manifest/id/{import_id}", import_id))
+    }
+
+    /// Get the hash of an uploaded file from the upload service
+    pub fn get_upload_hash<S>(
+        &self,
+        import_id: &ImportId,
+        file_name: S,
+    ) -> Future<response::FileHash>
+    where
+        S: Into<String>,
+    {
+        get!(
+            self,
+            route!("/upload/hash/id/{import_id}", import_id),
+            params!("fileName" => file_name)
+        )
+    }
+
+    /// Verify that a file was uploaded intact, by comparing the hash of the
+    /// local file at `path` against the hash the upload service computed
+    /// for the file of the same name in the import. Meant to be called once
+    /// every chunk of the file has been sent. Fails with an upload error
+    /// carrying both hashes if they differ.
+    pub fn verify_upload<P>(&self, import_id: &ImportId, path: P) -> Future<Checksum>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        let file_name = match path.file_name() {
+            Some(file_name) => file_name.to_string_lossy().to_string(),
+            None => {
+                return into_future_trait(future::err(Error::invalid_arguments(format!(
+                    "not a file: {:?}",
+                    path
+                ))))
+            }
+        };
+
+        let f = self
+            .get_upload_hash(import_id, file_name.clone())
+            .and_then(move |remote| {
+                let local = response::FileHash::ALGORITHM.checksum_of_file(&path)?;
+                if local.matches(&remote.hash) {
+                    Ok(local)
+                } else {
+                    Err(Error::upload_error(format!(
+                        "{} hash mismatch for {}: local {}, uploaded {}",
+                        response::FileHash::ALGORITHM,
+                        file_name,
+                        local.0,
+                        remote.hash
+                    )))
+                }
+            });
+
+        into_future_trait(f)
+    }
+
+    /// Upload a batch of files using the upload service, retrying on
+    /// failure, and stream the resulting progress updates.
+    ///
+    /// The returned stream drives the upload itself: it yields every
+    /// progress update as parts complete, and finishes once the upload
+    /// does.
+    pub fn upload_file_chunks_with_progress_stream<P>(
+        &self,
+        organization_id: &OrganizationId,
+        import_id: &ImportId,
+        path: &P,
+        files: Vec<model::S3File>,
+        parallelism: usize,
+    ) -> Stream<ProgressUpdate>
+    where
+        P: 'static + AsRef<Path> + Send,
+    {
+        let (sender, mut receiver) = futures::sync::mpsc::unbounded();
+        let mut upload = self.upload_file_chunks_with_retries(
+            organization_id,
+            import_id,
+            path,
+            files,
+            ProgressSender::new(sender),
+            parallelism,
+        );
+        let mut upload_done = false;
+
+        // Progress updates are sent from within `upload.poll()`, so every
+        // update has been queued by the time the upload completes:
+        let progress = stream::poll_fn(move || loop {
+            if let Ok(Async::Ready(Some(update))) = receiver.poll() {
+                return Ok(Async::Ready(Some(update)));
+            }
+            if upload_done {
+                return Ok(Async::Ready(None));
+            }
+            match upload.poll()? {
+                Async::Ready(Some(_)) => continue,
+                Async::Ready(None) => upload_done = true,
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        });
+
+        into_stream_trait(progress)
+    }
+
+    /// Upload a batch of files using the upload service, resuming the
+    /// upload from its missing parts whenever it fails.
+    ///
+    /// If the config has adaptive parallelism bounds, `parallelism` is only
+    /// the starting point: it shrinks when chunks are throttled and grows
+    /// back while they succeed, for the whole upload including its retries.
+    pub fn upload_file_chunks_with_retries<P, C>(
+        &self,
+        organization_id: &OrganizationId,
+        import_id: &ImportId,
+        path: &P,
+        files: Vec<model::S3File>,
+        progress_callback: C,
+        parallelism: usize,
+    ) -> Stream<ImportId>
+    where
+        P: 'static + AsRef<Path> + Send,
+        C: 'static + ProgressCallback + Clone,
+    {
+        self.upload_file_chunks_with_retries_controlled(
+            organization_id,
+            import_id,
+            path,
+            files,
+            progress_callback,
+            self.upload_parallelism(parallelism),
+            CancellationToken::new(),
+        )
+    }
+
+    /// Like `upload_file_chunks_with_retries`, but the upload can be stopped
+    /// by calling `cancel()` on `cancellation` (or any clone of it) from
+    /// another thread. Once cancelled, no further chunks are sent and the
+    /// stream fails with `ErrorKind::Cancelled`; the upload isn't retried.
+    #[allow(clippy::too_many_arguments)]
+    pub fn upload_file_chunks_cancellable<P, C>(
+        &self,
+        organization_id: &OrganizationId,
+        import_id: &ImportId,
+        path: &P,
+        files: Vec<model::S3File>,
+        progress_callback: C,
+        parallelism: usize,
+        cancellation: CancellationToken,
+    ) -> Stream<ImportId>
+    where
+        P: 'static + AsRef<Path> + Send,
+        C: 'static + ProgressCallback + Clone,
+    {
+        self.upload_file_chunks_with_retries_controlled(
+            organization_id,
+            import_id,
+            path,
+            files,
+            progress_callback,
+            self.upload_parallelism(parallelism),
+            cancellation,
+        )
+    }
+
+    /// The parallelism an upload starting at `parallelism` should use: fixed,
+    /// or adaptive if the config has adaptive parallelism bounds.
+    fn upload_parallelism(&self, parallelism: usize) -> Parallelism {
+        match self.inner.lock().unwrap().config.adaptive_parallelism() {
+            Some(bounds) => Parallelism::adaptive(parallelism, *bounds),
+            None => Parallelism::fixed(parallelism),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn upload_file_chunks_with_retries_controlled<P, C>(
+        &self,
+        organization_id: &OrganizationId,
+        import_id: &ImportId,
+        path: &P,
+        files: Vec<model::S3File>,
+        progress_callback: C,
+        parallelism: Parallelism,
+        cancellation: CancellationToken,
+    ) -> Stream<ImportId>
+    where
+        P: 'static + AsRef<Path> + Send,
+        C: 'static + ProgressCallback + Clone,
+    {
+        #[derive(Clone)]
+        struct LoopDependencies<C: ProgressCallback + Clone> {
+            organization_id: OrganizationId,
+            import_id: ImportId,
+            path: PathBuf,
+            files: Vec<model::S3File>,
+            missing_parts: Option<response::FilesMissingParts>,
+            result: Option<Vec<ImportId>>,
+            progress_callback: C,
+            try_num: usize,
+            ps: Pennsieve,
+            parallelism: Parallelism,
+            cancellation: CancellationToken,
+            retrier: Retrier,
+        }
+
+        impl<C: ProgressCallback + Clone> LoopDependencies<C> {
+            pub fn increment_attempt_count(self) -> Self {
+                Self {
+                    organization_id: self.organization_id,
+                    import_id: self.import_id,
+                    path: self.path,
+                    files: self.files,
+                    missing_parts: self.missing_parts,
+                    result: self.result,
+                    progress_callback: self.progress_callback,
+                    try_num: self.try_num + 1,
+                    ps: self.ps,
+                    parallelism: self.parallelism,
+                    cancellation: self.cancellation,
+                    retrier: self.retrier,
+                }
+            }
+        }
+
+        let ld = LoopDependencies {
+            organization_id: organization_id.clone(),
+            import_id: import_id.clone(),
+            path: path.as_ref().to_path_buf(),
+            files,
+            missing_parts: None,
+            result: None,
+            progress_callback,
+            try_num: 0,
+            ps: self.clone(),
+            parallelism,
+            cancellation,
+            retrier: Retrier::new(self.inner.lock().unwrap().config.retry_policy().clone()),
+        };
+
+        let retry_loop = future::loop_fn(ld, |mut ld| {
+
+            let ld_err = ld.clone();
+
+            ld.ps
+                .get_upload_status(&ld.organization_id, &ld.import_id)
+                .map(|parts| {
+                    ld.missing_parts = parts;
+                    ld
+                })
+                .and_then(|ld| {
+                    ld.ps
+                        .upload_file_chunks_controlled(
+                            &ld.organization_id,
+                            &ld.import_id,
+                            ld.path.clone(),
+                            ld.files.clone(),
+                            ld.missing_parts.clone(),
+                            ld.progress_callback.clone(),
+                            ld.parallelism.clone(),
+                            ld.cancellation.clone(),
+                        )
+                        .collect()
+                        .map(future::Loop::Break)
+                })
+                .into_future()
+                .or_else(move |err| {
+
+                    debug!("Upload encountered an error: {error}", error = err);
+                    match ld_err.retrier.backoff(ld_err.try_num, retry::error_is_retryable(&err)) {
+                        // resume the upload from its missing parts once the
+                        // backoff has elapsed
+                        Some(wait) => {
+                            ld_err.progress_callback.on_retry(
+                                ld_err.try_num + 1,
+                                ld_err.retrier.policy().max_retries(),
+                                &err,
+                            );
+                            let continue_loop = wait.map(move |_| {
+                                debug!(
+                                    "Attempting to resume missing parts. Attempt {try_num}/{retries})...",
+                                    try_num = ld_err.try_num + 1,
+                                    retries = ld_err.retrier.policy().max_retries()
+                                );
+                                future::Loop::Continue(ld_err.increment_attempt_count())
+                            });
+                            into_future_trait(continue_loop)
+                        }
+
+                        // the error cannot be retried, or the maximum number
+                        // of retries has been exceeded: bubble up the error
+                        None => {
+                            error!("Upload failed. Bubbling up error {error}", error = err);
+                            into_future_trait(future::err(err))
+                        }
+                    }
+                })
+        })
+        .map(|import_ids| {
+            future::ok::<Stream<ImportId>, Error>(
+                into_stream_trait(stream::futures_unordered(
+                    import_ids
+                        .iter()
+                        .map(|import_id| future::ok(import_id.clone())),
+                )),
+            )
+                .into_stream()
+            .flatten()
+        })
+        .into_stream()
+        .flatten();
+
+        into_stream_trait(retry_loop)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::retry::retry_delay;
+    use super::*;
+    use std::collections::HashSet;
+    use std::{fs, path, result, sync};
+
+    use lazy_static::lazy_static;
+    use mockito::mock;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    // use ps::api::{PSChildren, PSId, PSName};
+    use crate::ps::config::{Environment, ParallelismBounds};
+    use crate::ps::util::futures::into_future_trait;
+    use crate::ps::util::rand_suffix;
+
+    const TEST_ENVIRONMENT: Environment = Environment::NonProduction;
+    const TEST_API_KEY: &str = env!("PENNSIEVE_API_KEY");
+    const TEST_SECRET_KEY: &str = env!("PENNSIEVE_SECRET_KEY");
+
+    // "Agent Testing"
+    const FIXTURE_ORGANIZATION: &str = "N:organization:713eeb6e-c42c-445d-8a60-818c741ea87a";
+
+    // Dedicated agent email
+    #[allow(dead_code)]
+    const FIXTURE_EMAIL: &str = "agent-test@pennsieve.com";
+
+    // // Dedicated agent user
+    // #[allow(dead_code)]
+    // const FIXTURE_USER: &str = "N:user:6caa1955-c39e-4198-83c6-aa8fe3afbe93";
+
+    const FIXTURE_DATASET: &str = "N:dataset:e5902b32-7954-463b-bb4c-2c9cf5b3bcfb";
+    const FIXTURE_DATASET_NAME: &str = "AGENT-FIXTURE";
+
+    const FIXTURE_PACKAGE: &str = "N:collection:c602852e-3cc0-4b24-a68a-dd84045dfa47";
+    const FIXTURE_PACKAGE_NAME: &str = "AGENT-TEST-PACKAGE";
+
+    lazy_static! {
+        static ref CONFIG: Config = Config::new(TEST_ENVIRONMENT);
+        static ref TEST_FILES: Vec<String> = test_data_files("/small");
+        static ref TEST_DATA_DIR: String = test_data_dir("/small");
+        pub static ref BIG_TEST_FILES: Vec<String> = test_data_files("/big");
+        pub static ref BIG_TEST_DATA_DIR: String = test_data_dir("/big");
+        pub static ref MEDIUM_TEST_FILES: Vec<String> = test_data_files("/medium");
+        pub static ref MEDIUM_TEST_DATA_DIR: String = test_data_dir("/medium");
+    }
+
+    /// given a 'runner' function, run the given Pennsieve instance
+    /// through that function and block until completion
+    fn run<F, T>(ps: &Pennsieve, runner: F) -> Result<T>
+    where
+        F: Fn(Pennsieve) -> Future<T>,
+        T: 'static + Send,
+    {
+        let mut rt = tokio::runtime::Runtime::new()?;
+        let result = rt.block_on(runner(ps.clone()));
+        rt.shutdown_on_idle();
+        result
+    }
+
+    struct Inner(sync::Mutex<bool>);
+
+    impl Inner {
+        pub fn new() -> Self {
+            Inner(sync::Mutex::new(false))
+        }
+    }
+
+    pub struct ProgressIndicator {
+        inner: sync::Arc<Inner>,
+    }
+
+    impl Clone for ProgressIndicator {
+        fn clone(&self) -> Self {
+            Self {
+                inner: Arc::clone(&self.inner),
+            }
+        }
+    }
+
+    impl ProgressIndicator {
+        pub fn new() -> Self {
+            Self {
+                inner: sync::Arc::new(Inner::new()),
+            }
+        }
+    }
+
+    impl ProgressCallback for ProgressIndicator {
+        fn on_update(&self, _update: &ProgressUpdate) {
+            *self.inner.0.lock().unwrap() = true;
+        }
+    }
+
+    fn ps() -> Pennsieve {
+        Pennsieve::new((*CONFIG).clone())
+    }
+
+    // Returns the test data directory `<project>/data/<data_dir>`:
+    fn test_data_dir(data_dir: &str) -> String {
+        concat!(env!("CARGO_MANIFEST_DIR"), "/test/data").to_string() + data_dir
+    }
+
+    // Returns a `Vec<String>` of test data filenames taken from the specified
+    // test data directory:
+    fn test_data_files(data_dir: &str) -> Vec<String> {
+        match fs::read_dir(test_data_dir(data_dir)) {
             Ok(entries) => entries
                 .map(|entry| entry.unwrap().file_name().into_string().clone())
                 .collect::<result::Result<Vec<_>, _>>()
                 .unwrap(),
-            Err(e) => {
-                eprintln!("{:?} :: {:?}", data_dir, e);
-                vec![]
+            Err(e) => {
+                eprintln!("{:?} :: {:?}", data_dir, e);
+                vec![]
+            }
+        }
+    }
+
+    fn add_upload_ids(file_paths: &Vec<String>) -> Vec<(UploadId, String)> {
+        file_paths
+            .iter()
+            .enumerate()
+            .map(|(id, file)| (UploadId::from(id as u64), file.to_string()))
+            .collect()
+    }
+
+    // Mocks the upload service's status endpoint, with no part of any file
+    // uploaded yet:
+    fn mock_upload_status() -> mockito::Mock {
+        mock(
+            "GET",
+            mockito::Matcher::Regex(r"^/upload/status/organizations/.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body("null")
+        .create()
+    }
+
+    // The upload service's response to a chunk it accepted:
+    const CHUNK_UPLOADED: &str = r#"{ "success": true, "error": null }"#;
+
+    // A mock of the upload service's chunk endpoint answering every chunk
+    // with `status` and `body`, to be created once any expectations are set:
+    fn upload_chunk_mock(status: usize, body: &str) -> mockito::Mock {
+        mock(
+            "POST",
+            mockito::Matcher::Regex(r"^/upload/chunk/organizations/.*$".to_string()),
+        )
+        .with_status(status)
+        .with_body(body)
+    }
+
+    // The file `name` in `data_dir`, ready to be uploaded in parts:
+    fn multipart_file(data_dir: &str, name: &str, chunk_size: Option<u64>) -> model::S3File {
+        model::S3File::from_file_path(format!("{}/{}", data_dir, name), None, None)
+            .unwrap()
+            .with_chunk_size(chunk_size)
+            .with_multipart_upload_id(Some(MultipartUploadId("multipart".to_string())))
+    }
+
+    #[test]
+    fn default_retry_policy_backs_off_exponentially_up_to_30s() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.max_retries(), 20);
+        assert!(policy.is_retryable(StatusCode::TOO_MANY_REQUESTS, &Method::POST));
+        assert!(policy.is_retryable(StatusCode::BAD_GATEWAY, &Method::GET));
+        assert!(!policy.is_retryable(StatusCode::BAD_GATEWAY, &Method::POST));
+        assert!(!policy.is_retryable(StatusCode::NOT_FOUND, &Method::GET));
+        assert_eq!(policy.max_delay_ms(), Some(30_000));
+        assert_eq!(CONFIG.retry_policy(), &policy);
+    }
+
+    #[test]
+    fn bodies_are_only_logged_when_enabled() {
+        assert!(!CONFIG.log_bodies());
+        assert!(Config::new(TEST_ENVIRONMENT)
+            .with_log_bodies(true)
+            .log_bodies());
+
+        let body = hyper::Chunk::from(r#"{ "session_token": "secret" }"#);
+        assert_eq!(Pennsieve::loggable_body(&body, false), "<29 bytes>");
+        assert_eq!(
+            Pennsieve::loggable_body(&body, true),
+            r#"{ "session_token": "secret" }"#
+        );
+    }
+
+    #[test]
+    fn custom_retry_policy_is_respected() {
+        let policy = RetryPolicy::new(3, 100)
+            .with_max_delay_ms(250)
+            .with_retryable_status_code(StatusCode::INTERNAL_SERVER_ERROR, vec![Method::GET])
+            .without_retryable_status_code(StatusCode::TOO_MANY_REQUESTS);
+
+        assert_eq!(policy.max_retries(), 3);
+        assert!(policy.is_retryable(StatusCode::INTERNAL_SERVER_ERROR, &Method::GET));
+        assert!(!policy.is_retryable(StatusCode::TOO_MANY_REQUESTS, &Method::GET));
+        let mut rng = StdRng::seed_from_u64(7);
+        for try_num in 0..20 {
+            assert!(retry_delay(try_num, &policy, &mut rng) <= 250);
+        }
+
+        let config = Config::new(TEST_ENVIRONMENT).with_retry_policy(policy.clone());
+        assert_eq!(config.retry_policy(), &policy);
+    }
+
+    #[test]
+    fn retry_delay_is_jittered_exponential_backoff() {
+        let policy = RetryPolicy::new(20, 100).with_max_delay_ms(5_000);
+        let mut rng = StdRng::seed_from_u64(42);
+
+        for &(try_num, upper_bound) in &[(0, 100), (1, 200), (3, 800), (5, 3_200), (6, 5_000)] {
+            let delays: Vec<u64> = (0..100)
+                .map(|_| retry_delay(try_num, &policy, &mut rng))
+                .collect();
+            assert!(delays.iter().all(|&delay| delay <= upper_bound));
+            // full jitter spreads the delays out rather than pinning them
+            // to the upper bound:
+            assert!(delays.iter().any(|&delay| delay < upper_bound / 2));
+        }
+
+        // absurdly large attempt numbers saturate at the cap instead of
+        // overflowing:
+        assert!(retry_delay(200, &policy, &mut rng) <= 5_000);
+    }
+
+    #[test]
+    fn retry_delay_is_deterministic_for_a_seeded_rng() {
+        let policy = RetryPolicy::default();
+        let delays = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..10)
+                .map(|try_num| retry_delay(try_num, &policy, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(delays(1), delays(1));
+    }
+
+    #[test]
+    fn login_successfully_locally() {
+        let ps = ps();
+        let result = run(&ps, move |ps| ps.login(TEST_API_KEY, TEST_SECRET_KEY));
+        assert!(result.is_ok());
+        assert!(ps.session_token().is_some());
+    }
+
+    #[test]
+    fn login_fails_locally() {
+        let ps = ps();
+        let result = run(&ps, move |ps| {
+            ps.login(TEST_API_KEY, "this-is-a-bad-secret")
+        });
+        assert!(result.is_err());
+        assert!(ps.session_token().is_none());
+    }
+
+    #[test]
+    fn login_with_retry_successfully() {
+        let ps = ps();
+        let result = run(&ps, move |ps| {
+            ps.login_with_retry(
+                TEST_API_KEY,
+                TEST_SECRET_KEY,
+                3,
+                time::Duration::from_millis(100),
+            )
+        });
+        assert!(result.is_ok());
+        assert!(ps.session_token().is_some());
+    }
+
+    #[test]
+    fn login_with_retry_fails_fast_with_bad_credentials() {
+        let ps = ps();
+        let started = time::Instant::now();
+        let result = run(&ps, move |ps| {
+            ps.login_with_retry(
+                TEST_API_KEY,
+                "this-is-a-bad-secret",
+                5,
+                time::Duration::from_secs(10),
+            )
+        });
+        match result.map_err(|err| err.kind().clone()) {
+            Err(ErrorKind::InitiateAuthError { .. }) => (),
+            other => panic!("expected an authentication error, got {:?}", other),
+        }
+        assert!(started.elapsed() < time::Duration::from_secs(10));
+        assert!(ps.session_token().is_none());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn login_with_retry_waits_out_an_unavailable_platform() {
+        // Only `login_with_retry` retries here, not each request:
+        let ps = Pennsieve::new(CONFIG.clone().with_retry_policy(RetryPolicy::new(0, 1)));
+
+        let unavailable = mock("GET", "/authentication/cognito-config")
+            .with_status(503)
+            .expect(2)
+            .create();
+        // Served once the platform is back, failing the login at the next
+        // step without a trip to Cognito:
+        let available = mock("GET", "/authentication/cognito-config")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let result = run(&ps, move |ps| {
+            ps.login_with_retry(
+                TEST_API_KEY,
+                TEST_SECRET_KEY,
+                5,
+                time::Duration::from_millis(1),
+            )
+        });
+
+        unavailable.assert();
+        available.assert();
+        assert_eq!(
+            result.unwrap_err().kind(),
+            crate::ps::Error::initiate_auth_error(
+                "Pennsieve server Cognito config missing token pool."
+            )
+            .kind()
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn login_with_retry_gives_up_after_max_attempts() {
+        let ps = Pennsieve::new(CONFIG.clone().with_retry_policy(RetryPolicy::new(0, 1)));
+
+        let unavailable = mock("GET", "/authentication/cognito-config")
+            .with_status(502)
+            .expect(3)
+            .create();
+
+        let result = run(&ps, move |ps| {
+            ps.login_with_retry(
+                TEST_API_KEY,
+                TEST_SECRET_KEY,
+                3,
+                time::Duration::from_millis(1),
+            )
+        });
+
+        unavailable.assert();
+        match result.map_err(|err| err.kind().clone()) {
+            Err(ErrorKind::ApiError { status_code, .. }) => {
+                assert_eq!(status_code, StatusCode::BAD_GATEWAY)
+            }
+            other => panic!("expected an api error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn login_with_retry_does_not_retry_rejected_requests() {
+        let ps = ps();
+
+        let forbidden = mock("GET", "/authentication/cognito-config")
+            .with_status(403)
+            .expect(1)
+            .create();
+
+        let result = run(&ps, move |ps| {
+            ps.login_with_retry(
+                TEST_API_KEY,
+                TEST_SECRET_KEY,
+                5,
+                time::Duration::from_millis(1),
+            )
+        });
+
+        forbidden.assert();
+        assert!(result.is_err());
+        assert!(ps.session_token().is_none());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn login_returns_error_when_no_token_pool_config_present() {
+        let ps = ps();
+
+        let _mock = mock("GET", "/authentication/cognito-config")
+            .with_status(200)
+            .with_body("{}")
+            .create();
+
+        let result = run(&ps, move |ps| ps.login(TEST_API_KEY, TEST_SECRET_KEY));
+
+        assert!(result.is_err());
+        assert!(ps.session_token().is_none());
+
+        if let Err(error) = result {
+            assert_eq!(
+                error.kind(),
+                crate::ps::Error::initiate_auth_error(
+                    "Pennsieve server Cognito config missing token pool."
+                )
+                .kind()
+            );
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn login_returns_error_when_no_token_pool_client_id_config_present() {
+        let ps = ps();
+        let body = " { \"tokenPool\": {} } ";
+
+        let _mock = mock("GET", "/authentication/cognito-config")
+            .with_status(200)
+            .with_body(body)
+            .create();
+
+        let result = run(&ps, move |ps| ps.login(TEST_API_KEY, TEST_SECRET_KEY));
+
+        assert!(result.is_err());
+        assert!(ps.session_token().is_none());
+
+        if let Err(error) = result {
+            assert_eq!(
+                error.kind(),
+                crate::ps::Error::initiate_auth_error(
+                    "Pennsieve server Cognito config missing token pool client id."
+                )
+                .kind()
+            );
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn login_returns_error_when_token_pool_client_id_is_not_a_string() {
+        let ps = ps();
+        let body = " { \"tokenPool\": { \"appClientId\": [] } } ";
+
+        let _mock = mock("GET", "/authentication/cognito-config")
+            .with_status(200)
+            .with_body(body)
+            .create();
+
+        let result = run(&ps, move |ps| ps.login(TEST_API_KEY, TEST_SECRET_KEY));
+
+        assert!(result.is_err());
+        assert!(ps.session_token().is_none());
+
+        if let Err(error) = result {
+            assert_eq!(
+                error.kind(),
+                crate::ps::Error::initiate_auth_error(
+                    "Pennsieve server Cognito config token pool client id could not be interpreted as a string."
+                )
+                    .kind()
+            );
+        }
+    }
+
+    #[test]
+    fn custom_environment_uses_the_given_url() {
+        let url = "https://api.staging.example.com/"
+            .parse::<url::Url>()
+            .unwrap();
+        let config = Config::new(Environment::Custom(url.clone()));
+        assert_eq!(config.api_url(), url);
+
+        let parsed = "https://api.staging.example.com"
+            .parse::<Environment>()
+            .unwrap();
+        assert_eq!(parsed, Environment::Custom(url.clone()));
+        assert_eq!(parsed.to_string().parse::<Environment>().unwrap(), parsed);
+
+        for invalid in &["custom", "ftp://api.staging.example.com", "staging"] {
+            match invalid
+                .parse::<Environment>()
+                .map_err(|err| err.kind().clone())
+            {
+                Err(ErrorKind::EnvParseError { .. }) => {}
+                other => panic!("expected {} to be rejected, got {:?}", invalid, other),
+            }
+        }
+        assert!("custom"
+            .parse::<Environment>()
+            .unwrap_err()
+            .to_string()
+            .contains("API URL"));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn requests_slower_than_the_timeout_fail() {
+        let slow = mock("GET", "/health")
+            .with_status(200)
+            .with_body_from_fn(|body| {
+                std::thread::sleep(time::Duration::from_millis(500));
+                body.write_all(b"ok")
+            })
+            .expect(1)
+            .create();
+
+        let ps = Pennsieve::new(
+            CONFIG
+                .clone()
+                .with_timeout(time::Duration::from_millis(100)),
+        );
+        let err = run(&ps, move |ps| ps.ping()).unwrap_err();
+
+        slow.assert();
+        match err.kind() {
+            ErrorKind::TimeoutError { timeout } => {
+                assert_eq!(*timeout, time::Duration::from_millis(100))
+            }
+            kind => panic!("expected a timeout, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn request_timeout_is_preserved_when_changing_environment() {
+        let ps = Pennsieve::new(
+            (*CONFIG)
+                .clone()
+                .with_timeout(time::Duration::from_secs(30)),
+        );
+        assert_eq!(
+            ps.inner.lock().unwrap().config.request_timeout(),
+            Some(&time::Duration::from_secs(30))
+        );
+
+        ps.set_environment(Environment::Production);
+
+        let config = ps.inner.lock().unwrap().config.clone();
+        assert_eq!(config.env(), &Environment::Production);
+        assert_eq!(
+            config.request_timeout(),
+            Some(&time::Duration::from_secs(30))
+        );
+        assert_eq!(Config::new(TEST_ENVIRONMENT).request_timeout(), None);
+    }
+
+    #[test]
+    fn fetching_organizations_after_login_is_successful() {
+        let org = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_organizations()),
+            )
+        });
+
+        if org.is_err() {
+            panic!("{}", org.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn fetching_user_after_login_is_successful() {
+        let user = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_user()),
+            )
+        });
+
+        if user.is_err() {
+            panic!("{}", user.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn updating_org_after_login_is_successful() {
+        let user = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_user().map(|user| (user, ps)))
+                    .and_then(move |(user, ps)| {
+                        let org = user.preferred_organization().clone();
+                        ps.set_preferred_organization(org.cloned()).map(|_| ps)
+                    })
+                    .and_then(move |ps| ps.get_user()),
+            )
+        });
+
+        if user.is_err() {
+            panic!("{}", user.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn initializing_a_session_sets_the_preferred_organization() {
+        let result = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.initialize_session(TEST_API_KEY, TEST_SECRET_KEY)
+                    .map(move |session| (session, ps.current_organization())),
+            )
+        });
+
+        match result {
+            Ok(((user, organizations), current_organization)) => {
+                assert_eq!(current_organization.as_ref(), user.preferred_organization());
+                assert!(!organizations.is_empty());
+            }
+            Err(err) => panic!("{}", err.to_string()),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn switching_organization_requires_membership() {
+        let _organizations = mock("GET", "/organizations/")
+            .with_status(200)
+            .with_body(
+                r#"{ "organizations": [{ "isAdmin": false, "isOwner": false, "owners": [],
+                "administrators": [], "organization": { "id": "N:organization:1",
+                "name": "Lab", "slug": "lab", "encryptionKeyId": "key" } }] }"#,
+            )
+            .create();
+
+        let ps = ps();
+        let organization = run(&ps, move |ps| {
+            ps.switch_organization(OrganizationId::new("N:organization:1"))
+        })
+        .unwrap();
+        assert_eq!(organization.organization().name(), "Lab");
+        assert_eq!(
+            ps.current_organization(),
+            Some(OrganizationId::new("N:organization:1"))
+        );
+
+        let err = run(&ps, move |ps| {
+            ps.switch_organization(OrganizationId::new("N:organization:2"))
+        })
+        .unwrap_err();
+        match err.kind() {
+            ErrorKind::InvalidArguments { message } => {
+                assert!(message.contains("N:organization:2"))
+            }
+            kind => panic!("expected invalid arguments, got {:?}", kind),
+        }
+        assert_eq!(
+            ps.current_organization(),
+            Some(OrganizationId::new("N:organization:1"))
+        );
+    }
+
+    #[test]
+    fn fetching_organizations_fails_if_login_fails() {
+        let org = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, "another-bad-secret")
+                    .and_then(move |_| ps.get_organizations()),
+            )
+        });
+
+        assert!(org.is_err());
+    }
+
+    #[test]
+    fn fetching_organization_by_id_is_successful() {
+        let org = run(&ps(), move |ps| {
+            into_future_trait(ps.login(TEST_API_KEY, TEST_SECRET_KEY).and_then(move |_| {
+                ps.get_organization_by_id(OrganizationId::new(FIXTURE_ORGANIZATION))
+            }))
+        });
+
+        if org.is_err() {
+            panic!("{}", org.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn fetching_datasets_after_login_is_successful() {
+        let ds = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_datasets()),
+            )
+        });
+
+        if ds.is_err() {
+            panic!("{}", ds.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn fetching_datasets_paginated_after_login_is_successful() {
+        let page = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_datasets_paginated(1, 0)),
+            )
+        });
+
+        match page {
+            Ok(page) => {
+                assert!(page.datasets().len() <= 1);
+                assert!(page.total_count() >= page.datasets().len());
+            }
+            Err(err) => panic!("{}", err.to_string()),
+        }
+    }
+
+    #[test]
+    fn streaming_all_datasets_matches_listing() {
+        let result = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_datasets().map(|datasets| (ps, datasets)))
+                    .and_then(move |(ps, datasets)| {
+                        ps.get_all_datasets_stream()
+                            .collect()
+                            .map(move |streamed| (datasets, streamed))
+                    }),
+            )
+        });
+
+        match result {
+            Ok((datasets, streamed)) => assert_eq!(datasets.len(), streamed.len()),
+            Err(err) => panic!("{}", err.to_string()),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn a_dataset_cursor_walks_every_page() {
+        let dataset = |n: usize| {
+            serde_json::json!({
+                "organization": FIXTURE_ORGANIZATION,
+                "owner": "N:user:1",
+                "content": {
+                    "id": format!("N:dataset:{}", n),
+                    "name": format!("dataset {}", n),
+                    "status": "NO_STATUS",
+                    "automaticallyProcessPackages": false,
+                    "intId": n,
+                    "createdAt": "2020-01-01T00:00:00Z",
+                    "updatedAt": "2020-01-01T00:00:00Z"
+                }
+            })
+        };
+        let pages: Vec<_> = vec![0, 2, 4]
+            .into_iter()
+            .map(|offset: usize| {
+                let datasets: Vec<_> = (offset..(offset + 2).min(5)).map(dataset).collect();
+                mock("GET", "/datasets/paginated")
+                    .match_query(mockito::Matcher::AllOf(vec![
+                        mockito::Matcher::UrlEncoded("limit".to_string(), "2".to_string()),
+                        mockito::Matcher::UrlEncoded("offset".to_string(), offset.to_string()),
+                    ]))
+                    .with_status(200)
+                    .with_body(
+                        serde_json::json!({
+                            "limit": 2,
+                            "offset": offset,
+                            "totalCount": 5,
+                            "datasets": datasets
+                        })
+                        .to_string(),
+                    )
+                    .expect(1)
+                    .create()
+            })
+            .collect();
+
+        let datasets = run(&ps(), move |ps| {
+            into_future_trait(DatasetCursor::new(ps.clone(), 2).collect())
+        })
+        .unwrap();
+
+        for page in pages {
+            page.assert();
+        }
+        assert_eq!(datasets.len(), 5);
+        assert_eq!(datasets[4].int_id(), &DatasetId::new(4));
+    }
+
+    #[test]
+    fn fetching_datasets_fails_if_login_fails() {
+        let ds = run(&ps(), move |ps| into_future_trait(ps.get_datasets()));
+        assert!(ds.is_err());
+    }
+
+    #[test]
+    fn fetching_dataset_by_id_successful_if_logged_in_and_exists() {
+        let ds = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_dataset_by_id(DatasetNodeId::new(FIXTURE_DATASET))),
+            )
+        });
+
+        if ds.is_err() {
+            panic!("{}", ds.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn fetching_dataset_by_name_successful_if_logged_in_and_exists() {
+        let ds = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_dataset_by_name(FIXTURE_DATASET_NAME)),
+            )
+        });
+
+        if ds.is_err() {
+            panic!("{}", ds.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn fetching_dataset_generic_works_with_name() {
+        let ds = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_dataset(FIXTURE_DATASET_NAME)),
+            )
+        });
+
+        if ds.is_err() {
+            panic!("{}", ds.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn fetching_dataset_generic_works_with_id() {
+        let ds = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_dataset(DatasetNodeId::new(FIXTURE_DATASET))),
+            )
+        });
+
+        if ds.is_err() {
+            panic!("{}", ds.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn fetching_child_dataset_by_id_is_successful_can_contains_child_packages_if_found_by_id() {
+        let ds = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_dataset_by_id(FIXTURE_DATASET.into())),
+            )
+        });
+
+        if ds.is_err() {
+            panic!("{}", ds.unwrap_err().to_string());
+        }
+
+        assert!(ds
+            .unwrap()
+            .get_package_by_id(Into::<model::PackageId>::into(FIXTURE_PACKAGE))
+            .is_some());
+    }
+
+    #[test]
+    fn fetching_child_dataset_by_name_is_successful_can_contains_child_packages_if_found_by_id() {
+        let ds = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_dataset_by_id(FIXTURE_DATASET.into())),
+            )
+        });
+
+        if ds.is_err() {
+            panic!("{}", ds.unwrap_err().to_string());
+        }
+
+        assert!(ds
+            .unwrap()
+            .get_package_by_name(FIXTURE_PACKAGE_NAME)
+            .is_some());
+    }
+
+    #[test]
+    fn fetching_child_dataset_by_id_is_successful_can_contains_child_packages_if_found_by_name() {
+        let ds = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_dataset_by_name(FIXTURE_DATASET_NAME)),
+            )
+        });
+
+        if ds.is_err() {
+            panic!("{}", ds.unwrap_err().to_string());
+        }
+
+        assert!(ds
+            .unwrap()
+            .get_package_by_id(Into::<model::PackageId>::into(FIXTURE_PACKAGE))
+            .is_some());
+    }
+
+    #[test]
+    fn fetching_child_dataset_by_name_is_successful_can_contains_child_packages_if_found_by_name() {
+        let ds = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_dataset_by_name(FIXTURE_DATASET_NAME)),
+            )
+        });
+
+        if ds.is_err() {
+            panic!("{}", ds.unwrap_err().to_string());
+        }
+
+        assert!(ds
+            .unwrap()
+            .get_package_by_name(FIXTURE_PACKAGE_NAME)
+            .is_some());
+    }
+
+    #[test]
+    fn fetching_child_dataset_fails_if_it_does_not_exists() {
+        let ds = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_dataset_by_name(FIXTURE_DATASET_NAME)),
+            )
+        });
+
+        if ds.is_err() {
+            panic!("{}", ds.unwrap_err().to_string());
+        }
+
+        assert!(ds.unwrap().get_package_by_name("doesnotexist").is_none());
+    }
+
+    #[test]
+    fn fetching_dataset_by_name_fails_if_it_does_not_exist() {
+        let ds = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_dataset_by_name("doesnotexist")),
+            )
+        });
+
+        assert!(ds.is_err());
+    }
+
+    #[test]
+    fn fetching_package_by_id_successful_if_logged_in_and_exists() {
+        let package = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_package_by_id(PackageId::new(FIXTURE_PACKAGE))),
+            )
+        });
+        if package.is_err() {
+            panic!("{}", package.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn fetching_package_by_id_invalid_if_logged_in_and_exists() {
+        let package = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_package_by_id(PackageId::new("invalid_package_id"))),
+            )
+        });
+
+        if let Err(e) = package {
+            match e.kind() {
+                // pennsieve api returns 403 in this case..it should really be 404 I think
+                ErrorKind::ApiError { status_code, .. } => assert_eq!(status_code.as_u16(), 404),
+                _ => assert!(false),
+            }
+        }
+    }
+
+    #[test]
+    fn fetching_dataset_by_id_fails_if_logged_in_but_doesnt_exists() {
+        let ds = run(&ps(), move |ps| {
+            into_future_trait(ps.login(TEST_API_KEY, TEST_SECRET_KEY).and_then(move |_| {
+                ps.get_dataset_by_id(DatasetNodeId::new(
+                    "N:dataset:not-real-6803-4a67-ps20-83076774a5c7",
+                ))
+            }))
+        });
+        assert!(ds.is_err());
+    }
+
+    #[test]
+    fn fetch_dataset_user_collaborators() {
+        let collaborators = run(&ps(), move |ps| {
+            into_future_trait(ps.login(TEST_API_KEY, TEST_SECRET_KEY).and_then(move |_| {
+                ps.get_dataset_user_collaborators(DatasetNodeId::new(FIXTURE_DATASET))
+            }))
+        })
+        .unwrap();
+
+        assert!(collaborators.iter().all(|c| c.role().is_some()));
+
+        let collaborators: Vec<(String, model::Role)> = collaborators
+            .iter()
+            .map(|u| (u.first_name().clone(), u.role_enum().unwrap()))
+            .collect();
+
+        let expected = ("Bo".to_string(), model::Role::Owner);
+
+        assert!(collaborators.contains(&expected));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn add_dataset_collaborator_sends_the_role() {
+        let put = mock(
+            "PUT",
+            format!("/datasets/{}/collaborators/users", FIXTURE_DATASET).as_str(),
+        )
+        .match_body(r#"{"id":"N:user:1","role":"editor"}"#)
+        .with_status(200)
+        .with_body(r#"{ "success": true, "message": null }"#)
+        .create();
+
+        let result = run(&ps(), move |ps| {
+            ps.add_dataset_collaborator(
+                DatasetNodeId::new(FIXTURE_DATASET),
+                UserId::new("N:user:1"),
+                DatasetRole::Editor,
+            )
+        });
+
+        put.assert();
+        assert!(result.unwrap().success());
+    }
+
+    fn timeseries_channel(rate: f64, start: i64, end: i64) -> model::Channel {
+        serde_json::from_value(serde_json::json!({
+            "id": "N:channel:1",
+            "name": "EEG 1",
+            "rate": rate,
+            "start": start,
+            "end": end,
+            "unit": "uV",
+            "channelType": "CONTINUOUS"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn channel_data_is_requested_a_window_at_a_time() {
+        // At 10kHz each window of 10,000 samples covers one second:
+        let channel = timeseries_channel(10_000.0, 1_000_000, 2_500_000);
+        let window = |start: i64, end: i64, body: &str| {
+            mock("GET", "/timeseries/N:package:1/channels/N:channel:1/data")
+                .match_query(mockito::Matcher::AllOf(vec![
+                    mockito::Matcher::UrlEncoded("start".to_string(), start.to_string()),
+                    mockito::Matcher::UrlEncoded("end".to_string(), end.to_string()),
+                ]))
+                .with_status(200)
+                .with_body(body)
+                .create()
+        };
+        let first = window(
+            1_000_000,
+            2_000_000,
+            r#"{ "data": [[1000000, 0.5], [1000100, 1.5]] }"#,
+        );
+        let second = window(2_000_000, 2_500_000, r#"{ "data": [[2000000, -2.0]] }"#);
+
+        let samples = run(&ps(), move |ps| {
+            // The requested range is wider than the channel, so it's clamped:
+            into_future_trait(
+                ps.get_channel_data(PackageId::new("N:package:1"), &channel, 0, 9_000_000)
+                    .collect(),
+            )
+        })
+        .unwrap();
+
+        first.assert();
+        second.assert();
+        assert_eq!(
+            samples,
+            vec![(1_000_000, 0.5), (1_000_100, 1.5), (2_000_000, -2.0)]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn channel_data_for_an_inverted_range_is_empty() {
+        let channel = timeseries_channel(10_000.0, 1_000_000, 2_500_000);
+        let data = mock(
+            "GET",
+            mockito::Matcher::Regex(r"^/timeseries/.*$".to_string()),
+        )
+        .expect(0)
+        .create();
+
+        let samples = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.get_channel_data(
+                    PackageId::new("N:package:1"),
+                    &channel,
+                    2_000_000,
+                    1_500_000,
+                )
+                .collect(),
+            )
+        })
+        .unwrap();
+
+        data.assert();
+        assert!(samples.is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn a_bad_dataset_in_a_list_is_reported_by_index() {
+        let dataset = |id: u32| {
+            format!(
+                r#"{{ "organization": "N:organization:1", "owner": "N:user:1",
+                "content": {{ "id": "N:dataset:{id}", "name": "dataset {id}", "status": "NO_STATUS",
+                "automaticallyProcessPackages": false, "intId": {id},
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                id = id
+            )
+        };
+        let bad_dataset = r#"{ "organization": "N:organization:1", "owner": "N:user:1",
+            "content": { "id": "N:dataset:3", "name": "dataset 3" } }"#;
+        let _datasets = mock("GET", "/datasets/")
+            .with_status(200)
+            .with_body(format!("[{}, {}, {}]", dataset(1), dataset(2), bad_dataset))
+            .create();
+
+        let err = run(&ps(), move |ps| ps.get_datasets()).unwrap_err();
+
+        match err.kind() {
+            ErrorKind::SerdeJsonError { error } => {
+                assert!(
+                    error.starts_with("failed to parse dataset at index 2: missing field"),
+                    "unexpected error: {}",
+                    error
+                );
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn search_packages_within_a_dataset() {
+        let _search = mock("GET", "/search/packages")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("query".to_string(), "brain scan".to_string()),
+                mockito::Matcher::UrlEncoded("datasetId".to_string(), "N:dataset:1".to_string()),
+            ]))
+            .with_status(200)
+            .with_body(
+                r#"[{ "content": { "id": "N:package:1", "name": "brain scan",
+                "datasetId": "N:dataset:1", "createdAt": "2020-01-01T00:00:00Z",
+                "updatedAt": "2020-01-01T00:00:00Z" } }]"#,
+            )
+            .create();
+
+        let packages = run(&ps(), move |ps| {
+            ps.search_packages("brain scan", Some(DatasetNodeId::new("N:dataset:1")))
+        })
+        .unwrap();
+
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].name(), "brain scan");
+    }
+
+    #[test]
+    fn empty_bodies_only_parse_as_types_with_nothing_to_parse() {
+        let empty = || hyper::Chunk::from(vec![]);
+
+        assert!(parse_json::<()>(empty()).is_ok());
+        assert!(parse_json::<response::EmptyMap>(empty()).is_ok());
+        assert_eq!(parse_json::<Option<String>>(empty()).unwrap(), None);
+        assert!(parse_json::<response::EmptyMap>(hyper::Chunk::from("{}")).is_ok());
+
+        match parse_json::<response::Dataset>(empty()).unwrap_err().kind() {
+            ErrorKind::EmptyResponse { expected } => assert!(expected.ends_with("Dataset")),
+            kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn deleting_a_dataset_accepts_an_empty_response() {
+        let _delete = mock("DELETE", "/datasets/N:dataset:1")
+            .with_status(200)
+            .with_body("")
+            .create();
+
+        run(&ps(), move |ps| {
+            ps.delete_dataset(DatasetNodeId::new("N:dataset:1"))
+        })
+        .unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn datasets_are_deleted_in_bulk_without_stopping_at_a_failure() {
+        let _deleted = mock("DELETE", "/datasets/N:dataset:1")
+            .with_status(200)
+            .with_body("{}")
+            .create();
+        let _missing = mock("DELETE", "/datasets/N:dataset:2")
+            .with_status(404)
+            .with_body(r#"{ "message": "no such dataset" }"#)
+            .create();
+        let also_deleted = mock("DELETE", "/datasets/N:dataset:3")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let results = run(&ps(), move |ps| {
+            ps.delete_datasets(vec![
+                DatasetNodeId::new("N:dataset:1"),
+                DatasetNodeId::new("N:dataset:2"),
+                DatasetNodeId::new("N:dataset:3"),
+            ])
+        })
+        .unwrap();
+
+        also_deleted.assert();
+        let ids: Vec<&DatasetNodeId> = results.iter().map(|(id, _)| id).collect();
+        assert_eq!(
+            ids,
+            vec![
+                &DatasetNodeId::new("N:dataset:1"),
+                &DatasetNodeId::new("N:dataset:2"),
+                &DatasetNodeId::new("N:dataset:3")
+            ]
+        );
+        assert!(results[0].1.is_ok());
+        assert_eq!(
+            results[1].1.as_ref().unwrap_err().error_message(),
+            Some("no such dataset")
+        );
+        assert!(results[2].1.is_ok());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn packages_are_deleted_in_a_single_request() {
+        let delete = mock("POST", "/data/delete")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "things": ["N:package:1", "N:package:2"]
+            })))
+            .with_status(200)
+            .with_body(
+                r#"{ "success": ["N:package:1"],
+                "failures": [{ "id": "N:package:2", "error": "locked" }] }"#,
+            )
+            .expect(1)
+            .create();
+
+        let results = run(&ps(), move |ps| {
+            ps.delete_packages(vec![
+                PackageId::new("N:package:1"),
+                PackageId::new("N:package:2"),
+            ])
+        })
+        .unwrap();
+
+        delete.assert();
+        assert_eq!(results[0].0, PackageId::new("N:package:1"));
+        assert!(results[0].1.is_ok());
+        assert_eq!(results[1].0, PackageId::new("N:package:2"));
+        assert_eq!(
+            results[1].1.as_ref().unwrap_err().error_message(),
+            Some("failed to delete package N:package:2: locked")
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn search_packages_without_matches_is_empty() {
+        let _search = mock("GET", "/search/packages")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "query".to_string(),
+                "nothing".to_string(),
+            ))
+            .with_status(200)
+            .with_body("")
+            .create();
+
+        let packages = run(&ps(), move |ps| ps.search_packages("nothing", None)).unwrap();
+
+        assert!(packages.is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn create_dataset_from_the_organization_template() {
+        let _template = mock("GET", "/organizations/N:organization:1/dataset-template")
+            .with_status(200)
+            .with_body(
+                r#"{ "description": "A study dataset", "license": "CC-BY-4.0",
+                "tags": ["eeg", "study"], "collaborators": [{ "id": "N:user:2", "role": "editor" }] }"#,
+            )
+            .create();
+        let create = mock("POST", "/datasets/")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "name": "from template",
+                "description": "A study dataset",
+                "automaticallyProcessPackages": false,
+                "license": "CC-BY-4.0",
+                "tags": ["eeg", "study"]
+            })))
+            .with_status(201)
+            .with_body(
+                r#"{ "organization": "N:organization:1", "owner": "N:user:1",
+                "content": { "id": "N:dataset:1", "name": "from template",
+                "description": "A study dataset", "license": "CC-BY-4.0", "tags": ["eeg", "study"],
+                "status": "NO_STATUS", "automaticallyProcessPackages": false, "intId": 1,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" } }"#,
+            )
+            .create();
+        let collaborator = mock("PUT", "/datasets/N:dataset:1/collaborators/users")
+            .match_body(r#"{"id":"N:user:2","role":"editor"}"#)
+            .with_status(200)
+            .with_body(r#"{ "success": true, "message": null }"#)
+            .create();
+
+        let dataset = run(&ps(), move |ps| {
+            ps.create_dataset_from_template(
+                OrganizationId::new("N:organization:1"),
+                "from template",
+            )
+        })
+        .unwrap();
+
+        create.assert();
+        collaborator.assert();
+        assert_eq!(
+            dataset.description().map(String::as_str),
+            Some("A study dataset")
+        );
+        assert_eq!(dataset.license().map(String::as_str), Some("CC-BY-4.0"));
+        assert_eq!(
+            dataset.tags(),
+            &vec!["eeg".to_string(), "study".to_string()]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn organization_without_a_dataset_template() {
+        let _template = mock("GET", "/organizations/N:organization:1/dataset-template")
+            .with_status(404)
+            .with_body("not found")
+            .create();
+
+        let template = run(&ps(), move |ps| {
+            ps.get_dataset_template(OrganizationId::new("N:organization:1"))
+        })
+        .unwrap();
+
+        assert_eq!(template, None);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn fetch_dataset_by_int_id() {
+        let by_int_id = mock("GET", "/datasets/int/42")
+            .with_status(200)
+            .with_header("ETag", r#""v1""#)
+            .with_body(
+                r#"{ "organization": "N:organization:1", "owner": "N:user:1",
+                "content": { "id": "N:dataset:1", "name": "numbered", "status": "NO_STATUS",
+                "automaticallyProcessPackages": false, "intId": 42,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" },
+                "children": [{ "content": { "id": "N:package:1", "name": "child",
+                "datasetId": "N:dataset:1", "createdAt": "2020-01-01T00:00:00Z",
+                "updatedAt": "2020-01-01T00:00:00Z" } }] }"#,
+            )
+            .create();
+
+        let dataset = run(&ps(), move |ps| {
+            ps.get_dataset_by_int_id(DatasetId::new(42))
+        })
+        .unwrap();
+
+        assert_eq!(dataset.int_id(), &DatasetId::new(42));
+        assert_eq!(*dataset.id(), DatasetNodeId::new("N:dataset:1"));
+        assert_eq!(dataset.children().map(Vec::len), Some(1));
+        assert_eq!(dataset.etag(), Some(r#""v1""#));
+        by_int_id.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn dataset_children_can_be_sorted() {
+        let child = |id: u32, name: &str, created_at: &str| {
+            format!(
+                r#"{{ "content": {{ "id": "N:package:{id}", "name": "{name}",
+                "datasetId": "N:dataset:1", "createdAt": "{created_at}",
+                "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                id = id,
+                name = name,
+                created_at = created_at
+            )
+        };
+        let _dataset = mock("GET", "/datasets/N:dataset:1")
+            .with_status(200)
+            .with_body(format!(
+                r#"{{ "organization": "N:organization:1", "owner": "N:user:1",
+                "content": {{ "id": "N:dataset:1", "name": "sorted", "status": "NO_STATUS",
+                "automaticallyProcessPackages": false, "intId": 1,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }},
+                "children": [{}, {}, {}, {}] }}"#,
+                child(1, "charlie", "2020-01-02T00:00:00Z"),
+                child(2, "alpha", "2020-01-04T00:00:00Z"),
+                child(3, "bravo", "2020-01-01T00:00:00Z"),
+                child(4, "alpha", "2020-01-03T00:00:00Z"),
+            ))
+            .create();
+
+        let ids = |order| {
+            let dataset = run(&ps(), move |ps| {
+                ps.get_dataset_by_id_sorted(DatasetNodeId::new("N:dataset:1"), order)
+            })
+            .unwrap();
+            dataset
+                .children()
+                .unwrap()
+                .iter()
+                .map(|package| package.id().clone().take())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ids(response::ChildOrder::Name),
+            vec!["N:package:2", "N:package:4", "N:package:3", "N:package:1"]
+        );
+        assert_eq!(
+            ids(response::ChildOrder::CreatedAt),
+            vec!["N:package:3", "N:package:1", "N:package:4", "N:package:2"]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn renaming_a_dataset_sends_only_its_name() {
+        let put = mock("PUT", "/datasets/N:dataset:1")
+            .match_body(r#"{"name":"renamed"}"#)
+            .with_status(200)
+            .with_body(
+                r#"{ "organization": "N:organization:1", "owner": "N:user:1",
+                "content": { "id": "N:dataset:1", "name": "renamed", "description": "kept",
+                "status": "NO_STATUS", "automaticallyProcessPackages": false, "intId": 1,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" } }"#,
+            )
+            .create();
+
+        let dataset = run(&ps(), move |ps| {
+            ps.rename_dataset(DatasetNodeId::new("N:dataset:1"), "renamed")
+        })
+        .unwrap();
+
+        put.assert();
+        assert_eq!(dataset.name(), "renamed");
+        assert_eq!(dataset.description().map(String::as_str), Some("kept"));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn fetch_upload_credentials_for_a_direct_upload() {
+        let _credentials = mock("GET", "/security/user/credentials/upload/N:dataset:1")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "organizationId".to_string(),
+                "N:organization:1".to_string(),
+            ))
+            .with_status(200)
+            .with_body(
+                r#"{ "tempCredentials": { "accessKey": "access", "secretKey": "secret",
+                "region": "us-east-1", "sessionToken": "token",
+                "expiration": "2020-01-01T01:00:00Z" },
+                "encryptionKeyId": "key-id", "s3Bucket": "uploads",
+                "s3Key": "agent-test@pennsieve.com" }"#,
+            )
+            .create();
+
+        let credential = run(&ps(), move |ps| {
+            ps.get_upload_credentials(
+                &OrganizationId::new("N:organization:1"),
+                &DatasetNodeId::new("N:dataset:1"),
+            )
+        })
+        .unwrap();
+
+        assert_eq!(credential.temp_credentials().region(), "us-east-1");
+        assert_eq!(
+            String::from(
+                credential
+                    .s3_key()
+                    .as_upload_key(&ImportId::new("import"), "brain.jpg")
+            ),
+            "agent-test@pennsieve.com/import/brain.jpg"
+        );
+
+        // Without an organization given, the current one is used:
+        let ps = ps();
+        ps.set_current_organization(Some(&OrganizationId::new("N:organization:1")));
+        let credential = run(&ps, move |ps| {
+            ps.get_upload_credentials_resolved(DatasetNodeId::new("N:dataset:1"))
+        })
+        .unwrap();
+        assert_eq!(
+            credential.s3_bucket(),
+            &model::S3Bucket::new("uploads".to_string())
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn requests_identify_the_library_by_default() {
+        let user = mock("GET", "/user/")
+            .match_header(
+                "user-agent",
+                format!("pennsieve-rust/{}", env!("CARGO_PKG_VERSION")).as_str(),
+            )
+            .with_status(200)
+            .with_body(format!(
+                "{{ \"id\": \"N:user:1\", \"firstName\": \"Agent\", \"lastName\": \"Test\", \
+                 \"email\": \"{}\", \"preferredOrganization\": null }}",
+                FIXTURE_EMAIL
+            ))
+            .create();
+
+        run(&ps(), move |ps| ps.get_user()).unwrap();
+
+        user.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn the_user_agent_can_be_overridden() {
+        let health = mock("GET", "/health")
+            .match_header("user-agent", "pennsieve-agent/1.2.3")
+            .with_status(200)
+            .create();
+
+        let ps = Pennsieve::new(
+            CONFIG
+                .clone()
+                .with_user_agent("pennsieve-agent/1.2.3")
+                .unwrap(),
+        );
+        run(&ps, move |ps| ps.ping()).unwrap();
+
+        health.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn requests_name_the_current_organization() {
+        let without = mock("GET", "/health")
+            .match_header("x-organization-id", mockito::Matcher::Missing)
+            .with_status(200)
+            .expect(1)
+            .create();
+        let ps = ps();
+        run(&ps, move |ps| ps.ping()).unwrap();
+        without.assert();
+
+        let with = mock("GET", "/health")
+            .match_header("x-organization-id", "N:organization:2")
+            .with_status(200)
+            .expect(1)
+            .create();
+        ps.set_current_organization(Some(&OrganizationId::new("N:organization:2")));
+        run(&ps, move |ps| ps.ping()).unwrap();
+        with.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn requests_can_be_authenticated_with_an_api_key() {
+        let cognito_config = mock("GET", "/authentication/cognito-config")
+            .expect(0)
+            .create();
+        let health = mock("GET", "/health")
+            .match_header("x-api-key", "machine-key")
+            .match_header("x-session-id", mockito::Matcher::Missing)
+            .match_header("authorization", mockito::Matcher::Missing)
+            .with_status(200)
+            .create();
+
+        let ps = Pennsieve::new(CONFIG.clone().with_auth_mode(AuthMode::ApiKey {
+            key: "machine-key".to_string(),
+        }));
+        assert!(!ps.has_session());
+        let session = run(&ps, move |ps| ps.login("unused", "unused")).unwrap();
+        assert!(ps.has_session());
+        assert_eq!(
+            session.session_token(),
+            &SessionToken::new("machine-key".into())
+        );
+        run(&ps, move |ps| ps.ping()).unwrap();
+
+        cognito_config.assert();
+        health.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn api_keys_that_cant_be_sent_are_rejected() {
+        let health = mock("GET", "/health").with_status(200).expect(0).create();
+
+        let ps = Pennsieve::new(CONFIG.clone().with_auth_mode(AuthMode::ApiKey {
+            key: "machine\nkey".to_string(),
+        }));
+        let err = run(&ps, move |ps| ps.ping()).unwrap_err();
+
+        match err.kind() {
+            ErrorKind::InvalidArguments { message } => assert!(message.contains("API key")),
+            kind => panic!("expected invalid arguments, got {:?}", kind),
+        }
+        health.assert();
+    }
+
+    #[derive(Default)]
+    struct InMemoryDebugCapture(sync::Mutex<Vec<(CapturedRequest, CapturedResponse)>>);
+
+    impl DebugCapture for InMemoryDebugCapture {
+        fn on_exchange(&self, request: &CapturedRequest, response: &CapturedResponse) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((request.clone(), response.clone()));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn debug_capture_sees_the_request_and_response() {
+        let body = format!(
+            "{{ \"id\": \"N:user:1\", \"firstName\": \"Agent\", \"lastName\": \"Test\", \
+             \"email\": \"{}\", \"preferredOrganization\": null }}",
+            FIXTURE_EMAIL
+        );
+        let _user = mock("GET", "/user/")
+            .with_status(200)
+            .with_body(&body)
+            .create();
+
+        let ps = Pennsieve::new(CONFIG.clone().with_debug_capture(true));
+        ps.set_session_token(Some(SessionToken::new("secret-session".to_string())));
+        let capture = sync::Arc::new(InMemoryDebugCapture::default());
+        ps.set_debug_capture(Some(capture.clone()));
+        run(&ps, move |ps| ps.get_user()).unwrap();
+
+        let exchanges = capture.0.lock().unwrap().clone();
+        assert_eq!(exchanges.len(), 1);
+        let (request, response) = &exchanges[0];
+        assert_eq!(request.method(), "GET");
+        assert!(request.url().ends_with("/user/"));
+        assert!(request.body().is_empty());
+        assert!(request
+            .headers()
+            .iter()
+            .any(|(name, value)| name == "x-session-id" && value == "<redacted>"));
+        assert!(request
+            .headers()
+            .iter()
+            .all(|(_, value)| !value.contains("secret-session")));
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body(), body.as_bytes());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn debug_capture_is_off_by_default() {
+        let _health = mock("GET", "/health").with_status(200).create();
+
+        let ps = ps();
+        let capture = sync::Arc::new(InMemoryDebugCapture::default());
+        ps.set_debug_capture(Some(capture.clone()));
+        run(&ps, move |ps| ps.ping()).unwrap();
+
+        assert!(capture.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn responses_larger_than_the_limit_are_rejected() {
+        let tags: Vec<String> = (0..100).map(|n| format!("tag-{:04}", n)).collect();
+        let body = serde_json::to_string(&tags).unwrap();
+        let tags_mock = mock("GET", "/data-canvases/tags")
+            .with_status(200)
+            .with_body(body.as_str())
+            .expect(2)
+            .create();
+
+        let limited = |max_response_bytes: usize| {
+            let ps = Pennsieve::new(CONFIG.clone().with_max_response_bytes(max_response_bytes));
+            run(&ps, move |ps| {
+                get!(ps, "/data-canvases/tags") as Future<Vec<String>>
+            })
+        };
+
+        assert_eq!(limited(body.len()).unwrap(), tags);
+        let err = limited(body.len() - 1).unwrap_err();
+        match err.kind() {
+            ErrorKind::ResponseTooLarge { max_bytes } => assert_eq!(*max_bytes, body.len() - 1),
+            kind => panic!("unexpected error: {:?}", kind),
+        }
+        // ...and isn't retried:
+        tags_mock.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn requests_go_through_the_configured_proxy() {
+        // Requests sent to a proxy name the whole URL, where those sent
+        // directly only have the path:
+        let health = mock(
+            "GET",
+            mockito::Matcher::Regex(r"^http://[^/]+/health$".to_string()),
+        )
+        .with_status(200)
+        .create();
+
+        let config = CONFIG
+            .clone()
+            .with_proxy(mockito::server_url().parse::<url::Url>().unwrap());
+        run(&Pennsieve::new(config), move |ps| ps.ping()).unwrap();
+
+        health.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn ping_measures_the_round_trip_to_the_platform() {
+        let health = mock("GET", "/health")
+            .with_status(200)
+            .with_body("ok")
+            .create();
+
+        let latency = run(&ps(), move |ps| ps.ping()).unwrap();
+
+        health.assert();
+        assert!(latency > time::Duration::from_millis(0));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn ping_fails_when_the_health_check_is_missing() {
+        let _health = mock("GET", "/health")
+            .with_status(404)
+            .with_body("not found")
+            .create();
+
+        let result = run(&ps(), move |ps| ps.ping());
+
+        match result.map_err(|err| err.kind().clone()) {
+            Err(ErrorKind::ApiError { status_code, .. }) => {
+                assert_eq!(status_code, hyper::StatusCode::NOT_FOUND)
             }
+            other => panic!("expected an API error, got {:?}", other),
         }
     }
 
-    fn add_upload_ids(file_paths: &Vec<String>) -> Vec<(UploadId, String)> {
-        file_paths
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn ping_fails_when_the_platform_is_unreachable() {
+        // Nothing listens on port 1, so following the redirect fails to connect:
+        let _health = mock("GET", "/health")
+            .with_status(307)
+            .with_header("location", "http://127.0.0.1:1/health")
+            .create();
+
+        let ps = Pennsieve::new(Config::new(TEST_ENVIRONMENT).with_follow_redirects(true));
+        let result = run(&ps, move |ps| ps.ping());
+
+        match result.map_err(|err| err.kind().clone()) {
+            Err(ErrorKind::HyperError { .. }) => (),
+            other => panic!("expected a connection error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn tagging_datasets_in_bulk_reports_each_dataset() {
+        let dataset = |id: u32, tags: &str| {
+            format!(
+                r#"{{ "organization": "N:organization:1", "owner": "N:user:1",
+                "content": {{ "id": "N:dataset:{id}", "name": "dataset {id}", "status": "NO_STATUS",
+                "automaticallyProcessPackages": false, "intId": {id}, "tags": [{tags}],
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                id = id,
+                tags = tags
+            )
+        };
+        let _get_1 = mock("GET", "/datasets/N:dataset:1")
+            .with_status(200)
+            .with_body(dataset(1, r#""eeg""#))
+            .create();
+        let put_1 = mock("PUT", "/datasets/N:dataset:1")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "name": "dataset 1",
+                "tags": ["eeg", "reviewed"]
+            })))
+            .with_status(200)
+            .with_body(dataset(1, r#""eeg", "reviewed""#))
+            .create();
+        // Already tagged, so left alone:
+        let _get_2 = mock("GET", "/datasets/N:dataset:2")
+            .with_status(200)
+            .with_body(dataset(2, r#""reviewed""#))
+            .create();
+        let put_2 = mock("PUT", "/datasets/N:dataset:2").expect(0).create();
+        let _missing = mock("GET", "/datasets/N:dataset:missing")
+            .with_status(404)
+            .with_body("not found")
+            .create();
+        // A dataset that isn't found by its id isn't looked for by name:
+        let by_name = mock("GET", "/datasets")
+            .match_query(mockito::Matcher::Any)
+            .expect(0)
+            .create();
+
+        let tagged = run(&ps(), move |ps| {
+            ps.add_tag_to_datasets(
+                vec![
+                    DatasetNodeId::new("N:dataset:1"),
+                    DatasetNodeId::new("N:dataset:missing"),
+                    DatasetNodeId::new("N:dataset:2"),
+                ],
+                "reviewed",
+            )
+        })
+        .unwrap();
+
+        put_1.assert();
+        put_2.assert();
+        by_name.assert();
+        assert_eq!(
+            tagged,
+            vec![
+                (DatasetNodeId::new("N:dataset:1"), true),
+                (DatasetNodeId::new("N:dataset:missing"), false),
+                (DatasetNodeId::new("N:dataset:2"), true),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn dataset_updates_can_be_conditional_on_the_etag() {
+        let dataset = |name: &str| {
+            format!(
+                r#"{{ "organization": "N:organization:1", "owner": "N:user:1",
+                "content": {{ "id": "N:dataset:1", "name": "{name}", "status": "NO_STATUS",
+                "automaticallyProcessPackages": false, "intId": 1, "tags": [],
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                name = name
+            )
+        };
+        let _get = mock("GET", "/datasets/N:dataset:1")
+            .with_status(200)
+            .with_header("ETag", r#""v1""#)
+            .with_body(dataset("original"))
+            .create();
+        let put = mock("PUT", "/datasets/N:dataset:1")
+            .match_header("If-Match", r#""v1""#)
+            .with_status(200)
+            .with_header("ETag", r#""v2""#)
+            .with_body(dataset("renamed"))
+            .create();
+        // Someone else changed the dataset since it was fetched as "v0":
+        let stale_put = mock("PUT", "/datasets/N:dataset:1")
+            .match_header("If-Match", r#""v0""#)
+            .with_status(412)
+            .with_body(r#"{ "message": "dataset has changed" }"#)
+            .create();
+        let unconditional_put = mock("PUT", "/datasets/N:dataset:1")
+            .match_header("If-Match", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_body(dataset("renamed again"))
+            .create();
+
+        let (fetched, updated) = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.get_dataset_by_id(DatasetNodeId::new("N:dataset:1"))
+                    .and_then(move |fetched| {
+                        ps.update_dataset_if_match(
+                            fetched.id().clone(),
+                            "renamed",
+                            None::<String>,
+                            fetched.etag(),
+                        )
+                        .map(|updated| (fetched, updated))
+                    }),
+            )
+        })
+        .unwrap();
+        let stale = run(&ps(), move |ps| {
+            ps.update_dataset_if_match(
+                DatasetNodeId::new("N:dataset:1"),
+                "renamed",
+                None::<String>,
+                Some(r#""v0""#),
+            )
+        });
+        let unconditional = run(&ps(), move |ps| {
+            ps.update_dataset(
+                DatasetNodeId::new("N:dataset:1"),
+                "renamed again",
+                None::<String>,
+            )
+        })
+        .unwrap();
+
+        put.assert();
+        stale_put.assert();
+        unconditional_put.assert();
+        assert_eq!(fetched.etag(), Some(r#""v1""#));
+        assert_eq!(updated.name(), "renamed");
+        assert_eq!(updated.etag(), Some(r#""v2""#));
+        match stale.unwrap_err().kind() {
+            ErrorKind::Conflict { message } => assert_eq!(message, "dataset has changed"),
+            kind => panic!("expected a conflict, got {:?}", kind),
+        }
+        assert_eq!(unconditional.etag(), None);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn update_dataset_collaborator_role_promotes_the_collaborator() {
+        let put = mock(
+            "PUT",
+            format!("/datasets/{}/collaborators/users", FIXTURE_DATASET).as_str(),
+        )
+        .match_body(r#"{"id":"N:user:1","role":"manager"}"#)
+        .with_status(200)
+        .with_body(r#"{ "success": true, "message": null }"#)
+        .create();
+        let _get = mock(
+            "GET",
+            format!("/datasets/{}/collaborators/users", FIXTURE_DATASET).as_str(),
+        )
+        .with_status(200)
+        .with_body(format!(
+            "[{{ \"id\": \"N:user:1\", \"firstName\": \"Agent\", \"lastName\": \"Test\", \
+             \"email\": \"{}\", \"preferredOrganization\": null, \"role\": \"manager\" }}]",
+            FIXTURE_EMAIL
+        ))
+        .create();
+
+        let user = run(&ps(), move |ps| {
+            ps.update_dataset_collaborator_role(
+                DatasetNodeId::new(FIXTURE_DATASET),
+                UserId::new("N:user:1"),
+                DatasetRole::Manager,
+            )
+        })
+        .unwrap();
+
+        put.assert();
+        assert_eq!(user.role().map(String::as_str), Some("manager"));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn update_dataset_collaborator_role_refuses_ownership() {
+        let put = mock(
+            "PUT",
+            format!("/datasets/{}/collaborators/users", FIXTURE_DATASET).as_str(),
+        )
+        .expect(0)
+        .create();
+
+        let result = run(&ps(), move |ps| {
+            ps.update_dataset_collaborator_role(
+                DatasetNodeId::new(FIXTURE_DATASET),
+                UserId::new("N:user:1"),
+                DatasetRole::Owner,
+            )
+        });
+
+        put.assert();
+        match result.map_err(|err| err.kind().clone()) {
+            Err(ErrorKind::InvalidArguments { .. }) => (),
+            other => panic!("expected invalid arguments, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn remove_dataset_collaborator_by_email_resolves_user_id() {
+        let collaborators = format!(
+            "[{{ \"id\": \"N:user:1\", \"firstName\": \"Agent\", \"lastName\": \"Test\", \
+             \"email\": \"{}\", \"preferredOrganization\": null, \"role\": \"editor\" }}]",
+            FIXTURE_EMAIL
+        );
+
+        let _get = mock(
+            "GET",
+            format!("/datasets/{}/collaborators/users", FIXTURE_DATASET).as_str(),
+        )
+        .with_status(200)
+        .with_body(collaborators)
+        .create();
+
+        let delete = mock(
+            "DELETE",
+            format!("/datasets/{}/collaborators/users", FIXTURE_DATASET).as_str(),
+        )
+        .match_body(r#"{"id":"N:user:1"}"#)
+        .with_status(200)
+        .with_body(r#"{ "success": true, "message": null }"#)
+        .create();
+
+        let result = run(&ps(), move |ps| {
+            ps.remove_dataset_collaborator_by_email(
+                DatasetNodeId::new(FIXTURE_DATASET),
+                FIXTURE_EMAIL,
+            )
+        });
+
+        delete.assert();
+        assert!(result.unwrap().success());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn remove_dataset_collaborator_by_unknown_email_is_not_found() {
+        let _get = mock(
+            "GET",
+            format!("/datasets/{}/collaborators/users", FIXTURE_DATASET).as_str(),
+        )
+        .with_status(200)
+        .with_body("[]")
+        .create();
+
+        let result = run(&ps(), move |ps| {
+            ps.remove_dataset_collaborator_by_email(
+                DatasetNodeId::new(FIXTURE_DATASET),
+                FIXTURE_EMAIL,
+            )
+        });
+
+        match result.unwrap_err().kind() {
+            ErrorKind::NotFound { .. } => (),
+            kind => panic!("unexpected error: {}", kind),
+        }
+    }
+
+    #[test]
+    fn fetch_dataset_team_collaborators() {
+        let collaborators = run(&ps(), move |ps| {
+            into_future_trait(ps.login(TEST_API_KEY, TEST_SECRET_KEY).and_then(move |_| {
+                ps.get_dataset_team_collaborators(DatasetNodeId::new(FIXTURE_DATASET))
+            }))
+        })
+        .unwrap();
+        assert!(collaborators.iter().all(|c| c.role().is_some()));
+
+        let mut collaborators: Vec<(String, String)> = collaborators
             .iter()
-            .enumerate()
-            .map(|(id, file)| (UploadId::from(id as u64), file.to_string()))
-            .collect()
+            .map(|t| (t.name().clone(), t.role().unwrap().clone()))
+            .collect();
+        collaborators.sort();
+
+        let expected = vec![("Agent Devs".to_string(), "manager".to_string())];
+
+        assert_eq!(collaborators, expected);
+    }
+
+    #[test]
+    fn fetch_dataset_organization_role() {
+        let organization_role = run(&ps(), move |ps| {
+            into_future_trait(ps.login(TEST_API_KEY, TEST_SECRET_KEY).and_then(move |_| {
+                ps.get_dataset_organization_role(DatasetNodeId::new(FIXTURE_DATASET))
+            }))
+        })
+        .unwrap();
+
+        let organization_role = (
+            organization_role.name().clone(),
+            organization_role.role().cloned(),
+        );
+        let expected = ("Test-Org".to_string(), None);
+
+        assert_eq!(organization_role, expected);
     }
 
     #[test]
-    fn login_successfully_locally() {
-        let ps = ps();
-        let result = run(&ps, move |ps| ps.login(TEST_API_KEY, TEST_SECRET_KEY));
-        assert!(result.is_ok());
-        assert!(ps.session_token().is_some());
+    fn fetch_members() {
+        let members = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.initialize_session(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_members()),
+            )
+        });
+        assert!(members.is_ok());
     }
 
     #[test]
-    fn login_fails_locally() {
-        let ps = ps();
-        let result = run(&ps, move |ps| {
-            ps.login(TEST_API_KEY, "this-is-a-bad-secret")
+    fn fetch_teams() {
+        let teams = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.initialize_session(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| ps.get_teams()),
+            )
         });
-        assert!(result.is_err());
-        assert!(ps.session_token().is_none());
+        assert!(teams.is_ok());
     }
 
     #[test]
     #[cfg_attr(not(feature = "mocks"), ignore)]
-    fn login_returns_error_when_no_token_pool_config_present() {
-        let ps = ps();
-
-        let _mock = mock("GET", "/authentication/cognito-config")
+    fn setting_then_getting_dataset_pipeline_round_trips() {
+        let route = format!("/datasets/{}/pipeline", FIXTURE_DATASET);
+        let put = mock("PUT", route.as_str())
+            .match_body(r#"{"pipelineId":"N:pipeline:1"}"#)
             .with_status(200)
-            .with_body("{}")
+            .with_body(r#"{ "pipelineId": "N:pipeline:1" }"#)
+            .create();
+        let _get = mock("GET", route.as_str())
+            .with_status(200)
+            .with_body(r#"{ "pipelineId": "N:pipeline:1" }"#)
             .create();
 
-        let result = run(&ps, move |ps| ps.login(TEST_API_KEY, TEST_SECRET_KEY));
+        let pipeline = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.set_dataset_pipeline(
+                    DatasetNodeId::new(FIXTURE_DATASET),
+                    PipelineId::new("N:pipeline:1"),
+                )
+                .and_then(move |_| ps.get_dataset_pipeline(DatasetNodeId::new(FIXTURE_DATASET))),
+            )
+        })
+        .unwrap();
 
-        assert!(result.is_err());
-        assert!(ps.session_token().is_none());
+        put.assert();
+        assert_eq!(
+            pipeline.pipeline_id(),
+            Some(&PipelineId::new("N:pipeline:1"))
+        );
+    }
 
-        if let Err(error) = result {
-            assert_eq!(
-                error.kind(),
-                crate::ps::Error::initiate_auth_error(
-                    "Pennsieve server Cognito config missing token pool."
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn setting_then_getting_dataset_readme_round_trips() {
+        let route = format!("/datasets/{}/readme", FIXTURE_DATASET);
+        let put = mock("PUT", route.as_str())
+            .match_body(r##"{"readme":"# Results\n\nSee *figure 1*."}"##)
+            .with_status(200)
+            .create();
+        let _get = mock("GET", route.as_str())
+            .with_status(200)
+            .with_body(r##"{ "readme": "# Results\n\nSee *figure 1*." }"##)
+            .create();
+
+        let readme = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.update_dataset_readme(
+                    DatasetNodeId::new(FIXTURE_DATASET),
+                    "# Results\n\nSee *figure 1*.".to_string(),
                 )
-                .kind()
-            );
-        }
+                .and_then(move |_| ps.get_dataset_readme(DatasetNodeId::new(FIXTURE_DATASET))),
+            )
+        })
+        .unwrap();
+
+        put.assert();
+        assert_eq!(readme, "# Results\n\nSee *figure 1*.");
     }
 
     #[test]
     #[cfg_attr(not(feature = "mocks"), ignore)]
-    fn login_returns_error_when_no_token_pool_client_id_config_present() {
-        let ps = ps();
-        let body = " { \"tokenPool\": {} } ";
+    fn dataset_without_pipeline_has_no_pipeline_id() {
+        let _get = mock(
+            "GET",
+            format!("/datasets/{}/pipeline", FIXTURE_DATASET).as_str(),
+        )
+        .with_status(200)
+        .with_body(r#"{ "pipelineId": null }"#)
+        .create();
 
-        let _mock = mock("GET", "/authentication/cognito-config")
+        let pipeline = run(&ps(), move |ps| {
+            ps.get_dataset_pipeline(DatasetNodeId::new(FIXTURE_DATASET))
+        })
+        .unwrap();
+
+        assert_eq!(pipeline.pipeline_id(), None);
+    }
+
+    #[test]
+    fn creating_then_updating_then_delete_dataset_successful() {
+        let new_dataset_name = rand_suffix("$new-test-dataset".to_string());
+        let result = run(&ps(), move |ps| {
+            let new_dataset_name = new_dataset_name.clone();
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| {
+                        let new_dataset_name = new_dataset_name.clone();
+                        ps.create_dataset(
+                            rand_suffix("__agent-test-dataset".to_string()),
+                            Some("A test dataset created by the agent".to_string()),
+                        )
+                        .map(|ds| (ps, ds, new_dataset_name))
+                    })
+                    .and_then(move |(ps, ds, new_dataset_name)| {
+                        Ok(ds.id().clone()).map(|id| (ps, id, new_dataset_name))
+                    })
+                    .and_then(move |(ps, id, new_dataset_name)| {
+                        ps.update_dataset(
+                            id.clone(),
+                            new_dataset_name.clone(),
+                            None as Option<String>,
+                        )
+                        .map(|_| (ps, id, new_dataset_name))
+                    })
+                    .and_then(move |(ps, id, new_dataset_name)| {
+                        let id = id.clone();
+                        ps.get_dataset_by_id(id.clone())
+                            .and_then(move |ds| {
+                                assert_eq!(ds.take().name().clone(), new_dataset_name);
+                                Ok(id)
+                            })
+                            .map(|id| (ps, id))
+                    })
+                    .and_then(move |(ps, id)| ps.delete_dataset(id)),
+            )
+        });
+
+        if result.is_err() {
+            panic!("{}", result.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn creating_then_updating_then_delete_package_successful() {
+        let result = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| {
+                        ps.create_dataset(
+                            rand_suffix("__agent-test-dataset".to_string()),
+                            Some("A test dataset created by the agent".to_string()),
+                        )
+                        .map(|ds| (ps, ds))
+                    })
+                    .and_then(move |(ps, ds)| Ok(ds.id().clone()).map(|id| (ps, id)))
+                    .and_then(move |(ps, ds_id)| {
+                        ps.create_package(
+                            rand_suffix("__agent-test-package"),
+                            "Text",
+                            ds_id.clone(),
+                            None as Option<String>,
+                            None,
+                        )
+                        .map(|pkg| (ps, ds_id, pkg))
+                    })
+                    .and_then(move |(ps, ds_id, pkg)| {
+                        let pkg_id = pkg.take().id().clone();
+                        ps.update_package(pkg_id.clone(), "new-package-name")
+                            .map(|_| (ps, pkg_id, ds_id))
+                    })
+                    .and_then(move |(ps, pkg_id, ds_id)| {
+                        ps.get_package_by_id(pkg_id).and_then(|pkg| {
+                            assert_eq!(pkg.take().name().clone(), "new-package-name".to_string());
+                            Ok((ps, ds_id))
+                        })
+                    })
+                    .and_then(move |(ps, ds_id)| ps.delete_dataset(ds_id)),
+            )
+        });
+
+        if result.is_err() {
+            panic!("{}", result.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn creating_a_package_with_properties_then_updating_them() {
+        let result = run(&ps(), move |ps| {
+            let species = model::Property::new("species".to_string(), "mouse".to_string());
+            let strain = model::Property::new("strain".to_string(), "C57".to_string());
+            let expected = vec![species.clone()];
+            let updated = strain.clone();
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| {
+                        ps.create_dataset(
+                            rand_suffix("__agent-test-dataset".to_string()),
+                            Some("A test dataset created by the agent".to_string()),
+                        )
+                        .map(|ds| (ps, ds.id().clone()))
+                    })
+                    .and_then(move |(ps, ds_id)| {
+                        ps.create_package(
+                            rand_suffix("__agent-test-package"),
+                            "Text",
+                            ds_id.clone(),
+                            None as Option<String>,
+                            Some(vec![species]),
+                        )
+                        .map(|pkg| (ps, ds_id, pkg.take().id().clone()))
+                    })
+                    .and_then(move |(ps, ds_id, pkg_id)| {
+                        ps.get_package_properties(pkg_id.clone())
+                            .map(|props| (ps, ds_id, pkg_id, props))
+                    })
+                    .and_then(move |(ps, ds_id, pkg_id, props)| {
+                        assert_eq!(props, expected);
+                        ps.update_package_properties(pkg_id.clone(), vec![strain])
+                            .map(|_| (ps, ds_id, pkg_id))
+                    })
+                    .and_then(move |(ps, ds_id, pkg_id)| {
+                        ps.get_package_properties(pkg_id)
+                            .map(|props| (ps, ds_id, props))
+                    })
+                    .and_then(move |(ps, ds_id, props)| {
+                        assert!(props.contains(&updated));
+                        Ok((ps, ds_id))
+                    })
+                    .and_then(move |(ps, ds_id)| ps.delete_dataset(ds_id)),
+            )
+        });
+
+        if result.is_err() {
+            panic!("{}", result.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn package_properties_round_trip_through_create() {
+        let _create = mock("POST", "/packages/")
+            .match_body(mockito::Matcher::Regex(
+                r#""properties":\[\{"key":"species","value":"mouse"\}\]"#.to_string(),
+            ))
+            .with_status(201)
+            .with_body(
+                r#"{ "content": { "id": "N:package:1", "name": "notes", "datasetId": "N:dataset:1",
+                "state": "READY", "packageType": "Text",
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" } }"#,
+            )
+            .create();
+        let _properties = mock("GET", "/packages/N:package:1/properties")
             .with_status(200)
-            .with_body(body)
+            .with_body(r#"[{ "key": "species", "value": "mouse", "dataType": "string" }]"#)
             .create();
 
-        let result = run(&ps, move |ps| ps.login(TEST_API_KEY, TEST_SECRET_KEY));
+        let species = model::Property::new("species".to_string(), "mouse".to_string());
+        let expected = vec![species.clone()];
+        let properties = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.create_package(
+                    "notes",
+                    "Text",
+                    DatasetNodeId::new("N:dataset:1"),
+                    None as Option<String>,
+                    Some(vec![species.clone()]),
+                )
+                .and_then(move |pkg| ps.get_package_properties(pkg.take().id().clone())),
+            )
+        })
+        .unwrap();
 
-        assert!(result.is_err());
-        assert!(ps.session_token().is_none());
+        assert_eq!(properties, expected);
+    }
 
-        if let Err(error) = result {
-            assert_eq!(
-                error.kind(),
-                crate::ps::Error::initiate_auth_error(
-                    "Pennsieve server Cognito config missing token pool client id."
-                )
-                .kind()
-            );
+    #[test]
+    fn creating_then_delete_package_successful() {
+        let result = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| {
+                        ps.create_dataset(
+                            rand_suffix("__agent-test-dataset".to_string()),
+                            Some("A test dataset created by the agent".to_string()),
+                        )
+                        .map(|ds| (ps, ds))
+                    })
+                    .and_then(move |(ps, ds)| Ok(ds.id().clone()).map(|id| (ps, id)))
+                    .and_then(move |(ps, ds_id)| {
+                        ps.create_package(
+                            rand_suffix("__agent-test-package"),
+                            "Text",
+                            ds_id.clone(),
+                            None as Option<String>,
+                            None,
+                        )
+                        .map(|pkg| (ps, ds_id, pkg))
+                    })
+                    .and_then(move |(ps, ds_id, pkg)| {
+                        let pkg_id = pkg.take().id().clone();
+                        ps.delete_package(pkg_id.clone())
+                            .map(|_| (ps, pkg_id, ds_id))
+                    })
+                    .and_then(move |(ps, pkg_id, ds_id)| {
+                        ps.get_package_by_id(pkg_id).then(|pkg| {
+                            assert!(pkg.is_err());
+                            Ok((ps, ds_id))
+                        })
+                    })
+                    .and_then(move |(ps, ds_id)| ps.delete_dataset(ds_id)),
+            )
+        });
+
+        if result.is_err() {
+            panic!("{}", result.unwrap_err().to_string());
         }
     }
 
     #[test]
     #[cfg_attr(not(feature = "mocks"), ignore)]
-    fn login_returns_error_when_token_pool_client_id_is_not_a_string() {
-        let ps = ps();
-        let body = " { \"tokenPool\": { \"appClientId\": [] } } ";
-
-        let _mock = mock("GET", "/authentication/cognito-config")
+    fn waiting_for_package_state_returns_the_package() {
+        let _package = mock("GET", "/packages/N:package:1")
+            .with_status(200)
+            .with_body(
+                r#"{ "content": { "id": "N:package:1", "name": "example",
+                "datasetId": "N:dataset:1", "state": "READY", "packageType": "CSV",
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" } }"#,
+            )
+            .create();
+
+        let package = run(&ps(), move |ps| {
+            ps.wait_for_package_state(
+                PackageId::new("N:package:1"),
+                model::PackageState::Ready,
+                time::Duration::from_millis(10),
+                time::Duration::from_secs(5),
+            )
+        })
+        .unwrap();
+
+        assert_eq!(package.id(), &PackageId::new("N:package:1"));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn waiting_for_package_state_times_out() {
+        let _package = mock("GET", "/packages/N:package:1")
             .with_status(200)
-            .with_body(body)
+            .with_body(
+                r#"{ "content": { "id": "N:package:1", "name": "example",
+                "datasetId": "N:dataset:1", "state": "IMPORTING", "packageType": "CSV",
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" } }"#,
+            )
             .create();
 
-        let result = run(&ps, move |ps| ps.login(TEST_API_KEY, TEST_SECRET_KEY));
-
-        assert!(result.is_err());
-        assert!(ps.session_token().is_none());
+        let timeout = time::Duration::from_millis(200);
+        let result = run(&ps(), move |ps| {
+            ps.wait_for_package_state(
+                PackageId::new("N:package:1"),
+                model::PackageState::Ready,
+                time::Duration::from_millis(50),
+                timeout,
+            )
+        });
 
-        if let Err(error) = result {
-            assert_eq!(
-                error.kind(),
-                crate::ps::Error::initiate_auth_error(
-                    "Pennsieve server Cognito config token pool client id could not be interpreted as a string."
-                )
-                    .kind()
-            );
+        match result.map_err(|err| err.kind().clone()) {
+            Err(ErrorKind::PackageStateTimeout {
+                package,
+                state,
+                timeout: elapsed,
+            }) => {
+                assert_eq!(package, "N:package:1");
+                assert_eq!(state, "READY");
+                assert_eq!(elapsed, timeout);
+            }
+            other => panic!("expected the wait to time out, got {:?}", other),
         }
     }
 
     #[test]
-    fn fetching_organizations_after_login_is_successful() {
-        let org = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_organizations()),
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn fetch_processing_packages_across_pages() {
+        let package = |id: &str, state: &str| {
+            format!(
+                r#"{{ "content": {{ "id": "{id}", "name": "{id}", "datasetId": "N:dataset:1",
+                "state": "{state}", "packageType": "CSV",
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                id = id,
+                state = state
             )
-        });
+        };
 
-        if org.is_err() {
-            panic!("{}", org.unwrap_err().to_string());
-        }
+        let _first = mock("GET", "/datasets/N:dataset:1/packages")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "pageSize".to_string(),
+                PACKAGE_PAGE_SIZE.to_string(),
+            ))
+            .with_status(200)
+            .with_body(format!(
+                r#"{{ "packages": [{}, {}, {}], "cursor": "page-2" }}"#,
+                package("N:package:1", "READY"),
+                package("N:package:2", "IMPORTING"),
+                package("N:package:3", "FAILED")
+            ))
+            .create();
+        let _second = mock("GET", "/datasets/N:dataset:1/packages")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("pageSize".to_string(), PACKAGE_PAGE_SIZE.to_string()),
+                mockito::Matcher::UrlEncoded("cursor".to_string(), "page-2".to_string()),
+            ]))
+            .with_status(200)
+            .with_body(format!(
+                r#"{{ "packages": [{}, {}], "cursor": null }}"#,
+                package("N:package:4", "RUNNING"),
+                package("N:package:5", "IMPORT_FAILED")
+            ))
+            .create();
+
+        let packages = run(&ps(), move |ps| {
+            ps.get_processing_packages(DatasetNodeId::new("N:dataset:1"))
+        })
+        .unwrap();
+
+        let ids: Vec<&String> = packages
+            .iter()
+            .map(|package| package.id().borrow())
+            .collect();
+        assert_eq!(ids, vec!["N:package:2", "N:package:4"]);
     }
 
     #[test]
-    fn fetching_user_after_login_is_successful() {
-        let user = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_user()),
-            )
-        });
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn existence_checks_make_head_requests() {
+        let _dataset = mock("HEAD", "/datasets/N:dataset:1")
+            .with_status(200)
+            .create();
+        let _missing_dataset = mock("HEAD", "/datasets/N:dataset:2")
+            .with_status(404)
+            .create();
+        let _package = mock("HEAD", "/packages/N:package:1")
+            .with_status(200)
+            .create();
+        let _forbidden_package = mock("HEAD", "/packages/N:package:2")
+            .with_status(403)
+            .create();
 
-        if user.is_err() {
-            panic!("{}", user.unwrap_err().to_string());
+        let ps = ps();
+        assert!(run(&ps, |ps| ps
+            .dataset_exists(DatasetNodeId::new("N:dataset:1")))
+        .unwrap());
+        assert!(!run(&ps, |ps| ps
+            .dataset_exists(DatasetNodeId::new("N:dataset:2")))
+        .unwrap());
+        assert!(run(&ps, |ps| ps.package_exists(PackageId::new("N:package:1"))).unwrap());
+        match run(&ps, |ps| ps.package_exists(PackageId::new("N:package:2")))
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::ApiError { status_code, .. } => {
+                assert_eq!(*status_code, StatusCode::FORBIDDEN)
+            }
+            kind => panic!("unexpected error: {:?}", kind),
         }
     }
 
     #[test]
-    fn updating_org_after_login_is_successful() {
-        let user = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_user().map(|user| (user, ps)))
-                    .and_then(move |(user, ps)| {
-                        let org = user.preferred_organization().clone();
-                        ps.set_preferred_organization(org.cloned()).map(|_| ps)
-                    })
-                    .and_then(move |ps| ps.get_user()),
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn only_uploaded_packages_in_a_dataset_are_processed() {
+        let package = |id: &str, state: &str| {
+            format!(
+                r#"{{ "content": {{ "id": "{id}", "name": "{id}", "datasetId": "N:dataset:1",
+                "state": "{state}", "packageType": "CSV",
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                id = id,
+                state = state
             )
-        });
+        };
+        let _packages = mock("GET", "/datasets/N:dataset:1/packages")
+            .match_query(mockito::Matcher::Any)
+            .with_status(200)
+            .with_body(format!(
+                r#"{{ "packages": [{}, {}, {}], "cursor": null }}"#,
+                package("N:package:1", "UPLOADED"),
+                package("N:package:2", "READY"),
+                package("N:package:3", "UPLOADED")
+            ))
+            .create();
+        let processed = mock("PUT", "/packages/N:package:1/process")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+        let ready = mock("PUT", "/packages/N:package:2/process")
+            .expect(0)
+            .create();
+        let _failed = mock("PUT", "/packages/N:package:3/process")
+            .with_status(400)
+            .with_body(r#"{ "message": "no sources" }"#)
+            .create();
 
-        if user.is_err() {
-            panic!("{}", user.unwrap_err().to_string());
-        }
+        let results = run(&ps(), move |ps| {
+            ps.process_dataset_packages(DatasetNodeId::new("N:dataset:1"))
+        })
+        .unwrap();
+
+        processed.assert();
+        ready.assert();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, PackageId::new("N:package:1"));
+        assert!(results[0].1.is_ok());
+        assert_eq!(results[1].0, PackageId::new("N:package:3"));
+        assert_eq!(
+            results[1].1.as_ref().unwrap_err().error_message(),
+            Some("no sources")
+        );
     }
 
     #[test]
-    fn fetching_organizations_fails_if_login_fails() {
-        let org = run(&ps(), move |ps| {
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn dataset_file_manifest_lists_the_sources_of_every_package() {
+        let package = |id: &str, package_type: &str| {
+            format!(
+                r#"{{ "content": {{ "id": "{id}", "name": "{id}", "datasetId": "N:dataset:1",
+                "state": "READY", "packageType": "{package_type}",
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                id = id,
+                package_type = package_type
+            )
+        };
+        let source = |id: u64, name: &str| {
+            format!(
+                r#"{{ "content": {{ "id": {id}, "name": "{name}", "fileType": "CSV",
+                "s3bucket": "bucket", "s3key": "key/{name}", "objectType": "source", "size": 1,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                id = id,
+                name = name
+            )
+        };
+        let _packages = mock("GET", "/datasets/N:dataset:1/packages")
+            .match_query(mockito::Matcher::Any)
+            .with_status(200)
+            .with_body(format!(
+                r#"{{ "packages": [{}, {}, {}], "cursor": null }}"#,
+                package("N:collection:1", "Collection"),
+                package("N:package:1", "CSV"),
+                package("N:package:2", "CSV")
+            ))
+            .create();
+        let collection_sources = mock("GET", "/packages/N:collection:1/sources")
+            .expect(0)
+            .create();
+        let _sources_1 = mock("GET", "/packages/N:package:1/sources")
+            .with_status(200)
+            .with_body(format!("[{}, {}]", source(1, "a.csv"), source(2, "b.csv")))
+            .create();
+        let _sources_2 = mock("GET", "/packages/N:package:2/sources")
+            .with_status(200)
+            .with_body(format!("[{}]", source(3, "c.csv")))
+            .create();
+
+        let manifest = run(&ps(), move |ps| {
             into_future_trait(
-                ps.login(TEST_API_KEY, "another-bad-secret")
-                    .and_then(move |_| ps.get_organizations()),
+                ps.get_dataset_file_manifest(DatasetNodeId::new("N:dataset:1"))
+                    .collect(),
             )
-        });
+        })
+        .unwrap();
 
-        assert!(org.is_err());
+        collection_sources.assert();
+        assert_eq!(
+            manifest
+                .iter()
+                .map(|(package_id, file)| (package_id.clone(), file.s3_url()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    PackageId::new("N:package:1"),
+                    "http://bucket.s3.amazonaws.com/key/a.csv".to_string()
+                ),
+                (
+                    PackageId::new("N:package:1"),
+                    "http://bucket.s3.amazonaws.com/key/b.csv".to_string()
+                ),
+                (
+                    PackageId::new("N:package:2"),
+                    "http://bucket.s3.amazonaws.com/key/c.csv".to_string()
+                ),
+            ]
+        );
     }
 
     #[test]
-    fn fetching_organization_by_id_is_successful() {
-        let org = run(&ps(), move |ps| {
-            into_future_trait(ps.login(TEST_API_KEY, TEST_SECRET_KEY).and_then(move |_| {
-                ps.get_organization_by_id(OrganizationId::new(FIXTURE_ORGANIZATION))
-            }))
-        });
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn dataset_without_a_doi_has_no_publication_doi() {
+        let _dataset = mock("GET", "/datasets/N:dataset:1")
+            .with_status(200)
+            .with_body(
+                r#"{ "organization": "N:organization:1", "owner": "N:user:1",
+                "content": { "id": "N:dataset:1", "name": "draft" },
+                "publication": { "status": "requested", "type": "publication" } }"#,
+            )
+            .create();
+        let _doi = mock("GET", "/datasets/N:dataset:1/doi")
+            .with_status(404)
+            .with_body("no doi")
+            .create();
 
-        if org.is_err() {
-            panic!("{}", org.unwrap_err().to_string());
-        }
+        let info = run(&ps(), move |ps| {
+            ps.get_dataset_publication(DatasetNodeId::new("N:dataset:1"))
+        })
+        .unwrap();
+
+        assert_eq!(info.doi(), None);
+        assert_eq!(info.status(), "requested");
+        assert!(!info.is_published());
     }
 
     #[test]
-    fn fetching_datasets_after_login_is_successful() {
-        let ds = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_datasets()),
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn fetch_datasets_with_collaborator_counts() {
+        let dataset = |id: u32, organization: &str| {
+            format!(
+                r#"{{ "organization": "{organization}", "owner": "N:user:{id}",
+                "content": {{ "id": "N:dataset:{id}", "name": "dataset {id}", "status": "NO_STATUS",
+                "automaticallyProcessPackages": false, "intId": {id},
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                id = id,
+                organization = organization
             )
-        });
+        };
+        let _datasets = mock("GET", "/datasets/")
+            .with_status(200)
+            .with_body(format!(
+                "[{}, {}, {}]",
+                dataset(1, "N:organization:1"),
+                dataset(2, "N:organization:2"),
+                dataset(3, "N:organization:1")
+            ))
+            .create();
+        let _first = mock("GET", "/datasets/N:dataset:1/collaborators/counts")
+            .with_status(200)
+            .with_body(r#"{ "users": 3, "organizations": 0, "teams": 1 }"#)
+            .create();
+        let other = mock("GET", "/datasets/N:dataset:2/collaborators/counts")
+            .expect(0)
+            .create();
+        let _third = mock("GET", "/datasets/N:dataset:3/collaborators/counts")
+            .with_status(200)
+            .with_body(r#"{ "users": 1, "organizations": 1, "teams": 0 }"#)
+            .create();
 
-        if ds.is_err() {
-            panic!("{}", ds.unwrap_err().to_string());
-        }
-    }
+        let datasets = run(&ps(), move |ps| {
+            ps.get_datasets_with_counts(OrganizationId::new("N:organization:1"))
+        })
+        .unwrap();
 
-    #[test]
-    fn fetching_datasets_fails_if_login_fails() {
-        let ds = run(&ps(), move |ps| into_future_trait(ps.get_datasets()));
-        assert!(ds.is_err());
+        let summary: Vec<(String, String, u32, u32, u32)> = datasets
+            .iter()
+            .map(|(dataset, counts)| {
+                (
+                    dataset.id().to_string(),
+                    dataset.owner().clone(),
+                    counts.users(),
+                    counts.organizations(),
+                    counts.teams(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("N:dataset:1".to_string(), "N:user:1".to_string(), 3, 0, 1),
+                ("N:dataset:3".to_string(), "N:user:3".to_string(), 1, 1, 0),
+            ]
+        );
+        other.assert();
     }
 
     #[test]
-    fn fetching_dataset_by_id_successful_if_logged_in_and_exists() {
-        let ds = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_dataset_by_id(DatasetNodeId::new(FIXTURE_DATASET))),
-            )
-        });
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn fetch_dataset_file_count() {
+        let _counts = mock("GET", "/datasets/N:dataset:1/packageTypeCounts")
+            .with_status(200)
+            .with_body(r#"{ "Collection": 3, "CSV": 4, "Image": 2, "TimeSeries": 1 }"#)
+            .create();
 
-        if ds.is_err() {
-            panic!("{}", ds.unwrap_err().to_string());
-        }
+        let count = run(&ps(), move |ps| {
+            ps.get_dataset_file_count(DatasetNodeId::new("N:dataset:1"))
+        })
+        .unwrap();
+
+        assert_eq!(count, 7);
     }
 
-    #[test]
-    fn fetching_dataset_by_name_successful_if_logged_in_and_exists() {
-        let ds = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_dataset_by_name(FIXTURE_DATASET_NAME)),
-            )
-        });
+    // Redirects /organizations/{from}/features -> /organizations/same-origin/features
+    // -> http://localhost:<port>/organizations/other-origin/features. The mock
+    // server listens on 127.0.0.1, so the last hop is to a different origin.
+    fn mock_redirect_chain(from: &str) -> Vec<mockito::Mock> {
+        let other_origin = mockito::server_url().replace("127.0.0.1", "localhost");
+        vec![
+            mock("GET", format!("/organizations/{}/features", from).as_str())
+                .match_header("x-session-id", "session")
+                .with_status(302)
+                .with_header("location", "/organizations/same-origin/features")
+                .create(),
+            mock("GET", "/organizations/same-origin/features")
+                .match_header("x-session-id", "session")
+                .with_status(307)
+                .with_header(
+                    "location",
+                    &format!("{}/organizations/other-origin/features", other_origin),
+                )
+                .create(),
+            mock("GET", "/organizations/other-origin/features")
+                .match_header("x-session-id", mockito::Matcher::Missing)
+                .match_header("authorization", mockito::Matcher::Missing)
+                .with_status(200)
+                .with_body(r#"{ "multipartUpload": true }"#)
+                .create(),
+        ]
+    }
 
-        if ds.is_err() {
-            panic!("{}", ds.unwrap_err().to_string());
-        }
+    #[test]
+    fn sessions_are_renewed_shortly_before_they_expire() {
+        assert!(!session_is_expiring(
+            1000 + SESSION_REFRESH_MARGIN_SECS + 1,
+            1000
+        ));
+        assert!(session_is_expiring(
+            1000 + SESSION_REFRESH_MARGIN_SECS,
+            1000
+        ));
+        assert!(session_is_expiring(900, 1000));
     }
 
     #[test]
-    fn fetching_dataset_generic_works_with_name() {
-        let ds = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_dataset(FIXTURE_DATASET_NAME)),
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn concurrent_requests_share_one_session_refresh() {
+        let user = mock("GET", "/user/")
+            .match_header("x-session-id", "refreshed")
+            .with_status(200)
+            .with_body(
+                r#"{ "id": "N:user:1", "email": "agent@pennsieve.io", "firstName": "Agent",
+                "lastName": "Smith", "preferredOrganization": null }"#,
             )
-        });
+            .expect(3)
+            .create();
 
-        if ds.is_err() {
-            panic!("{}", ds.unwrap_err().to_string());
+        let ps = Pennsieve::new(CONFIG.clone().with_auto_refresh(true));
+        ps.set_session_token(Some(SessionToken::new("expiring".to_string())));
+
+        // Stand in for the Cognito login with a refresh that counts itself:
+        let refreshes = sync::Arc::new(sync::atomic::AtomicUsize::new(0));
+        let refresh = {
+            let ps = ps.clone();
+            let refreshes = refreshes.clone();
+            into_future_trait(future::lazy(move || {
+                refreshes.fetch_add(1, sync::atomic::Ordering::SeqCst);
+                let token = SessionToken::new("refreshed".to_string());
+                ps.set_session_token(Some(token.clone()));
+                Ok(response::ApiSession::new(
+                    token,
+                    FIXTURE_ORGANIZATION.to_string(),
+                    0,
+                ))
+            }))
+            .shared()
+        };
+        {
+            let mut inner = ps.inner.lock().unwrap();
+            inner.session_expiry = Some(0);
+            inner.login_credentials = Some(LoginCredentials {
+                api_key: "key".to_string(),
+                api_secret: "secret".to_string(),
+                app_client_id: "client".to_string(),
+            });
+            inner.session_refresh = Some(refresh);
         }
-    }
 
-    #[test]
-    fn fetching_dataset_generic_works_with_id() {
-        let ds = run(&ps(), move |ps| {
+        run(&ps, move |ps| {
             into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_dataset(DatasetNodeId::new(FIXTURE_DATASET))),
+                ps.get_user()
+                    .join3(ps.get_user(), ps.get_user())
+                    .map(|_| ()),
             )
-        });
+        })
+        .unwrap();
 
-        if ds.is_err() {
-            panic!("{}", ds.unwrap_err().to_string());
-        }
+        assert_eq!(refreshes.load(sync::atomic::Ordering::SeqCst), 1);
+        user.assert();
     }
 
-    #[test]
-    fn fetching_child_dataset_by_id_is_successful_can_contains_child_packages_if_found_by_id() {
-        let ds = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_dataset_by_id(FIXTURE_DATASET.into())),
-            )
+    // Mocks a Cognito login, expected once, that starts a session with
+    // `access_token` in the fixture organization:
+    fn mock_cognito_login(access_token: &str) -> mockito::Mock {
+        let claims = serde_json::json!({
+            "custom:organization_node_id": FIXTURE_ORGANIZATION,
+            "exp": i64::from(i32::MAX),
         });
+        let id_token = format!(
+            "header.{}.signature",
+            base64_url::encode(&claims.to_string())
+        );
+        mock("POST", "/")
+            .match_header(
+                "x-amz-target",
+                "AWSCognitoIdentityProviderService.InitiateAuth",
+            )
+            .with_status(200)
+            .with_body(
+                serde_json::json!({
+                    "AuthenticationResult": {
+                        "AccessToken": access_token,
+                        "IdToken": id_token,
+                    }
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create()
+    }
 
-        if ds.is_err() {
-            panic!("{}", ds.unwrap_err().to_string());
+    // A client with auto refresh whose session is about to expire, which
+    // it renews by logging in to the mocked Cognito:
+    fn ps_with_expiring_session() -> Pennsieve {
+        let ps = Pennsieve::new(CONFIG.clone().with_auto_refresh(true));
+        ps.set_session_token(Some(SessionToken::new("expiring".to_string())));
+        {
+            let mut inner = ps.inner.lock().unwrap();
+            inner.session_expiry = Some(0);
+            inner.login_credentials = Some(LoginCredentials {
+                api_key: "key".to_string(),
+                api_secret: "secret".to_string(),
+                app_client_id: "client".to_string(),
+            });
+            inner.cognito_region = rusoto_core::region::Region::Custom {
+                name: "us-east-1".to_string(),
+                endpoint: mockito::server_url(),
+            };
         }
-
-        assert!(ds
-            .unwrap()
-            .get_package_by_id(Into::<model::PackageId>::into(FIXTURE_PACKAGE))
-            .is_some());
+        ps
     }
 
+    const USER_BODY: &str = r#"{ "id": "N:user:1", "email": "agent@pennsieve.io",
+        "firstName": "Agent", "lastName": "Smith", "preferredOrganization": null }"#;
+
     #[test]
-    fn fetching_child_dataset_by_name_is_successful_can_contains_child_packages_if_found_by_id() {
-        let ds = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_dataset_by_id(FIXTURE_DATASET.into())),
-            )
-        });
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn expiring_sessions_are_renewed_by_logging_in_again() {
+        let cognito_login = mock_cognito_login("refreshed");
+        let user = mock("GET", "/user/")
+            .match_header("x-session-id", "refreshed")
+            .with_status(200)
+            .with_body(USER_BODY)
+            .expect(2)
+            .create();
 
-        if ds.is_err() {
-            panic!("{}", ds.unwrap_err().to_string());
-        }
+        let ps = ps_with_expiring_session();
+        run(&ps, move |ps| {
+            into_future_trait(ps.get_user().join(ps.get_user()).map(|_| ()))
+        })
+        .unwrap();
 
-        assert!(ds
-            .unwrap()
-            .get_package_by_name(FIXTURE_PACKAGE_NAME)
-            .is_some());
+        assert_eq!(
+            ps.session_token(),
+            Some(SessionToken::new("refreshed".to_string()))
+        );
+        assert_eq!(
+            ps.inner.lock().unwrap().session_expiry,
+            Some(i64::from(i32::MAX))
+        );
+        cognito_login.assert();
+        user.assert();
     }
 
     #[test]
-    fn fetching_child_dataset_by_id_is_successful_can_contains_child_packages_if_found_by_name() {
-        let ds = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_dataset_by_name(FIXTURE_DATASET_NAME)),
-            )
-        });
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn renewing_a_session_keeps_the_current_organization() {
+        let cognito_login = mock_cognito_login("refreshed");
+        let user = mock("GET", "/user/")
+            .match_header("x-session-id", "refreshed")
+            .match_header("x-organization-id", "N:organization:switched")
+            .with_status(200)
+            .with_body(USER_BODY)
+            .expect(1)
+            .create();
 
-        if ds.is_err() {
-            panic!("{}", ds.unwrap_err().to_string());
-        }
+        let ps = ps_with_expiring_session();
+        let switched = OrganizationId::new("N:organization:switched");
+        ps.set_current_organization(Some(&switched));
+        run(&ps, move |ps| ps.get_user()).unwrap();
 
-        assert!(ds
-            .unwrap()
-            .get_package_by_id(Into::<model::PackageId>::into(FIXTURE_PACKAGE))
-            .is_some());
+        assert_eq!(ps.current_organization(), Some(switched));
+        cognito_login.assert();
+        user.assert();
     }
 
     #[test]
-    fn fetching_child_dataset_by_name_is_successful_can_contains_child_packages_if_found_by_name() {
-        let ds = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_dataset_by_name(FIXTURE_DATASET_NAME)),
-            )
-        });
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn a_pending_session_renewal_doesnt_keep_the_client_alive() {
+        let _cognito_login = mock_cognito_login("refreshed");
 
-        if ds.is_err() {
-            panic!("{}", ds.unwrap_err().to_string());
-        }
+        let ps = ps_with_expiring_session();
+        let client = sync::Arc::downgrade(&ps.inner);
+        // Start a renewal, then drop it before it's run:
+        drop(ps.ensure_fresh_session());
+        assert!(ps.inner.lock().unwrap().session_refresh.is_some());
 
-        assert!(ds
-            .unwrap()
-            .get_package_by_name(FIXTURE_PACKAGE_NAME)
-            .is_some());
+        drop(ps);
+        assert!(client.upgrade().is_none());
     }
 
     #[test]
-    fn fetching_child_dataset_fails_if_it_does_not_exists() {
-        let ds = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_dataset_by_name(FIXTURE_DATASET_NAME)),
-            )
-        });
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn redirects_are_followed_without_leaking_credentials() {
+        let _redirects = mock_redirect_chain("redirected");
 
-        if ds.is_err() {
-            panic!("{}", ds.unwrap_err().to_string());
-        }
+        let ps = Pennsieve::new(CONFIG.clone().with_follow_redirects(true));
+        ps.set_session_token(Some(SessionToken::new("session".to_string())));
 
-        assert!(ds.unwrap().get_package_by_name("doesnotexist").is_none());
+        let features = run(&ps, move |ps| {
+            ps.get_organization_features(OrganizationId::new("redirected"))
+        })
+        .unwrap();
+
+        assert!(features.multipart_upload_enabled());
     }
 
     #[test]
-    fn fetching_dataset_by_name_fails_if_it_does_not_exist() {
-        let ds = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_dataset_by_name("doesnotexist")),
-            )
-        });
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn redirects_beyond_the_limit_are_not_followed() {
+        let _redirects = mock_redirect_chain("too-far");
+
+        for config in &[
+            CONFIG.clone(),
+            CONFIG
+                .clone()
+                .with_follow_redirects(true)
+                .with_max_redirects(1),
+        ] {
+            let ps = Pennsieve::new(config.clone());
+            ps.set_session_token(Some(SessionToken::new("session".to_string())));
+
+            let result = run(&ps, move |ps| {
+                ps.get_organization_features(OrganizationId::new("too-far"))
+            });
 
-        assert!(ds.is_err());
+            match result.map_err(|err| err.kind().clone()) {
+                Err(ErrorKind::ApiError { status_code, .. }) => {
+                    assert!(status_code.is_redirection())
+                }
+                other => panic!("expected a redirect error, got {:?}", other),
+            }
+        }
     }
 
     #[test]
-    fn fetching_package_by_id_successful_if_logged_in_and_exists() {
-        let package = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_package_by_id(PackageId::new(FIXTURE_PACKAGE))),
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn fetch_organization_features() {
+        let _features = mock("GET", "/organizations/N:organization:1/features")
+            .with_status(200)
+            .with_body(
+                r#"{
+                    "multipartUpload": true,
+                    "maxFileSize": 5000000000,
+                    "timeSeriesViewer": false,
+                    "region": "us-east-1"
+                }"#,
             )
-        });
-        if package.is_err() {
-            panic!("{}", package.unwrap_err().to_string());
-        }
+            .create();
+
+        let features = run(&ps(), move |ps| {
+            ps.get_organization_features(OrganizationId::new("N:organization:1"))
+        })
+        .unwrap();
+
+        assert_eq!(features.len(), 4);
+        assert!(features.multipart_upload_enabled());
+        assert_eq!(features.max_file_size(), Some(5_000_000_000));
+        assert!(!features.is_enabled("timeSeriesViewer"));
+        assert!(!features.is_enabled("region"));
+        assert!(!features.is_enabled("unknownFlag"));
+        assert_eq!(
+            features.get("region").and_then(|region| region.as_str()),
+            Some("us-east-1")
+        );
     }
 
     #[test]
-    fn fetching_package_by_id_invalid_if_logged_in_and_exists() {
-        let package = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_package_by_id(PackageId::new("invalid_package_id"))),
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn a_single_source_file_is_fetched_by_id() {
+        let _file = mock("GET", "/packages/N:package:1/sources/1")
+            .with_status(200)
+            .with_body(
+                r#"{ "content": { "id": 1, "packageId": "N:package:1", "name": "brain.jpg",
+                "fileType": "JPEG", "s3bucket": "bucket", "s3key": "key/brain.jpg",
+                "objectType": "source", "size": 1024,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" } }"#,
             )
+            .create();
+        let _unknown = mock("GET", "/packages/N:package:1/sources/2")
+            .with_status(404)
+            .with_body(r#"{ "message": "file not found" }"#)
+            .create();
+
+        let file = run(&ps(), move |ps| {
+            ps.get_file(PackageId::new("N:package:1"), 1)
+        })
+        .unwrap()
+        .take();
+        let unknown = run(&ps(), move |ps| {
+            ps.get_file(PackageId::new("N:package:1"), 2)
         });
 
-        if let Err(e) = package {
-            match e.kind() {
-                // pennsieve api returns 403 in this case..it should really be 404 I think
-                ErrorKind::ApiError { status_code, .. } => assert_eq!(status_code.as_u16(), 404),
-                _ => assert!(false),
+        assert_eq!(file.id(), Some(1));
+        assert_eq!(file.s3_bucket(), "bucket");
+        assert_eq!(file.s3_key(), "key/brain.jpg");
+        match unknown.unwrap_err().kind() {
+            ErrorKind::ApiError { status_code, .. } => {
+                assert_eq!(*status_code, StatusCode::NOT_FOUND)
             }
+            kind => panic!("expected an api error, got {:?}", kind),
         }
     }
 
     #[test]
-    fn fetching_dataset_by_id_fails_if_logged_in_but_doesnt_exists() {
-        let ds = run(&ps(), move |ps| {
-            into_future_trait(ps.login(TEST_API_KEY, TEST_SECRET_KEY).and_then(move |_| {
-                ps.get_dataset_by_id(DatasetNodeId::new(
-                    "N:dataset:not-real-6803-4a67-ps20-83076774a5c7",
-                ))
-            }))
-        });
-        assert!(ds.is_err());
-    }
-
-    #[test]
-    fn fetch_dataset_user_collaborators() {
-        let collaborators = run(&ps(), move |ps| {
-            into_future_trait(ps.login(TEST_API_KEY, TEST_SECRET_KEY).and_then(move |_| {
-                ps.get_dataset_user_collaborators(DatasetNodeId::new(FIXTURE_DATASET))
-            }))
-        })
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn download_file_writes_the_source_to_disk() {
+        let source = format!("{}/{}", *TEST_DATA_DIR, "brain.jpg");
+        let file: model::File = serde_json::from_str(&format!(
+            r#"{{ "id": 1, "packageId": "N:package:1", "name": "brain.jpg",
+            "fileType": "JPEG", "s3bucket": "bucket", "s3key": "key/brain.jpg",
+            "objectType": "source", "size": {size},
+            "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }}"#,
+            size = fs::metadata(&source).unwrap().len()
+        ))
         .unwrap();
 
-        assert!(collaborators.iter().all(|c| c.role().is_some()));
-
-        let mut collaborators: Vec<(String, String)> = collaborators
-            .iter()
-            .map(|u| (u.first_name().clone(), u.role().unwrap().clone()))
-            .collect();
-        collaborators.sort();
-
-        let expected = ("Bo".to_string(), "owner".to_string());
+        let _url = mock("GET", "/packages/N:package:1/files/1")
+            .with_status(200)
+            .with_body(format!(
+                r#"{{ "url": "{}/presigned/brain.jpg" }}"#,
+                mockito::server_url()
+            ))
+            .create();
+        let _download = mock("GET", "/presigned/brain.jpg")
+            .with_status(200)
+            .with_body(fs::read(&source).unwrap())
+            .create();
 
-        assert!(collaborators.contains(&expected));
-    }
+        let dest_dir = std::env::temp_dir().join(rand_suffix("ps-download"));
+        fs::create_dir_all(&dest_dir).unwrap();
 
-    #[test]
-    fn fetch_dataset_team_collaborators() {
-        let collaborators = run(&ps(), move |ps| {
-            into_future_trait(ps.login(TEST_API_KEY, TEST_SECRET_KEY).and_then(move |_| {
-                ps.get_dataset_team_collaborators(DatasetNodeId::new(FIXTURE_DATASET))
-            }))
+        let (sender, receiver) = futures::sync::mpsc::unbounded();
+        let result = run(&ps(), move |ps| {
+            ps.download_file(&file, &dest_dir, ProgressSender::new(sender.clone()))
         })
         .unwrap();
-        assert!(collaborators.iter().all(|c| c.role().is_some()));
 
-        let mut collaborators: Vec<(String, String)> = collaborators
-            .iter()
-            .map(|t| (t.name().clone(), t.role().unwrap().clone()))
-            .collect();
-        collaborators.sort();
+        assert_eq!(result.file_name().unwrap(), "brain.jpg");
+        assert_eq!(
+            Checksum::of_file(&result).unwrap(),
+            Checksum::of_file(&source).unwrap()
+        );
+        assert!(!result.with_file_name("brain.jpg.part").exists());
 
-        let expected = vec![("Agent Devs".to_string(), "manager".to_string())];
+        let updates: Vec<ProgressUpdate> = receiver.collect().wait().unwrap();
+        assert!(updates.last().unwrap().is_done());
 
-        assert_eq!(collaborators, expected);
+        fs::remove_dir_all(result.parent().unwrap()).unwrap();
     }
 
-    #[test]
-    fn fetch_dataset_organization_role() {
-        let organization_role = run(&ps(), move |ps| {
-            into_future_trait(ps.login(TEST_API_KEY, TEST_SECRET_KEY).and_then(move |_| {
-                ps.get_dataset_organization_role(DatasetNodeId::new(FIXTURE_DATASET))
-            }))
-        })
+    // A file presigned to be downloaded from `/presigned/{name}`, with a
+    // partial download of its first `downloaded` bytes in a fresh directory.
+    // Returns the file, its contents, the directory, and the mock of the
+    // presigned URL.
+    fn partially_downloaded_file(
+        name: &str,
+        downloaded: usize,
+    ) -> (model::File, Vec<u8>, PathBuf, mockito::Mock) {
+        let contents = fs::read(format!("{}/{}", *TEST_DATA_DIR, "brain.jpg")).unwrap();
+        let file: model::File = serde_json::from_str(&format!(
+            r#"{{ "id": 1, "packageId": "N:package:{name}", "name": "{name}",
+            "fileType": "JPEG", "s3bucket": "bucket", "s3key": "key/{name}",
+            "objectType": "source", "size": {size},
+            "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }}"#,
+            name = name,
+            size = contents.len()
+        ))
         .unwrap();
+        let url = mock(
+            "GET",
+            format!("/packages/N:package:{}/files/1", name).as_str(),
+        )
+        .with_status(200)
+        .with_body(format!(
+            r#"{{ "url": "{}/presigned/{}" }}"#,
+            mockito::server_url(),
+            name
+        ))
+        .create();
 
-        let organization_role = (
-            organization_role.name().clone(),
-            organization_role.role().cloned(),
-        );
-        let expected = ("Test-Org".to_string(), None);
+        let dest_dir = std::env::temp_dir().join(rand_suffix("ps-download"));
+        fs::create_dir_all(&dest_dir).unwrap();
+        fs::write(
+            dest_dir.join(format!("{}.part", name)),
+            &contents[..downloaded],
+        )
+        .unwrap();
 
-        assert_eq!(organization_role, expected);
+        (file, contents, dest_dir, url)
     }
 
     #[test]
-    fn fetch_members() {
-        let members = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_user().map(|user| (user, ps)))
-                    .and_then(move |(user, ps)| {
-                        let org = user.preferred_organization().clone();
-                        ps.set_preferred_organization(org.cloned()).map(|_| ps)
-                    })
-                    .and_then(move |ps| ps.get_members()),
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn interrupted_downloads_resume_from_the_bytes_already_written() {
+        let (file, contents, dest_dir, _url) = partially_downloaded_file("resumed.jpg", 1000);
+        let rest = mock("GET", "/presigned/resumed.jpg")
+            .match_header("range", "bytes=1000-")
+            .with_status(206)
+            .with_header(
+                "content-range",
+                &format!("bytes 1000-{}/{}", contents.len() - 1, contents.len()),
             )
-        });
-        assert!(members.is_ok());
-    }
+            .with_body(&contents[1000..])
+            .expect(1)
+            .create();
 
-    #[test]
-    fn fetch_teams() {
-        let teams = run(&ps(), move |ps| {
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| ps.get_user().map(|user| (user, ps)))
-                    .and_then(move |(user, ps)| {
-                        let org = user.preferred_organization().clone();
-                        ps.set_preferred_organization(org.cloned()).map(|_| ps)
-                    })
-                    .and_then(move |ps| ps.get_teams()),
-            )
-        });
-        assert!(teams.is_ok());
+        let result = run(&ps(), move |ps| {
+            ps.download_file_resumable(&file, &dest_dir, ProgressIndicator::new())
+        })
+        .unwrap();
+
+        rest.assert();
+        assert_eq!(fs::read(&result).unwrap(), contents);
+        assert!(!result.with_file_name("resumed.jpg.part").exists());
+        fs::remove_dir_all(result.parent().unwrap()).unwrap();
     }
 
     #[test]
-    fn creating_then_updating_then_delete_dataset_successful() {
-        let new_dataset_name = rand_suffix("$new-test-dataset".to_string());
-        let result = run(&ps(), move |ps| {
-            let new_dataset_name = new_dataset_name.clone();
-            into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| {
-                        let new_dataset_name = new_dataset_name.clone();
-                        ps.create_dataset(
-                            rand_suffix("__agent-test-dataset".to_string()),
-                            Some("A test dataset created by the agent".to_string()),
-                        )
-                        .map(|ds| (ps, ds, new_dataset_name))
-                    })
-                    .and_then(move |(ps, ds, new_dataset_name)| {
-                        Ok(ds.id().clone()).map(|id| (ps, id, new_dataset_name))
-                    })
-                    .and_then(move |(ps, id, new_dataset_name)| {
-                        ps.update_dataset(
-                            id.clone(),
-                            new_dataset_name.clone(),
-                            None as Option<String>,
-                        )
-                        .map(|_| (ps, id, new_dataset_name))
-                    })
-                    .and_then(move |(ps, id, new_dataset_name)| {
-                        let id = id.clone();
-                        ps.get_dataset_by_id(id.clone())
-                            .and_then(move |ds| {
-                                assert_eq!(ds.take().name().clone(), new_dataset_name);
-                                Ok(id)
-                            })
-                            .map(|id| (ps, id))
-                    })
-                    .and_then(move |(ps, id)| ps.delete_dataset(id)),
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn downloads_start_over_when_the_wrong_range_is_returned() {
+        let (file, contents, dest_dir, _url) = partially_downloaded_file("restarted.jpg", 1000);
+        let wrong_range = mock("GET", "/presigned/restarted.jpg")
+            .match_header("range", "bytes=1000-")
+            .with_status(206)
+            .with_header(
+                "content-range",
+                &format!("bytes 0-{}/{}", contents.len() - 1, contents.len()),
             )
-        });
+            .with_body(&contents)
+            .expect(1)
+            .create();
+        let whole = mock("GET", "/presigned/restarted.jpg")
+            .match_header("range", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_body(&contents)
+            .expect(1)
+            .create();
+
+        let result = run(&ps(), move |ps| {
+            ps.download_file_resumable(&file, &dest_dir, ProgressIndicator::new())
+        })
+        .unwrap();
 
-        if result.is_err() {
-            panic!("{}", result.unwrap_err().to_string());
-        }
+        wrong_range.assert();
+        whole.assert();
+        assert_eq!(fs::read(&result).unwrap(), contents);
+        fs::remove_dir_all(result.parent().unwrap()).unwrap();
     }
 
     #[test]
-    fn creating_then_updating_then_delete_package_successful() {
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn failed_resumable_downloads_keep_their_partial_file() {
+        let (file, _, dest_dir, _url) = partially_downloaded_file("kept.jpg", 1000);
+        let failed = mock("GET", "/presigned/kept.jpg")
+            .with_status(503)
+            .expect(1)
+            .create();
+
+        let part_dir = dest_dir.clone();
         let result = run(&ps(), move |ps| {
+            ps.download_file_resumable(&file, &dest_dir, ProgressIndicator::new())
+        });
+
+        failed.assert();
+        assert!(result.is_err());
+        assert_eq!(
+            fs::metadata(part_dir.join("kept.jpg.part")).unwrap().len(),
+            1000
+        );
+        fs::remove_dir_all(part_dir).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn downloads_and_upload_events_are_sent_like_any_other_request() {
+        let (file, contents, dest_dir, _url) = partially_downloaded_file("shared.jpg", 0);
+        // The presigned URL is never sent the platform's auth headers:
+        let download = mock("GET", "/presigned/shared.jpg")
+            .match_header("user-agent", "pennsieve-agent/1.2.3")
+            .match_header("x-session-id", mockito::Matcher::Missing)
+            .match_header("x-organization-id", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_body(&contents)
+            .expect(1)
+            .create();
+        let events = mock(
+            "GET",
+            mockito::Matcher::Regex(r"^/upload/events/organizations/.*/id/shared$".to_string()),
+        )
+        .match_header("user-agent", "pennsieve-agent/1.2.3")
+        .match_header("x-session-id", "session")
+        .match_header("x-organization-id", FIXTURE_ORGANIZATION)
+        .with_status(200)
+        .with_header("content-type", "text/event-stream")
+        .with_body("data: {\"type\":\"completed\"}\n\n")
+        .expect(1)
+        .create();
+
+        let ps = Pennsieve::new(
+            CONFIG
+                .clone()
+                .with_user_agent("pennsieve-agent/1.2.3")
+                .unwrap()
+                .with_concurrency_limit(1),
+        );
+        ps.set_session_token(Some(SessionToken::new("session".to_string())));
+        ps.set_current_organization(Some(&OrganizationId::new(FIXTURE_ORGANIZATION)));
+
+        let downloaded = run(&ps, move |ps| {
+            ps.download_file(&file, &dest_dir, ProgressIndicator::new())
+        })
+        .unwrap();
+        run(&ps, move |ps| {
             into_future_trait(
-                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
-                    .and_then(move |_| {
-                        ps.create_dataset(
-                            rand_suffix("__agent-test-dataset".to_string()),
-                            Some("A test dataset created by the agent".to_string()),
-                        )
-                        .map(|ds| (ps, ds))
-                    })
-                    .and_then(move |(ps, ds)| Ok(ds.id().clone()).map(|id| (ps, id)))
-                    .and_then(move |(ps, ds_id)| {
-                        ps.create_package(
-                            rand_suffix("__agent-test-package"),
-                            "Text",
-                            ds_id.clone(),
-                            None as Option<String>,
-                        )
-                        .map(|pkg| (ps, ds_id, pkg))
-                    })
-                    .and_then(move |(ps, ds_id, pkg)| {
-                        let pkg_id = pkg.take().id().clone();
-                        ps.update_package(pkg_id.clone(), "new-package-name")
-                            .map(|_| (ps, pkg_id, ds_id))
-                    })
-                    .and_then(move |(ps, pkg_id, ds_id)| {
-                        ps.get_package_by_id(pkg_id).and_then(|pkg| {
-                            assert_eq!(pkg.take().name().clone(), "new-package-name".to_string());
-                            Ok((ps, ds_id))
-                        })
-                    })
-                    .and_then(move |(ps, ds_id)| ps.delete_dataset(ds_id)),
+                ps.watch_upload(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("shared"),
+                )
+                .collect(),
             )
-        });
+        })
+        .unwrap();
 
-        if result.is_err() {
-            panic!("{}", result.unwrap_err().to_string());
-        }
+        download.assert();
+        events.assert();
+        assert_eq!(fs::read(&downloaded).unwrap(), contents);
+        assert_eq!(ps.peak_in_flight_requests(), 1);
+        assert_eq!(ps.in_flight_requests(), 0);
+        fs::remove_dir_all(downloaded.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn verify_package_against_local_reports_matches_and_mismatches() {
+        let package = r#"{
+            "content": {
+                "id": "N:package:1",
+                "name": "example",
+                "datasetId": "N:dataset:1",
+                "state": "READY",
+                "packageType": "CSV",
+                "importId": "import-1",
+                "createdAt": "2020-01-01T00:00:00Z",
+                "updatedAt": "2020-01-01T00:00:00Z"
+            }
+        }"#;
+        let source = |name: &str| {
+            format!(
+                r#"{{ "content": {{ "name": "{name}", "fileType": "CSV", "s3bucket": "bucket",
+                "s3key": "key/{name}", "objectType": "source", "size": 0,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                name = name
+            )
+        };
+
+        let _package = mock("GET", "/packages/N:package:1")
+            .with_status(200)
+            .with_body(package)
+            .create();
+        let _sources = mock("GET", "/packages/N:package:1/sources")
+            .with_status(200)
+            .with_body(format!(
+                "[{}, {}]",
+                source("example.csv"),
+                source("empty_file")
+            ))
+            .create();
+        let _matching = mock("GET", "/upload/hash/id/import-1?fileName=example.csv")
+            .with_status(200)
+            .with_body(
+                r#"{ "hash": "70641a2ef9a116fa7f5b1376654657926a91a2bed913837c62f5598c77a3e191" }"#,
+            )
+            .create();
+        let _mismatching = mock("GET", "/upload/hash/id/import-1?fileName=empty_file")
+            .with_status(200)
+            .with_body(r#"{ "hash": "not-the-hash-of-an-empty-file" }"#)
+            .create();
+
+        let result = run(&ps(), move |ps| {
+            ps.verify_package_against_local(
+                PackageId::new("N:package:1"),
+                path::Path::new(&*TEST_DATA_DIR),
+            )
+        })
+        .unwrap();
+
+        assert_eq!(
+            result,
+            vec![
+                ("example.csv".to_string(), true),
+                ("empty_file".to_string(), false)
+            ]
+        );
     }
 
     #[test]
@@ -2011,9 +8000,8 @@ pub mod tests {
                     })
                     .and_then(move |(ps, ds)| Ok(ds.id().clone()).map(|id| (ps, id)))
                     .and_then(move |(ps, ds_id)| {
-                        ps.create_package(
+                        ps.create_collection(
                             rand_suffix("__agent-test-collection"),
-                            "Collection",
                             ds_id.clone(),
                             None as Option<String>,
                         )
@@ -2025,6 +8013,7 @@ pub mod tests {
                             "Text",
                             ds_id.clone(),
                             Some(col.id().clone()),
+                            None,
                         )
                         .map(|pkg| (ps, ds_id, pkg, col))
                     })
@@ -2052,9 +8041,633 @@ pub mod tests {
             )
         });
 
-        if result.is_err() {
-            panic!("{}", result.unwrap_err().to_string());
+        if result.is_err() {
+            panic!("{}", result.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn move_nested_collection_to_another_dataset() {
+        let result = run(&ps(), move |ps| {
+            let create_dataset = |ps: Pennsieve| {
+                ps.create_dataset(
+                    rand_suffix("__agent-test-dataset".to_string()),
+                    Some("A test dataset created by the agent".to_string()),
+                )
+                .map(|ds| ds.id().clone())
+            };
+
+            into_future_trait(
+                ps.login(TEST_API_KEY, TEST_SECRET_KEY)
+                    .and_then(move |_| {
+                        create_dataset(ps.clone())
+                            .join(create_dataset(ps.clone()))
+                            .map(|(source_id, target_id)| (ps, source_id, target_id))
+                    })
+                    .and_then(move |(ps, source_id, target_id)| {
+                        ps.create_collection(
+                            "__agent-test-collection",
+                            source_id.clone(),
+                            None as Option<String>,
+                        )
+                        .map(|col| (ps, source_id, target_id, col.id().clone()))
+                    })
+                    .and_then(move |(ps, source_id, target_id, col_id)| {
+                        ps.create_collection(
+                            "__agent-test-nested-collection",
+                            source_id.clone(),
+                            Some(col_id.clone()),
+                        )
+                        .join(ps.create_package(
+                            "__agent-test-package",
+                            "Text",
+                            source_id.clone(),
+                            Some(col_id.clone()),
+                            None,
+                        ))
+                        .map(|(nested, _)| (ps, source_id, target_id, col_id, nested.id().clone()))
+                    })
+                    .and_then(move |(ps, source_id, target_id, col_id, nested_id)| {
+                        ps.create_package(
+                            "__agent-test-nested-package",
+                            "Text",
+                            source_id.clone(),
+                            Some(nested_id),
+                            None,
+                        )
+                        .map(|_| (ps, source_id, target_id, col_id))
+                    })
+                    .and_then(move |(ps, source_id, target_id, col_id)| {
+                        ps.move_collection_to_dataset(col_id, target_id.clone())
+                            .map(|response| (ps, source_id, target_id, response))
+                    })
+                    .and_then(move |(ps, source_id, target_id, response)| {
+                        assert!(response.failures().is_empty());
+                        assert_eq!(response.success().len(), 2);
+                        ps.get_dataset_by_id(source_id.clone())
+                            .join(ps.get_dataset_by_id(target_id.clone()))
+                            .map(|(source, target)| (ps, source_id, target_id, source, target))
+                    })
+                    .and_then(move |(ps, source_id, target_id, source, target)| {
+                        // The source collection was emptied and removed:
+                        assert!(source
+                            .get_package_by_name("__agent-test-collection")
+                            .is_none());
+                        let col = target
+                            .get_package_by_name("__agent-test-collection")
+                            .unwrap();
+                        ps.get_package_by_id(col.id().clone())
+                            .map(|col| (ps, source_id, target_id, col))
+                    })
+                    .and_then(move |(ps, source_id, target_id, col)| {
+                        assert_eq!(col.children().unwrap().len(), 2);
+                        assert!(col.get_package_by_name("__agent-test-package").is_some());
+                        let nested = col
+                            .get_package_by_name("__agent-test-nested-collection")
+                            .unwrap();
+                        ps.get_package_by_id(nested.id().clone())
+                            .map(|nested| (ps, source_id, target_id, nested))
+                    })
+                    .and_then(move |(ps, source_id, target_id, nested)| {
+                        assert!(nested
+                            .get_package_by_name("__agent-test-nested-package")
+                            .is_some());
+                        ps.delete_dataset(source_id)
+                            .join(ps.delete_dataset(target_id))
+                            .map(|_| ())
+                    }),
+            )
+        });
+
+        if result.is_err() {
+            panic!("{}", result.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn mv_strict_fails_when_any_package_was_not_moved() {
+        let mv = mock("POST", "/data/move")
+            .with_status(200)
+            .with_body(
+                r#"{ "success": ["N:package:1"],
+                     "failures": [{ "id": "N:package:2", "error": "not found" },
+                                  { "id": "N:package:3", "error": "locked" }],
+                     "destination": "N:collection:1" }"#,
+            )
+            .create();
+
+        let result = run(&ps(), move |ps| {
+            ps.mv_strict(
+                vec!["N:package:1", "N:package:2", "N:package:3"],
+                Some("N:collection:1"),
+            )
+        });
+
+        mv.assert();
+        match result.map_err(|err| err.kind().clone()) {
+            Err(ErrorKind::ApiError {
+                status_code,
+                message,
+                ..
+            }) => {
+                assert_eq!(status_code, StatusCode::BAD_REQUEST);
+                assert!(message.contains("N:package:2: not found"));
+                assert!(message.contains("N:package:3: locked"));
+                assert!(!message.contains("N:package:1"));
+            }
+            other => panic!("expected an api error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn mv_strict_returns_the_response_when_everything_moved() {
+        let _mv = mock("POST", "/data/move")
+            .with_status(200)
+            .with_body(r#"{ "success": ["N:package:1"], "failures": [], "destination": null }"#)
+            .create();
+
+        let response = run(&ps(), move |ps| {
+            ps.mv_strict(vec!["N:package:1"], None as Option<PackageId>)
+        })
+        .unwrap();
+
+        assert_eq!(response.success(), &vec!["N:package:1".to_string()]);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn create_collection_creates_a_collection_package() {
+        let create = mock("POST", "/packages/")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "name": "scans",
+                "packageType": "Collection",
+                "properties": [],
+                "dataset": FIXTURE_DATASET,
+                "parent": "N:collection:1"
+            })))
+            .with_status(201)
+            .with_body(format!(
+                r#"{{ "content": {{ "id": "N:collection:2", "name": "scans",
+                "datasetId": "{}", "state": "READY", "packageType": "Collection",
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                FIXTURE_DATASET
+            ))
+            .create();
+
+        let collection = run(&ps(), move |ps| {
+            ps.create_collection(
+                "scans",
+                DatasetNodeId::new(FIXTURE_DATASET),
+                Some("N:collection:1"),
+            )
+        })
+        .unwrap();
+
+        create.assert();
+        assert_eq!(
+            collection
+                .package_type()
+                .cloned()
+                .map(model::PackageType::from),
+            Some(model::PackageType::Collection)
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn packages_are_fetched_in_bulk_in_the_order_asked_for() {
+        let package = |n: usize| {
+            mock("GET", format!("/packages/N:package:{}", n).as_str())
+                .with_status(200)
+                .with_body(format!(
+                    r#"{{ "content": {{ "id": "N:package:{n}", "name": "package {n}",
+                    "datasetId": "N:dataset:1", "packageType": "Text",
+                    "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                    n = n
+                ))
+                .expect(1)
+                .create()
+        };
+        let packages: Vec<_> = (1..=10).filter(|n| *n != 4).map(package).collect();
+        let _missing = mock("GET", "/packages/N:package:4")
+            .with_status(404)
+            .with_body(r#"{ "message": "package not found" }"#)
+            .create();
+
+        let ids: Vec<PackageId> = (1..=10)
+            .rev()
+            .map(|n| PackageId::new(format!("N:package:{}", n)))
+            .collect();
+        let expected = ids.clone();
+        let results = run(&ps(), move |ps| ps.get_packages(ids.clone())).unwrap();
+
+        for package in packages {
+            package.assert();
+        }
+        assert_eq!(results.len(), expected.len());
+        for (id, result) in expected.iter().zip(results.iter()) {
+            match result {
+                Ok(package) => assert_eq!(package.id(), id),
+                Err(err) => {
+                    assert_eq!(id, &PackageId::new("N:package:4"));
+                    assert_eq!(err.error_message(), Some("package not found"));
+                }
+            }
+        }
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn retried_package_creation_returns_the_existing_package() {
+        let create = mock("POST", "/packages/")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "name": "notes",
+                "importId": "client-import"
+            })))
+            .with_status(409)
+            .with_body(r#"{ "message": "a package with import id client-import exists" }"#)
+            .expect(1)
+            .create();
+        let _dataset = mock("GET", format!("/datasets/{}", FIXTURE_DATASET).as_str())
+            .with_status(200)
+            .with_body(format!(
+                r#"{{ "organization": "{org}", "owner": "N:user:1",
+                "children": [
+                    {{ "content": {{ "id": "N:package:1", "name": "notes", "datasetId": "{id}",
+                    "packageType": "Text", "importId": "other-import",
+                    "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }},
+                    {{ "content": {{ "id": "N:package:2", "name": "notes", "datasetId": "{id}",
+                    "packageType": "Text", "importId": "client-import",
+                    "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}
+                ],
+                "content": {{ "id": "{id}", "name": "dataset", "status": "NO_STATUS",
+                "automaticallyProcessPackages": false, "intId": 7,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                org = FIXTURE_ORGANIZATION,
+                id = FIXTURE_DATASET
+            ))
+            .create();
+
+        let create_notes = |import_id: &str| {
+            let import_id = ImportId::new(import_id);
+            run(&ps(), move |ps| {
+                ps.create_package_with_id(
+                    "notes",
+                    "Text",
+                    DatasetNodeId::new(FIXTURE_DATASET),
+                    None as Option<String>,
+                    None,
+                    import_id.clone(),
+                )
+            })
+        };
+
+        let package = create_notes("client-import").unwrap();
+        create.assert();
+        assert_eq!(package.id(), &PackageId::new("N:package:2"));
+
+        // A conflict over a package that can't be found is passed along:
+        let _conflict = mock("POST", "/packages/")
+            .with_status(409)
+            .with_body(r#"{ "message": "conflict" }"#)
+            .create();
+        let err = create_notes("missing-import").unwrap_err();
+        match err.kind() {
+            ErrorKind::ApiError { status_code, .. } => {
+                assert_eq!(*status_code, StatusCode::CONFLICT)
+            }
+            kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn dataset_packages_are_found_by_type_at_the_top_level_or_any_depth() {
+        let package = |id: &str, package_type: &str| {
+            format!(
+                r#"{{ "content": {{ "id": "{id}", "name": "{id}", "datasetId": "{dataset}",
+                "packageType": "{package_type}",
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                id = id,
+                dataset = FIXTURE_DATASET,
+                package_type = package_type
+            )
+        };
+        let _dataset = mock("GET", format!("/datasets/{}", FIXTURE_DATASET).as_str())
+            .with_status(200)
+            .with_body(format!(
+                r#"{{ "organization": "{org}", "owner": "N:user:1",
+                "children": [{}, {}, {}],
+                "content": {{ "id": "{id}", "name": "dataset", "status": "NO_STATUS",
+                "automaticallyProcessPackages": false, "intId": 7,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                package("N:package:1", "TimeSeries"),
+                package("N:collection:1", "Collection"),
+                package("N:package:2", "CSV"),
+                org = FIXTURE_ORGANIZATION,
+                id = FIXTURE_DATASET
+            ))
+            .create();
+        let _packages = mock(
+            "GET",
+            format!("/datasets/{}/packages", FIXTURE_DATASET).as_str(),
+        )
+        .match_query(mockito::Matcher::Any)
+        .with_status(200)
+        .with_body(format!(
+            r#"{{ "packages": [{}, {}, {}, {}], "cursor": null }}"#,
+            package("N:package:1", "TimeSeries"),
+            package("N:collection:1", "Collection"),
+            package("N:package:2", "CSV"),
+            package("N:package:3", "TimeSeries")
+        ))
+        .create();
+
+        let find = |recursive: bool| {
+            let ids: Vec<PackageId> = run(&ps(), move |ps| {
+                ps.get_dataset_packages_by_type(
+                    DatasetNodeId::new(FIXTURE_DATASET),
+                    model::PackageType::TimeSeries,
+                    recursive,
+                )
+            })
+            .unwrap()
+            .iter()
+            .map(|package| package.id().clone())
+            .collect();
+            ids
+        };
+
+        assert_eq!(find(false), vec![PackageId::new("N:package:1")]);
+        assert_eq!(
+            find(true),
+            vec![PackageId::new("N:package:1"), PackageId::new("N:package:3")]
+        );
+    }
+
+    #[test]
+    fn package_types_round_trip_through_their_names() {
+        for name in &["Collection", "CSV", "MSWord", "TimeSeries", "Unknown"] {
+            assert_eq!(model::PackageType::from(*name).as_str(), *name);
+        }
+        assert_eq!(
+            model::PackageType::from("Hologram"),
+            model::PackageType::Other("Hologram".to_string())
+        );
+        assert_eq!(String::from(model::PackageType::Mri), "MRI");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn package_children_are_streamed_a_page_at_a_time() {
+        let page = |offset: usize, ids: Vec<usize>| {
+            let children: Vec<String> = ids
+                .iter()
+                .map(|id| {
+                    format!(
+                        r#"{{ "content": {{ "id": "N:package:{id}", "name": "file {id}",
+                        "datasetId": "N:dataset:1", "state": "READY", "packageType": "Text",
+                        "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                        id = id
+                    )
+                })
+                .collect();
+            format!(
+                r#"{{ "limit": {limit}, "offset": {offset}, "totalCount": 150,
+                "children": [{children}] }}"#,
+                limit = PACKAGE_CHILDREN_PAGE_SIZE,
+                offset = offset,
+                children = children.join(",")
+            )
+        };
+        let pages: Vec<_> = [(0, 0..100), (100, 100..150)]
+            .iter()
+            .cloned()
+            .map(|(offset, ids)| {
+                mock("GET", "/packages/N:collection:1/children")
+                    .match_query(mockito::Matcher::AllOf(vec![
+                        mockito::Matcher::UrlEncoded(
+                            "limit".to_string(),
+                            PACKAGE_CHILDREN_PAGE_SIZE.to_string(),
+                        ),
+                        mockito::Matcher::UrlEncoded("offset".to_string(), offset.to_string()),
+                    ]))
+                    .with_status(200)
+                    .with_body(page(offset, ids.collect()))
+                    .expect(1)
+                    .create()
+            })
+            .collect();
+
+        let children = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.get_package_children_stream(PackageId::new("N:collection:1"))
+                    .collect(),
+            )
+        })
+        .unwrap();
+
+        for page in pages {
+            page.assert();
+        }
+        assert_eq!(children.len(), 150);
+        assert_eq!(children[0].id(), &PackageId::new("N:package:0"));
+        assert_eq!(children[149].id(), &PackageId::new("N:package:149"));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn checked_renames_refuse_names_taken_by_siblings() {
+        let package = |id: &str, name: &str, package_type: &str, extra: &str| {
+            format!(
+                r#"{{ "content": {{ "id": "{id}", "name": "{name}", "datasetId": "N:dataset:1",
+                "state": "READY", "packageType": "{package_type}",
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }}
+                {extra} }}"#,
+                id = id,
+                name = name,
+                package_type = package_type,
+                extra = extra
+            )
+        };
+        let _renamed = mock("GET", "/packages/N:package:1?includeAncestors=true")
+            .with_status(200)
+            .with_body(package(
+                "N:package:1",
+                "draft",
+                "Text",
+                &format!(
+                    r#", "ancestors": [{}]"#,
+                    package("N:collection:1", "notes", "Collection", "")
+                ),
+            ))
+            .create();
+        let _parent = mock("GET", "/packages/N:collection:1")
+            .with_status(200)
+            .with_body(package(
+                "N:collection:1",
+                "notes",
+                "Collection",
+                &format!(
+                    r#", "children": [{}, {}]"#,
+                    package("N:package:1", "draft", "Text", ""),
+                    package("N:package:2", "final", "Text", "")
+                ),
+            ))
+            .create();
+        let put = mock("PUT", "/packages/N:package:1")
+            .match_body(mockito::Matcher::Json(
+                serde_json::json!({ "name": "final v2" }),
+            ))
+            .with_status(200)
+            .with_body(package("N:package:1", "final v2", "Text", ""))
+            .expect(1)
+            .create();
+
+        let taken = run(&ps(), move |ps| {
+            ps.rename_package_checked(PackageId::new("N:package:1"), "final")
+        });
+        let renamed = run(&ps(), move |ps| {
+            ps.rename_package_checked(PackageId::new("N:package:1"), "final v2")
+        })
+        .unwrap();
+
+        match taken.unwrap_err().kind() {
+            ErrorKind::InvalidArguments { message } => assert!(message.contains("final")),
+            kind => panic!("expected invalid arguments, got {:?}", kind),
         }
+        put.assert();
+        assert_eq!(renamed.name(), "final v2");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn package_ancestors_run_from_the_top_level_down() {
+        let package = |id: &str, package_type: &str, ancestors: &str| {
+            format!(
+                r#"{{ "content": {{ "id": "{id}", "name": "{id}", "datasetId": "N:dataset:1",
+                "state": "READY", "packageType": "{package_type}",
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }},
+                "ancestors": [{ancestors}] }}"#,
+                id = id,
+                package_type = package_type,
+                ancestors = ancestors
+            )
+        };
+        let _nested = mock("GET", "/packages/N:package:1?includeAncestors=true")
+            .with_status(200)
+            .with_body(package(
+                "N:package:1",
+                "Text",
+                &[
+                    package("N:collection:1", "Collection", ""),
+                    package("N:collection:2", "Collection", ""),
+                ]
+                .join(","),
+            ))
+            .create();
+        let _top_level = mock("GET", "/packages/N:package:2?includeAncestors=true")
+            .with_status(200)
+            .with_body(package("N:package:2", "Text", ""))
+            .create();
+        let _missing = mock("GET", "/packages/N:package:3?includeAncestors=true")
+            .with_status(404)
+            .with_body("not found")
+            .create();
+
+        let (nested, top_level) = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.get_package_ancestors(PackageId::new("N:package:1"))
+                    .join(ps.get_package_ancestors(PackageId::new("N:package:2"))),
+            )
+        })
+        .unwrap();
+        let missing = run(&ps(), move |ps| {
+            ps.get_package_ancestors(PackageId::new("N:package:3"))
+        });
+
+        assert_eq!(
+            nested
+                .iter()
+                .map(|ancestor| ancestor.id().clone())
+                .collect::<Vec<_>>(),
+            vec![
+                PackageId::new("N:collection:1"),
+                PackageId::new("N:collection:2")
+            ]
+        );
+        assert!(top_level.is_empty());
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn move_collection_to_dataset_reports_partial_failures() {
+        let package = |id: &str, name: &str, package_type: &str, children: &str| {
+            format!(
+                r#"{{ "content": {{ "id": "{id}", "name": "{name}", "datasetId": "N:dataset:1",
+                "state": "READY", "packageType": "{package_type}",
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }},
+                "children": [{children}] }}"#,
+                id = id,
+                name = name,
+                package_type = package_type,
+                children = children
+            )
+        };
+
+        let _source = mock("GET", "/packages/N:collection:1")
+            .with_status(200)
+            .with_body(package(
+                "N:collection:1",
+                "collection",
+                "Collection",
+                &[
+                    package("N:package:1", "moved", "Text", ""),
+                    package("N:package:2", "stuck", "Text", ""),
+                ]
+                .join(","),
+            ))
+            .create();
+        let _target = mock("GET", "/datasets/N:dataset:2")
+            .with_status(200)
+            .with_body(
+                r#"{ "organization": "N:organization:1", "owner": "N:user:1", "children": [],
+                "content": { "id": "N:dataset:2", "name": "target", "status": "NO_STATUS",
+                "automaticallyProcessPackages": false, "intId": 2,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" } }"#,
+            )
+            .create();
+        let _create = mock("POST", "/packages/")
+            .with_status(201)
+            .with_body(package("N:collection:2", "collection", "Collection", ""))
+            .create();
+        let _move = mock("POST", "/data/move")
+            .with_status(200)
+            .with_body(
+                r#"{ "success": ["N:package:1"], "destination": "N:collection:2",
+                "failures": [{ "id": "N:package:2", "error": "locked" }] }"#,
+            )
+            .create();
+        let delete = mock("POST", "/data/delete").expect(0).create();
+
+        let response = run(&ps(), move |ps| {
+            ps.move_collection_to_dataset(
+                PackageId::new("N:collection:1"),
+                DatasetNodeId::new("N:dataset:2"),
+            )
+        })
+        .unwrap();
+
+        assert_eq!(response.success(), &vec!["N:package:1".to_string()]);
+        assert_eq!(response.failures().len(), 1);
+        assert_eq!(response.failures()[0].id(), "N:package:2");
+        assert_eq!(response.destination(), Some(&"N:collection:2".to_string()));
+        // the source collection still holds a package, so it must be kept:
+        delete.assert();
     }
 
     #[test]
@@ -2089,40 +8702,27 @@ pub mod tests {
         .unwrap()
         .unwrap();
 
-        let now = std::time::SystemTime::now();
-        let sleep_duration = std::time::Duration::new(5, 0); // 5 seconds
-        let timeout_duration = std::time::Duration::new(120, 0); // 2 minutes
-        'infinite: loop {
-            if now.elapsed().unwrap() < timeout_duration {
-                let current_package = run(&ps(), |ps| {
-                    let ps_clone = ps.clone();
-                    let package = package.clone();
-                    let f = ps
-                        .login(TEST_API_KEY, TEST_SECRET_KEY)
-                        .and_then(move |_| ps_clone.get_package_by_id(package.id().clone()));
-                    into_future_trait(f)
+        let result = run(&ps(), |ps| {
+            let ps_clone = ps.clone();
+            let package = package.clone();
+            let f = ps
+                .login(TEST_API_KEY, TEST_SECRET_KEY)
+                .and_then(move |_| {
+                    ps_clone
+                        .wait_for_package_state(
+                            package.id().clone(),
+                            model::PackageState::Uploaded,
+                            time::Duration::from_secs(5),
+                            time::Duration::from_secs(120),
+                        )
+                        .map(|package| (ps_clone, package))
                 })
-                .unwrap();
-                if current_package.state().unwrap().clone() == "UPLOADED".to_string() {
-                    let result = run(&ps(), |ps| {
-                        let ps_clone = ps.clone();
-                        let current_package_clone = current_package.clone();
-                        let f = ps.login(TEST_API_KEY, TEST_SECRET_KEY).and_then(move |_| {
-                            ps_clone.process_package(current_package_clone.id().clone())
-                        });
-                        into_future_trait(f)
-                    });
-                    if let Err(err) = result {
-                        println!("{}", err.to_string());
-                        panic!()
-                    }
-                    break 'infinite;
-                } else {
-                    thread::sleep(sleep_duration);
-                }
-            } else {
-                panic!()
-            }
+                .and_then(|(ps, package)| ps.process_package(package.id().clone()));
+            into_future_trait(f)
+        });
+        if let Err(err) = result {
+            println!("{}", err.to_string());
+            panic!()
         }
         run(&ps(), |ps| {
             let ps_clone = ps.clone();
@@ -2149,9 +8749,8 @@ pub mod tests {
                     })
                     .and_then(move |(ps, ds)| Ok(ds.id().clone()).map(|id| (ps, id)))
                     .and_then(move |(ps, ds_id)| {
-                        ps.create_package(
+                        ps.create_collection(
                             rand_suffix("__agent-test-collection"),
-                            "Collection",
                             ds_id.clone(),
                             None as Option<String>,
                         )
@@ -2163,6 +8762,7 @@ pub mod tests {
                             "Text",
                             ds_id.clone(),
                             None as Option<String>,
+                            None,
                         )
                         .map(|pkg| (ps, ds_id, pkg, col))
                     })
@@ -2183,9 +8783,20 @@ pub mod tests {
                             .and_then(|collection| {
                                 // Collection now has one child
                                 assert_eq!(collection.children().unwrap().len(), 1);
-                                Ok((ps, ds_id))
+                                Ok((ps, ds_id, col))
                             })
                     })
+                    .and_then(move |(ps, ds_id, col)| {
+                        // Moving a package that doesn't exist is an error
+                        ps.mv_strict(
+                            vec![PackageId::new("N:package:does-not-exist")],
+                            Some(col.id().clone()),
+                        )
+                        .then(|result| {
+                            assert!(result.is_err());
+                            Ok((ps, ds_id))
+                        })
+                    })
                     .and_then(move |(ps, ds_id)| ps.delete_dataset(ds_id)),
             )
         });
@@ -2246,47 +8857,288 @@ pub mod tests {
                 let dataset_id = dataset_id.clone();
                 let dataset_id_clone = dataset_id.clone();
 
-                let upload_futures = preview.into_iter().map(move |package| {
-                    let import_id = package.import_id().clone();
-                    let ps = ps.clone();
-                    let ps_clone = ps.clone();
-                    let organization_id = organization_id.clone();
+                let upload_futures = preview.into_iter().map(move |package| {
+                    let import_id = package.import_id().clone();
+                    let ps = ps.clone();
+                    let ps_clone = ps.clone();
+                    let organization_id = organization_id.clone();
+
+                    let dataset_id = dataset_id.clone();
+                    let package = package.clone();
+
+                    let head_file: PathBuf = files_clone[0].1.clone().into();
+                    let file_path = head_file.parent().unwrap().canonicalize().unwrap();
+
+                    let progress_indicator = ProgressIndicator::new();
+
+                    // upload using the retries function
+                    ps.upload_file_chunks_with_retries(
+                        &organization_id,
+                        &import_id,
+                        &file_path,
+                        package.files().to_vec(),
+                        progress_indicator.clone(),
+                        1,
+                    )
+                    .collect()
+                    .map(|_| (ps_clone, dataset_id))
+                    .and_then(move |(ps, dataset_id)| {
+                        ps.complete_upload(&organization_id, &import_id, &dataset_id, None, false)
+                            .map(|_| import_id)
+                    })
+                });
+
+                futures::future::join_all(upload_futures)
+                    .map(|import_ids| (ps_clone, dataset_id_clone, import_ids[0].clone()))
+            });
+
+        into_future_trait(f)
+    }
+
+    #[test]
+    fn upload_using_upload_service() {
+        // create upload
+        let result = run(&ps(), move |ps| {
+            let f = ps
+                .login(TEST_API_KEY, TEST_SECRET_KEY)
+                .and_then(move |_| {
+                    ps.create_dataset(
+                        rand_suffix("__agent-test-dataset".to_string()),
+                        Some("A test dataset created by the agent".to_string()),
+                    )
+                    .map(move |ds| (ps, ds.id().clone(), ds.int_id().clone()))
+                })
+                .and_then(|(ps, dataset_id, dataset_int_id)| {
+                    ps.get_user().map(|user| {
+                        (
+                            ps,
+                            dataset_id,
+                            user.preferred_organization().unwrap().clone(),
+                            dataset_int_id,
+                        )
+                    })
+                })
+                .and_then(move |(ps, dataset_id, organization_id, dataset_int_id)| {
+                    let files: Vec<(UploadId, String)> = add_upload_ids(&*TEST_FILES)
+                        .iter()
+                        .map(|(id, file)| (*id, format!("{}/{}", *TEST_DATA_DIR, file)))
+                        .collect();
+                    ps.preview_upload(
+                        &organization_id,
+                        &dataset_int_id,
+                        None as Option<String>,
+                        &files,
+                        false,
+                        false,
+                    )
+                    .map(|preview| (ps, dataset_id, organization_id, preview))
+                })
+                .and_then(move |(ps, dataset_id, organization_id, preview)| {
+                    let ps = ps.clone();
+                    let ps_clone = ps.clone();
+                    let dataset_id = dataset_id.clone();
+                    let dataset_id_clone = dataset_id.clone();
+
+                    let upload_futures = preview.into_iter().map(move |package| {
+                        let import_id = package.import_id().clone();
+                        let ps = ps.clone();
+                        let ps_clone = ps.clone();
+                        let organization_id = organization_id.clone();
+
+                        let dataset_id = dataset_id.clone();
+                        let package = package.clone();
+
+                        let file_path = path::Path::new(&TEST_DATA_DIR.to_string())
+                            .to_path_buf()
+                            .canonicalize()
+                            .unwrap();
+
+                        let progress_indicator = ProgressIndicator::new();
+
+                        ps.upload_file_chunks(
+                            &organization_id,
+                            &import_id,
+                            file_path,
+                            package.files().to_vec(),
+                            None,
+                            progress_indicator,
+                            1,
+                        )
+                        .collect()
+                        .map(|_| (ps_clone, dataset_id))
+                        .and_then(move |(ps, dataset_id)| {
+                            ps.complete_upload(
+                                &organization_id,
+                                &import_id,
+                                &dataset_id,
+                                None,
+                                false,
+                            )
+                        })
+                    });
+
+                    futures::future::join_all(upload_futures).map(|_| (ps_clone, dataset_id_clone))
+                })
+                .and_then(move |(ps, dataset_id)| ps.delete_dataset(dataset_id));
+
+            into_future_trait(f)
+        });
+
+        // check result
+        if result.is_err() {
+            println!("{}", result.unwrap_err().to_string());
+            panic!();
+        }
+    }
+
+    #[test]
+    fn upload_missing_parts_using_upload_service() {
+        // create upload
+        let result = run(&ps(), move |ps| {
+            let f = ps
+                .login(TEST_API_KEY, TEST_SECRET_KEY)
+                .and_then(move |_| {
+                    ps.create_dataset(
+                        rand_suffix("__agent-test-dataset".to_string()),
+                        Some("A test dataset created by the agent".to_string()),
+                    )
+                    .map(move |ds| (ps, ds.id().clone(), ds.int_id().clone()))
+                })
+                .and_then(|(ps, dataset_id, dataset_int_id)| {
+                    ps.get_user().map(|user| {
+                        (
+                            ps,
+                            dataset_id,
+                            user.preferred_organization().unwrap().clone(),
+                            dataset_int_id,
+                        )
+                    })
+                })
+                .and_then(move |(ps, dataset_id, organization_id, dataset_int_id)| {
+                    let enumerated_files = add_upload_ids(&*MEDIUM_TEST_FILES);
+                    ps.preview_upload(
+                        &organization_id,
+                        &dataset_int_id,
+                        Some((*MEDIUM_TEST_DATA_DIR).to_string()),
+                        &enumerated_files,
+                        false,
+                        false,
+                    )
+                    .map(|preview| (ps, dataset_id, organization_id, preview))
+                })
+                .and_then(move |(ps, dataset_id, organization_id, preview)| {
+                    let ps = ps.clone();
+                    let ps_clone = ps.clone();
+                    let dataset_id = dataset_id.clone();
+                    let dataset_id_clone = dataset_id.clone();
+
+                    let upload_futures = preview.into_iter().map(move |package| {
+                        let import_id = package.import_id().clone();
+                        let ps = ps.clone();
+                        let ps_clone = ps.clone();
+                        let organization_id = organization_id.clone();
+
+                        let dataset_id = dataset_id.clone();
+                        let package = package.clone();
 
-                    let dataset_id = dataset_id.clone();
-                    let package = package.clone();
+                        let file_path = path::Path::new(&MEDIUM_TEST_DATA_DIR.to_string())
+                            .to_path_buf()
+                            .canonicalize()
+                            .unwrap();
 
-                    let head_file: PathBuf = files_clone[0].1.clone().into();
-                    let file_path = head_file.parent().unwrap().canonicalize().unwrap();
+                        let progress_indicator = ProgressIndicator::new();
 
-                    let progress_indicator = ProgressIndicator::new();
+                        // only upload the first chunk
+                        ps.upload_file_chunks(
+                            &organization_id,
+                            &import_id,
+                            file_path.clone(),
+                            package.files().to_vec(),
+                            Some(response::FilesMissingParts {
+                                files: package
+                                    .files()
+                                    .to_vec()
+                                    .iter()
+                                    .map(|file| response::FileMissingParts {
+                                        file_name: file.file_name().to_string(),
+                                        missing_parts: vec![1],
+                                        expected_total_parts: 2,
+                                    })
+                                    .collect(),
+                            }),
+                            progress_indicator.clone(),
+                            1,
+                        )
+                        .collect()
+                        .map(|_| (ps_clone, dataset_id))
+                        .and_then(move |(ps, dataset_id)| {
+                            ps.get_upload_status(&organization_id, &import_id)
+                                .map(|status| (ps, dataset_id, organization_id, import_id, status))
+                        })
+                        .and_then(
+                            move |(ps, dataset_id, organization_id, import_id, status)| {
+                                // upload the rest of the chunks based on the status response
+                                ps.upload_file_chunks(
+                                    &organization_id,
+                                    &import_id,
+                                    file_path,
+                                    package.files().to_vec(),
+                                    status,
+                                    progress_indicator,
+                                    1,
+                                )
+                                .collect()
+                                .map(|_| (ps, dataset_id, organization_id, import_id))
+                            },
+                        )
+                        .and_then(
+                            move |(ps, dataset_id, organization_id, import_id)| {
+                                ps.complete_upload(
+                                    &organization_id,
+                                    &import_id,
+                                    &dataset_id,
+                                    None,
+                                    false,
+                                )
+                            },
+                        )
+                    });
 
-                    // upload using the retries function
-                    ps.upload_file_chunks_with_retries(
-                        &organization_id,
-                        &import_id,
-                        &file_path,
-                        package.files().to_vec(),
-                        progress_indicator.clone(),
-                        1,
-                    )
-                    .collect()
-                    .map(|_| (ps_clone, dataset_id))
-                    .and_then(move |(ps, dataset_id)| {
-                        ps.complete_upload(&organization_id, &import_id, &dataset_id, None, false)
-                            .map(|_| import_id)
-                    })
-                });
+                    futures::future::join_all(upload_futures).map(|_| (ps_clone, dataset_id_clone))
+                })
+                .and_then(move |(ps, dataset_id)| ps.delete_dataset(dataset_id));
 
-                futures::future::join_all(upload_futures)
-                    .map(|import_ids| (ps_clone, dataset_id_clone, import_ids[0].clone()))
-            });
+            into_future_trait(f)
+        });
 
-        into_future_trait(f)
+        // check result
+        if result.is_err() {
+            println!("{}", result.unwrap_err().to_string());
+            panic!();
+        }
     }
 
     #[test]
-    fn upload_using_upload_service() {
+    fn upload_to_upload_service_with_retries() {
+        let file_paths: Vec<String> = MEDIUM_TEST_FILES
+            .iter()
+            .map(|file_name| {
+                format!("{}/{}", MEDIUM_TEST_DATA_DIR.to_string(), file_name).to_string()
+            })
+            .collect();
+        let enumerated_files = add_upload_ids(&file_paths);
         // create upload
+        let result = upload_to_upload_service_with_delete(enumerated_files);
+
+        // check result
+        if result.is_err() {
+            println!("{}", result.unwrap_err().to_string());
+            panic!();
+        }
+    }
+
+    #[test]
+    fn upload_progress_can_be_streamed() {
         let result = run(&ps(), move |ps| {
             let f = ps
                 .login(TEST_API_KEY, TEST_SECRET_KEY)
@@ -2308,221 +9160,694 @@ pub mod tests {
                     })
                 })
                 .and_then(move |(ps, dataset_id, organization_id, dataset_int_id)| {
-                    let files: Vec<(UploadId, String)> = add_upload_ids(&*TEST_FILES)
-                        .iter()
-                        .map(|(id, file)| (*id, format!("{}/{}", *TEST_DATA_DIR, file)))
-                        .collect();
+                    let enumerated_files = add_upload_ids(&*MEDIUM_TEST_FILES);
                     ps.preview_upload(
                         &organization_id,
                         &dataset_int_id,
-                        None as Option<String>,
-                        &files,
+                        Some((*MEDIUM_TEST_DATA_DIR).to_string()),
+                        &enumerated_files,
                         false,
                         false,
                     )
                     .map(|preview| (ps, dataset_id, organization_id, preview))
                 })
                 .and_then(move |(ps, dataset_id, organization_id, preview)| {
-                    let ps = ps.clone();
-                    let ps_clone = ps.clone();
-                    let dataset_id = dataset_id.clone();
-                    let dataset_id_clone = dataset_id.clone();
+                    let package = preview.take().remove(0);
+                    let file_path = path::Path::new(&MEDIUM_TEST_DATA_DIR.to_string())
+                        .to_path_buf()
+                        .canonicalize()
+                        .unwrap();
+
+                    ps.upload_file_chunks_with_progress_stream(
+                        &organization_id,
+                        package.import_id(),
+                        &file_path,
+                        package.files().to_vec(),
+                        1,
+                    )
+                    .collect()
+                    .map(|updates| (ps, dataset_id, updates))
+                })
+                .and_then(move |(ps, dataset_id, updates)| {
+                    ps.delete_dataset(dataset_id).map(|_| updates)
+                });
+
+            into_future_trait(f)
+        });
+
+        match result {
+            Ok(updates) => {
+                assert!(!updates.is_empty());
+                assert!(updates.iter().any(|update| update.is_done()));
+            }
+            Err(err) => panic!("{}", err.to_string()),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn fetch_upload_statuses_for_many_imports() {
+        let _complete = mock(
+            "GET",
+            "/upload/status/organizations/N:organization:1/id/complete",
+        )
+        .with_status(200)
+        .with_body("null")
+        .create();
+        let _partial = mock(
+            "GET",
+            "/upload/status/organizations/N:organization:1/id/partial",
+        )
+        .with_status(200)
+        .with_body(
+            r#"{ "files": [{ "fileName": "brain.jpg", "missingParts": [1, 3],
+                "expectedTotalParts": 4 }] }"#,
+        )
+        .create();
+
+        let statuses = run(&ps(), move |ps| {
+            ps.get_upload_statuses(
+                &OrganizationId::new("N:organization:1"),
+                vec![ImportId::new("complete"), ImportId::new("partial")],
+            )
+        })
+        .unwrap();
+
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[&ImportId::new("complete")], None);
+        let partial = statuses[&ImportId::new("partial")].as_ref().unwrap();
+        assert_eq!(partial.files[0].missing_parts, vec![1, 3]);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn upload_progress_stream_yields_every_part() {
+        let _status = mock_upload_status();
+        let _chunk = upload_chunk_mock(200, CHUNK_UPLOADED).create();
+
+        let file = multipart_file(&MEDIUM_TEST_DATA_DIR, "earth.jpg", Some(1000 * 1000));
+        let expected_parts = (file.size() as f64 / (1000 * 1000) as f64).ceil() as usize;
+
+        let updates = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.upload_file_chunks_with_progress_stream(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import id"),
+                    &*MEDIUM_TEST_DATA_DIR,
+                    vec![file.clone()],
+                    2,
+                )
+                .collect(),
+            )
+        })
+        .unwrap();
+
+        assert_eq!(updates.len(), expected_parts);
+        assert!(updates
+            .iter()
+            .any(|update| update.bytes_sent() == update.size()));
+    }
+
+    fn upload_example_csv(ps: &Pennsieve) -> Result<Vec<ImportId>> {
+        let file = multipart_file(&TEST_DATA_DIR, "example.csv", Some(1000 * 1000));
+
+        run(ps, move |ps| {
+            into_future_trait(
+                ps.upload_file_chunks(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import"),
+                    &*TEST_DATA_DIR,
+                    vec![file.clone()],
+                    None,
+                    ProgressIndicator::new(),
+                    1,
+                )
+                .collect(),
+            )
+        })
+    }
+
+    // Uploads earth.jpg, which is a little over 7 MB, without the upload
+    // service dictating a chunk size:
+    fn upload_earth_jpg(ps: &Pennsieve) -> Result<Vec<ImportId>> {
+        let file = multipart_file(&MEDIUM_TEST_DATA_DIR, "earth.jpg", None);
+
+        run(ps, move |ps| {
+            into_future_trait(
+                ps.upload_file_chunks(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import"),
+                    &*MEDIUM_TEST_DATA_DIR,
+                    vec![file.clone()],
+                    None,
+                    ProgressIndicator::new(),
+                    1,
+                )
+                .collect(),
+            )
+        })
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn the_configured_chunk_size_is_used_when_the_service_dictates_none() {
+        let chunk = |chunk_number: usize, hits: usize| {
+            mock(
+                "POST",
+                mockito::Matcher::Regex(r"^/upload/chunk/organizations/.*/id/import$".to_string()),
+            )
+            .match_query(mockito::Matcher::UrlEncoded(
+                "chunkNumber".into(),
+                chunk_number.to_string(),
+            ))
+            .with_status(200)
+            .with_body(CHUNK_UPLOADED)
+            .expect(hits)
+            .create()
+        };
+
+        // By default, the file is sent in 5 MiB chunks:
+        let chunks = vec![chunk(0, 1), chunk(1, 1)];
+        upload_earth_jpg(&ps()).unwrap();
+        for chunk in chunks {
+            chunk.assert();
+        }
+
+        // ...but 8 MiB chunks fit it in one:
+        let chunks = vec![chunk(0, 1), chunk(1, 0)];
+        upload_earth_jpg(&Pennsieve::new(
+            CONFIG.clone().with_chunk_size(8 * 1024 * 1024).unwrap(),
+        ))
+        .unwrap();
+        for chunk in chunks {
+            chunk.assert();
+        }
+    }
+
+    // Records the start and completion of every file, along with its updates:
+    #[derive(Clone, Default)]
+    struct FileEvents(sync::Arc<sync::Mutex<Vec<String>>>);
+
+    impl ProgressCallback for FileEvents {
+        fn on_update(&self, update: &ProgressUpdate) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("update {}", update.bytes_sent()));
+        }
+
+        fn on_file_start(&self, file_path: &Path, size: u64) {
+            self.0.lock().unwrap().push(format!(
+                "start {} {}",
+                file_path.file_name().unwrap().to_string_lossy(),
+                size
+            ));
+        }
+
+        fn on_file_complete(&self, file_path: &Path) {
+            self.0.lock().unwrap().push(format!(
+                "complete {}",
+                file_path.file_name().unwrap().to_string_lossy()
+            ));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn file_start_and_completion_bracket_the_chunk_updates() {
+        let _chunk = upload_chunk_mock(200, CHUNK_UPLOADED).create();
+
+        let file = multipart_file(&TEST_DATA_DIR, "example.csv", Some(100));
+        let size = file.size();
+
+        let events = FileEvents::default();
+        let callback = events.clone();
+        run(&ps(), move |ps| {
+            into_future_trait(
+                ps.upload_file_chunks(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import"),
+                    &*TEST_DATA_DIR,
+                    vec![file.clone()],
+                    None,
+                    callback.clone(),
+                    1,
+                )
+                .collect(),
+            )
+        })
+        .unwrap();
+
+        let events = events.0.lock().unwrap();
+        assert!(events.len() > 3);
+        assert_eq!(events[0], format!("start example.csv {}", size));
+        assert_eq!(events[events.len() - 2], format!("update {}", size));
+        assert_eq!(events[events.len() - 1], "complete example.csv");
+        assert!(events[1..events.len() - 1]
+            .iter()
+            .all(|event| event.starts_with("update ")));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn chunks_sent_with_an_idempotency_key_are_retried() {
+        let failed = mock(
+            "POST",
+            mockito::Matcher::Regex(r"^/upload/chunk/organizations/.*/id/import$".to_string()),
+        )
+        .match_query(mockito::Matcher::Any)
+        .match_header(
+            "idempotency-key",
+            mockito::Matcher::Regex(r"^import:0:[0-9a-f]+$".to_string()),
+        )
+        .with_status(502)
+        .expect(1)
+        .create();
+        let succeeded = mock(
+            "POST",
+            mockito::Matcher::Regex(r"^/upload/chunk/organizations/.*/id/import$".to_string()),
+        )
+        .match_query(mockito::Matcher::Any)
+        .match_header(
+            "idempotency-key",
+            mockito::Matcher::Regex(r"^import:0:[0-9a-f]+$".to_string()),
+        )
+        .with_status(200)
+        .with_body(CHUNK_UPLOADED)
+        .expect(1)
+        .create();
+
+        let ps = Pennsieve::new(
+            CONFIG
+                .clone()
+                .with_retry_policy(RetryPolicy::new(2, 1))
+                .with_idempotency_key_header("Idempotency-Key")
+                .unwrap(),
+        );
+        let result = upload_example_csv(&ps);
+
+        assert!(result.is_ok(), "{:?}", result.err());
+        failed.assert();
+        succeeded.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn chunks_sent_without_an_idempotency_key_are_not_retried() {
+        let failed = mock(
+            "POST",
+            mockito::Matcher::Regex(r"^/upload/chunk/organizations/.*/id/import$".to_string()),
+        )
+        .match_query(mockito::Matcher::Any)
+        .match_header("idempotency-key", mockito::Matcher::Missing)
+        .with_status(502)
+        .expect(1)
+        .create();
+
+        let ps = Pennsieve::new(CONFIG.clone().with_retry_policy(RetryPolicy::new(2, 1)));
+        let result = upload_example_csv(&ps);
+
+        assert!(result.is_err());
+        failed.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn throttled_uploads_reduce_their_parallelism() {
+        let _status = mock_upload_status();
+        let _chunk = upload_chunk_mock(429, "slow down").create();
+
+        let file = multipart_file(&MEDIUM_TEST_DATA_DIR, "earth.jpg", Some(1000 * 1000));
+
+        let ps = Pennsieve::new(
+            CONFIG
+                .clone()
+                .with_retry_policy(RetryPolicy::new(2, 1))
+                .with_adaptive_parallelism(ParallelismBounds::new(1, 8)),
+        );
+        let parallelism = Parallelism::adaptive(8, ParallelismBounds::new(1, 8));
+        let upload_parallelism = parallelism.clone();
+
+        let result = run(&ps, move |ps| {
+            into_future_trait(
+                ps.upload_file_chunks_with_retries_controlled(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import id"),
+                    &*MEDIUM_TEST_DATA_DIR,
+                    vec![file.clone()],
+                    ProgressIndicator::new(),
+                    upload_parallelism.clone(),
+                    CancellationToken::new(),
+                )
+                .collect(),
+            )
+        });
 
-                    let upload_futures = preview.into_iter().map(move |package| {
-                        let import_id = package.import_id().clone();
-                        let ps = ps.clone();
-                        let ps_clone = ps.clone();
-                        let organization_id = organization_id.clone();
+        assert!(result.is_err());
+        assert!(parallelism.current() < 8);
+    }
 
-                        let dataset_id = dataset_id.clone();
-                        let package = package.clone();
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn uploads_hold_no_more_chunk_bytes_than_the_budget() {
+        let _status = mock_upload_status();
+        let _chunk = upload_chunk_mock(200, CHUNK_UPLOADED).create();
 
-                        let file_path = path::Path::new(&TEST_DATA_DIR.to_string())
-                            .to_path_buf()
-                            .canonicalize()
-                            .unwrap();
+        let chunk_size = 100 * 1000;
+        let file = multipart_file(&MEDIUM_TEST_DATA_DIR, "earth.jpg", Some(chunk_size));
+        let size = file.size();
 
-                        let progress_indicator = ProgressIndicator::new();
+        // Room for two chunks, though eight may be sent at once:
+        let ps = Pennsieve::new(CONFIG.clone().with_max_in_flight_bytes(2 * chunk_size));
+        let result = run(&ps, move |ps| {
+            into_future_trait(
+                ps.upload_file_chunks_with_retries(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import id"),
+                    &*MEDIUM_TEST_DATA_DIR,
+                    vec![file.clone()],
+                    ProgressIndicator::new(),
+                    8,
+                )
+                .collect(),
+            )
+        });
 
-                        ps.upload_file_chunks(
-                            &organization_id,
-                            &import_id,
-                            file_path,
-                            package.files().to_vec(),
-                            None,
-                            progress_indicator,
-                            1,
-                        )
-                        .collect()
-                        .map(|_| (ps_clone, dataset_id))
-                        .and_then(move |(ps, dataset_id)| {
-                            ps.complete_upload(
-                                &organization_id,
-                                &import_id,
-                                &dataset_id,
-                                None,
-                                false,
-                            )
-                        })
-                    });
+        assert!(result.is_ok(), "{:?}", result.err());
+        assert!(size > 8 * chunk_size);
+        assert!(ps.peak_in_flight_bytes() <= 2 * chunk_size);
+        assert!(ps.peak_in_flight_bytes() > 0);
+        assert_eq!(ps.in_flight_bytes(), 0);
+    }
 
-                    futures::future::join_all(upload_futures).map(|_| (ps_clone, dataset_id_clone))
-                })
-                .and_then(move |(ps, dataset_id)| ps.delete_dataset(dataset_id));
+    #[derive(Clone, Default)]
+    struct RetryEvents(sync::Arc<sync::Mutex<Vec<String>>>);
 
-            into_future_trait(f)
+    impl ProgressCallback for RetryEvents {
+        fn on_update(&self, _update: &ProgressUpdate) {}
+
+        fn on_retry(&self, attempt: usize, max: usize, err: &Error) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("retry {}/{}: {}", attempt, max, err));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn upload_retries_are_reported_to_the_progress_callback() {
+        let _status = mock_upload_status();
+        let _chunk = upload_chunk_mock(400, r#"{ "message": "try again" }"#).create();
+
+        let file = multipart_file(&TEST_DATA_DIR, "example.csv", Some(1000));
+
+        let ps = Pennsieve::new(CONFIG.clone().with_retry_policy(RetryPolicy::new(2, 1)));
+        let retries = RetryEvents::default();
+        let callback = retries.clone();
+        let result = run(&ps, move |ps| {
+            into_future_trait(
+                ps.upload_file_chunks_with_retries(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import id"),
+                    &*TEST_DATA_DIR,
+                    vec![file.clone()],
+                    callback.clone(),
+                    1,
+                )
+                .collect(),
+            )
         });
 
-        // check result
-        if result.is_err() {
-            println!("{}", result.unwrap_err().to_string());
-            panic!();
+        assert!(result.is_err());
+        assert_eq!(
+            *retries.0.lock().unwrap(),
+            vec![
+                "retry 1/2: api error: 400 Bad Request try again",
+                "retry 2/2: api error: 400 Bad Request try again"
+            ]
+        );
+    }
+
+    #[derive(Clone)]
+    struct CancelOnUpdate(CancellationToken);
+
+    impl ProgressCallback for CancelOnUpdate {
+        fn on_update(&self, _update: &ProgressUpdate) {
+            self.0.cancel();
         }
     }
 
     #[test]
-    fn upload_missing_parts_using_upload_service() {
-        // create upload
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn cancelled_uploads_stop_sending_chunks() {
+        let _status = mock_upload_status();
+        let chunk = upload_chunk_mock(200, CHUNK_UPLOADED).expect(1).create();
+
+        let file = multipart_file(&MEDIUM_TEST_DATA_DIR, "earth.jpg", Some(1000 * 1000));
+
+        let cancellation = CancellationToken::new();
         let result = run(&ps(), move |ps| {
-            let f = ps
-                .login(TEST_API_KEY, TEST_SECRET_KEY)
-                .and_then(move |_| {
-                    ps.create_dataset(
-                        rand_suffix("__agent-test-dataset".to_string()),
-                        Some("A test dataset created by the agent".to_string()),
-                    )
-                    .map(move |ds| (ps, ds.id().clone(), ds.int_id().clone()))
-                })
-                .and_then(|(ps, dataset_id, dataset_int_id)| {
-                    ps.get_user().map(|user| {
-                        (
-                            ps,
-                            dataset_id,
-                            user.preferred_organization().unwrap().clone(),
-                            dataset_int_id,
-                        )
-                    })
-                })
-                .and_then(move |(ps, dataset_id, organization_id, dataset_int_id)| {
-                    let enumerated_files = add_upload_ids(&*MEDIUM_TEST_FILES);
-                    ps.preview_upload(
-                        &organization_id,
-                        &dataset_int_id,
-                        Some((*MEDIUM_TEST_DATA_DIR).to_string()),
-                        &enumerated_files,
-                        false,
-                        false,
-                    )
-                    .map(|preview| (ps, dataset_id, organization_id, preview))
-                })
-                .and_then(move |(ps, dataset_id, organization_id, preview)| {
-                    let ps = ps.clone();
-                    let ps_clone = ps.clone();
-                    let dataset_id = dataset_id.clone();
-                    let dataset_id_clone = dataset_id.clone();
+            into_future_trait(
+                ps.upload_file_chunks_cancellable(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import id"),
+                    &*MEDIUM_TEST_DATA_DIR,
+                    vec![file.clone()],
+                    CancelOnUpdate(cancellation.clone()),
+                    1,
+                    cancellation.clone(),
+                )
+                .collect(),
+            )
+        });
 
-                    let upload_futures = preview.into_iter().map(move |package| {
-                        let import_id = package.import_id().clone();
-                        let ps = ps.clone();
-                        let ps_clone = ps.clone();
-                        let organization_id = organization_id.clone();
+        match result.map_err(|err| err.kind().clone()) {
+            Err(ErrorKind::Cancelled) => (),
+            other => panic!("expected the upload to be cancelled, got {:?}", other),
+        }
+        chunk.assert();
+    }
 
-                        let dataset_id = dataset_id.clone();
-                        let package = package.clone();
+    #[derive(Clone)]
+    struct ShutdownOnUpdate {
+        ps: Pennsieve,
+        updates: sync::Arc<sync::Mutex<Vec<ProgressUpdate>>>,
+    }
 
-                        let file_path = path::Path::new(&MEDIUM_TEST_DATA_DIR.to_string())
-                            .to_path_buf()
-                            .canonicalize()
-                            .unwrap();
+    impl ProgressCallback for ShutdownOnUpdate {
+        fn on_update(&self, update: &ProgressUpdate) {
+            self.ps.begin_shutdown();
+            self.updates.lock().unwrap().push(update.clone());
+        }
+    }
 
-                        let progress_indicator = ProgressIndicator::new();
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn shutdown_drains_the_current_file_without_starting_another() {
+        let _status = mock_upload_status();
+        let _chunk = upload_chunk_mock(200, CHUNK_UPLOADED).create();
 
-                        // only upload the first chunk
-                        ps.upload_file_chunks(
-                            &organization_id,
-                            &import_id,
-                            file_path.clone(),
-                            package.files().to_vec(),
-                            Some(response::FilesMissingParts {
-                                files: package
-                                    .files()
-                                    .to_vec()
-                                    .iter()
-                                    .map(|file| response::FileMissingParts {
-                                        file_name: file.file_name().to_string(),
-                                        missing_parts: vec![1],
-                                        expected_total_parts: 2,
-                                    })
-                                    .collect(),
-                            }),
-                            progress_indicator.clone(),
-                            1,
-                        )
-                        .collect()
-                        .map(|_| (ps_clone, dataset_id))
-                        .and_then(move |(ps, dataset_id)| {
-                            ps.get_upload_status(&organization_id, &import_id)
-                                .map(|status| (ps, dataset_id, organization_id, import_id, status))
-                        })
-                        .and_then(
-                            move |(ps, dataset_id, organization_id, import_id, status)| {
-                                // upload the rest of the chunks based on the status response
-                                ps.upload_file_chunks(
-                                    &organization_id,
-                                    &import_id,
-                                    file_path,
-                                    package.files().to_vec(),
-                                    status,
-                                    progress_indicator,
-                                    1,
-                                )
-                                .collect()
-                                .map(|_| (ps, dataset_id, organization_id, import_id))
-                            },
-                        )
-                        .and_then(
-                            move |(ps, dataset_id, organization_id, import_id)| {
-                                ps.complete_upload(
-                                    &organization_id,
-                                    &import_id,
-                                    &dataset_id,
-                                    None,
-                                    false,
-                                )
-                            },
-                        )
-                    });
+        let files: Vec<model::S3File> = vec!["brain.jpg", "pdf-sample.pdf"]
+            .into_iter()
+            .map(|name| multipart_file(&TEST_DATA_DIR, name, Some(4000)))
+            .collect();
 
-                    futures::future::join_all(upload_futures).map(|_| (ps_clone, dataset_id_clone))
-                })
-                .and_then(move |(ps, dataset_id)| ps.delete_dataset(dataset_id));
+        let ps = ps();
+        let updates = sync::Arc::new(sync::Mutex::new(vec![]));
+        let callback = ShutdownOnUpdate {
+            ps: ps.clone(),
+            updates: updates.clone(),
+        };
+        let result = run(&ps, move |ps| {
+            into_future_trait(
+                ps.upload_file_chunks_with_retries(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import id"),
+                    &*TEST_DATA_DIR,
+                    files.clone(),
+                    callback.clone(),
+                    1,
+                )
+                .collect(),
+            )
+        });
 
-            into_future_trait(f)
+        match result.map_err(|err| err.kind().clone()) {
+            Err(ErrorKind::ShuttingDown) => (),
+            other => panic!("expected the upload to shut down, got {:?}", other),
+        }
+
+        // Only the file that was in flight was uploaded, and all of it was:
+        let updates = updates.lock().unwrap();
+        let first_file = updates[0].file_path();
+        assert!(updates
+            .iter()
+            .all(|update| update.file_path() == first_file));
+        assert!(updates
+            .iter()
+            .any(|update| update.bytes_sent() == update.size()));
+    }
+
+    // Tracks how many files have started but not finished uploading:
+    #[derive(Clone, Default)]
+    struct OpenFiles {
+        open: sync::Arc<sync::Mutex<HashSet<PathBuf>>>,
+        finished: sync::Arc<sync::Mutex<HashSet<PathBuf>>>,
+        most_open: sync::Arc<sync::Mutex<usize>>,
+    }
+
+    impl ProgressCallback for OpenFiles {
+        fn on_update(&self, update: &ProgressUpdate) {
+            let mut open = self.open.lock().unwrap();
+            if update.bytes_sent() == update.size() {
+                open.remove(update.file_path());
+                self.finished
+                    .lock()
+                    .unwrap()
+                    .insert(update.file_path().to_path_buf());
+            } else {
+                open.insert(update.file_path().to_path_buf());
+            }
+            let mut most_open = self.most_open.lock().unwrap();
+            *most_open = (*most_open).max(open.len());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn file_parallelism_bounds_the_files_uploaded_at_once() {
+        let _status = mock_upload_status();
+        let _chunk = upload_chunk_mock(200, CHUNK_UPLOADED).create();
+
+        let names = [
+            "brain.jpg",
+            "example.csv",
+            "pdf-sample.pdf",
+            "test-tiny.png",
+        ];
+        let files: Vec<model::S3File> = names
+            .iter()
+            .map(|name| multipart_file(&TEST_DATA_DIR, name, Some(1000)))
+            .collect();
+
+        let ps = Pennsieve::new(CONFIG.clone().with_file_parallelism(2));
+        let open_files = OpenFiles::default();
+        let callback = open_files.clone();
+        let result = run(&ps, move |ps| {
+            into_future_trait(
+                ps.upload_file_chunks_with_retries(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import id"),
+                    &*TEST_DATA_DIR,
+                    files.clone(),
+                    callback.clone(),
+                    1,
+                )
+                .collect(),
+            )
         });
 
-        // check result
-        if result.is_err() {
-            println!("{}", result.unwrap_err().to_string());
-            panic!();
+        // The files are 39399, 1117, 7945 and 125 bytes, in 1000 byte chunks:
+        assert_eq!(result.unwrap().len(), 40 + 2 + 8 + 1);
+        assert_eq!(open_files.finished.lock().unwrap().len(), names.len());
+        assert!(*open_files.most_open.lock().unwrap() <= 2);
+    }
+
+    #[derive(Default)]
+    struct InMemoryAuditSink(sync::Mutex<Vec<UploadAuditRecord>>);
+
+    impl UploadAuditSink for InMemoryAuditSink {
+        fn on_file_uploaded(&self, record: &UploadAuditRecord) {
+            self.0.lock().unwrap().push(record.clone());
         }
     }
 
     #[test]
-    fn upload_to_upload_service_with_retries() {
-        let file_paths: Vec<String> = MEDIUM_TEST_FILES
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn uploaded_files_are_recorded_in_the_audit_sink() {
+        let _status = mock_upload_status();
+        let _chunk = upload_chunk_mock(200, CHUNK_UPLOADED).create();
+
+        let files: Vec<model::S3File> = vec!["brain.jpg", "pdf-sample.pdf"]
+            .into_iter()
+            .map(|name| multipart_file(&TEST_DATA_DIR, name, Some(4000)))
+            .collect();
+
+        let ps = ps();
+        let sink = sync::Arc::new(InMemoryAuditSink::default());
+        ps.set_upload_audit_sink(Some(sink.clone()));
+        run(&ps, move |ps| {
+            into_future_trait(
+                ps.upload_file_chunks_with_retries(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import id"),
+                    &*TEST_DATA_DIR,
+                    files.clone(),
+                    ProgressIndicator::new(),
+                    2,
+                )
+                .collect(),
+            )
+        })
+        .unwrap();
+
+        let mut records = sink.0.lock().unwrap().clone();
+        records.sort_by(|a, b| a.file_path().cmp(b.file_path()));
+        let recorded: Vec<(String, u64, String)> = records
             .iter()
-            .map(|file_name| {
-                format!("{}/{}", MEDIUM_TEST_DATA_DIR.to_string(), file_name).to_string()
+            .map(|record| {
+                (
+                    record
+                        .file_path()
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string(),
+                    record.size(),
+                    record.import_id().to_string(),
+                )
             })
             .collect();
-        let enumerated_files = add_upload_ids(&file_paths);
-        // create upload
-        let result = upload_to_upload_service_with_delete(enumerated_files);
+        assert_eq!(
+            recorded,
+            vec![
+                ("brain.jpg".to_string(), 39399, "import id".to_string()),
+                ("pdf-sample.pdf".to_string(), 7945, "import id".to_string()),
+            ]
+        );
+        assert!(records.iter().all(|record| record.sha256().len() == 64));
+    }
+
+    #[test]
+    fn uploading_from_a_different_path_than_the_preview_fails() {
+        let file = model::S3File::from_file_path(
+            format!("{}/{}", *MEDIUM_TEST_DATA_DIR, "earth.jpg"),
+            None,
+            None,
+        )
+        .unwrap();
 
-        // check result
-        if result.is_err() {
-            println!("{}", result.unwrap_err().to_string());
-            panic!();
+        let result = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.upload_file_chunks(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import id"),
+                    TEST_DATA_DIR.to_string(),
+                    vec![file.clone()],
+                    None,
+                    ProgressIndicator::new(),
+                    1,
+                )
+                .collect(),
+            )
+        });
+
+        match result.map_err(|err| err.kind().clone()) {
+            Err(ErrorKind::PathDoesNotExist { path }) => {
+                assert_eq!(path, Path::new(&*TEST_DATA_DIR).join("earth.jpg"))
+            }
+            other => panic!("expected a missing path error, got {:?}", other),
         }
     }
 
@@ -2555,6 +9880,768 @@ pub mod tests {
         }
     }
 
+    fn user_json(preferred_organization: Option<&str>) -> String {
+        format!(
+            "{{ \"id\": \"N:user:1\", \"firstName\": \"Agent\", \"lastName\": \"Test\", \
+             \"email\": \"{}\", \"preferredOrganization\": {} }}",
+            FIXTURE_EMAIL,
+            preferred_organization
+                .map(|org| format!("\"{}\"", org))
+                .unwrap_or_else(|| "null".to_string())
+        )
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn requests_in_flight_are_capped_by_the_concurrency_limit() {
+        let user = mock("GET", "/user/")
+            .with_status(200)
+            .with_body_from_fn(|w| {
+                std::thread::sleep(time::Duration::from_millis(50));
+                w.write_all(user_json(None).as_bytes())
+            })
+            .expect(6)
+            .create();
+
+        let ps = Pennsieve::new(CONFIG.clone().with_concurrency_limit(2));
+        let users = run(&ps, move |ps| {
+            into_future_trait(future::join_all((0..6).map(move |_| ps.get_user())))
+        })
+        .unwrap();
+
+        user.assert();
+        assert_eq!(users.len(), 6);
+        assert!(ps.peak_in_flight_requests() <= 2);
+        assert!(ps.peak_in_flight_requests() > 0);
+        assert_eq!(ps.in_flight_requests(), 0);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn members_and_teams_fall_back_to_the_preferred_organization() {
+        let user = mock("GET", "/user/")
+            .with_status(200)
+            .with_body(user_json(Some("N:organization:preferred")))
+            .expect(1)
+            .create();
+        let members = mock("GET", "/organizations/N:organization:preferred/members")
+            .with_status(200)
+            .with_body(format!("[{}]", user_json(None)))
+            .create();
+        let teams = mock("GET", "/organizations/N:organization:preferred/teams")
+            .with_status(200)
+            .with_body("[]")
+            .create();
+
+        let ps = ps();
+        let found = run(&ps, move |ps| ps.get_members_resolved()).unwrap();
+        // The organization is only looked up once:
+        run(&ps, move |ps| ps.get_teams_resolved()).unwrap();
+
+        user.assert();
+        members.assert();
+        teams.assert();
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            ps.current_organization(),
+            Some(OrganizationId::new("N:organization:preferred"))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn team_members_are_listed() {
+        let _members = mock(
+            "GET",
+            "/organizations/N:organization:1/teams/N:team:1/members",
+        )
+        .with_status(200)
+        .with_body(format!("[{}]", user_json(None)))
+        .create();
+        let _empty = mock(
+            "GET",
+            "/organizations/N:organization:1/teams/N:team:2/members",
+        )
+        .with_status(200)
+        .with_body("[]")
+        .create();
+
+        let members = |team: &str| {
+            let team = model::TeamId::new(team);
+            run(&ps(), move |ps| {
+                ps.get_team_members(OrganizationId::new("N:organization:1"), team.clone())
+            })
+            .unwrap()
+        };
+
+        assert_eq!(members("N:team:1").len(), 1);
+        assert!(members("N:team:2").is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn members_are_filtered_by_role() {
+        let member = |id: &str, role: &str| {
+            serde_json::json!({
+                "id": id,
+                "firstName": "First",
+                "lastName": "Last",
+                "email": format!("{}@example.com", id),
+                "preferredOrganization": null,
+                "role": role
+            })
+        };
+        let _members = mock("GET", "/organizations/N:organization:1/members")
+            .with_status(200)
+            .with_body(
+                serde_json::json!([
+                    member("N:user:1", "owner"),
+                    member("N:user:2", "admin"),
+                    member("N:user:3", "owner"),
+                    member("N:user:4", "curator")
+                ])
+                .to_string(),
+            )
+            .create();
+
+        let members_with = |role: model::Role| {
+            run(&ps(), move |ps| {
+                ps.get_members_by_role(OrganizationId::new("N:organization:1"), role.clone())
+            })
+            .unwrap()
+            .into_iter()
+            .map(|member| member.id().clone())
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            members_with(model::Role::Owner),
+            vec![UserId::new("N:user:1"), UserId::new("N:user:3")]
+        );
+        assert_eq!(
+            members_with(model::Role::Other("curator".to_string())),
+            vec![UserId::new("N:user:4")]
+        );
+        assert!(members_with(model::Role::Viewer).is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn resolving_the_organization_fails_without_a_preferred_one() {
+        let _user = mock("GET", "/user/")
+            .with_status(200)
+            .with_body(user_json(None))
+            .create();
+
+        let ps = ps();
+        let result = run(&ps, move |ps| ps.get_members_resolved());
+
+        match result.map_err(|err| err.kind().clone()) {
+            Err(ErrorKind::NoOrganizationSet) => (),
+            other => panic!("expected no organization to be set, got {:?}", other),
+        }
+        assert!(ps.current_organization().is_none());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn complete_upload_sends_the_processing_options() {
+        let complete = mock(
+            "POST",
+            mockito::Matcher::Regex(r"^/upload/complete/organizations/.*/id/import$".to_string()),
+        )
+        .match_query(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("datasetId".into(), FIXTURE_DATASET.into()),
+            mockito::Matcher::UrlEncoded("append".into(), "false".into()),
+        ]))
+        .match_body(mockito::Matcher::Json(serde_json::json!({
+            "workflow": "eeg",
+            "autoProcess": false,
+            "parameters": { "channels": 64, "montage": "10-20" }
+        })))
+        .with_status(200)
+        .with_body("[]")
+        .create();
+
+        let options = request::CompleteUpload::new()
+            .with_workflow("eeg")
+            .with_auto_process(false)
+            .with_parameter("montage", "10-20")
+            .with_parameter("channels", 64);
+        run(&ps(), move |ps| {
+            ps.complete_upload_with_options(
+                &OrganizationId::new(FIXTURE_ORGANIZATION),
+                &ImportId::new("import"),
+                &DatasetNodeId::new(FIXTURE_DATASET),
+                None,
+                false,
+                &options,
+            )
+        })
+        .unwrap();
+
+        complete.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn manifest_status_is_parsed() {
+        let _running = mock("GET", "/upload/manifest/id/import")
+            .with_status(200)
+            .with_body(r#"{ "importId": "import", "type": "append", "status": "RUNNING" }"#)
+            .create();
+        let _unknown = mock("GET", "/upload/manifest/id/other")
+            .with_status(200)
+            .with_body(r#"{ "importId": "other", "status": "QUEUED" }"#)
+            .create();
+
+        let (running, unknown) = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.get_manifest_status(&ImportId::new("import"))
+                    .join(ps.get_manifest_status(&ImportId::new("other"))),
+            )
+        })
+        .unwrap();
+
+        assert_eq!(running.import_id(), &ImportId::new("import"));
+        assert_eq!(running.job_type(), Some(model::PayloadType::Append));
+        assert_eq!(running.status(), &model::ManifestStatus::Running);
+        assert!(!running.status().is_terminal());
+        assert_eq!(unknown.job_type(), None);
+        assert_eq!(
+            unknown.take(),
+            model::ManifestStatus::Unknown("QUEUED".to_string())
+        );
+    }
+
+    #[test]
+    fn manifest_entries_expose_their_job_type() {
+        let manifests: response::Manifests = serde_json::from_str(
+            r#"[{ "manifest": { "type": "workflow", "importId": "import",
+                "content": { "files": ["earth.jpg"] } } }]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            manifests.entries()[0].job_type(),
+            model::PayloadType::Workflow
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn complete_upload_sends_no_body_by_default() {
+        let complete = mock(
+            "POST",
+            mockito::Matcher::Regex(r"^/upload/complete/organizations/.*/id/import$".to_string()),
+        )
+        .match_query(mockito::Matcher::Any)
+        .match_body("")
+        .with_status(200)
+        .with_body("[]")
+        .create();
+
+        run(&ps(), move |ps| {
+            ps.complete_upload(
+                &OrganizationId::new(FIXTURE_ORGANIZATION),
+                &ImportId::new("import"),
+                &DatasetNodeId::new(FIXTURE_DATASET),
+                None,
+                false,
+            )
+        })
+        .unwrap();
+
+        complete.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn files_are_appended_to_an_existing_package() {
+        let _dataset = mock("GET", format!("/datasets/{}", FIXTURE_DATASET).as_str())
+            .with_status(200)
+            .with_body(format!(
+                r#"{{ "organization": "{org}", "owner": "N:user:1",
+                "content": {{ "id": "{id}", "name": "dataset", "status": "NO_STATUS",
+                "automaticallyProcessPackages": false, "intId": 7,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                org = FIXTURE_ORGANIZATION,
+                id = FIXTURE_DATASET
+            ))
+            .create();
+
+        let file = multipart_file(&MEDIUM_TEST_DATA_DIR, "earth.jpg", Some(1000 * 1000))
+            .with_upload_id(UploadId::from(0));
+        let preview = mock(
+            "POST",
+            mockito::Matcher::Regex(r"^/upload/preview/organizations/.*$".to_string()),
+        )
+        .match_query(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("append".into(), "true".into()),
+            mockito::Matcher::UrlEncoded("dataset_id".into(), "7".into()),
+        ]))
+        .with_status(200)
+        .with_body(
+            serde_json::json!({
+                "packages": [{
+                    "packageName": "earth",
+                    "packageType": null,
+                    "fileType": null,
+                    "importId": "append-import",
+                    "files": [file],
+                    "groupSize": 1,
+                    "previewPath": null
+                }]
+            })
+            .to_string(),
+        )
+        .create();
+        let _status = mock_upload_status();
+        let chunk = mock(
+            "POST",
+            mockito::Matcher::Regex(
+                r"^/upload/chunk/organizations/.*/id/append-import$".to_string(),
+            ),
+        )
+        .match_query(mockito::Matcher::Any)
+        .with_status(200)
+        .with_body(CHUNK_UPLOADED)
+        .expect_at_least(1)
+        .create();
+        let complete = mock(
+            "POST",
+            mockito::Matcher::Regex(
+                r"^/upload/complete/organizations/.*/id/append-import$".to_string(),
+            ),
+        )
+        .match_query(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("append".into(), "true".into()),
+            mockito::Matcher::UrlEncoded("destinationId".into(), FIXTURE_PACKAGE.into()),
+        ]))
+        .with_status(200)
+        .with_body(
+            r#"[{ "manifest": { "type": "append", "importId": "append-import",
+                "content": { "files": ["earth.jpg"] } } }]"#,
+        )
+        .create();
+
+        let manifests = run(&ps(), move |ps| {
+            ps.append_to_package(
+                &OrganizationId::new(FIXTURE_ORGANIZATION),
+                &DatasetNodeId::new(FIXTURE_DATASET),
+                &PackageId::new(FIXTURE_PACKAGE),
+                vec![format!("{}/{}", *MEDIUM_TEST_DATA_DIR, "earth.jpg")],
+                ProgressIndicator::new(),
+                2,
+            )
+        })
+        .unwrap();
+
+        preview.assert();
+        chunk.assert();
+        complete.assert();
+        assert_eq!(manifests.entries().len(), 1);
+        assert_eq!(
+            manifests.entries()[0].files(),
+            &vec!["earth.jpg".to_string()]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn upload_plans_summarize_the_preview_without_uploading() {
+        let _dataset = mock("GET", format!("/datasets/{}", FIXTURE_DATASET).as_str())
+            .with_status(200)
+            .with_body(format!(
+                r#"{{ "organization": "{org}", "owner": "N:user:1",
+                "content": {{ "id": "{id}", "name": "dataset", "status": "NO_STATUS",
+                "automaticallyProcessPackages": false, "intId": 7,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                org = FIXTURE_ORGANIZATION,
+                id = FIXTURE_DATASET
+            ))
+            .create();
+        let csv = model::S3File::new("example.csv".to_string(), 100, None, None);
+        let png = model::S3File::new(
+            "test-tiny.png".to_string(),
+            20,
+            Some(vec!["images".to_string(), "tiny".to_string()]),
+            None,
+        );
+        let package = |name: &str, import_id: &str, file: &model::S3File| {
+            serde_json::json!({
+                "packageName": name,
+                "packageType": null,
+                "fileType": null,
+                "importId": import_id,
+                "files": [file],
+                "groupSize": 1,
+                "previewPath": null
+            })
+        };
+        let _preview = mock(
+            "POST",
+            mockito::Matcher::Regex(r"^/upload/preview/organizations/.*$".to_string()),
+        )
+        .match_query(mockito::Matcher::Any)
+        .with_status(200)
+        .with_body(
+            serde_json::json!({
+                "packages": [
+                    package("example", "import-1", &csv),
+                    package("test-tiny", "import-2", &png)
+                ]
+            })
+            .to_string(),
+        )
+        .create();
+        let chunks = mock(
+            "POST",
+            mockito::Matcher::Regex(r"^/upload/chunk/organizations/.*$".to_string()),
+        )
+        .expect(0)
+        .create();
+
+        let plan = run(&ps(), move |ps| {
+            ps.plan_upload(
+                &OrganizationId::new(FIXTURE_ORGANIZATION),
+                &DatasetNodeId::new(FIXTURE_DATASET),
+                &*TEST_DATA_DIR,
+                &[
+                    (UploadId::from(0), "example.csv"),
+                    (UploadId::from(1), "test-tiny.png"),
+                ],
+                false,
+                false,
+            )
+        })
+        .unwrap();
+        assert_eq!(plan.package_count(), 2);
+        assert_eq!(plan.total_bytes(), 120);
+        assert_eq!(
+            plan.destination_paths(),
+            vec![
+                PathBuf::from("example.csv"),
+                PathBuf::from("images/tiny/test-tiny.png")
+            ]
+        );
+        chunks.assert();
+
+        // Files that aren't there are caught before anything is previewed:
+        let err = run(&ps(), move |ps| {
+            ps.plan_upload(
+                &OrganizationId::new(FIXTURE_ORGANIZATION),
+                &DatasetNodeId::new(FIXTURE_DATASET),
+                &*TEST_DATA_DIR,
+                &[(UploadId::from(0), "does-not-exist.csv")],
+                false,
+                false,
+            )
+        })
+        .unwrap_err();
+        match err.kind() {
+            ErrorKind::PathDoesNotExist { .. } => {}
+            kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn a_persisted_upload_session_resumes_from_its_missing_parts() {
+        let _dataset = mock("GET", format!("/datasets/{}", FIXTURE_DATASET).as_str())
+            .with_status(200)
+            .with_body(format!(
+                r#"{{ "organization": "{org}", "owner": "N:user:1",
+                "content": {{ "id": "{id}", "name": "dataset", "status": "NO_STATUS",
+                "automaticallyProcessPackages": false, "intId": 7,
+                "createdAt": "2020-01-01T00:00:00Z", "updatedAt": "2020-01-01T00:00:00Z" }} }}"#,
+                org = FIXTURE_ORGANIZATION,
+                id = FIXTURE_DATASET
+            ))
+            .create();
+        let file = multipart_file(&MEDIUM_TEST_DATA_DIR, "earth.jpg", Some(1000 * 1000))
+            .with_upload_id(UploadId::from(0));
+        let _preview = mock(
+            "POST",
+            mockito::Matcher::Regex(r"^/upload/preview/organizations/.*$".to_string()),
+        )
+        .match_query(mockito::Matcher::Any)
+        .with_status(200)
+        .with_body(
+            serde_json::json!({
+                "packages": [{
+                    "packageName": "earth",
+                    "packageType": null,
+                    "fileType": null,
+                    "importId": "session-import",
+                    "files": [file],
+                    "groupSize": 1,
+                    "previewPath": null
+                }]
+            })
+            .to_string(),
+        )
+        .create();
+
+        let sessions = run(&ps(), move |ps| {
+            ps.begin_upload(
+                &OrganizationId::new(FIXTURE_ORGANIZATION),
+                &DatasetNodeId::new(FIXTURE_DATASET),
+                &*MEDIUM_TEST_DATA_DIR,
+                &[(UploadId::from(0), "earth.jpg")],
+                false,
+                false,
+            )
+        })
+        .unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].import_id(), &ImportId::new("session-import"));
+        assert_eq!(
+            sessions[0].dataset_id(),
+            &DatasetNodeId::new(FIXTURE_DATASET)
+        );
+
+        // As if the process died after sending all but one chunk, and the
+        // session was read back from disk:
+        let persisted = serde_json::to_string(&sessions[0]).unwrap();
+        let session: model::UploadSession = serde_json::from_str(&persisted).unwrap();
+        assert_eq!(session, sessions[0]);
+
+        let _status = mock(
+            "GET",
+            mockito::Matcher::Regex(
+                r"^/upload/status/organizations/.*/id/session-import$".to_string(),
+            ),
+        )
+        .with_status(200)
+        .with_body(
+            r#"{ "files": [{ "fileName": "earth.jpg", "missingParts": [3],
+                "expectedTotalParts": 8 }] }"#,
+        )
+        .create();
+        let missing_chunk = mock(
+            "POST",
+            mockito::Matcher::Regex(
+                r"^/upload/chunk/organizations/.*/id/session-import$".to_string(),
+            ),
+        )
+        .match_query(mockito::Matcher::UrlEncoded(
+            "chunkNumber".into(),
+            "3".into(),
+        ))
+        .with_status(200)
+        .with_body(CHUNK_UPLOADED)
+        .expect(1)
+        .create();
+        let other_chunks = mock(
+            "POST",
+            mockito::Matcher::Regex(
+                r"^/upload/chunk/organizations/.*/id/session-import$".to_string(),
+            ),
+        )
+        .match_query(mockito::Matcher::Any)
+        .with_status(200)
+        .with_body(CHUNK_UPLOADED)
+        .expect(0)
+        .create();
+
+        run(&ps(), move |ps| {
+            into_future_trait(
+                ps.resume_upload(&session, ProgressIndicator::new(), 2)
+                    .collect(),
+            )
+        })
+        .unwrap();
+
+        missing_chunk.assert();
+        other_chunks.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn upload_events_are_streamed() {
+        let event_stream = mock(
+            "GET",
+            mockito::Matcher::Regex(r"^/upload/events/organizations/.*/id/import$".to_string()),
+        )
+        .match_header("accept", "text/event-stream")
+        .with_status(200)
+        .with_header("content-type", "text/event-stream")
+        .with_body(
+            "data: {\"type\":\"progress\",\"partsUploaded\":3,\"partsTotal\":4}\n\n\
+             data: {\"type\":\"completed\"}\n\n",
+        )
+        .create();
+
+        let events = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.watch_upload(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import"),
+                )
+                .collect(),
+            )
+        })
+        .unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                model::UploadEvent::Progress {
+                    parts_uploaded: 3,
+                    parts_total: 4
+                },
+                model::UploadEvent::Completed
+            ]
+        );
+        event_stream.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn upload_events_are_polled_without_an_event_stream() {
+        let _events = mock(
+            "GET",
+            mockito::Matcher::Regex(r"^/upload/events/organizations/.*/id/import$".to_string()),
+        )
+        .with_status(404)
+        .create();
+        let in_progress = mock(
+            "GET",
+            mockito::Matcher::Regex(r"^/upload/status/organizations/.*/id/import$".to_string()),
+        )
+        .with_status(200)
+        .with_body(
+            r#"{ "files": [{ "fileName": "earth.jpg", "missingParts": [2, 3],
+                "expectedTotalParts": 5 }] }"#,
+        )
+        .expect(1)
+        .create();
+        let _completed = mock(
+            "GET",
+            mockito::Matcher::Regex(r"^/upload/status/organizations/.*/id/import$".to_string()),
+        )
+        .with_status(200)
+        .with_body("null")
+        .create();
+
+        let events = run(&ps(), move |ps| {
+            into_future_trait(
+                ps.watch_upload(
+                    &OrganizationId::new(FIXTURE_ORGANIZATION),
+                    &ImportId::new("import"),
+                )
+                .collect(),
+            )
+        })
+        .unwrap();
+
+        in_progress.assert();
+        assert_eq!(
+            events,
+            vec![
+                model::UploadEvent::Progress {
+                    parts_uploaded: 3,
+                    parts_total: 5
+                },
+                model::UploadEvent::Completed
+            ]
+        );
+    }
+
+    #[test]
+    fn files_with_the_same_name_cannot_be_appended_together() {
+        let err = run(&ps(), move |ps| {
+            ps.append_to_package(
+                &OrganizationId::new(FIXTURE_ORGANIZATION),
+                &DatasetNodeId::new(FIXTURE_DATASET),
+                &PackageId::new(FIXTURE_PACKAGE),
+                vec!["one/data.csv", "two/data.csv"],
+                ProgressIndicator::new(),
+                2,
+            )
+        })
+        .unwrap_err();
+
+        match err.kind() {
+            ErrorKind::InvalidArguments { message } => assert!(message.contains("data.csv")),
+            kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn appending_to_a_missing_dataset_doesnt_look_it_up_by_name() {
+        let _missing = mock("GET", "/datasets/N:dataset:missing")
+            .with_status(404)
+            .with_body("not found")
+            .create();
+        let by_name = mock("GET", "/datasets")
+            .match_query(mockito::Matcher::Any)
+            .expect(0)
+            .create();
+
+        let result = run(&ps(), move |ps| {
+            ps.append_to_package(
+                &OrganizationId::new(FIXTURE_ORGANIZATION),
+                &DatasetNodeId::new("N:dataset:missing"),
+                &PackageId::new(FIXTURE_PACKAGE),
+                vec![format!("{}/example.csv", *TEST_DATA_DIR)],
+                ProgressIndicator::new(),
+                2,
+            )
+        });
+
+        assert!(result.is_err());
+        by_name.assert();
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "mocks"), ignore)]
+    fn verify_upload_detects_a_corrupted_byte() {
+        let source = format!("{}/brain.jpg", *TEST_DATA_DIR);
+        let uploaded = Checksum::of_file(&source).unwrap();
+        let hash = mock("GET", "/upload/hash/id/import")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "fileName".into(),
+                "brain.jpg".into(),
+            ))
+            .with_status(200)
+            .with_body(format!(r#"{{ "hash": "{}" }}"#, uploaded.0))
+            .expect(2)
+            .create();
+
+        let dir = std::env::temp_dir().join(rand_suffix("ps-verify"));
+        fs::create_dir_all(&dir).unwrap();
+        let local = dir.join("brain.jpg");
+        let mut bytes = fs::read(&source).unwrap();
+        fs::write(&local, &bytes).unwrap();
+
+        let verified = run(&ps(), {
+            let local = local.clone();
+            move |ps| ps.verify_upload(&ImportId::new("import"), &local)
+        })
+        .unwrap();
+        assert_eq!(verified, uploaded);
+
+        bytes[100] ^= 0xff;
+        fs::write(&local, &bytes).unwrap();
+        let corrupted = Checksum::of_file(&local).unwrap();
+        let result = run(&ps(), {
+            let local = local.clone();
+            move |ps| ps.verify_upload(&ImportId::new("import"), &local)
+        });
+        fs::remove_dir_all(&dir).unwrap();
+
+        hash.assert();
+        match result.map_err(|err| err.kind().clone()) {
+            Err(ErrorKind::UploadError { message }) => {
+                assert!(message.contains(&uploaded.0));
+                assert!(message.contains(&corrupted.0));
+            }
+            other => panic!("expected an upload error, got {:?}", other),
+        }
+    }
+
     #[test]
     #[cfg_attr(target_os = "windows", ignore)]
     fn upload_directory() {
@@ -0,0 +1,297 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+//! Control over how many files and file chunks an upload sends
+//! concurrently.
+
+use std::sync::{Arc, Mutex};
+
+use futures::stream::{Fuse, FuturesUnordered, StreamFuture};
+use futures::{Async, IntoFuture, Poll, Stream};
+
+use crate::ps::config::ParallelismBounds;
+use crate::ps::{ErrorKind, Result};
+
+#[derive(Debug)]
+struct State {
+    current: usize,
+    successes: usize,
+}
+
+/// The number of chunks an upload may have in flight at once. Clones share
+/// the same count.
+///
+/// Within its bounds the count behaves like a TCP congestion window: it is
+/// halved whenever a chunk is throttled, and grows by one once as many chunks
+/// as are currently allowed in flight have succeeded in a row.
+#[derive(Clone, Debug)]
+pub struct Parallelism {
+    bounds: ParallelismBounds,
+    state: Arc<Mutex<State>>,
+}
+
+impl Parallelism {
+    /// A parallelism that never changes.
+    pub fn fixed(parallelism: usize) -> Self {
+        Self::adaptive(
+            parallelism,
+            ParallelismBounds::new(parallelism, parallelism),
+        )
+    }
+
+    /// A parallelism starting at `initial` that adapts within `bounds`.
+    pub fn adaptive(initial: usize, bounds: ParallelismBounds) -> Self {
+        Self {
+            bounds,
+            state: Arc::new(Mutex::new(State {
+                current: bounds.clamp(initial),
+                successes: 0,
+            })),
+        }
+    }
+
+    /// Returns the number of chunks currently allowed in flight.
+    pub fn current(&self) -> usize {
+        self.state.lock().unwrap().current
+    }
+
+    /// Records that a chunk was uploaded successfully.
+    pub fn on_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.successes += 1;
+        if state.successes >= state.current {
+            state.current = self.bounds.clamp(state.current + 1);
+            state.successes = 0;
+        }
+    }
+
+    /// Records that a chunk was throttled or failed on the server.
+    pub fn on_throttled(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.current = self.bounds.clamp(state.current / 2);
+        state.successes = 0;
+    }
+
+    /// Records the outcome of uploading a chunk. Only throttling (429) and
+    /// server errors shrink the parallelism; any other failure isn't caused
+    /// by sending too much at once, so it's ignored.
+    pub fn on_result<T>(&self, result: &Result<T>) {
+        match result {
+            Ok(_) => self.on_success(),
+            Err(err) => match err.kind() {
+                ErrorKind::ApiError { status_code, .. }
+                    if status_code.as_u16() == 429 || status_code.is_server_error() =>
+                {
+                    self.on_throttled()
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+/// Like `Stream::buffer_unordered`, except the number of futures run at once
+/// is read from a `Parallelism` on every poll rather than fixed up front.
+#[must_use = "streams do nothing unless polled"]
+pub struct BufferAdaptive<S>
+where
+    S: Stream,
+    S::Item: IntoFuture<Error = S::Error>,
+{
+    stream: Fuse<S>,
+    in_flight: FuturesUnordered<<S::Item as IntoFuture>::Future>,
+    parallelism: Parallelism,
+}
+
+impl<S> BufferAdaptive<S>
+where
+    S: Stream,
+    S::Item: IntoFuture<Error = S::Error>,
+{
+    pub fn new(stream: S, parallelism: Parallelism) -> Self {
+        Self {
+            stream: stream.fuse(),
+            in_flight: FuturesUnordered::new(),
+            parallelism,
+        }
+    }
+}
+
+impl<S> Stream for BufferAdaptive<S>
+where
+    S: Stream,
+    S::Item: IntoFuture<Error = S::Error>,
+{
+    type Item = <S::Item as IntoFuture>::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        // Top up the futures in flight to whatever is allowed right now:
+        while self.in_flight.len() < self.parallelism.current() {
+            match self.stream.poll()? {
+                Async::Ready(Some(future)) => self.in_flight.push(future.into_future()),
+                Async::Ready(None) | Async::NotReady => break,
+            }
+        }
+
+        match self.in_flight.poll()? {
+            Async::Ready(Some(item)) => Ok(Async::Ready(Some(item))),
+            Async::Ready(None) if self.stream.is_done() => Ok(Async::Ready(None)),
+            Async::Ready(None) | Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// Like `Stream::flatten`, except up to `limit` of the inner streams are
+/// polled at once, and their items are yielded as they arrive rather than
+/// one inner stream after another. The next inner stream is only taken
+/// from `stream` once one of those being polled has ended.
+#[must_use = "streams do nothing unless polled"]
+pub struct FlattenUnordered<S>
+where
+    S: Stream,
+    S::Item: Stream<Error = S::Error>,
+{
+    stream: Fuse<S>,
+    active: FuturesUnordered<StreamFuture<S::Item>>,
+    limit: usize,
+}
+
+impl<S> FlattenUnordered<S>
+where
+    S: Stream,
+    S::Item: Stream<Error = S::Error>,
+{
+    pub fn new(stream: S, limit: usize) -> Self {
+        Self {
+            stream: stream.fuse(),
+            active: FuturesUnordered::new(),
+            limit: limit.max(1),
+        }
+    }
+}
+
+impl<S> Stream for FlattenUnordered<S>
+where
+    S: Stream,
+    S::Item: Stream<Error = S::Error>,
+{
+    type Item = <S::Item as Stream>::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            // Top up the inner streams being polled to the limit:
+            while self.active.len() < self.limit {
+                match self.stream.poll()? {
+                    Async::Ready(Some(inner)) => self.active.push(inner.into_future()),
+                    Async::Ready(None) | Async::NotReady => break,
+                }
+            }
+
+            match self.active.poll() {
+                Ok(Async::Ready(Some((Some(item), rest)))) => {
+                    self.active.push(rest.into_future());
+                    return Ok(Async::Ready(Some(item)));
+                }
+                // An inner stream ended, making room for the next one:
+                Ok(Async::Ready(Some((None, _)))) => continue,
+                Ok(Async::Ready(None)) if self.stream.is_done() => return Ok(Async::Ready(None)),
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err((err, rest)) => {
+                    self.active.push(rest.into_future());
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::sync::mpsc;
+    use futures::{future, stream, Future};
+
+    #[test]
+    fn parallelism_halves_when_throttled() {
+        let parallelism = Parallelism::adaptive(8, ParallelismBounds::new(2, 16));
+        parallelism.on_throttled();
+        assert_eq!(parallelism.current(), 4);
+        parallelism.on_throttled();
+        parallelism.on_throttled();
+        assert_eq!(parallelism.current(), 2);
+    }
+
+    #[test]
+    fn parallelism_grows_after_a_window_of_successes() {
+        let parallelism = Parallelism::adaptive(2, ParallelismBounds::new(1, 3));
+        parallelism.on_success();
+        assert_eq!(parallelism.current(), 2);
+        parallelism.on_success();
+        assert_eq!(parallelism.current(), 3);
+        for _ in 0..10 {
+            parallelism.on_success();
+        }
+        assert_eq!(parallelism.current(), 3);
+    }
+
+    #[test]
+    fn fixed_parallelism_never_changes() {
+        let parallelism = Parallelism::fixed(4);
+        parallelism.on_throttled();
+        parallelism.on_success();
+        assert_eq!(parallelism.current(), 4);
+    }
+
+    #[test]
+    fn buffer_adaptive_yields_every_item() {
+        let parallelism = Parallelism::fixed(3);
+        let futures = stream::iter_ok::<_, ()>((0..10).map(future::ok::<usize, ()>));
+        let mut items = BufferAdaptive::new(futures, parallelism)
+            .collect()
+            .wait()
+            .unwrap();
+        items.sort();
+        assert_eq!(items, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn flatten_unordered_yields_items_before_their_stream_ends() {
+        let (sender, receiver) = mpsc::unbounded::<usize>();
+        sender.unbounded_send(1).unwrap();
+        let streams = stream::iter_ok::<_, ()>(vec![receiver]);
+
+        // The inner stream is still open, so this would never return if its
+        // items were held back until it ended:
+        let mut items = FlattenUnordered::new(streams, 1).wait();
+        assert_eq!(items.next(), Some(Ok(1)));
+        drop(sender);
+        assert_eq!(items.next(), None);
+    }
+
+    #[test]
+    fn flatten_unordered_only_polls_up_to_its_limit_of_streams() {
+        let (senders, receivers): (Vec<_>, Vec<_>) =
+            (0..3).map(|_| mpsc::unbounded::<usize>()).unzip();
+        for (n, sender) in senders.iter().enumerate() {
+            sender.unbounded_send(n).unwrap();
+        }
+        let mut senders = senders.into_iter();
+        let streams = stream::iter_ok::<_, ()>(receivers);
+
+        let mut items = FlattenUnordered::new(streams, 2).wait();
+        let mut first_two = vec![
+            items.next().unwrap().unwrap(),
+            items.next().unwrap().unwrap(),
+        ];
+        first_two.sort();
+        assert_eq!(first_two, vec![0, 1]);
+
+        // The third stream is only polled once one of the first two ends:
+        drop(senders.next());
+        assert_eq!(items.next(), Some(Ok(2)));
+        drop(senders);
+        assert_eq!(items.next(), None);
+    }
+}
@@ -1,16 +1,35 @@
 // Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
 
+use std::collections::HashMap;
+use std::fmt;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::sync::mpsc;
 
 use crate::ps::model::ImportId;
+use crate::ps::Error;
 
 /// A trait defining a progress indicator callback. Every time a file part
 /// successfully completes, `update` will be called with new, update statistics
-/// for the file.
+/// for the file. `on_file_start` and `on_file_complete` bracket the updates
+/// for each file, for callers that only track whole files. `on_retry` is
+/// called when a failed upload is about to be resumed.
 pub trait ProgressCallback: Send + Sync {
     /// Called when an uploaded progress update occurs.
     fn on_update(&self, _: &ProgressUpdate);
+
+    /// Called before the first part of a file is sent, with the size of the
+    /// file in bytes.
+    fn on_file_start(&self, _file_path: &Path, _size: u64) {}
+
+    /// Called once every part of a file has been sent.
+    fn on_file_complete(&self, _file_path: &Path) {}
+
+    /// Called when an upload failed with `err` and will be retried, before
+    /// waiting to make retry number `attempt` of at most `max`.
+    fn on_retry(&self, _attempt: usize, _max: usize, _err: &Error) {}
 }
 
 /// An implementation of `ProgressCallback` that does nothing.
@@ -23,18 +42,59 @@ impl ProgressCallback for NoProgress {
     }
 }
 
+/// An implementation of `ProgressCallback` that forwards every update into
+/// a channel, allowing progress to be consumed as a stream.
+#[derive(Debug, Clone)]
+pub struct ProgressSender(mpsc::UnboundedSender<ProgressUpdate>);
+
+impl ProgressSender {
+    pub fn new(sender: mpsc::UnboundedSender<ProgressUpdate>) -> Self {
+        ProgressSender(sender)
+    }
+}
+
+impl ProgressCallback for ProgressSender {
+    fn on_update(&self, update: &ProgressUpdate) {
+        // The receiving end being dropped just means nobody is listening:
+        let _ = self.0.unbounded_send(update.clone());
+    }
+}
+
 impl ProgressCallback for Box<dyn ProgressCallback> {
     fn on_update(&self, _update: &ProgressUpdate) {
         self.as_ref().on_update(_update)
     }
+
+    fn on_file_start(&self, file_path: &Path, size: u64) {
+        self.as_ref().on_file_start(file_path, size)
+    }
+
+    fn on_file_complete(&self, file_path: &Path) {
+        self.as_ref().on_file_complete(file_path)
+    }
+
+    fn on_retry(&self, attempt: usize, max: usize, err: &Error) {
+        self.as_ref().on_retry(attempt, max, err)
+    }
 }
 
-impl ProgressCallback for Arc<Box<dyn ProgressCallback>> {
-    fn on_update(&self, _update: &ProgressUpdate) {
-        let this = self.clone();
-        if let Ok(cb) = Arc::try_unwrap(this) {
-            cb.on_update(_update)
-        }
+/// Shares a callback between uploads, or with the caller, without it
+/// having to be `Clone` itself.
+impl<T: ProgressCallback + ?Sized> ProgressCallback for Arc<T> {
+    fn on_update(&self, update: &ProgressUpdate) {
+        self.as_ref().on_update(update)
+    }
+
+    fn on_file_start(&self, file_path: &Path, size: u64) {
+        self.as_ref().on_file_start(file_path, size)
+    }
+
+    fn on_file_complete(&self, file_path: &Path) {
+        self.as_ref().on_file_complete(file_path)
+    }
+
+    fn on_retry(&self, attempt: usize, max: usize, err: &Error) {
+        self.as_ref().on_retry(attempt, max, err)
     }
 }
 
@@ -47,6 +107,8 @@ pub struct ProgressUpdate {
     bytes_sent: u64,
     size: u64,
     done: bool,
+    timestamp: Instant,
+    started: Option<(Instant, u64)>,
 }
 
 impl ProgressUpdate {
@@ -65,9 +127,26 @@ impl ProgressUpdate {
             bytes_sent,
             size,
             done,
+            timestamp: Instant::now(),
+            started: None,
         }
     }
 
+    /// Times the update against the file having been started at `started`,
+    /// with `start_bytes_sent` of it already sent, e.g. by an earlier
+    /// attempt at the upload.
+    pub(crate) fn with_start(mut self, started: Instant, start_bytes_sent: u64) -> Self {
+        self.started = Some((started, start_bytes_sent));
+        self
+    }
+
+    /// Stamps the update as made at `timestamp`, e.g. once the chunk it's
+    /// for has been sent, rather than when the chunk was read.
+    pub(crate) fn timestamped(mut self, timestamp: Instant) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
     /// Returns the part number of the uploading file.
     pub fn part_number(&self) -> usize {
         self.part_number
@@ -114,4 +193,284 @@ impl ProgressUpdate {
 
         (self.bytes_sent as f32 / self.size as f32) * 100.0
     }
+
+    /// Returns the time between the file being started and this update, if
+    /// the update was timed.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.started
+            .map(|(started, _)| self.timestamp.saturating_duration_since(started))
+    }
+
+    /// Returns the average rate the file has been sent at since it was
+    /// started, in bytes per second. Bytes sent before then, e.g. by an
+    /// earlier attempt at the upload, don't count towards the rate.
+    pub fn bytes_per_second(&self) -> Option<f64> {
+        let (_, start_bytes_sent) = self.started?;
+        let seconds = self.elapsed()?.as_secs_f64();
+        if seconds > 0.0 {
+            Some(self.bytes_sent.saturating_sub(start_bytes_sent) as f64 / seconds)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the estimated time left until the file is sent, going by the
+    /// rate it has been sent at so far.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.done {
+            return Some(Duration::from_secs(0));
+        }
+        let rate = self.bytes_per_second().filter(|rate| *rate > 0.0)?;
+        let remaining = self.size.saturating_sub(self.bytes_sent) as f64;
+        Some(Duration::from_secs_f64(remaining / rate))
+    }
+}
+
+// Formats a rate in bytes per second with binary units, e.g. `2.3 MiB/s`:
+fn format_rate(bytes_per_second: f64) -> String {
+    const UNITS: [&str; 4] = ["B/s", "KiB/s", "MiB/s", "GiB/s"];
+    let mut rate = bytes_per_second;
+    let mut unit = 0;
+    while rate >= 1024.0 && unit < UNITS.len() - 1 {
+        rate /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", rate, UNITS[unit])
+}
+
+/// Formats the update for people, e.g. `earth.jpg: 45% (2.3 MiB/s)`. The
+/// rate is left out of updates that weren't timed.
+impl fmt::Display for ProgressUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self
+            .file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.import_id.to_string());
+        write!(f, "{}: {:.0}%", name, self.percent_done())?;
+        if let Some(rate) = self.bytes_per_second() {
+            write!(f, " ({})", format_rate(rate))?;
+        }
+        Ok(())
+    }
+}
+
+/// The throughput recorded for a single file over the course of an upload.
+#[derive(Debug, Clone, Hash)]
+pub struct FileThroughput {
+    file_path: PathBuf,
+    bytes: u64,
+    duration: Duration,
+    timed_bytes: u64,
+}
+
+impl FileThroughput {
+    /// Returns the path of the uploaded file.
+    pub fn file_path(&self) -> &Path {
+        self.file_path.as_ref()
+    }
+
+    /// Returns the total number of bytes sent for the file.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the time elapsed between the first and last progress
+    /// update received for the file.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the average throughput of the file in bytes per second.
+    ///
+    /// The first part of a file only marks the start of the timing
+    /// window, so files that were sent in a single part have no
+    /// measurable throughput.
+    pub fn bytes_per_second(&self) -> Option<f64> {
+        let seconds = self.duration.as_secs_f64();
+        if seconds > 0.0 {
+            Some(self.timed_bytes as f64 / seconds)
+        } else {
+            None
+        }
+    }
+}
+
+/// A summary of the files sent during an upload.
+#[derive(Debug, Clone, Hash)]
+pub struct UploadSummary {
+    files: Vec<FileThroughput>,
+}
+
+impl UploadSummary {
+    /// Returns the per-file throughput records, ordered by file path.
+    pub fn files(&self) -> &Vec<FileThroughput> {
+        &self.files
+    }
+
+    /// Returns the total number of bytes sent across all files.
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.bytes()).sum()
+    }
+}
+
+// The first and most recent progress updates seen for a file.
+struct FileProgress {
+    first_update: Instant,
+    first_bytes_sent: u64,
+    last_update: Instant,
+    bytes_sent: u64,
+}
+
+/// An implementation of `ProgressCallback` that timestamps each update
+/// in order to record the throughput of every file in an upload.
+#[derive(Clone, Default)]
+pub struct ThroughputRecorder {
+    files: Arc<Mutex<HashMap<PathBuf, FileProgress>>>,
+}
+
+impl ThroughputRecorder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Summarizes the throughput of every file seen so far.
+    pub fn summary(&self) -> UploadSummary {
+        let files = self.files.lock().unwrap();
+        let mut files: Vec<FileThroughput> = files
+            .iter()
+            .map(|(file_path, progress)| FileThroughput {
+                file_path: file_path.clone(),
+                bytes: progress.bytes_sent,
+                duration: progress.last_update - progress.first_update,
+                timed_bytes: progress.bytes_sent - progress.first_bytes_sent,
+            })
+            .collect();
+        files.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+
+        UploadSummary { files }
+    }
+}
+
+impl ProgressCallback for ThroughputRecorder {
+    fn on_update(&self, update: &ProgressUpdate) {
+        let now = Instant::now();
+        let mut files = self.files.lock().unwrap();
+        let progress = files
+            .entry(update.file_path().to_path_buf())
+            .or_insert_with(|| FileProgress {
+                first_update: now,
+                first_bytes_sent: update.bytes_sent(),
+                last_update: now,
+                bytes_sent: update.bytes_sent(),
+            });
+
+        // Parts may complete out of order, so only ever move forward:
+        if update.bytes_sent() >= progress.bytes_sent {
+            progress.last_update = now;
+            progress.bytes_sent = update.bytes_sent();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(file_path: &str, part_number: usize, bytes_sent: u64, size: u64) -> ProgressUpdate {
+        ProgressUpdate::new(
+            part_number,
+            ImportId::new("import id"),
+            PathBuf::from(file_path),
+            bytes_sent,
+            size,
+            bytes_sent == size,
+        )
+    }
+
+    #[test]
+    fn per_file_throughput_sums_to_total() {
+        let recorder = ThroughputRecorder::new();
+
+        recorder.on_update(&update("a.csv", 1, 100, 250));
+        recorder.on_update(&update("b.csv", 1, 40, 40));
+        recorder.on_update(&update("a.csv", 2, 200, 250));
+        recorder.on_update(&update("a.csv", 3, 250, 250));
+
+        let summary = recorder.summary();
+        assert_eq!(summary.files().len(), 2);
+        assert_eq!(summary.files()[0].bytes(), 250);
+        assert_eq!(summary.files()[1].bytes(), 40);
+        assert_eq!(
+            summary.files().iter().map(|f| f.bytes()).sum::<u64>(),
+            summary.total_bytes()
+        );
+        assert_eq!(summary.total_bytes(), 290);
+    }
+
+    #[test]
+    fn timed_updates_report_their_rate_and_eta() {
+        const MIB: u64 = 1024 * 1024;
+        let started = Instant::now() - Duration::from_secs(2);
+
+        // 1 MiB was sent before the file was started, and 4 MiB since:
+        let update = update("data/earth.jpg", 5, 5 * MIB, 10 * MIB).with_start(started, MIB);
+
+        assert!(update.elapsed().unwrap() >= Duration::from_secs(2));
+        let rate = update.bytes_per_second().unwrap();
+        assert!(rate <= (2 * MIB) as f64 && rate > (2 * MIB) as f64 * 0.95);
+        let eta = update.eta().unwrap();
+        assert!(eta >= Duration::from_millis(2500) && eta < Duration::from_millis(2700));
+        assert_eq!(update.to_string(), "earth.jpg: 50% (2.0 MiB/s)");
+    }
+
+    #[test]
+    fn updates_are_timed_to_their_timestamp() {
+        let started = Instant::now() - Duration::from_secs(4);
+        let update = update("a.csv", 1, 100, 250).with_start(started, 0);
+
+        let restamped = update.clone().timestamped(started + Duration::from_secs(1));
+        assert_eq!(restamped.elapsed(), Some(Duration::from_secs(1)));
+        assert!(update.elapsed().unwrap() >= Duration::from_secs(4));
+    }
+
+    #[test]
+    fn untimed_updates_have_no_rate() {
+        let update = update("a.csv", 1, 100, 250);
+
+        assert_eq!(update.elapsed(), None);
+        assert_eq!(update.bytes_per_second(), None);
+        assert_eq!(update.eta(), None);
+        assert_eq!(update.to_string(), "a.csv: 40%");
+    }
+
+    #[test]
+    fn every_clone_of_a_shared_callback_reports_to_it() {
+        let recorder = ThroughputRecorder::new();
+        let shared: Arc<dyn ProgressCallback> = Arc::new(recorder.clone());
+        let (first, second) = (shared.clone(), shared.clone());
+
+        first.on_update(&update("a.csv", 1, 100, 100));
+        second.on_update(&update("b.csv", 1, 40, 40));
+
+        assert_eq!(recorder.summary().files().len(), 2);
+        assert_eq!(recorder.summary().total_bytes(), 140);
+
+        // ...boxed callbacks included:
+        let boxed: Arc<Box<dyn ProgressCallback>> = Arc::new(Box::new(recorder.clone()));
+        let _other_reference = boxed.clone();
+        boxed.on_update(&update("c.csv", 1, 10, 10));
+
+        assert_eq!(recorder.summary().total_bytes(), 150);
+    }
+
+    #[test]
+    fn out_of_order_parts_do_not_reduce_bytes_sent() {
+        let recorder = ThroughputRecorder::new();
+
+        recorder.on_update(&update("a.csv", 2, 200, 200));
+        recorder.on_update(&update("a.csv", 1, 100, 200));
+
+        assert_eq!(recorder.summary().total_bytes(), 200);
+    }
 }
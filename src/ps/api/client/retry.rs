@@ -0,0 +1,289 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+//! The retry behaviour shared by every loop that retries requests: how
+//! many attempts are made, how long to wait between them, and which
+//! failures are worth retrying at all.
+
+use std::sync::Arc;
+use std::time;
+
+use futures::Future as _Future;
+use hyper::{Method, StatusCode};
+use lazy_static::lazy_static;
+use log::debug;
+use rand::Rng;
+
+use crate::ps::config::RetryPolicy;
+use crate::ps::util::futures::into_future_trait;
+use crate::ps::{Error, ErrorKind, Future};
+
+lazy_static! {
+    /// A vec of status codes that cannot be resolved by retrying the
+    /// request and should be bubbled up directly to the caller
+    static ref NONRETRYABLE_STATUS_CODES: Vec<StatusCode> = vec![
+        StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN
+    ];
+}
+
+/// Given the number of the current attempt, calculate the delay (in
+/// milliseconds) for how long we should wait until the next retry
+///
+/// The delay grows exponentially (`base * 2^try_num`, capped at the
+/// policy's maximum) and is then drawn uniformly from `[0, delay]`, so
+/// that many clients failing at the same moment don't retry in lockstep.
+///
+/// # Arguments
+///
+/// * `try_num` - The number of this attempt, indexed at 0
+/// * `retry_policy` - The policy supplying the base and maximum delays
+/// * `rng` - The source of randomness used to jitter the delay
+pub fn retry_delay<R: Rng + ?Sized>(
+    try_num: usize,
+    retry_policy: &RetryPolicy,
+    rng: &mut R,
+) -> u64 {
+    let factor = 1u64.checked_shl(try_num as u32).unwrap_or(u64::MAX);
+    let delay = retry_policy.base_delay_ms().saturating_mul(factor);
+    let delay = match retry_policy.max_delay_ms() {
+        Some(max_delay_ms) => delay.min(max_delay_ms),
+        None => delay,
+    };
+    rng.gen_range(0, delay.saturating_add(1))
+}
+
+/// Tests if a response to a request made with `method` should be retried,
+/// going by its status code. A request sent with an idempotency key can be
+/// repeated safely whatever its method, as the platform ignores repeats, so
+/// it's retried as a `PUT` would be.
+pub fn status_is_retryable(
+    policy: &RetryPolicy,
+    status_code: StatusCode,
+    method: &Method,
+    idempotency_key: bool,
+) -> bool {
+    let method = if idempotency_key {
+        &Method::PUT
+    } else {
+        method
+    };
+    policy.is_retryable(status_code, method)
+}
+
+/// Tests if an operation that failed with `err` is worth retrying, going by
+/// the kind of error. Retrying can't fix rejected credentials, files that
+/// aren't there, arguments that failed validation before anything was
+/// sent, a resource that changed under a conditional update, a response too
+/// large to accept, or a caller that asked to stop; anything else may be
+/// transient.
+pub fn error_is_retryable(err: &Error) -> bool {
+    match err.kind() {
+        ErrorKind::ApiError { status_code, .. } => !NONRETRYABLE_STATUS_CODES.contains(status_code),
+        ErrorKind::PathDoesNotExist { .. } | ErrorKind::PathIsNotAFile { .. } => false,
+        ErrorKind::PathIsNotADirectory { .. }
+        | ErrorKind::NoPathParent { .. }
+        | ErrorKind::CouldNotGetFilename { .. }
+        | ErrorKind::InvalidUnicodePath { .. } => false,
+        ErrorKind::InvalidArguments { .. }
+        | ErrorKind::EnvParseError { .. }
+        | ErrorKind::NoOrganizationSet => false,
+        ErrorKind::Conflict { .. } | ErrorKind::ResponseTooLarge { .. } => false,
+        ErrorKind::Cancelled | ErrorKind::ShuttingDown => false,
+        _ => true,
+    }
+}
+
+/// Waits out the delays between attempts. Tests substitute one that doesn't
+/// actually wait.
+pub trait Clock: Send + Sync {
+    fn sleep(&self, duration: time::Duration) -> Future<()>;
+}
+
+/// A clock backed by tokio's timer.
+#[derive(Debug, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn sleep(&self, duration: time::Duration) -> Future<()> {
+        let deadline = time::Instant::now() + duration;
+        into_future_trait(tokio::timer::Delay::new(deadline).map_err(Into::into))
+    }
+}
+
+/// Decides, for a loop retrying some operation, whether a failed attempt is
+/// followed by another and how long to wait first.
+#[derive(Clone)]
+pub struct Retrier {
+    policy: RetryPolicy,
+    clock: Arc<dyn Clock>,
+}
+
+impl Retrier {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            clock: Arc::new(TokioClock),
+        }
+    }
+
+    /// Waits with `clock` rather than tokio's timer.
+    #[allow(dead_code)]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Called when the attempt numbered `try_num` (indexed at 0) has failed.
+    /// If the failure is `retryable` and retries remain, returns a future
+    /// that resolves once it's time for the next attempt; otherwise returns
+    /// `None`, and the loop should give up.
+    pub fn backoff(&self, try_num: usize, retryable: bool) -> Option<Future<()>> {
+        if !retryable || try_num >= self.policy.max_retries() {
+            return None;
+        }
+        let delay = retry_delay(try_num, &self.policy, &mut rand::thread_rng());
+        debug!(
+            "Retrying in {delay} ms (retry {retry}/{retries})...",
+            delay = delay,
+            retry = try_num + 1,
+            retries = self.policy.max_retries()
+        );
+        Some(self.clock.sleep(time::Duration::from_millis(delay)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use futures::future;
+
+    #[derive(Default)]
+    struct RecordingClock(Mutex<Vec<time::Duration>>);
+
+    impl Clock for RecordingClock {
+        fn sleep(&self, duration: time::Duration) -> Future<()> {
+            self.0.lock().unwrap().push(duration);
+            into_future_trait(future::ok(()))
+        }
+    }
+
+    fn retrier(max_retries: usize) -> (Retrier, Arc<RecordingClock>) {
+        let clock = Arc::new(RecordingClock::default());
+        let policy = RetryPolicy::new(max_retries, 100).with_max_delay_ms(250);
+        (Retrier::new(policy).with_clock(clock.clone()), clock)
+    }
+
+    #[test]
+    fn retryable_failures_are_retried_until_the_attempts_run_out() {
+        let (retrier, clock) = retrier(3);
+
+        for try_num in 0..3 {
+            retrier.backoff(try_num, true).unwrap().wait().unwrap();
+        }
+        assert!(retrier.backoff(3, true).is_none());
+
+        let delays = clock.0.lock().unwrap().clone();
+        assert_eq!(delays.len(), 3);
+        assert!(delays
+            .iter()
+            .all(|delay| *delay <= time::Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn unretryable_failures_are_never_retried() {
+        let (retrier, clock) = retrier(3);
+        assert!(retrier.backoff(0, false).is_none());
+        assert!(clock.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn retryability_by_status_code() {
+        let policy = RetryPolicy::default();
+        assert!(status_is_retryable(
+            &policy,
+            StatusCode::TOO_MANY_REQUESTS,
+            &Method::POST,
+            false
+        ));
+        assert!(status_is_retryable(
+            &policy,
+            StatusCode::BAD_GATEWAY,
+            &Method::GET,
+            false
+        ));
+        // Not idempotent, so the request may have gone through:
+        assert!(!status_is_retryable(
+            &policy,
+            StatusCode::BAD_GATEWAY,
+            &Method::POST,
+            false
+        ));
+        // ...unless the platform can tell it's a repeat:
+        assert!(status_is_retryable(
+            &policy,
+            StatusCode::BAD_GATEWAY,
+            &Method::POST,
+            true
+        ));
+        assert!(!status_is_retryable(
+            &policy,
+            StatusCode::NOT_FOUND,
+            &Method::GET,
+            true
+        ));
+    }
+
+    #[test]
+    fn retryability_by_error_kind() {
+        assert!(error_is_retryable(&Error::api_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ""
+        )));
+        assert!(error_is_retryable(
+            &ErrorKind::HyperError {
+                error: "connection reset".to_string()
+            }
+            .into()
+        ));
+        assert!(!error_is_retryable(&Error::api_error(
+            StatusCode::UNAUTHORIZED,
+            ""
+        )));
+        assert!(!error_is_retryable(
+            &ErrorKind::PathDoesNotExist {
+                path: "missing".into()
+            }
+            .into()
+        ));
+        assert!(!error_is_retryable(&Error::conflict("dataset changed")));
+        assert!(!error_is_retryable(&Error::response_too_large(1024)));
+        assert!(!error_is_retryable(&ErrorKind::Cancelled.into()));
+        assert!(!error_is_retryable(&ErrorKind::ShuttingDown.into()));
+    }
+
+    #[test]
+    fn local_validation_errors_are_not_retried() {
+        assert!(!error_is_retryable(&Error::invalid_arguments(
+            "chunk size is too small"
+        )));
+        assert!(!error_is_retryable(&ErrorKind::NoOrganizationSet.into()));
+        assert!(!error_is_retryable(
+            &ErrorKind::PathIsNotADirectory {
+                path: "file.txt".into()
+            }
+            .into()
+        ));
+        assert!(!error_is_retryable(
+            &ErrorKind::EnvParseError {
+                value: "staging".to_string()
+            }
+            .into()
+        ));
+    }
+}
@@ -2,15 +2,26 @@
 
 //! The Pennsieve platform API.
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
 mod client;
 pub mod request;
 pub mod response;
 
 use std::borrow::Borrow;
 
-pub use self::client::progress::{ProgressCallback, ProgressUpdate};
+pub use self::client::audit::{FileAuditSink, UploadAuditRecord, UploadAuditSink};
+pub use self::client::capture::{
+    CapturedRequest, CapturedResponse, DebugCapture, FileDebugCapture,
+};
+pub use self::client::progress::{
+    FileThroughput, ProgressCallback, ProgressUpdate, ThroughputRecorder, UploadSummary,
+};
 
-pub use self::client::Pennsieve;
+pub use self::client::{CancellationToken, DatasetCursor, Pennsieve};
+
+#[cfg(feature = "blocking")]
+pub use self::blocking::BlockingPennsieve;
 
 /// Objects with a Pennsieve identifier implement this trait.
 pub trait PSId {
@@ -2,6 +2,7 @@
 use std::fs::File;
 use std::io::{self, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 use futures::Async::Ready;
 use sha2::{Digest, Sha256};
@@ -13,21 +14,32 @@ use crate::ps::model::upload::Checksum;
 use crate::ps::model::ImportId;
 
 // 5MiB (the minimum part size for s3 multipart requests)
-const DEFAULT_CHUNK_SIZE_BYTES: u64 = 5_242_880;
+pub const MIN_CHUNK_SIZE_BYTES: u64 = 5_242_880;
+
+const DEFAULT_CHUNK_SIZE_BYTES: u64 = MIN_CHUNK_SIZE_BYTES;
 
 // SHA256 hash of an empty byte array
 const EMPTY_SHA256_HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
 
+/// A seekable source of bytes that can be chunked and uploaded.
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
 pub struct ChunkedFilePayload {
     import_id: ImportId,
     file_path: PathBuf,
-    file: File,
+    file: Box<dyn ReadSeek>,
     chunk_size_bytes: u64,
     bytes_sent: u64,
     file_size: u64,
     parts_sent: usize,
     expected_total_parts: Option<usize>,
     missing_parts: Vec<usize>,
+    // When the payload was created, and how much of the file had already
+    // been sent by then, for timing progress updates:
+    started: Instant,
+    start_bytes_sent: u64,
 }
 
 pub struct FileChunk {
@@ -62,6 +74,53 @@ impl ChunkedFilePayload {
     where
         P: AsRef<Path>,
     {
+        let file_path = file_path.as_ref().to_path_buf();
+
+        let file = File::open(file_path.clone()).unwrap();
+        let file_size = file.metadata().unwrap().len();
+
+        Self::new_from_source(
+            import_id,
+            file_path,
+            Box::new(file),
+            file_size,
+            chunk_size_bytes,
+            missing_parts,
+        )
+    }
+
+    /// Chunks `total_size` bytes read from `reader` rather than from a file,
+    /// e.g. from a `Cursor<Vec<u8>>` of generated data. Progress updates
+    /// for the payload have an empty file path.
+    #[allow(dead_code)]
+    pub fn new_from_reader<R>(
+        import_id: ImportId,
+        reader: R,
+        total_size: u64,
+        chunk_size_bytes: u64,
+        missing_parts: Option<&FileMissingParts>,
+    ) -> Self
+    where
+        R: 'static + ReadSeek,
+    {
+        Self::new_from_source(
+            import_id,
+            PathBuf::new(),
+            Box::new(reader),
+            total_size,
+            chunk_size_bytes,
+            missing_parts,
+        )
+    }
+
+    fn new_from_source(
+        import_id: ImportId,
+        file_path: PathBuf,
+        file: Box<dyn ReadSeek>,
+        file_size: u64,
+        chunk_size_bytes: u64,
+        missing_parts: Option<&FileMissingParts>,
+    ) -> Self {
         // ensure missing parts are sorted
         let mut sorted_missing_parts = missing_parts
             .iter()
@@ -70,11 +129,6 @@ impl ChunkedFilePayload {
             .unwrap_or_else(|| vec![]);
         sorted_missing_parts.sort_unstable();
 
-        let file_path = file_path.as_ref().to_path_buf();
-
-        let file = File::open(file_path.clone()).unwrap();
-        let file_size = file.metadata().unwrap().len();
-
         // update the 'parts_sent' and 'bytes_sent' to reflect any
         // parts that were already sent based on missing_parts
         let (parts_sent, bytes_sent, expected_total_parts) = match missing_parts {
@@ -112,6 +166,8 @@ impl ChunkedFilePayload {
             parts_sent,
             expected_total_parts,
             missing_parts: sorted_missing_parts,
+            started: Instant::now(),
+            start_bytes_sent: bytes_sent,
         }
     }
 
@@ -124,11 +180,60 @@ impl ChunkedFilePayload {
             self.file_size,
             done,
         )
+        .with_start(self.started, self.start_bytes_sent)
     }
 
     fn all_parts_sent(&self) -> bool {
         self.expected_total_parts == Some(self.parts_sent)
     }
+
+    // The number of the chunk that will be read next:
+    fn next_chunk_number(&self) -> usize {
+        // if expected_total_parts is not defined, the upload
+        // service has not given any information about this
+        // upload.  by default, assume all chunks are required.
+        match self.expected_total_parts {
+            None => self.parts_sent,
+            Some(expected_total_parts) => {
+                if self.missing_parts.is_empty() {
+                    self.parts_sent
+                } else {
+                    self.missing_parts[((self.parts_sent as isize - expected_total_parts as isize)
+                        + self.missing_parts.len() as isize)
+                        as usize]
+                }
+            }
+        }
+    }
+
+    /// Returns the size in bytes of the chunk that will be read next, or 0
+    /// if there are none left.
+    pub fn next_chunk_size(&self) -> u64 {
+        if self.file_size == 0 || self.all_parts_sent() {
+            0
+        } else {
+            let offset = self.next_chunk_number() as u64 * self.chunk_size_bytes;
+            self.file_size
+                .saturating_sub(offset)
+                .min(self.chunk_size_bytes)
+        }
+    }
+}
+
+/// Reads from `reader` until `buffer` is full or the end of the source is
+/// reached, returning the number of bytes read. A single `read` may return
+/// fewer bytes than asked for even when more are to come.
+fn read_full<R: Read + ?Sized>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(bytes_read) => filled += bytes_read,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
 }
 
 impl Stream for ChunkedFilePayload {
@@ -156,29 +261,13 @@ impl Stream for ChunkedFilePayload {
             Ok(Ready(None))
         } else {
             let mut buffer = vec![0; self.chunk_size_bytes as usize];
-
-            // if expected_total_parts is not defined, the upload
-            // service has not given any information about this
-            // upload.  by default, assume all chunks are required.
-            let seek_from_chunk_number = match self.expected_total_parts {
-                None => self.parts_sent,
-                Some(expected_total_parts) => {
-                    if self.missing_parts.is_empty() {
-                        self.parts_sent
-                    } else {
-                        self.missing_parts[((self.parts_sent as isize
-                            - expected_total_parts as isize)
-                            + self.missing_parts.len() as isize)
-                            as usize]
-                    }
-                }
-            };
+            let seek_from_chunk_number = self.next_chunk_number();
 
             self.file
                 .seek(SeekFrom::Start(
                     seek_from_chunk_number as u64 * self.chunk_size_bytes,
                 ))
-                .and_then(|_| self.file.read(&mut buffer))
+                .and_then(|_| read_full(&mut self.file, &mut buffer))
                 .map(|bytes_read| {
                     if bytes_read > 0 {
                         self.bytes_sent += bytes_read as u64;
@@ -208,7 +297,8 @@ impl Stream for ChunkedFilePayload {
 
 #[cfg(test)]
 mod tests {
-    use std::path;
+    use std::io::Cursor;
+    use std::{fs, path};
 
     use super::*;
     use crate::ps::api::client;
@@ -375,6 +465,84 @@ mod tests {
         assert!(chunks.len() == 4);
     }
 
+    #[test]
+    fn reader_chunks_match_file_chunks() {
+        let bytes = fs::read(test_file_path()).unwrap();
+        let size = bytes.len() as u64;
+        let mut reader_payload = ChunkedFilePayload::new_from_reader(
+            ImportId::new("import id"),
+            Cursor::new(bytes),
+            size,
+            1000 * 1000, // 1mb
+            None,
+        );
+
+        let from_reader = chunks(&mut reader_payload);
+        let from_file = chunks(&mut chunked_payload());
+
+        assert_eq!(from_reader.len(), from_file.len());
+        assert!(from_reader.iter().zip(from_file.iter()).all(|(r, f)| {
+            r.bytes == f.bytes && r.checksum == f.checksum && r.chunk_number == f.chunk_number
+        }));
+    }
+
+    // A reader that returns at most a few bytes from every read:
+    struct ShortReads(Cursor<Vec<u8>>);
+
+    impl Read for ShortReads {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(7);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    impl Seek for ShortReads {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn chunks_are_filled_across_short_reads() {
+        let bytes = fs::read(test_file_path()).unwrap();
+        let size = bytes.len() as u64;
+        let mut short_reads_payload = ChunkedFilePayload::new_from_reader(
+            ImportId::new("import id"),
+            ShortReads(Cursor::new(bytes)),
+            size,
+            1000 * 1000, // 1mb
+            None,
+        );
+
+        let from_short_reads = chunks(&mut short_reads_payload);
+        let from_file = chunks(&mut chunked_payload());
+
+        assert_eq!(from_short_reads.len(), from_file.len());
+        assert!(from_short_reads
+            .iter()
+            .zip(from_file.iter())
+            .all(|(r, f)| r.bytes == f.bytes && r.chunk_number == f.chunk_number));
+    }
+
+    #[test]
+    fn progress_is_timed_from_the_bytes_already_sent() {
+        let missing_parts = FileMissingParts {
+            file_name: TEST_FILE_NAME.to_string(),
+            missing_parts: vec![6, 7],
+            expected_total_parts: 8,
+        };
+
+        let mut chunked_payload = chunked_payload_missing_parts(&missing_parts);
+        let start_bytes_sent = chunked_payload.bytes_sent;
+        let progresses = progress(chunked_payload.by_ref());
+
+        assert_eq!(start_bytes_sent, 6 * chunked_payload.chunk_size_bytes);
+        assert!(progresses.iter().all(|p| p.elapsed().is_some()));
+        let last = progresses.last().unwrap();
+        assert!(last.is_done());
+        assert_eq!(last.eta(), Some(std::time::Duration::from_secs(0)));
+    }
+
     #[test]
     fn zero_byte_files_progress_is_updated_correctly() {
         let mut zero_byte_chunked_payload = ChunkedFilePayload::new(
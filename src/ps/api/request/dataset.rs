@@ -1,12 +1,18 @@
 // Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
 use serde_derive::Serialize;
 
+use crate::ps::model::{DatasetRole, PipelineId, UserId};
+
 #[derive(Clone, Hash, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Create {
     name: String,
     description: Option<String>,
     automatically_process_packages: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
 }
 
 impl Create {
@@ -19,6 +25,8 @@ impl Create {
             name: name.into(),
             description: description.map(Into::into),
             automatically_process_packages: false,
+            license: None,
+            tags: vec![],
         }
     }
 
@@ -29,13 +37,28 @@ impl Create {
         self.automatically_process_packages = automatically_process_packages;
         self
     }
+
+    pub fn with_license<L: Into<String>>(mut self, license: Option<L>) -> Self {
+        self.license = license.map(Into::into);
+        self
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
 }
 
 #[derive(Clone, Hash, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Update {
     name: String,
+    // Left out when not given, so the platform keeps the existing description:
+    #[serde(skip_serializing_if = "Option::is_none")]
     description: Option<String>,
+    // Likewise for the tags:
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
 }
 
 impl Update {
@@ -47,6 +70,77 @@ impl Update {
         Self {
             name: name.into(),
             description: description.map(Into::into),
+            tags: None,
+        }
+    }
+
+    /// An update that changes only the name of a dataset.
+    pub fn rename<P: Into<String>>(name: P) -> Self {
+        Self {
+            name: name.into(),
+            description: None,
+            tags: None,
         }
     }
+
+    /// Replaces the tags of the dataset with `tags`.
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = Some(tags);
+        self
+    }
+}
+
+/// A request to replace the readme of a dataset.
+#[derive(Clone, Hash, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateReadme {
+    readme: String,
+}
+
+impl UpdateReadme {
+    pub fn new<R: Into<String>>(readme: R) -> Self {
+        Self {
+            readme: readme.into(),
+        }
+    }
+}
+
+/// A request to add a user collaborator to a dataset, or change their role.
+#[derive(Clone, Hash, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddCollaborator {
+    id: UserId,
+    role: DatasetRole,
+}
+
+impl AddCollaborator {
+    pub fn new(id: UserId, role: DatasetRole) -> Self {
+        Self { id, role }
+    }
+}
+
+/// A request to remove a user collaborator from a dataset.
+#[derive(Clone, Hash, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveCollaborator {
+    id: UserId,
+}
+
+impl RemoveCollaborator {
+    pub fn new(id: UserId) -> Self {
+        Self { id }
+    }
+}
+
+/// A request to set the default processing pipeline of a dataset.
+#[derive(Clone, Hash, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetPipeline {
+    pipeline_id: PipelineId,
+}
+
+impl SetPipeline {
+    pub fn new(pipeline_id: PipelineId) -> Self {
+        Self { pipeline_id }
+    }
 }
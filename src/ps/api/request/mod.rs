@@ -12,5 +12,5 @@ mod user;
 
 // Re-export:
 pub use self::account::ApiLogin;
-pub use self::upload::UploadPreview;
+pub use self::upload::{CompleteUpload, UploadPreview};
 pub use self::user::User;
@@ -1,7 +1,7 @@
 // Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
 use serde_derive::Serialize;
 
-use crate::ps::model::{DatasetNodeId, Property};
+use crate::ps::model::{DatasetNodeId, ImportId, PackageId, Property};
 
 #[derive(Clone, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -11,6 +11,8 @@ pub struct Create {
     properties: Vec<Property>,
     dataset: DatasetNodeId,
     parent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    import_id: Option<ImportId>,
 }
 
 impl Create {
@@ -27,8 +29,22 @@ impl Create {
             properties: vec![],
             dataset: dataset.into(),
             parent: parent.map(Into::into),
+            import_id: None,
         }
     }
+
+    /// Attaches metadata properties to the package being created.
+    pub fn with_properties(mut self, properties: Vec<Property>) -> Self {
+        self.properties = properties;
+        self
+    }
+
+    /// Tags the package with a client-generated import ID, so the platform
+    /// can tell a repeated request from a request for another package.
+    pub fn with_import_id(mut self, import_id: ImportId) -> Self {
+        self.import_id = Some(import_id);
+        self
+    }
 }
 
 #[derive(Clone, PartialEq, Serialize)]
@@ -45,3 +61,32 @@ impl Update {
         Self { name: name.into() }
     }
 }
+
+#[derive(Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateProperties {
+    properties: Vec<Property>,
+}
+
+impl UpdateProperties {
+    pub fn new(properties: Vec<Property>) -> Self {
+        Self { properties }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Delete {
+    things: Vec<PackageId>,
+}
+
+impl Delete {
+    pub fn new<T>(things: Vec<T>) -> Self
+    where
+        T: Into<PackageId>,
+    {
+        Self {
+            things: things.into_iter().map(Into::into).collect::<Vec<_>>(),
+        }
+    }
+}
@@ -1,5 +1,8 @@
 // Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+use std::collections::BTreeMap;
+
 use serde_derive::Serialize;
+use serde_json::Value;
 
 use crate::ps::model::S3File;
 
@@ -18,3 +21,55 @@ impl UploadPreview {
         }
     }
 }
+
+/// How the files of an upload are processed once it's complete.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompleteUpload {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workflow: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auto_process: Option<bool>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    parameters: BTreeMap<String, Value>,
+}
+
+impl CompleteUpload {
+    /// Completes an upload the platform's default way.
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Runs the named processing workflow rather than the one the platform
+    /// would pick for the files.
+    #[allow(dead_code)]
+    pub fn with_workflow<S: Into<String>>(mut self, workflow: S) -> Self {
+        self.workflow = Some(workflow.into());
+        self
+    }
+
+    /// Sets whether the uploaded files are processed right away, or left
+    /// in the `UPLOADED` state until `process_package` is called.
+    #[allow(dead_code)]
+    pub fn with_auto_process(mut self, auto_process: bool) -> Self {
+        self.auto_process = Some(auto_process);
+        self
+    }
+
+    /// Passes a parameter to the processing pipeline.
+    #[allow(dead_code)]
+    pub fn with_parameter<K, V>(mut self, name: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        self.parameters.insert(name.into(), value.into());
+        self
+    }
+
+    /// Tests if nothing differs from the platform's default.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
@@ -24,3 +24,18 @@ impl Borrow<model::Channel> for Channel {
         &self.content
     }
 }
+
+/// A window of samples from a timeseries channel, as `(timestamp, value)`
+/// pairs with timestamps in microseconds.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelData {
+    data: Vec<(i64, f64)>,
+}
+
+impl ChannelData {
+    /// Take ownership of the samples.
+    pub fn take(self) -> Vec<(i64, f64)> {
+        self.data
+    }
+}
@@ -1,7 +1,9 @@
 // Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
 
 use std::borrow::Borrow;
+use std::collections::{hash_map, HashMap};
 use std::ops::Deref;
+use std::{slice, vec};
 
 use serde_derive::Deserialize;
 
@@ -9,6 +11,15 @@ use crate::ps::api::response::package::Package;
 use crate::ps::api::PSChildren;
 use crate::ps::model;
 
+/// An order in which the children of a dataset can be listed.
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+pub enum ChildOrder {
+    /// Alphabetically by name.
+    Name,
+    /// Oldest first.
+    CreatedAt,
+}
+
 /// A response wrapping a `model::Dataset`, along with and related metadata.
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -17,6 +28,9 @@ pub struct Dataset {
     owner: String,
     children: Option<Vec<Package>>,
     content: model::Dataset,
+    // Sent in a header rather than the body:
+    #[serde(skip)]
+    etag: Option<String>,
 }
 
 impl PSChildren for Dataset {
@@ -50,11 +64,40 @@ impl Dataset {
         &self.owner
     }
 
+    /// Get the `ETag` the platform tagged this version of the dataset with,
+    /// if any, to update it only if it's unchanged.
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    pub(crate) fn with_etag(mut self, etag: Option<String>) -> Self {
+        self.etag = etag;
+        self
+    }
+
     // Get the child packages contained in this dataset.
     pub fn children(&self) -> Option<&Vec<Package>> {
         self.children.as_ref()
     }
 
+    /// Sort the child packages of this dataset into the given order. Ties
+    /// are broken by package ID, so the order is the same on every call.
+    pub fn sort_children(&mut self, order: ChildOrder) {
+        if let Some(children) = self.children.as_mut() {
+            children.sort_by(|a, b| {
+                let id_order = || {
+                    let (a_id, b_id): (&str, &str) = (a.id().borrow(), b.id().borrow());
+                    a_id.cmp(b_id)
+                };
+                match order {
+                    ChildOrder::Name => a.name().cmp(b.name()),
+                    ChildOrder::CreatedAt => a.create_at().cmp(b.create_at()),
+                }
+                .then_with(id_order)
+            });
+        }
+    }
+
     /// Take ownership of the dataset wrapped by this response object.
     pub fn take(self) -> model::Dataset {
         self.content
@@ -72,6 +115,109 @@ impl Dataset {
     }
 }
 
+/// A single page of a dataset listing.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetPage {
+    limit: usize,
+    offset: usize,
+    total_count: usize,
+    datasets: Vec<Dataset>,
+}
+
+impl DatasetPage {
+    /// Get the maximum number of datasets requested for this page.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Get the offset of the first dataset in this page.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Get the total number of datasets across all pages.
+    pub fn total_count(&self) -> usize {
+        self.total_count
+    }
+
+    /// Get the datasets contained in this page.
+    pub fn datasets(&self) -> &Vec<Dataset> {
+        &self.datasets
+    }
+
+    /// Test if there are datasets beyond this page.
+    pub fn has_more(&self) -> bool {
+        !self.datasets.is_empty() && self.offset + self.datasets.len() < self.total_count
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Dataset> {
+        self.datasets.iter()
+    }
+
+    /// Take ownership of the datasets contained in this page.
+    pub fn take(self) -> Vec<Dataset> {
+        self.datasets
+    }
+}
+
+impl IntoIterator for DatasetPage {
+    type Item = Dataset;
+    type IntoIter = vec::IntoIter<Dataset>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.datasets.into_iter()
+    }
+}
+
+/// The default processing pipeline of a dataset.
+#[derive(Debug, Clone, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetPipeline {
+    pipeline_id: Option<model::PipelineId>,
+}
+
+impl DatasetPipeline {
+    /// Get the ID of the pipeline, if the dataset has a default pipeline.
+    pub fn pipeline_id(&self) -> Option<&model::PipelineId> {
+        self.pipeline_id.as_ref()
+    }
+}
+
+/// The long-form readme of a dataset, as markdown.
+#[derive(Debug, Clone, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetReadme {
+    readme: String,
+}
+
+impl DatasetReadme {
+    /// Take ownership of the markdown.
+    pub fn take(self) -> String {
+        self.readme
+    }
+}
+
+/// The number of packages of each type in a dataset, keyed by package type.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct PackageTypeCounts(HashMap<String, usize>);
+
+impl PackageTypeCounts {
+    /// Get the number of packages of the given type.
+    pub fn get(&self, package_type: &str) -> usize {
+        self.0.get(package_type).cloned().unwrap_or(0)
+    }
+
+    /// Get the number of packages of every type, collections included.
+    pub fn total(&self) -> usize {
+        self.0.values().sum()
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, String, usize> {
+        self.0.iter()
+    }
+}
+
 /// A response wrapping a `model::Collaborators`, along with and related metadata.
 #[derive(Debug, Clone, Eq, Hash, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
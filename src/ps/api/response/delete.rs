@@ -0,0 +1,35 @@
+use serde_derive::Deserialize;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteResponse {
+    success: Vec<String>,
+    failures: Vec<DeleteFailure>,
+}
+
+impl DeleteResponse {
+    pub fn success(&self) -> &Vec<String> {
+        self.success.as_ref()
+    }
+
+    pub fn failures(&self) -> &Vec<DeleteFailure> {
+        self.failures.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteFailure {
+    id: String,
+    error: String,
+}
+
+impl DeleteFailure {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn error(&self) -> &String {
+        &self.error
+    }
+}
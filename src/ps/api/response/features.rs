@@ -0,0 +1,58 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+use std::collections::hash_map;
+use std::collections::HashMap;
+
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+// Flags with typed accessors below:
+const MULTIPART_UPLOAD: &str = "multipartUpload";
+const MAX_FILE_SIZE: &str = "maxFileSize";
+
+/// The feature flags enabled for an organization, keyed by flag name.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Features(HashMap<String, Value>);
+
+impl Features {
+    /// Returns the raw value of a flag, if the organization has it set.
+    pub fn get(&self, flag: &str) -> Option<&Value> {
+        self.0.get(flag)
+    }
+
+    /// Tests if a boolean flag is set and enabled.
+    pub fn is_enabled(&self, flag: &str) -> bool {
+        self.get(flag).and_then(Value::as_bool).unwrap_or(false)
+    }
+
+    /// Tests if files can be uploaded in multiple parts.
+    pub fn multipart_upload_enabled(&self) -> bool {
+        self.is_enabled(MULTIPART_UPLOAD)
+    }
+
+    /// Returns the largest file, in bytes, the organization may upload.
+    pub fn max_file_size(&self) -> Option<u64> {
+        self.get(MAX_FILE_SIZE).and_then(Value::as_u64)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, String, Value> {
+        self.0.iter()
+    }
+}
+
+impl IntoIterator for Features {
+    type Item = (String, Value);
+    type IntoIter = hash_map::IntoIter<String, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
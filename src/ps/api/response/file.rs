@@ -25,3 +25,20 @@ impl Files {
         self.0.into_iter().map(|file| file.take()).collect()
     }
 }
+
+/// A response containing a presigned URL from which a file can be downloaded.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileUrl {
+    url: String,
+}
+
+impl FileUrl {
+    pub fn url(&self) -> &String {
+        &self.url
+    }
+
+    pub fn take(self) -> String {
+        self.url
+    }
+}
@@ -5,30 +5,50 @@
 mod account;
 mod channel;
 mod dataset;
+mod delete;
+mod features;
 mod file;
 mod mv;
 mod organization;
 mod package;
+mod publication;
 mod security;
 mod team;
 mod upload;
 
-use serde_derive::Deserialize;
+use std::collections::HashMap;
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
-#[serde(rename_all = "camelCase")]
+use serde::de::{Deserialize, Deserializer, IgnoredAny};
+
+/// A response with nothing of interest in it: an empty (or ignored) JSON
+/// object, or no body at all.
+#[derive(Debug, Clone, PartialEq)]
 pub struct EmptyMap {}
 
+impl<'de> Deserialize<'de> for EmptyMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // An empty body is parsed as `null`:
+        Option::<HashMap<String, IgnoredAny>>::deserialize(deserializer).map(|_| EmptyMap {})
+    }
+}
+
 // Re-export
 pub use self::account::ApiSession;
-pub use self::channel::Channel;
-pub use self::dataset::{ChangeResponse, CollaboratorCounts, Collaborators, Dataset};
-pub use self::file::{File, Files};
+pub use self::channel::{Channel, ChannelData};
+pub use self::dataset::{
+    ChangeResponse, ChildOrder, CollaboratorCounts, Collaborators, Dataset, DatasetPage,
+    DatasetPipeline, DatasetReadme, PackageTypeCounts,
+};
+pub use self::delete::{DeleteFailure, DeleteResponse};
+pub use self::features::Features;
+pub use self::file::{File, FileUrl, Files};
 pub use self::mv::MoveResponse;
 pub use self::organization::{Organization, OrganizationRole, Organizations};
-pub use self::package::Package;
+pub use self::package::{Package, PackageChildrenPage, PackagePage};
+pub use self::publication::{Doi, PublicationInfo};
 pub use self::security::{TemporaryCredential, UploadCredential};
 pub use self::team::Team;
 pub use self::upload::{
-    FileHash, FileMissingParts, FilesMissingParts, Manifests, UploadPreview, UploadResponse,
+    FileHash, FileMissingParts, FilesMissingParts, ManifestStatus, Manifests, UploadPreview,
+    UploadResponse,
 };
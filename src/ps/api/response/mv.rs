@@ -9,6 +9,35 @@ pub struct MoveResponse {
 }
 
 impl MoveResponse {
+    /// A response for a move into `destination` that hasn't moved anything.
+    pub fn empty<D: Into<String>>(destination: D) -> Self {
+        Self {
+            success: vec![],
+            failures: vec![],
+            destination: Some(destination.into()),
+        }
+    }
+
+    /// A response recording that the package `id` could not be moved.
+    pub fn failed<I: Into<String>, E: Into<String>>(id: I, error: E) -> Self {
+        Self {
+            success: vec![],
+            failures: vec![MoveFailure {
+                id: id.into(),
+                error: error.into(),
+            }],
+            destination: None,
+        }
+    }
+
+    /// Combines the outcome of another move with this one.
+    pub fn merge(mut self, other: Self) -> Self {
+        self.success.extend(other.success);
+        self.failures.extend(other.failures);
+        self.destination = self.destination.or(other.destination);
+        self
+    }
+
     pub fn success(&self) -> &Vec<String> {
         self.success.as_ref()
     }
@@ -16,6 +45,10 @@ impl MoveResponse {
     pub fn failures(&self) -> &Vec<MoveFailure> {
         self.failures.as_ref()
     }
+
+    pub fn destination(&self) -> Option<&String> {
+        self.destination.as_ref()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
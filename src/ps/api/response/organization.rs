@@ -116,4 +116,11 @@ impl OrganizationRole {
     pub fn role(&self) -> Option<&String> {
         self.role.as_ref()
     }
+
+    /// Returns the role, parsed.
+    pub fn role_enum(&self) -> Option<model::Role> {
+        self.role
+            .as_ref()
+            .map(|role| model::Role::from(role.as_str()))
+    }
 }
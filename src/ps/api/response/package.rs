@@ -1,12 +1,14 @@
 // Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
 
 use std::borrow::Borrow;
+use std::collections::HashSet;
 use std::ops::Deref;
+use std::{slice, vec};
 
 use serde_derive::Deserialize;
 
 use crate::ps::api::{response, PSChildren, PSId, PSName};
-use crate::ps::model;
+use crate::ps::model::{self, FileObjectType};
 
 // This corresponds to the `objects` map that is returned from `/packages/{:id}`
 // when the `include=` parameter is provided.
@@ -26,6 +28,7 @@ pub struct Package {
     content: model::Package,
     children: Option<Vec<Package>>,
     objects: Option<Objects>,
+    ancestors: Option<Vec<Package>>,
 }
 
 impl Borrow<model::Package> for Package {
@@ -84,6 +87,13 @@ impl Package {
         self.children.as_ref()
     }
 
+    /// Get the collections containing this package, from the top level of
+    /// the dataset down to the package's parent. Only returned when
+    /// requested with `includeAncestors`.
+    pub fn ancestors(&self) -> Option<&Vec<Self>> {
+        self.ancestors.as_ref()
+    }
+
     /// Gets a collection of channels associated with this package.
     pub fn channels(&self) -> Option<&Vec<response::channel::Channel>> {
         self.channels.as_ref()
@@ -113,6 +123,25 @@ impl Package {
         }
     }
 
+    /// Gets the kinds of files that were returned with this package. A kind
+    /// is present when it was requested, even if the package has no files
+    /// of that kind; the set is empty when no files were requested at all.
+    pub fn expansions_present(&self) -> HashSet<FileObjectType> {
+        let mut present = HashSet::new();
+        if let Some(ref o) = self.objects {
+            if o.source.is_some() {
+                present.insert(FileObjectType::Source);
+            }
+            if o.file.is_some() {
+                present.insert(FileObjectType::File);
+            }
+            if o.view.is_some() {
+                present.insert(FileObjectType::View);
+            }
+        }
+        present
+    }
+
     /// Fetch a package from a dataset by package ID.
     pub fn get_package_by_id(&self, package_id: model::PackageId) -> Option<model::Package> {
         self.get_child_by_id(package_id).map(|p| p.clone().take())
@@ -124,3 +153,140 @@ impl Package {
             .map(|p| p.clone().take())
     }
 }
+
+/// A single page of the packages in a dataset.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackagePage {
+    packages: Vec<Package>,
+    cursor: Option<String>,
+}
+
+impl PackagePage {
+    /// Get the packages contained in this page.
+    pub fn packages(&self) -> &Vec<Package> {
+        &self.packages
+    }
+
+    /// Get the cursor from which the next page can be requested, if there
+    /// are packages beyond this page.
+    pub fn cursor(&self) -> Option<&String> {
+        self.cursor.as_ref()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Package> {
+        self.packages.iter()
+    }
+
+    /// Take ownership of the packages contained in this page.
+    pub fn take(self) -> Vec<Package> {
+        self.packages
+    }
+}
+
+impl IntoIterator for PackagePage {
+    type Item = Package;
+    type IntoIter = vec::IntoIter<Package>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.packages.into_iter()
+    }
+}
+
+/// A single page of the children of a collection.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageChildrenPage {
+    limit: usize,
+    offset: usize,
+    total_count: usize,
+    children: Vec<Package>,
+}
+
+impl PackageChildrenPage {
+    /// Get the maximum number of children requested for this page.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Get the offset of the first child in this page.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Get the total number of children across all pages.
+    pub fn total_count(&self) -> usize {
+        self.total_count
+    }
+
+    /// Get the children contained in this page.
+    pub fn children(&self) -> &Vec<Package> {
+        &self.children
+    }
+
+    /// Test if there are children beyond this page.
+    pub fn has_more(&self) -> bool {
+        !self.children.is_empty() && self.offset + self.children.len() < self.total_count
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Package> {
+        self.children.iter()
+    }
+
+    /// Take ownership of the children contained in this page.
+    pub fn take(self) -> Vec<Package> {
+        self.children
+    }
+}
+
+impl IntoIterator for PackageChildrenPage {
+    type Item = Package;
+    type IntoIter = vec::IntoIter<Package>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.children.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(objects: &str) -> Package {
+        serde_json::from_str(&format!(
+            r#"{{
+                "content": {{
+                    "id": "N:package:1",
+                    "name": "brain scan",
+                    "datasetId": "N:dataset:1",
+                    "createdAt": "2020-01-01T00:00:00Z",
+                    "updatedAt": "2020-01-01T00:00:00Z"
+                }}
+                {}
+            }}"#,
+            objects
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn expansions_present_are_those_returned() {
+        let package = package(r#", "objects": { "source": [], "view": [] }"#);
+        assert_eq!(
+            package.expansions_present(),
+            vec![FileObjectType::Source, FileObjectType::View]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(package.source(), Some(&vec![]));
+        assert_eq!(package.file(), None);
+    }
+
+    #[test]
+    fn no_expansions_are_present_without_the_objects_map() {
+        assert!(package("").expansions_present().is_empty());
+        assert!(package(r#", "objects": {}"#)
+            .expansions_present()
+            .is_empty());
+    }
+}
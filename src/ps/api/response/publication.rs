@@ -0,0 +1,156 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+use chrono::{DateTime, Utc};
+use serde_derive::Deserialize;
+
+// The status of a dataset that has never been submitted for publication:
+const DRAFT: &str = "draft";
+
+/// The DOI registered for a dataset.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Doi {
+    doi: String,
+}
+
+impl Doi {
+    /// Take ownership of the DOI string.
+    pub fn take(self) -> String {
+        self.doi
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublishedDataset {
+    version: Option<u32>,
+    last_published_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Publication {
+    status: String,
+    published_dataset: Option<PublishedDataset>,
+}
+
+/// The `publication` section of a dataset response, which is all the
+/// publication info apart from the DOI.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DatasetPublication {
+    publication: Option<Publication>,
+}
+
+/// The publication state of a dataset: its DOI, publication status, and the
+/// version and date it was last published, if it ever was.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "DatasetPublication")]
+pub struct PublicationInfo {
+    doi: Option<String>,
+    status: String,
+    version: Option<u32>,
+    published_at: Option<DateTime<Utc>>,
+}
+
+impl From<DatasetPublication> for PublicationInfo {
+    fn from(dataset: DatasetPublication) -> Self {
+        let (status, published) = match dataset.publication {
+            Some(publication) => (publication.status, publication.published_dataset),
+            None => (DRAFT.to_string(), None),
+        };
+        Self {
+            doi: None,
+            status,
+            version: published.as_ref().and_then(|p| p.version),
+            published_at: published.and_then(|p| p.last_published_date),
+        }
+    }
+}
+
+impl PublicationInfo {
+    /// Sets the DOI, which is looked up separately from the rest.
+    pub fn with_doi(mut self, doi: Option<String>) -> Self {
+        self.doi = doi;
+        self
+    }
+
+    /// Get the DOI registered for the dataset, if any.
+    pub fn doi(&self) -> Option<&String> {
+        self.doi.as_ref()
+    }
+
+    /// Get the publication status, e.g. `"draft"` or `"completed"`.
+    pub fn status(&self) -> &String {
+        &self.status
+    }
+
+    /// Get the most recently published version of the dataset.
+    pub fn version(&self) -> Option<u32> {
+        self.version
+    }
+
+    /// Get when the dataset was last published.
+    pub fn published_at(&self) -> Option<&DateTime<Utc>> {
+        self.published_at.as_ref()
+    }
+
+    /// Tests if any version of the dataset has been published.
+    pub fn is_published(&self) -> bool {
+        self.version.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publication_info_of_a_published_dataset() {
+        let info: PublicationInfo = serde_json::from_str(
+            r#"{
+                "organization": "N:organization:1",
+                "owner": "N:user:1",
+                "content": { "id": "N:dataset:1", "name": "published" },
+                "publication": {
+                    "status": "completed",
+                    "type": "publication",
+                    "publishedDataset": {
+                        "id": 42,
+                        "version": 3,
+                        "lastPublishedDate": "2020-05-04T12:00:00Z"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let info = info.with_doi(Some("10.26275/abcd-efgh".to_string()));
+
+        assert_eq!(info.doi().map(String::as_str), Some("10.26275/abcd-efgh"));
+        assert_eq!(info.status(), "completed");
+        assert_eq!(info.version(), Some(3));
+        assert_eq!(
+            info.published_at().map(|date| date.to_rfc3339()),
+            Some("2020-05-04T12:00:00+00:00".to_string())
+        );
+        assert!(info.is_published());
+    }
+
+    #[test]
+    fn publication_info_of_an_unpublished_dataset() {
+        let info: PublicationInfo = serde_json::from_str(
+            r#"{
+                "organization": "N:organization:1",
+                "owner": "N:user:1",
+                "content": { "id": "N:dataset:2", "name": "draft" }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(info.doi(), None);
+        assert_eq!(info.status(), DRAFT);
+        assert_eq!(info.version(), None);
+        assert_eq!(info.published_at(), None);
+        assert!(!info.is_published());
+    }
+}
@@ -1,5 +1,6 @@
 // Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
 
+use std::iter;
 use std::slice;
 use std::vec;
 
@@ -66,6 +67,13 @@ pub struct FileHash {
     pub hash: String,
 }
 
+impl FileHash {
+    /// The hash function the upload service uses to hash uploaded files.
+    /// A local copy of a file must be hashed with the same function for the
+    /// hashes to be comparable.
+    pub const ALGORITHM: model::HashAlgorithm = model::HashAlgorithm::Sha256;
+}
+
 /// A manifest of files uploaded to the Pennsieve platform.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Manifests(Vec<model::ManifestEntry>);
@@ -85,6 +93,12 @@ impl Manifests {
     }
 }
 
+impl iter::FromIterator<model::ManifestEntry> for Manifests {
+    fn from_iter<I: IntoIterator<Item = model::ManifestEntry>>(entries: I) -> Self {
+        Manifests(entries.into_iter().collect())
+    }
+}
+
 impl IntoIterator for Manifests {
     type Item = model::ManifestEntry;
     type IntoIter = vec::IntoIter<model::ManifestEntry>;
@@ -94,6 +108,36 @@ impl IntoIterator for Manifests {
     }
 }
 
+/// The status of the ETL processing of a completed upload.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestStatus {
+    import_id: model::ImportId,
+    #[serde(rename = "type")]
+    job_type: Option<model::PayloadType>,
+    status: model::ManifestStatus,
+}
+
+impl ManifestStatus {
+    /// Unwraps the value.
+    pub fn take(self) -> model::ManifestStatus {
+        self.status
+    }
+
+    pub fn import_id(&self) -> &model::ImportId {
+        &self.import_id
+    }
+
+    /// The kind of job processing the upload, if the platform said.
+    pub fn job_type(&self) -> Option<model::PayloadType> {
+        self.job_type
+    }
+
+    pub fn status(&self) -> &model::ManifestStatus {
+        &self.status
+    }
+}
+
 /// A file upload preview response.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
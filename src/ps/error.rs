@@ -2,12 +2,15 @@
 
 //! Errors specific to the Pennsieve platform.
 use std::path::PathBuf;
+use std::time::Duration;
 use std::{fmt, io, num, result};
 
 use failure::{Backtrace, Context, Fail};
 
 use base64_url::base64;
 use hyper::http::header::ToStrError;
+use serde_derive::Deserialize;
+use serde_json::Value;
 
 /// Type alias for handling errors throughout the agent
 pub type Result<T> = result::Result<T, Error>;
@@ -24,10 +27,22 @@ impl Error {
         self.ctx.get_context()
     }
 
-    pub fn api_error<S: Into<String>>(status_code: hyper::StatusCode, message: S) -> Error {
+    /// Creates an error for a failed API request from the response body.
+    /// If the body is a JSON error object, its message and code are kept;
+    /// otherwise the whole body is used as the message.
+    pub fn api_error<S: Into<String>>(status_code: hyper::StatusCode, body: S) -> Error {
+        let body = body.into();
+        let (message, code) = match serde_json::from_str::<ApiErrorBody>(&body) {
+            Ok(ApiErrorBody {
+                message: Some(message),
+                code,
+            }) => (message, code.and_then(error_code_to_string)),
+            _ => (body, None),
+        };
         ErrorKind::ApiError {
             status_code,
-            message: message.into(),
+            message,
+            code,
         }
         .into()
     }
@@ -43,6 +58,20 @@ impl Error {
         ErrorKind::InvalidDatasetName { name: name.into() }.into()
     }
 
+    pub fn not_found<S: Into<String>>(message: S) -> Error {
+        ErrorKind::NotFound {
+            message: message.into(),
+        }
+        .into()
+    }
+
+    pub fn conflict<S: Into<String>>(message: S) -> Error {
+        ErrorKind::Conflict {
+            message: message.into(),
+        }
+        .into()
+    }
+
     pub fn invalid_arguments<S: Into<String>>(message: S) -> Error {
         ErrorKind::InvalidArguments {
             message: message.into(),
@@ -81,6 +110,49 @@ impl Error {
         ErrorKind::InvalidUnicodePath { path }.into()
     }
 
+    pub fn timeout_error(timeout: Duration) -> Error {
+        ErrorKind::TimeoutError { timeout }.into()
+    }
+
+    pub fn package_state_timeout<P, S>(package: P, state: S, timeout: Duration) -> Error
+    where
+        P: Into<String>,
+        S: Into<String>,
+    {
+        ErrorKind::PackageStateTimeout {
+            package: package.into(),
+            state: state.into(),
+            timeout,
+        }
+        .into()
+    }
+
+    pub fn cancelled() -> Error {
+        ErrorKind::Cancelled.into()
+    }
+
+    pub fn shutting_down() -> Error {
+        ErrorKind::ShuttingDown.into()
+    }
+
+    pub fn empty_response<S: Into<String>>(expected: S) -> Error {
+        ErrorKind::EmptyResponse {
+            expected: expected.into(),
+        }
+        .into()
+    }
+
+    pub fn response_too_large(max_bytes: usize) -> Error {
+        ErrorKind::ResponseTooLarge { max_bytes }.into()
+    }
+
+    pub fn serde_json_error<S: Into<String>>(error: S) -> Error {
+        ErrorKind::SerdeJsonError {
+            error: error.into(),
+        }
+        .into()
+    }
+
     pub fn initiate_auth_error<S: Into<String>>(error: S) -> Error {
         ErrorKind::InitiateAuthError {
             error: error.into(),
@@ -89,6 +161,42 @@ impl Error {
     }
 }
 
+impl Error {
+    /// Get the error code returned by the platform, if this is an API error
+    /// whose response included one.
+    pub fn error_code(&self) -> Option<&str> {
+        match self.kind() {
+            ErrorKind::ApiError { code, .. } => code.as_ref().map(String::as_str),
+            _ => None,
+        }
+    }
+
+    /// Get the error message returned by the platform, if this is an API
+    /// error.
+    pub fn error_message(&self) -> Option<&str> {
+        match self.kind() {
+            ErrorKind::ApiError { message, .. } => Some(message.as_str()),
+            _ => None,
+        }
+    }
+}
+
+/// The JSON error object returned by most platform endpoints.
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    message: Option<String>,
+    code: Option<Value>,
+}
+
+// Error codes are strings on some endpoints and numbers on others:
+fn error_code_to_string(code: Value) -> Option<String> {
+    match code {
+        Value::String(code) => Some(code),
+        Value::Number(code) => Some(code.to_string()),
+        _ => None,
+    }
+}
+
 impl Fail for Error {
     fn cause(&self) -> Option<&dyn Fail> {
         self.ctx.cause()
@@ -117,11 +225,15 @@ pub enum ErrorKind {
     ApiError {
         status_code: hyper::StatusCode,
         message: String,
+        code: Option<String>,
     },
 
     #[fail(display = "couldn't find dataset: \"{}\"", name)]
     InvalidDatasetName { name: String },
 
+    #[fail(display = "not found: {}", message)]
+    NotFound { message: String },
+
     #[fail(display = "upload error: {}", message)]
     UploadError { message: String },
 
@@ -134,6 +246,9 @@ pub enum ErrorKind {
     #[fail(display = "{}", message)]
     InvalidArguments { message: String },
 
+    #[fail(display = "conflict: {}", message)]
+    Conflict { message: String },
+
     #[fail(display = "could not get path parent: {:?}", path)]
     NoPathParent { path: PathBuf },
 
@@ -167,6 +282,34 @@ pub enum ErrorKind {
     #[fail(display = "tokio error: {}", error)]
     TokioError { error: String },
 
+    #[fail(display = "timed out after {:?}", timeout)]
+    TimeoutError { timeout: Duration },
+
+    #[fail(
+        display = "package {} didn't reach the {} state within {:?}",
+        package, state, timeout
+    )]
+    PackageStateTimeout {
+        package: String,
+        state: String,
+        timeout: Duration,
+    },
+
+    #[fail(display = "cancelled")]
+    Cancelled,
+
+    #[fail(display = "client is shutting down")]
+    ShuttingDown,
+
+    #[fail(display = "empty response body, expected a {}", expected)]
+    EmptyResponse { expected: String },
+
+    #[fail(
+        display = "response body is larger than the limit of {} bytes",
+        max_bytes
+    )]
+    ResponseTooLarge { max_bytes: usize },
+
     #[fail(display = "json serialization error: {}", error)]
     SerdeJsonError { error: String },
 
@@ -254,11 +397,26 @@ impl From<num::ParseIntError> for Error {
     }
 }
 
+/// map from Cognito errors. Failures to reach Cognito at all, and errors on
+/// its end, are kept apart from authentication failures, since only the
+/// former are worth retrying.
 impl From<rusoto_core::RusotoError<rusoto_cognito_idp::InitiateAuthError>> for Error {
     fn from(error: rusoto_core::RusotoError<rusoto_cognito_idp::InitiateAuthError>) -> Error {
-        Error::from(Context::new(ErrorKind::InitiateAuthError {
-            error: error.to_string(),
-        }))
+        match error {
+            rusoto_core::RusotoError::HttpDispatch(error) => {
+                Error::from(Context::new(ErrorKind::HyperError {
+                    error: error.to_string(),
+                }))
+            }
+            rusoto_core::RusotoError::Unknown(ref response)
+                if response.status.is_server_error() =>
+            {
+                Error::api_error(response.status, String::from_utf8_lossy(&response.body))
+            }
+            error => Error::from(Context::new(ErrorKind::InitiateAuthError {
+                error: error.to_string(),
+            })),
+        }
     }
 }
 
@@ -269,3 +427,40 @@ impl From<base64::DecodeError> for Error {
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hyper::StatusCode;
+
+    #[test]
+    fn api_errors_keep_the_platform_message_and_code() {
+        let err = Error::api_error(
+            StatusCode::BAD_REQUEST,
+            r#"{ "type": "BadRequest", "message": "name is taken", "code": "DuplicateName" }"#,
+        );
+        assert_eq!(err.error_message(), Some("name is taken"));
+        assert_eq!(err.error_code(), Some("DuplicateName"));
+        assert_eq!(err.to_string(), "api error: 400 Bad Request name is taken");
+
+        let err = Error::api_error(
+            StatusCode::NOT_FOUND,
+            r#"{ "message": "no such dataset", "code": 404 }"#,
+        );
+        assert_eq!(err.error_code(), Some("404"));
+    }
+
+    #[test]
+    fn api_errors_fall_back_to_the_raw_body() {
+        let err = Error::api_error(StatusCode::BAD_GATEWAY, "<html>bad gateway</html>");
+        assert_eq!(err.error_message(), Some("<html>bad gateway</html>"));
+        assert_eq!(err.error_code(), None);
+
+        let err = Error::api_error(StatusCode::BAD_REQUEST, r#"{ "code": "NoMessage" }"#);
+        assert_eq!(err.error_message(), Some(r#"{ "code": "NoMessage" }"#));
+        assert_eq!(err.error_code(), None);
+
+        assert_eq!(Error::cancelled().error_message(), None);
+    }
+}
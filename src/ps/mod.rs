@@ -11,5 +11,7 @@ mod util;
 
 // Re-export
 pub use crate::ps::api::Pennsieve;
-pub use crate::ps::config::{Config, Environment};
+pub use crate::ps::config::{
+    ApiCredentials, AuthMode, Config, Environment, ParallelismBounds, RetryPolicy,
+};
 pub use crate::ps::types::{Error, ErrorKind, Future, Result, Stream};
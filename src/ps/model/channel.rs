@@ -1,12 +1,82 @@
 // Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::Deref;
+
 use serde_derive::{Deserialize, Serialize};
 
-use crate::ps::api::PSName;
+use crate::ps::api::{PSId, PSName};
+
+/// An identifier for a timeseries channel on the Pennsieve platform.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct ChannelId(String);
+
+impl ChannelId {
+    #[allow(dead_code)]
+    pub fn new<S: Into<String>>(id: S) -> Self {
+        ChannelId(id.into())
+    }
+
+    /// Unwraps the value.
+    pub fn take(self) -> String {
+        self.0
+    }
+}
+
+impl Borrow<String> for ChannelId {
+    fn borrow(&self) -> &String {
+        &self.0
+    }
+}
+
+impl Borrow<str> for ChannelId {
+    fn borrow(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl Deref for ChannelId {
+    type Target = String;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<ChannelId> for String {
+    fn from(id: ChannelId) -> Self {
+        id.0
+    }
+}
+
+impl<'a> From<&'a ChannelId> for String {
+    fn from(id: &'a ChannelId) -> Self {
+        id.0.to_string()
+    }
+}
+
+impl From<String> for ChannelId {
+    fn from(id: String) -> Self {
+        Self::new(id)
+    }
+}
+
+impl<'a> From<&'a str> for ChannelId {
+    fn from(id: &'a str) -> Self {
+        Self::new(String::from(id))
+    }
+}
+
+impl fmt::Display for ChannelId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
 /// A Pennsieve timeseries channel.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Channel {
+    id: ChannelId,
     name: String,
     rate: f64,
     start: i64,
@@ -17,6 +87,13 @@ pub struct Channel {
     group: Option<String>,
 }
 
+impl PSId for Channel {
+    type Id = ChannelId;
+    fn id(&self) -> &Self::Id {
+        &self.id
+    }
+}
+
 impl PSName for Channel {
     fn name(&self) -> &String {
         &self.name
@@ -24,6 +101,10 @@ impl PSName for Channel {
 }
 
 impl Channel {
+    pub fn id(&self) -> &ChannelId {
+        &self.id
+    }
+
     pub fn rate(&self) -> f64 {
         self.rate
     }
@@ -8,6 +8,7 @@ use chrono::{DateTime, Utc};
 use serde_derive::{Deserialize, Serialize};
 
 use crate::ps::api::{PSId, PSName};
+use crate::ps::model::PackageState;
 
 /// An node identifier for a Pennsieve dataset (ex. N:dataset:c905919f-56f5-43ae-9c2a-8d5d542c133b).
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -122,6 +123,28 @@ impl fmt::Display for DatasetId {
     }
 }
 
+/// The role a collaborator has on a dataset.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DatasetRole {
+    Viewer,
+    Editor,
+    Manager,
+    Owner,
+}
+
+impl fmt::Display for DatasetRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let printable = match self {
+            DatasetRole::Viewer => "viewer",
+            DatasetRole::Editor => "editor",
+            DatasetRole::Manager => "manager",
+            DatasetRole::Owner => "owner",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
 /// A Pennsieve dataset.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -165,6 +188,9 @@ pub struct Dataset {
     // * Unsupported
     // * Video
     package_type: Option<String>,
+    license: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
     status: String,
     automatically_process_packages: bool,
     created_at: DateTime<Utc>,
@@ -203,6 +229,13 @@ impl Dataset {
         self.state.as_ref()
     }
 
+    #[allow(dead_code)]
+    pub fn state_enum(&self) -> Option<PackageState> {
+        self.state
+            .as_ref()
+            .map(|state| PackageState::from(state.as_str()))
+    }
+
     #[allow(dead_code)]
     pub fn description(&self) -> Option<&String> {
         self.description.as_ref()
@@ -213,6 +246,16 @@ impl Dataset {
         self.package_type.as_ref()
     }
 
+    #[allow(dead_code)]
+    pub fn license(&self) -> Option<&String> {
+        self.license.as_ref()
+    }
+
+    #[allow(dead_code)]
+    pub fn tags(&self) -> &Vec<String> {
+        &self.tags
+    }
+
     #[allow(dead_code)]
     pub fn status(&self) -> &String {
         &self.status
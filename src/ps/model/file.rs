@@ -2,6 +2,7 @@
 use serde_derive::{Deserialize, Serialize};
 
 use crate::ps::api::PSName;
+use crate::ps::model::PackageId;
 
 /// The representation type of a `model::File`.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -16,6 +17,8 @@ pub enum FileObjectType {
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct File {
+    id: Option<u64>,
+    package_id: Option<PackageId>,
     name: String,
     file_type: String, //TODO Make this typed
     s3bucket: String,
@@ -33,6 +36,16 @@ impl PSName for File {
 }
 
 impl File {
+    #[allow(dead_code)]
+    pub fn id(&self) -> Option<u64> {
+        self.id
+    }
+
+    #[allow(dead_code)]
+    pub fn package_id(&self) -> Option<&PackageId> {
+        self.package_id.as_ref()
+    }
+
     #[allow(dead_code)]
     pub fn file_type(&self) -> &String {
         &self.file_type
@@ -9,9 +9,12 @@ mod dataset;
 mod file;
 mod organization;
 mod package;
+mod pipeline;
 mod property;
+mod role;
 mod security;
 mod team;
+mod template;
 pub mod upload;
 mod user;
 
@@ -21,13 +24,19 @@ pub use self::aws::{
     AccessKey, S3Bucket, S3EncryptionKeyId, S3Key, S3ServerSideEncryption, S3UploadId, S3UploadKey,
     SecretKey,
 };
-pub use self::channel::Channel;
-pub use self::dataset::{Dataset, DatasetId, DatasetNodeId};
-pub use self::file::File;
+pub use self::channel::{Channel, ChannelId};
+pub use self::dataset::{Dataset, DatasetId, DatasetNodeId, DatasetRole};
+pub use self::file::{File, FileObjectType};
 pub use self::organization::{Organization, OrganizationId};
-pub use self::package::{Package, PackageId};
+pub use self::package::{Package, PackageId, PackageState, PackageType};
+pub use self::pipeline::PipelineId;
 pub use self::property::Property;
+pub use self::role::Role;
 pub use self::security::{TemporaryCredential, UploadCredential};
-pub use self::team::Team;
-pub use self::upload::{FileUpload, ImportId, ManifestEntry, PackagePreview, S3File, UploadId};
+pub use self::team::{Team, TeamId};
+pub use self::template::{DatasetTemplate, TemplateCollaborator};
+pub use self::upload::{
+    FileUpload, HashAlgorithm, ImportId, ManifestEntry, ManifestStatus, PackagePreview,
+    PayloadType, S3File, UploadEvent, UploadId, UploadPlan, UploadSession,
+};
 pub use self::user::{User, UserId};
@@ -75,6 +75,199 @@ impl fmt::Display for PackageId {
     }
 }
 
+/// The state of a package or dataset on the Pennsieve platform.
+///
+/// States the client doesn't know about are kept as `Unknown`, so new states
+/// added to the platform never fail deserialization.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum PackageState {
+    Deleting,
+    Error,
+    Exporting,
+    ExportFailed,
+    Failed,
+    Importing,
+    ImportFailed,
+    Pending,
+    Ready,
+    Runnable,
+    Running,
+    Starting,
+    Submitted,
+    Succeeded,
+    Unavailable,
+    Uploaded,
+    Unknown(String),
+}
+
+impl PackageState {
+    /// Returns the state as the platform spells it, e.g. `"IMPORT_FAILED"`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            PackageState::Deleting => "DELETING",
+            PackageState::Error => "ERROR",
+            PackageState::Exporting => "EXPORTING",
+            PackageState::ExportFailed => "EXPORT_FAILED",
+            PackageState::Failed => "FAILED",
+            PackageState::Importing => "IMPORTING",
+            PackageState::ImportFailed => "IMPORT_FAILED",
+            PackageState::Pending => "PENDING",
+            PackageState::Ready => "READY",
+            PackageState::Runnable => "RUNNABLE",
+            PackageState::Running => "RUNNING",
+            PackageState::Starting => "STARTING",
+            PackageState::Submitted => "SUBMITTED",
+            PackageState::Succeeded => "SUCCEEDED",
+            PackageState::Unavailable => "UNAVAILABLE",
+            PackageState::Uploaded => "UPLOADED",
+            PackageState::Unknown(state) => state.as_str(),
+        }
+    }
+
+    /// Tests if the state is final, i.e. nothing more will happen to the
+    /// package without further action. Unknown states are never considered
+    /// terminal, as there's no telling whether they are.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            PackageState::Error
+                | PackageState::ExportFailed
+                | PackageState::Failed
+                | PackageState::ImportFailed
+                | PackageState::Ready
+                | PackageState::Succeeded
+                | PackageState::Unavailable
+        )
+    }
+}
+
+impl<'a> From<&'a str> for PackageState {
+    fn from(state: &'a str) -> Self {
+        match state {
+            "DELETING" => PackageState::Deleting,
+            "ERROR" => PackageState::Error,
+            "EXPORTING" => PackageState::Exporting,
+            "EXPORT_FAILED" => PackageState::ExportFailed,
+            "FAILED" => PackageState::Failed,
+            "IMPORTING" => PackageState::Importing,
+            "IMPORT_FAILED" => PackageState::ImportFailed,
+            "PENDING" => PackageState::Pending,
+            "READY" => PackageState::Ready,
+            "RUNNABLE" => PackageState::Runnable,
+            "RUNNING" => PackageState::Running,
+            "STARTING" => PackageState::Starting,
+            "SUBMITTED" => PackageState::Submitted,
+            "SUCCEEDED" => PackageState::Succeeded,
+            "UNAVAILABLE" => PackageState::Unavailable,
+            "UPLOADED" => PackageState::Uploaded,
+            _ => PackageState::Unknown(state.to_string()),
+        }
+    }
+}
+
+impl From<String> for PackageState {
+    fn from(state: String) -> Self {
+        PackageState::from(state.as_str())
+    }
+}
+
+impl From<PackageState> for String {
+    fn from(state: PackageState) -> Self {
+        state.as_str().to_string()
+    }
+}
+
+impl fmt::Display for PackageState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The type of a package on the Pennsieve platform.
+///
+/// Types the client doesn't know about are kept as `Other`, so new types
+/// added to the platform never fail deserialization.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum PackageType {
+    Collection,
+    Csv,
+    Image,
+    Mri,
+    MsWord,
+    Pdf,
+    Slide,
+    Tabular,
+    Text,
+    TimeSeries,
+    Unknown,
+    Unsupported,
+    Video,
+    Other(String),
+}
+
+impl PackageType {
+    /// Returns the type as the platform spells it, e.g. `"TimeSeries"`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            PackageType::Collection => "Collection",
+            PackageType::Csv => "CSV",
+            PackageType::Image => "Image",
+            PackageType::Mri => "MRI",
+            PackageType::MsWord => "MSWord",
+            PackageType::Pdf => "PDF",
+            PackageType::Slide => "Slide",
+            PackageType::Tabular => "Tabular",
+            PackageType::Text => "Text",
+            PackageType::TimeSeries => "TimeSeries",
+            PackageType::Unknown => "Unknown",
+            PackageType::Unsupported => "Unsupported",
+            PackageType::Video => "Video",
+            PackageType::Other(package_type) => package_type.as_str(),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for PackageType {
+    fn from(package_type: &'a str) -> Self {
+        match package_type {
+            "Collection" => PackageType::Collection,
+            "CSV" => PackageType::Csv,
+            "Image" => PackageType::Image,
+            "MRI" => PackageType::Mri,
+            "MSWord" => PackageType::MsWord,
+            "PDF" => PackageType::Pdf,
+            "Slide" => PackageType::Slide,
+            "Tabular" => PackageType::Tabular,
+            "Text" => PackageType::Text,
+            "TimeSeries" => PackageType::TimeSeries,
+            "Unknown" => PackageType::Unknown,
+            "Unsupported" => PackageType::Unsupported,
+            "Video" => PackageType::Video,
+            _ => PackageType::Other(package_type.to_string()),
+        }
+    }
+}
+
+impl From<String> for PackageType {
+    fn from(package_type: String) -> Self {
+        PackageType::from(package_type.as_str())
+    }
+}
+
+impl From<PackageType> for String {
+    fn from(package_type: PackageType) -> Self {
+        package_type.as_str().to_string()
+    }
+}
+
+impl fmt::Display for PackageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 /// A "package" representation on the Pennsieve platform.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -84,6 +277,7 @@ pub struct Package {
     dataset_id: model::DatasetNodeId,
     state: Option<String>,
     package_type: Option<String>,
+    import_id: Option<model::ImportId>,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
 }
@@ -120,11 +314,23 @@ impl Package {
         self.state.as_ref()
     }
 
+    #[allow(dead_code)]
+    pub fn state_enum(&self) -> Option<PackageState> {
+        self.state
+            .as_ref()
+            .map(|state| PackageState::from(state.as_str()))
+    }
+
     #[allow(dead_code)]
     pub fn package_type(&self) -> Option<&String> {
         self.package_type.as_ref()
     }
 
+    #[allow(dead_code)]
+    pub fn import_id(&self) -> Option<&model::ImportId> {
+        self.import_id.as_ref()
+    }
+
     #[allow(dead_code)]
     pub fn create_at(&self) -> &DateTime<Utc> {
         &self.created_at
@@ -135,3 +341,27 @@ impl Package {
         &self.updated_at
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_state_round_trips_through_json() {
+        let states: Vec<PackageState> =
+            serde_json::from_str(r#"["READY", "IMPORT_FAILED", "UPLOADED", "ARCHIVED"]"#).unwrap();
+        assert_eq!(
+            states,
+            vec![
+                PackageState::Ready,
+                PackageState::ImportFailed,
+                PackageState::Uploaded,
+                PackageState::Unknown("ARCHIVED".to_string()),
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&states).unwrap(),
+            r#"["READY","IMPORT_FAILED","UPLOADED","ARCHIVED"]"#
+        );
+    }
+}
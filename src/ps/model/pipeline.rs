@@ -0,0 +1,72 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::Deref;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// An identifier for an ETL processing pipeline on the Pennsieve platform.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct PipelineId(String);
+
+impl PipelineId {
+    #[allow(dead_code)]
+    pub fn new<S: Into<String>>(id: S) -> Self {
+        PipelineId(id.into())
+    }
+
+    /// Unwraps the value.
+    pub fn take(self) -> String {
+        self.0
+    }
+}
+
+impl Borrow<String> for PipelineId {
+    fn borrow(&self) -> &String {
+        &self.0
+    }
+}
+
+impl Borrow<str> for PipelineId {
+    fn borrow(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl Deref for PipelineId {
+    type Target = String;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<PipelineId> for String {
+    fn from(id: PipelineId) -> Self {
+        id.0
+    }
+}
+
+impl<'a> From<&'a PipelineId> for String {
+    fn from(id: &'a PipelineId) -> Self {
+        id.0.to_string()
+    }
+}
+
+impl From<String> for PipelineId {
+    fn from(id: String) -> Self {
+        Self::new(id)
+    }
+}
+
+impl<'a> From<&'a str> for PipelineId {
+    fn from(id: &'a str) -> Self {
+        Self::new(String::from(id))
+    }
+}
+
+impl fmt::Display for PipelineId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -2,10 +2,10 @@
 
 use std::fmt;
 
-use serde_derive::Serialize;
+use serde_derive::{Deserialize, Serialize};
 
-/// A Pennsieve platform login request.
-#[derive(Clone, Hash, PartialEq, Eq, Serialize)]
+/// A key/value metadata property attached to a package.
+#[derive(Clone, Debug, Deserialize, Hash, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Property {
     key: String,
@@ -16,6 +16,16 @@ impl Property {
     pub fn new(key: String, value: String) -> Self {
         Self { key, value }
     }
+
+    #[allow(dead_code)]
+    pub fn key(&self) -> &String {
+        &self.key
+    }
+
+    #[allow(dead_code)]
+    pub fn value(&self) -> &String {
+        &self.value
+    }
 }
 
 impl fmt::Display for Property {
@@ -0,0 +1,88 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+use std::fmt;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// The role a user or team has on a dataset, in an organization or on a
+/// team.
+///
+/// Roles the client doesn't know about are kept as `Other`, so new roles
+/// added to the platform never fail deserialization.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Manager,
+    Admin,
+    Owner,
+    Other(String),
+}
+
+impl Role {
+    /// Returns the role as the platform spells it, e.g. `"manager"`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Manager => "manager",
+            Role::Admin => "admin",
+            Role::Owner => "owner",
+            Role::Other(role) => role.as_str(),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Role {
+    fn from(role: &'a str) -> Self {
+        match role {
+            "viewer" => Role::Viewer,
+            "editor" => Role::Editor,
+            "manager" => Role::Manager,
+            "admin" => Role::Admin,
+            "owner" => Role::Owner,
+            _ => Role::Other(role.to_string()),
+        }
+    }
+}
+
+impl From<String> for Role {
+    fn from(role: String) -> Self {
+        Role::from(role.as_str())
+    }
+}
+
+impl From<Role> for String {
+    fn from(role: Role) -> Self {
+        role.as_str().to_string()
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_roles_are_kept() {
+        let roles: Vec<Role> = serde_json::from_str(r#"["owner", "viewer", "curator"]"#).unwrap();
+        assert_eq!(
+            roles,
+            vec![
+                Role::Owner,
+                Role::Viewer,
+                Role::Other("curator".to_string())
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&roles).unwrap(),
+            r#"["owner","viewer","curator"]"#
+        );
+    }
+}
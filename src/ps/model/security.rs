@@ -84,3 +84,39 @@ impl UploadCredential {
         &self.s3_key
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_credentials_deserialize() {
+        let credential: UploadCredential = serde_json::from_str(
+            r#"{
+                "tempCredentials": {
+                    "accessKey": "access",
+                    "secretKey": "secret",
+                    "region": "us-east-1",
+                    "sessionToken": "token",
+                    "expiration": "2020-01-01T01:00:00Z"
+                },
+                "encryptionKeyId": "key-id",
+                "s3Bucket": "uploads",
+                "s3Key": "agent-test@pennsieve.com"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            credential.s3_bucket(),
+            &model::S3Bucket::new("uploads".to_string())
+        );
+        assert_eq!(credential.temp_credentials().region(), "us-east-1");
+        assert_eq!(
+            credential.temp_credentials().expiration().to_rfc3339(),
+            "2020-01-01T01:00:00+00:00"
+        );
+        let (access_key, _, _) = credential.take_temp_credentials().take();
+        assert_eq!(access_key, model::AccessKey::new("access".to_string()));
+    }
+}
@@ -6,6 +6,7 @@ use std::ops::Deref;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::ps::api::{PSId, PSName};
+use crate::ps::model;
 
 /// An identifier for a team on the Pennsieve platform.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -83,6 +84,13 @@ impl Team {
     pub fn role(&self) -> Option<&String> {
         self.role.as_ref()
     }
+
+    /// Returns the role, parsed.
+    pub fn role_enum(&self) -> Option<model::Role> {
+        self.role
+            .as_ref()
+            .map(|role| model::Role::from(role.as_str()))
+    }
 }
 
 impl PSId for Team {
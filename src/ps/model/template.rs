@@ -0,0 +1,60 @@
+// Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::ps::model::{DatasetRole, UserId};
+
+/// A user that is added as a collaborator to every dataset created from a
+/// template.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateCollaborator {
+    id: UserId,
+    role: DatasetRole,
+}
+
+impl TemplateCollaborator {
+    #[allow(dead_code)]
+    pub fn id(&self) -> &UserId {
+        &self.id
+    }
+
+    #[allow(dead_code)]
+    pub fn role(&self) -> DatasetRole {
+        self.role
+    }
+}
+
+/// The defaults an organization applies to the datasets its members create.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatasetTemplate {
+    description: Option<String>,
+    license: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    collaborators: Vec<TemplateCollaborator>,
+}
+
+impl DatasetTemplate {
+    #[allow(dead_code)]
+    pub fn description(&self) -> Option<&String> {
+        self.description.as_ref()
+    }
+
+    #[allow(dead_code)]
+    pub fn license(&self) -> Option<&String> {
+        self.license.as_ref()
+    }
+
+    #[allow(dead_code)]
+    pub fn tags(&self) -> &Vec<String> {
+        &self.tags
+    }
+
+    #[allow(dead_code)]
+    pub fn collaborators(&self) -> &Vec<TemplateCollaborator> {
+        &self.collaborators
+    }
+}
@@ -1,15 +1,19 @@
 // Copyright (c) 2018 Pennsieve, Inc. All Rights Reserved.
 
 use std::borrow::Borrow;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
-use std::{cmp, fmt, fs, result};
+use std::{cmp, fmt, fs, iter};
 
 use futures::*;
 use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-use crate::ps::util::futures::{into_future_trait, into_stream_trait};
-use crate::ps::{model, Error, Future, Result, Stream};
+use crate::ps::util::futures::into_stream_trait;
+use crate::ps::{model, Error, Result, Stream};
+
+// The most bytes of a file held in memory at once while reading it:
+const READ_BLOCK_SIZE: usize = 1024 * 1024;
 
 /// An identifier returned by the Pennsieve platform used to group
 /// a collection of files together for uploading.
@@ -136,11 +140,9 @@ impl S3FileChunk {
         } else {
             read_amount
         } as usize;
-        //let mut buf = vec![0u8; n];
-        let mut buf = Vec::with_capacity(n);
-        unsafe {
-            buf.set_len(n);
-        }
+        // Zeroing the buffer costs next to nothing next to reading it from
+        // disk, and a short read can't leave it uninitialized:
+        let mut buf = vec![0u8; n];
 
         self.handle.seek(SeekFrom::Start(offset))?;
         self.handle.read_exact(buf.as_mut_slice())?;
@@ -154,9 +156,54 @@ impl S3FileChunk {
     }
 }
 
+/// A hash function used to checksum files.
+#[derive(Copy, Clone, Deserialize, Debug, Eq, Hash, PartialEq, Serialize)]
+pub enum HashAlgorithm {
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Computes the hex-encoded checksum of the file at the given path.
+    pub fn checksum_of_file<P: AsRef<Path>>(self, path: P) -> Result<Checksum> {
+        match self {
+            HashAlgorithm::Sha256 => Checksum::of_file(path),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashAlgorithm::Sha256 => write!(f, "SHA-256"),
+        }
+    }
+}
+
 #[derive(Clone, Deserialize, Debug, Eq, Hash, PartialEq, Serialize)]
 pub struct Checksum(pub String);
 
+impl Checksum {
+    /// Computes the hex-encoded SHA-256 checksum of the file at the given path.
+    pub fn of_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = fs::File::open(path)?;
+        let mut sha256_hasher = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let bytes_read = file.read(&mut buffer)?;
+            if bytes_read == 0 {
+                break;
+            }
+            sha256_hasher.input(&buffer[..bytes_read]);
+        }
+        Ok(Checksum(format!("{:x}", sha256_hasher.result())))
+    }
+
+    /// Tests if this checksum matches the given hex-encoded hash.
+    pub fn matches<S: AsRef<str>>(&self, hash: S) -> bool {
+        self.0.eq_ignore_ascii_case(hash.as_ref())
+    }
+}
+
 #[derive(Clone, Deserialize, Debug, Eq, Hash, PartialEq, Serialize)]
 pub struct MultipartUploadId(pub String);
 
@@ -536,19 +583,13 @@ impl S3File {
         self.file_path.as_ref()
     }
 
+    /// Read the contents of the file a block at a time, so that no more
+    /// than a block is held in memory at once however large the file is.
+    /// Uploads should stream the file with `ChunkedFilePayload` instead.
     #[allow(dead_code)]
-    pub fn read_bytes<P: AsRef<Path>>(&self, from_path: P) -> Future<Vec<u8>> {
+    pub fn read_bytes<P: AsRef<Path>>(&self, from_path: P) -> Stream<Vec<u8>> {
         let file_path: PathBuf = from_path.as_ref().join(self.file_name.to_owned());
-        into_future_trait(future::lazy(move || {
-            let f = match fs::File::open(file_path) {
-                Ok(f) => f,
-                Err(e) => return future::err(e.into()),
-            };
-            f.bytes()
-                .collect::<result::Result<Vec<_>, _>>()
-                .map_err(Into::into)
-                .into_future()
-        }))
+        read_blocks(file_path, READ_BLOCK_SIZE)
     }
 
     pub fn chunks<P: AsRef<Path>>(&self, from_path: P, chunk_size: u64) -> Stream<S3FileChunk> {
@@ -560,15 +601,83 @@ impl S3File {
     }
 }
 
-// An ETL processor job type
-#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+/// The kind of job the ETL processor runs on an upload.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
-enum PayloadType {
+pub enum PayloadType {
+    /// Files uploaded into new packages.
     Upload,
+    /// Files appended to an existing package.
     Append,
+    /// Files run through a processing workflow.
     Workflow,
 }
 
+/// How far along the ETL processor is with processing an upload, once the
+/// upload has been completed.
+///
+/// Statuses the client doesn't know about are kept as `Unknown`, so new
+/// statuses added to the platform never fail deserialization.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum ManifestStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Unknown(String),
+}
+
+impl ManifestStatus {
+    /// Returns the status as the platform spells it, e.g. `"SUCCEEDED"`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ManifestStatus::Pending => "PENDING",
+            ManifestStatus::Running => "RUNNING",
+            ManifestStatus::Succeeded => "SUCCEEDED",
+            ManifestStatus::Failed => "FAILED",
+            ManifestStatus::Unknown(status) => status.as_str(),
+        }
+    }
+
+    /// Tests if processing has finished, one way or another. Unknown
+    /// statuses are never considered terminal, as there's no telling
+    /// whether they are.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, ManifestStatus::Succeeded | ManifestStatus::Failed)
+    }
+}
+
+impl<'a> From<&'a str> for ManifestStatus {
+    fn from(status: &'a str) -> Self {
+        match status {
+            "PENDING" => ManifestStatus::Pending,
+            "RUNNING" => ManifestStatus::Running,
+            "SUCCEEDED" => ManifestStatus::Succeeded,
+            "FAILED" => ManifestStatus::Failed,
+            _ => ManifestStatus::Unknown(status.to_string()),
+        }
+    }
+}
+
+impl From<String> for ManifestStatus {
+    fn from(status: String) -> Self {
+        ManifestStatus::from(status.as_str())
+    }
+}
+
+impl From<ManifestStatus> for String {
+    fn from(status: ManifestStatus) -> Self {
+        status.as_str().to_string()
+    }
+}
+
+impl fmt::Display for ManifestStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 // A manifest job, as generated by the Nextflow ETL processor.
 #[derive(Clone, Deserialize, Debug, Eq, Hash, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -630,6 +739,12 @@ impl ManifestEntry {
         &self.manifest.import_id()
     }
 
+    #[allow(dead_code)]
+    /// The kind of job the ETL processor runs on the upload.
+    pub fn job_type(&self) -> PayloadType {
+        *self.manifest.job_type()
+    }
+
     #[allow(dead_code)]
     /// A collection of uploaded files, relative to the Pennsieve S3 bucket.
     pub fn files(&self) -> &Vec<String> {
@@ -699,6 +814,167 @@ impl PackagePreview {
     }
 }
 
+/// Everything needed to carry on with an upload from another process, e.g.
+/// after a crash: which import it is, where it's going, and where its files
+/// are read from. Write it somewhere durable before uploading anything, then
+/// pass it to `resume_upload` to continue from the parts the upload service
+/// hasn't received.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadSession {
+    organization_id: model::OrganizationId,
+    import_id: ImportId,
+    dataset_id: model::DatasetNodeId,
+    files: Vec<S3File>,
+    path: PathBuf,
+}
+
+impl UploadSession {
+    pub fn new<P: Into<PathBuf>>(
+        organization_id: model::OrganizationId,
+        import_id: ImportId,
+        dataset_id: model::DatasetNodeId,
+        files: Vec<S3File>,
+        path: P,
+    ) -> Self {
+        Self {
+            organization_id,
+            import_id,
+            dataset_id,
+            files,
+            path: path.into(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn organization_id(&self) -> &model::OrganizationId {
+        &self.organization_id
+    }
+
+    #[allow(dead_code)]
+    pub fn import_id(&self) -> &ImportId {
+        &self.import_id
+    }
+
+    #[allow(dead_code)]
+    pub fn dataset_id(&self) -> &model::DatasetNodeId {
+        &self.dataset_id
+    }
+
+    #[allow(dead_code)]
+    pub fn files(&self) -> &Vec<S3File> {
+        &self.files
+    }
+
+    /// The directory the files are read from.
+    #[allow(dead_code)]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// What an upload would do, as worked out by the upload service, without
+/// anything having been sent: which packages would be created, and which
+/// files would go where.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct UploadPlan {
+    packages: Vec<PackagePreview>,
+}
+
+impl UploadPlan {
+    pub fn new(packages: Vec<PackagePreview>) -> Self {
+        Self { packages }
+    }
+
+    #[allow(dead_code)]
+    pub fn packages(&self) -> &Vec<PackagePreview> {
+        &self.packages
+    }
+
+    /// The number of packages the upload would create.
+    #[allow(dead_code)]
+    pub fn package_count(&self) -> usize {
+        self.packages.len()
+    }
+
+    /// Every file the upload would send.
+    #[allow(dead_code)]
+    pub fn files(&self) -> impl Iterator<Item = &S3File> {
+        self.packages.iter().flat_map(|package| package.files())
+    }
+
+    /// The number of bytes the upload would send, across every file.
+    #[allow(dead_code)]
+    pub fn total_bytes(&self) -> u64 {
+        self.files().map(S3File::size).sum()
+    }
+
+    /// The path within the dataset each file would be uploaded to, in the
+    /// order of `files`.
+    #[allow(dead_code)]
+    pub fn destination_paths(&self) -> Vec<PathBuf> {
+        self.files()
+            .map(|file| {
+                file.destination_path()
+                    .into_iter()
+                    .flatten()
+                    .chain(iter::once(file.file_name()))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// An update on the progress of an upload, as the upload service reports it.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum UploadEvent {
+    /// Some of the upload's parts have been received.
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        parts_uploaded: usize,
+        parts_total: usize,
+    },
+    /// Every part has been received.
+    Completed,
+    /// The upload failed, and won't make any more progress.
+    Failed { message: String },
+}
+
+impl UploadEvent {
+    /// Tests if no more events follow this one.
+    pub fn is_final(&self) -> bool {
+        match self {
+            UploadEvent::Progress { .. } => false,
+            UploadEvent::Completed | UploadEvent::Failed { .. } => true,
+        }
+    }
+}
+
+/// Streams the contents of the file at `path` in blocks of at most
+/// `block_size` bytes.
+fn read_blocks<P: AsRef<Path>>(path: P, block_size: usize) -> Stream<Vec<u8>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) => return into_stream_trait(stream::once(Err(e.into()))),
+    };
+    into_stream_trait(stream::unfold(Some(file), move |file| {
+        let mut file = file?;
+        let mut block = vec![0u8; block_size];
+        loop {
+            match file.read(&mut block) {
+                Ok(0) => return None,
+                Ok(n) => {
+                    block.truncate(n);
+                    return Some(future::ok((block, Some(file))));
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Some(future::err(e.into())),
+            }
+        }
+    }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -706,6 +982,15 @@ mod tests {
 
     const USE_CHUNK_SIZE: u64 = 100;
 
+    #[test]
+    pub fn file_checksum_is_sha256() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/data/small/empty_file").to_owned();
+        let checksum = Checksum::of_file(path).unwrap();
+        assert!(
+            checksum.matches("E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855")
+        );
+    }
+
     #[test]
     pub fn empty_file_chunking_works() {
         let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/data/small/empty_file").to_owned();
@@ -730,6 +1015,40 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn the_final_short_chunk_of_a_file_is_read_in_full() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/data/small/example.csv").to_owned();
+        let contents = fs::read(path.clone()).unwrap();
+        let file_size = contents.len() as u64;
+        assert_ne!(file_size % USE_CHUNK_SIZE, 0);
+
+        let mut chunks = file_chunks(path, file_size, USE_CHUNK_SIZE).unwrap();
+        let last = chunks.last_mut().unwrap();
+        let bytes = last.read().unwrap();
+
+        assert_eq!(bytes.len() as u64, file_size % USE_CHUNK_SIZE);
+        assert_eq!(
+            bytes.as_slice(),
+            &contents[(file_size - file_size % USE_CHUNK_SIZE) as usize..]
+        );
+    }
+
+    #[test]
+    pub fn files_are_read_a_block_at_a_time() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/data/small/example.csv").to_owned();
+        let contents = fs::read(path.clone()).unwrap();
+        let block_size = USE_CHUNK_SIZE as usize;
+        assert!(contents.len() > block_size);
+
+        let blocks = futures::Stream::collect(read_blocks(path, block_size))
+            .wait()
+            .unwrap();
+
+        assert_eq!(blocks.len(), contents.len().div_ceil(block_size));
+        assert!(blocks.iter().all(|block| block.len() <= block_size));
+        assert_eq!(blocks.concat(), contents);
+    }
+
     #[test]
     #[cfg_attr(target_os = "windows", ignore)]
     pub fn during_directory_upload_root_upload_directory_path_finding_works() {